@@ -0,0 +1,80 @@
+//! Self-hostable sync server for Ngurra collections.
+//!
+//! Only the sync modules are compiled in, so the server does not pull in the
+//! GUI and can run on a headless machine.
+
+#[path = "../sync/changes.rs"]
+#[allow(dead_code)]
+mod changes;
+#[path = "../repositories/guid.rs"]
+#[allow(dead_code)]
+mod guid;
+#[path = "../sync/protocol.rs"]
+#[allow(dead_code)]
+mod protocol;
+#[path = "../sync/server.rs"]
+mod server;
+
+/// The sync modules refer to the usn and grave kinds by their place in the
+/// app.
+mod repositories {
+    pub(crate) use super::guid;
+}
+
+use std::path::PathBuf;
+
+use server::SyncServer;
+
+const DEFAULT_ADDR: &str = "127.0.0.1:27701";
+
+struct Args {
+    addr: String,
+    collection: PathBuf,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut addr = std::env::var("NGURRA_SYNC_ADDR").unwrap_or_else(|_| DEFAULT_ADDR.to_string());
+    let mut collection = std::env::var_os("NGURRA_SYNC_COLLECTION").map(PathBuf::from);
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("{} expects a value", arg))
+        };
+
+        match arg.as_str() {
+            "--addr" => addr = value()?,
+            "--collection" => collection = Some(PathBuf::from(value()?)),
+            _ => return Err(format!("Unknown argument: {}", arg)),
+        }
+    }
+
+    let collection = collection
+        .or_else(|| dirs::data_dir().map(|dir| dir.join("ngurra-sync").join("collection.db")))
+        .ok_or("No data directory found, pass --collection")?;
+
+    Ok(Args { addr, collection })
+}
+
+fn main() {
+    env_logger::init();
+
+    let args = parse_args().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    });
+
+    let listener = tiny_http::Server::http(&args.addr).unwrap_or_else(|e| {
+        eprintln!("Could not listen on {}: {}", args.addr, e);
+        std::process::exit(1);
+    });
+
+    log::info!(
+        "Serving {:?} on http://{}",
+        args.collection,
+        listener.server_addr()
+    );
+
+    SyncServer::new(args.collection).serve(listener);
+}
@@ -0,0 +1,57 @@
+use std::path::PathBuf;
+
+/// Command line options accepted by the `ngurra` binary.
+#[derive(Debug, Default, PartialEq)]
+pub struct Args {
+    /// Folder holding the profiles. Falls back to `NGURRA_BASE`, then to the
+    /// platform data directory.
+    pub base: Option<PathBuf>,
+    /// Profile to open. Falls back to `NGURRA_PROFILE`; when unset and several
+    /// profiles exist, a profile switcher is shown at startup.
+    pub profile: Option<String>,
+    /// Backup file to restore as the live collection before starting.
+    pub restore: Option<PathBuf>,
+    /// Anki package to import into the collection before starting.
+    pub import: Option<PathBuf>,
+    /// Search to print the matching cards of, instead of starting the app.
+    pub search: Option<String>,
+}
+
+impl Args {
+    pub fn parse() -> Result<Self, String> {
+        let mut args = Self::parse_from(std::env::args().skip(1))?;
+
+        if args.base.is_none() {
+            args.base = std::env::var_os("NGURRA_BASE").map(PathBuf::from);
+        }
+
+        if args.profile.is_none() {
+            args.profile = std::env::var("NGURRA_PROFILE").ok();
+        }
+
+        Ok(args)
+    }
+
+    pub fn parse_from(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("{} expects a value", arg))
+            };
+
+            match arg.as_str() {
+                "--base" => parsed.base = Some(PathBuf::from(value()?)),
+                "--profile" => parsed.profile = Some(value()?),
+                "--restore" => parsed.restore = Some(PathBuf::from(value()?)),
+                "--import" => parsed.import = Some(PathBuf::from(value()?)),
+                "--search" => parsed.search = Some(value()?),
+                _ => return Err(format!("Unknown argument: {}", arg)),
+            }
+        }
+
+        Ok(parsed)
+    }
+}
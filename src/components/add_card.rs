@@ -1,95 +1,168 @@
 use gpui::{
-    div, prelude::*, AnyView, ClickEvent, EventEmitter, FocusHandle, Pixels, Render, View,
-    ViewContext, WindowContext,
+    div, prelude::*, AnyView, ClickEvent, EventEmitter, FocusHandle, Render, View, ViewContext,
+    WindowContext,
 };
 
 use crate::{
-    components::tab_bar_container::TabEvent,
-    repositories,
+    components::{
+        shared::{
+            card_form::{attach_media, CardForm},
+            tag_editor::TagEditor,
+        },
+        tab_bar_container::TabEvent,
+    },
+    errors::ErrorState,
+    repositories::{
+        self,
+        note::Note,
+        notetype::{Notetype, BASIC_GUID},
+    },
     state::{StackableView, StackableViewState},
     theme::Theme,
     ui::{button::button::Button, clickable::Clickable, text_field::text_field::TextField},
-    Deck,
 };
 
 pub struct AddCardView {
-    deck: Deck,
-    front_input: TextField,
-    back_input: TextField,
+    deck_id: u32,
+    notetypes: Vec<Notetype>,
+    notetype_at: usize,
+    field_inputs: Vec<TextField>,
+    tag_editor: View<TagEditor>,
     deck_input: TextField,
+    media_input: TextField,
     focus_handle: FocusHandle,
     focused_at: usize,
 }
 
 impl EventEmitter<TabEvent> for AddCardView {}
 
-const TABBALE_FIELDS: [&str; 3] = ["front", "back", "submit"];
-
 impl AddCardView {
     pub fn view(deck_id: u32, cx: &mut WindowContext) -> View<Self> {
         cx.new_view(|cx: &mut gpui::ViewContext<'_, AddCardView>| {
-            let deck = repositories::deck::Deck::load(
-                deck_id,
-                &cx.global::<crate::Collection>().storage.conn,
-            )
-            .unwrap();
+            let collection = cx.global::<crate::Collection>();
+            let deck = repositories::deck::Deck::load(deck_id, &collection.storage.conn);
+            let notetypes = collection.notetypes();
 
             let deck_input = TextField::new(cx, "".to_string(), true);
 
-            deck_input.view.update(cx, |view, _| {
-                view.text = deck.name.clone();
-            });
+            match deck {
+                Ok(deck) => deck_input.view.update(cx, |view, _| {
+                    view.text = deck.name.clone();
+                }),
+                Err(e) => ErrorState::report("Error loading deck", e, cx),
+            }
 
-            let front_input = TextField::new(cx, "".to_string(), false);
-            front_input.focus(cx);
+            let notetypes = notetypes.unwrap_or_else(|e| {
+                ErrorState::report("Error loading note types", e, cx);
+                Vec::new()
+            });
+            let notetype_at = notetypes
+                .iter()
+                .position(|notetype| notetype.guid == BASIC_GUID)
+                .unwrap_or_default();
 
             let focus_handle = cx.focus_handle();
 
-            Self {
-                deck,
-                front_input,
-                back_input: TextField::new(cx, "".to_string(), false),
+            let mut view = Self {
+                deck_id,
+                notetypes,
+                notetype_at,
+                field_inputs: Vec::new(),
+                tag_editor: TagEditor::view(&[], cx),
                 deck_input,
+                media_input: TextField::new(
+                    cx,
+                    "Path to an image or audio file".to_string(),
+                    false,
+                ),
                 focused_at: 0,
                 focus_handle,
-            }
+            };
+            view.build_field_inputs(cx);
+            view
         })
     }
 
-    fn save_click(&mut self, _event: &ClickEvent, cx: &mut ViewContext<Self>) {
-        let collection = cx.global::<crate::Collection>();
-        let front = &self.front_input.view.read(&cx).text;
-        let back = &self.back_input.view.read(&cx).text;
+    /// Creates one input per field of the chosen note type, keeping what was
+    /// already typed into the fields at the same positions.
+    fn build_field_inputs(&mut self, cx: &mut ViewContext<Self>) {
+        let Some(notetype) = self.notetypes.get(self.notetype_at) else {
+            return;
+        };
 
-        let mut card =
-            repositories::flash_card::FlashCard::new(self.deck.id.unwrap(), front, back, None);
-        match card.save(&collection.storage.conn) {
-            Ok(_) => {
-                StackableViewState::update(|state, cx| state.pop(cx), cx);
-            }
-            Err(e) => {
-                log::error!("Error saving card: {:?}", e);
-            }
+        let mut texts = self
+            .field_inputs
+            .iter()
+            .map(|input| input.view.read(cx).text.clone())
+            .collect::<Vec<_>>()
+            .into_iter();
+
+        self.field_inputs = notetype
+            .fields
+            .iter()
+            .map(|_| {
+                let input = TextField::new(cx, "".to_string(), false);
+                let text = texts.next().unwrap_or_default();
+                input.view.update(cx, |view, _| view.text = text);
+                input
+            })
+            .collect();
+
+        if let Some(first) = self.field_inputs.first() {
+            first.focus(cx);
         }
+        self.focused_at = 0;
     }
 
-    fn save_key_down(&mut self, cx: &mut ViewContext<Self>) {
-        let collection = cx.global::<crate::Collection>();
-        let front = &self.front_input.view.read(&cx).text;
-        let back = &self.back_input.view.read(&cx).text;
+    fn notetype_click(&mut self, _event: &ClickEvent, cx: &mut ViewContext<Self>) {
+        if self.notetypes.is_empty() {
+            return;
+        }
+
+        self.notetype_at = (self.notetype_at + 1) % self.notetypes.len();
+        self.build_field_inputs(cx);
+        cx.notify();
+    }
+
+    fn save_click(&mut self, _event: &ClickEvent, cx: &mut ViewContext<Self>) {
+        self.save(cx);
+    }
+
+    fn save(&mut self, cx: &mut ViewContext<Self>) {
+        let Some(notetype) = self.notetypes.get(self.notetype_at) else {
+            return;
+        };
 
-        let mut card =
-            repositories::flash_card::FlashCard::new(self.deck.id.unwrap(), front, back, None);
-        match card.save(&collection.storage.conn) {
+        let mut note = Note::new(notetype);
+        note.fields = self
+            .field_inputs
+            .iter()
+            .map(|input| input.view.read(cx).text.clone())
+            .collect();
+        note.tags = self.tag_editor.read(cx).tags(cx);
+
+        let deck_id = self.deck_id;
+        let result = cx.update_global(|collection: &mut crate::Collection, _| {
+            collection.add_note(&mut note, deck_id)
+        });
+
+        match result {
             Ok(_) => {
                 StackableViewState::update(|state, cx| state.pop(cx), cx);
                 cx.notify();
             }
             Err(e) => {
-                log::error!("Error saving card: {:?}", e);
+                ErrorState::report("Error saving note", e, cx);
             }
         }
     }
+
+    /// Copies the chosen file into the media folder and references it at the
+    /// end of the last field.
+    fn attach_click(&mut self, _event: &ClickEvent, cx: &mut ViewContext<Self>) {
+        attach_media(&self.media_input, self.field_inputs.last(), cx);
+        cx.notify();
+    }
 }
 
 impl Render for AddCardView {
@@ -101,13 +174,32 @@ impl Render for AddCardView {
             Button::new("create", "Create card", None).on_click(cx.listener(Self::save_click));
 
         let focused_at = self.focused_at;
-        let front_input = self.front_input.clone();
-        let back_input = self.back_input.clone();
+        let field_inputs = self.field_inputs.clone();
+        let tag_editor = self.tag_editor.clone();
+        // The fields, the tags, then the submit button.
+        let tabbable = self.field_inputs.len() + 2;
 
-        if self.focused_at == 2 {
+        if self.focused_at == self.field_inputs.len() + 1 {
             submit_btn.focus();
         }
 
+        let notetype_name = self
+            .notetypes
+            .get(self.notetype_at)
+            .map(|notetype| notetype.name.clone())
+            .unwrap_or_default();
+
+        let field_names = self
+            .notetypes
+            .get(self.notetype_at)
+            .map(|notetype| notetype.fields.clone())
+            .unwrap_or_default();
+
+        let fields = field_names
+            .into_iter()
+            .zip(self.field_inputs.iter().cloned())
+            .collect::<Vec<_>>();
+
         div().flex().size_full().justify_center().child(
             div().mt_20().child(
                 div()
@@ -124,59 +216,37 @@ impl Render for AddCardView {
 
                             match keystroke.as_str() {
                                 "tab" => {
-                                    let next = (focused_at + 1) % TABBALE_FIELDS.len();
+                                    let next = (focused_at + 1) % tabbable;
 
-                                    match TABBALE_FIELDS[next] {
-                                        "front" => front_input.focus(vc),
-                                        "back" => back_input.focus(vc),
-                                        "submit" => vc.focus(&add_view.focus_handle),
-                                        _ => {}
+                                    match field_inputs.get(next) {
+                                        Some(input) => input.focus(vc),
+                                        None if next == field_inputs.len() => {
+                                            let input = tag_editor.read(vc).input.clone();
+                                            input.focus(vc);
+                                        }
+                                        None => vc.focus(&add_view.focus_handle),
                                     }
                                     add_view.focused_at = next;
                                 }
                                 "enter" => {
-                                    add_view.save_key_down(vc);
+                                    add_view.save(vc);
                                 }
                                 _ => {}
                             }
                         });
                     })
                     .child(
-                        div()
-                            .w(Pixels(500.0))
-                            .child(
-                                div()
-                                    .text_xl()
-                                    .font_weight(gpui::FontWeight::EXTRA_BOLD)
-                                    .child("Add a new card"),
+                        CardForm::new("Add a new card", self.deck_input.clone(), submit_btn)
+                            .notetype(
+                                Button::new("notetype", notetype_name, None)
+                                    .on_click(cx.listener(Self::notetype_click)),
                             )
-                            .child(
-                                div()
-                                    .mt_5()
-                                    .child(
-                                        div()
-                                            .text_lg()
-                                            .font_weight(gpui::FontWeight::BOLD)
-                                            .child("Deck"),
-                                    )
-                                    .child(self.deck_input.clone())
-                                    .child(
-                                        div()
-                                            .mt_5()
-                                            .text_lg()
-                                            .font_weight(gpui::FontWeight::BOLD)
-                                            .child("Front"),
-                                    )
-                                    .child(self.front_input.clone())
-                                    .child(
-                                        div()
-                                            .mt_5()
-                                            .text_lg()
-                                            .font_weight(gpui::FontWeight::BOLD)
-                                            .child("Back"),
-                                    )
-                                    .child(self.back_input.clone())
-                                    .child(div().mt_5().flex().justify_end().child(submit_btn)),
+                            .fields(fields)
+                            .tags(self.tag_editor.clone())
+                            .media(
+                                self.media_input.clone(),
+                                Button::new("attach", "Attach", None)
+                                    .on_click(cx.listener(Self::attach_click)),
                             ),
                     ),
             ),
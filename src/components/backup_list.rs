@@ -0,0 +1,129 @@
+use std::path::PathBuf;
+
+use gpui::{
+    div, AnyView, FontWeight, ParentElement, Pixels, Render, Styled, View, ViewContext,
+    VisualContext, WindowContext,
+};
+
+use crate::{
+    errors::ErrorState,
+    models::collection::Collection,
+    state::{StackableView, StackableViewState},
+    storage::backup::{list_backups, BackupEntry},
+    theme::Theme,
+    ui::{button::button::Button, clickable::Clickable},
+};
+
+pub struct BackupListView {
+    backups: Vec<BackupEntry>,
+}
+
+impl BackupListView {
+    pub fn view(cx: &mut WindowContext) -> View<Self> {
+        let backups = Self::load_backups(cx.global::<Collection>());
+
+        cx.new_view(|_cx| Self { backups })
+    }
+
+    fn load_backups(collection: &Collection) -> Vec<BackupEntry> {
+        list_backups(&collection.backups_folder()).unwrap_or_else(|e| {
+            log::error!("Error listing backups: {:?}", e);
+            vec![]
+        })
+    }
+
+    fn backup_now(&mut self, cx: &mut ViewContext<Self>) {
+        if let Err(e) = cx.global::<Collection>().maybe_backup(true) {
+            ErrorState::report("Error backing up collection", e, cx);
+        }
+
+        self.backups = Self::load_backups(cx.global::<Collection>());
+        cx.notify();
+    }
+
+    fn restore(path: PathBuf, cx: &mut WindowContext) {
+        let result =
+            cx.update_global(|collection: &mut Collection, _| collection.restore_backup(&path));
+
+        match result {
+            Ok(_) => {
+                StackableViewState::update(|state, cx| state.pop(cx), cx);
+                cx.refresh();
+            }
+            Err(e) => {
+                ErrorState::report("Error restoring backup", e, cx);
+            }
+        }
+    }
+}
+
+impl Render for BackupListView {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl gpui::prelude::IntoElement {
+        let theme = cx.global::<Theme>();
+
+        let rows = self
+            .backups
+            .iter()
+            .enumerate()
+            .map(|(ix, backup)| {
+                let path = backup.path.clone();
+
+                div()
+                    .flex()
+                    .justify_between()
+                    .items_center()
+                    .py_1()
+                    .text_sm()
+                    .child(backup.created.format("%Y-%m-%d %H:%M:%S").to_string())
+                    .child(
+                        Button::new(("restore-backup", ix), "Restore", None)
+                            .on_click(move |_e, cx| Self::restore(path.clone(), cx)),
+                    )
+            })
+            .collect::<Vec<_>>();
+
+        let empty = if self.backups.is_empty() {
+            div().text_sm().child("No backups yet")
+        } else {
+            div()
+        };
+
+        div().flex().size_full().justify_center().child(
+            div().mt_20().child(
+                div()
+                    .w(Pixels(500.0))
+                    .flex()
+                    .flex_col()
+                    .text_color(theme.text)
+                    .child(
+                        div()
+                            .flex()
+                            .justify_between()
+                            .items_center()
+                            .pb_5()
+                            .border_b_1()
+                            .border_color(theme.crust)
+                            .child(
+                                div()
+                                    .text_xl()
+                                    .font_weight(FontWeight::EXTRA_BOLD)
+                                    .child("Backups"),
+                            )
+                            .child(
+                                Button::new("backup-now", "Back up now", None)
+                                    .on_click(cx.listener(|this, _e, cx| this.backup_now(cx))),
+                            ),
+                    )
+                    .child(div().mt_5().children(rows).child(empty)),
+            ),
+        )
+    }
+}
+
+pub struct BackupListBuilder;
+
+impl StackableView for BackupListBuilder {
+    fn build(&self, cx: &mut WindowContext) -> AnyView {
+        BackupListView::view(cx).into()
+    }
+}
@@ -0,0 +1,554 @@
+use std::collections::{HashMap, HashSet};
+
+use gpui::{
+    div, prelude::*, px, uniform_list, AnyView, Div, FocusHandle, FontWeight, KeyDownEvent,
+    MouseButton, MouseDownEvent, Pixels, Render, UniformListScrollHandle, View, ViewContext,
+    WindowContext,
+};
+
+use crate::{
+    components::{
+        browse_sidebar::{BrowseSidebar, SidebarEvent},
+        bulk_actions::{BulkActionEvent, BulkActionsView},
+        edit_card::{EditCardEvent, EditCardView},
+        find_replace::{FindReplaceEvent, FindReplaceView},
+    },
+    errors::{ErrorState, NgurraError},
+    models::{
+        browse::{load_rows, one_line, sort_rows, CardRow, Column},
+        collection::Collection,
+    },
+    search::fts::{split_highlight, Highlight},
+    theme::Theme,
+    ui::{
+        button::button::Button,
+        clickable::Clickable,
+        text_field::text_field::{TextEvent, TextField},
+    },
+};
+
+/// Every card of the collection in a table that can be searched and sorted by
+/// any column. Selecting a card opens it in an editor at the side; selecting
+/// several, with ctrl or shift held, offers changes to all of them instead.
+/// The sidebar lists saved searches, decks and tags to filter by.
+pub struct BrowseView {
+    rows: Vec<CardRow>,
+    /// The words the search matched in each card, by card id.
+    highlights: HashMap<u32, Highlight>,
+    load_error: Option<String>,
+    search_input: TextField,
+    sidebar: View<BrowseSidebar>,
+    sort_column: Column,
+    ascending: bool,
+    /// The card clicked last, where a shift-click range starts.
+    selected: Option<u32>,
+    /// Every selected card, including `selected`.
+    selection: HashSet<u32>,
+    editor: Option<View<EditCardView>>,
+    bulk_actions: Option<View<BulkActionsView>>,
+    /// Shown instead of the editor or the bulk actions while open.
+    find_replace: Option<View<FindReplaceView>>,
+    scroll_handle: UniformListScrollHandle,
+    focus_handle: FocusHandle,
+}
+
+impl BrowseView {
+    pub fn view(cx: &mut WindowContext) -> View<Self> {
+        cx.new_view(|cx: &mut ViewContext<Self>| {
+            let search_input =
+                TextField::new(cx, "Search, e.g. deck:Spanish is:due".to_string(), false);
+            cx.subscribe(&search_input.view, |this: &mut Self, _, event, cx| {
+                if let TextEvent::Input { .. } = event {
+                    this.reload(cx);
+                }
+            })
+            .detach();
+            search_input.focus(cx);
+
+            let sidebar = BrowseSidebar::view(cx);
+            cx.subscribe(&sidebar, |this: &mut Self, _, event, cx| match event {
+                SidebarEvent::Search(query) => this.search(query.clone(), cx),
+            })
+            .detach();
+
+            let mut view = Self {
+                rows: Vec::new(),
+                highlights: HashMap::new(),
+                load_error: None,
+                search_input,
+                sidebar,
+                sort_column: Column::Created,
+                ascending: true,
+                selected: None,
+                selection: HashSet::new(),
+                editor: None,
+                bulk_actions: None,
+                find_replace: None,
+                scroll_handle: UniformListScrollHandle::new(),
+                focus_handle: cx.focus_handle(),
+            };
+            view.reload(cx);
+            view
+        })
+    }
+
+    /// Loads the rows matching the search again, keeping the sort order.
+    fn reload(&mut self, cx: &mut ViewContext<Self>) {
+        let search = self.search_input.view.read(cx).text.clone();
+        let query = search.clone();
+        self.sidebar
+            .update(cx, |sidebar, cx| sidebar.set_query(query, cx));
+        let collection = cx.global::<Collection>();
+
+        match load_rows(&collection.storage.conn, &search, &collection.timing) {
+            Ok(mut rows) => {
+                sort_rows(&mut rows, self.sort_column, self.ascending);
+                self.rows = rows;
+                self.load_error = None;
+
+                let shown = self
+                    .rows
+                    .iter()
+                    .map(|row| row.card_id)
+                    .collect::<HashSet<_>>();
+                self.selection.retain(|id| shown.contains(id));
+                if let Some(bulk_actions) = &self.bulk_actions {
+                    let card_ids = self.selected_ids();
+                    bulk_actions.update(cx, |view, cx| view.set_cards(card_ids, cx));
+                }
+
+                self.highlights = collection.search_highlights(&search).unwrap_or_else(|e| {
+                    log::error!("Error highlighting matches: {}", e);
+                    HashMap::new()
+                });
+            }
+            // A search is usually incomplete while it is being typed, so
+            // this is not worth logging.
+            Err(NgurraError::Search(e)) => {
+                self.rows.clear();
+                self.load_error = Some(format!("Invalid search: {}", e));
+            }
+            Err(e) => {
+                log::error!("Error loading cards: {}", e);
+                self.load_error = Some(format!("Could not load cards: {}", e));
+            }
+        }
+
+        cx.notify();
+    }
+
+    /// Replaces the search with one picked in the sidebar.
+    fn search(&mut self, query: String, cx: &mut ViewContext<Self>) {
+        self.search_input
+            .view
+            .update(cx, |view, _| view.text = query);
+        self.reload(cx);
+    }
+
+    fn rebuild_index(&mut self, cx: &mut ViewContext<Self>) {
+        match cx.global::<Collection>().rebuild_search_index() {
+            Ok(()) => self.reload(cx),
+            Err(e) => ErrorState::report("Error rebuilding the search index", e, cx),
+        }
+    }
+
+    /// Sorts by the column, or reverses the order if it is sorted by it
+    /// already.
+    fn sort_by(&mut self, column: Column, cx: &mut ViewContext<Self>) {
+        if self.sort_column == column {
+            self.ascending = !self.ascending;
+        } else {
+            self.sort_column = column;
+            self.ascending = true;
+        }
+
+        sort_rows(&mut self.rows, self.sort_column, self.ascending);
+        cx.notify();
+    }
+
+    /// The selected cards, in the order of the table.
+    fn selected_ids(&self) -> Vec<u32> {
+        self.rows
+            .iter()
+            .map(|row| row.card_id)
+            .filter(|id| self.selection.contains(id))
+            .collect()
+    }
+
+    fn row_click(&mut self, card_id: u32, event: &MouseDownEvent, cx: &mut ViewContext<Self>) {
+        let modifiers = &event.modifiers;
+
+        if modifiers.shift {
+            let position = |id| self.rows.iter().position(|row| row.card_id == id);
+            let range = match (self.selected.and_then(position), position(card_id)) {
+                (Some(from), Some(to)) => from.min(to)..=from.max(to),
+                _ => return self.select(card_id, cx),
+            };
+
+            self.selection = self.rows[range].iter().map(|row| row.card_id).collect();
+            self.selection_changed(cx);
+        } else if modifiers.control || modifiers.command {
+            if !self.selection.remove(&card_id) {
+                self.selection.insert(card_id);
+            }
+            self.selected = Some(card_id);
+            self.selection_changed(cx);
+        } else {
+            self.select(card_id, cx);
+        }
+    }
+
+    fn select_all(&mut self, cx: &mut ViewContext<Self>) {
+        self.selection = self.rows.iter().map(|row| row.card_id).collect();
+        self.selection_changed(cx);
+    }
+
+    /// Shows the editor for a single selected card, or the bulk actions for
+    /// several.
+    fn selection_changed(&mut self, cx: &mut ViewContext<Self>) {
+        self.update_find_replace(cx);
+
+        match self.selection.len() {
+            0 => {
+                self.editor = None;
+                self.bulk_actions = None;
+                cx.notify();
+            }
+            1 => {
+                let card_id = *self.selection.iter().next().unwrap();
+                self.select(card_id, cx);
+            }
+            _ => {
+                self.editor = None;
+                let card_ids = self.selected_ids();
+
+                match &self.bulk_actions {
+                    Some(bulk_actions) => {
+                        bulk_actions.update(cx, |view, cx| view.set_cards(card_ids, cx))
+                    }
+                    None => {
+                        let bulk_actions = BulkActionsView::view(card_ids, cx);
+                        cx.subscribe(&bulk_actions, |this, _, event, cx| match event {
+                            BulkActionEvent::Changed => this.reload(cx),
+                        })
+                        .detach();
+                        self.bulk_actions = Some(bulk_actions);
+                    }
+                }
+
+                cx.focus(&self.focus_handle);
+                cx.notify();
+            }
+        }
+    }
+
+    /// Keeps an open find and replace working on the current selection.
+    fn update_find_replace(&self, cx: &mut ViewContext<Self>) {
+        if let Some(find_replace) = &self.find_replace {
+            let card_ids = self.selected_ids();
+            find_replace.update(cx, |view, cx| view.set_selected(card_ids, cx));
+        }
+    }
+
+    fn open_find_replace(&mut self, cx: &mut ViewContext<Self>) {
+        let search = self.search_input.view.read(cx).text.clone();
+        let find_replace = FindReplaceView::view(self.selected_ids(), search, cx);
+        cx.subscribe(&find_replace, |this, _, event, cx| match event {
+            FindReplaceEvent::Replaced => this.reload(cx),
+            FindReplaceEvent::Closed => {
+                this.find_replace = None;
+                cx.focus(&this.focus_handle);
+                cx.notify();
+            }
+        })
+        .detach();
+
+        self.find_replace = Some(find_replace);
+        cx.notify();
+    }
+
+    /// Takes back the last change made to the selected cards.
+    fn undo(&mut self, cx: &mut ViewContext<Self>) {
+        let result = cx.update_global(|collection: &mut Collection, _| collection.undo());
+
+        match result {
+            Ok(_) => self.reload(cx),
+            Err(e) => ErrorState::report("Error undoing", e, cx),
+        }
+    }
+
+    fn select(&mut self, card_id: u32, cx: &mut ViewContext<Self>) {
+        let editor = EditCardView::embedded(card_id, cx);
+        cx.subscribe(&editor, |this, _, event, cx| match event {
+            EditCardEvent::Saved => this.reload(cx),
+            EditCardEvent::Closed => {
+                this.editor = None;
+                this.selected = None;
+                this.selection.clear();
+                cx.focus(&this.focus_handle);
+                cx.notify();
+            }
+        })
+        .detach();
+
+        self.selected = Some(card_id);
+        self.selection = HashSet::from([card_id]);
+        self.editor = Some(editor);
+        self.bulk_actions = None;
+        self.update_find_replace(cx);
+
+        // The editor focuses its first field; the arrow keys should keep
+        // moving through the table until it is clicked.
+        cx.focus(&self.focus_handle);
+        cx.notify();
+    }
+
+    fn move_selection(&mut self, down: bool, cx: &mut ViewContext<Self>) {
+        if self.rows.is_empty() {
+            return;
+        }
+
+        let current = self
+            .selected
+            .and_then(|id| self.rows.iter().position(|row| row.card_id == id));
+        let next = match (current, down) {
+            (None, _) => 0,
+            (Some(ix), true) => (ix + 1).min(self.rows.len() - 1),
+            (Some(ix), false) => ix.saturating_sub(1),
+        };
+
+        self.scroll_handle.scroll_to_item(next);
+        self.select(self.rows[next].card_id, cx);
+    }
+
+    fn key_down(&mut self, event: &KeyDownEvent, cx: &mut ViewContext<Self>) {
+        let modifiers = &event.keystroke.modifiers;
+
+        match event.keystroke.key.as_str() {
+            "down" => self.move_selection(true, cx),
+            "up" => self.move_selection(false, cx),
+            "a" if modifiers.control || modifiers.command => self.select_all(cx),
+            _ => {}
+        }
+    }
+
+    /// A cell of the given column: the question and answer share the space
+    /// left by the others.
+    fn cell(column: Column) -> Div {
+        let cell = div().px_2().overflow_hidden().whitespace_nowrap();
+
+        match column {
+            Column::Question | Column::Answer => cell.flex_1().min_w(px(120.0)),
+            Column::Deck => cell.w(px(140.0)),
+            Column::Interval | Column::Ease => cell.w(px(72.0)),
+            Column::Due | Column::Queue | Column::Created | Column::LastReview => cell.w(px(104.0)),
+        }
+    }
+
+    /// A cell showing the words the search matched in bold.
+    fn highlighted_cell(column: Column, text: &str, theme: &Theme) -> Div {
+        Self::cell(column)
+            .flex()
+            .children(
+                split_highlight(&one_line(text))
+                    .into_iter()
+                    .map(|(piece, matched)| {
+                        div()
+                            .flex_none()
+                            .when(matched, |this| {
+                                this.text_color(theme.blue).font_weight(FontWeight::BOLD)
+                            })
+                            .child(piece.to_string())
+                    }),
+            )
+    }
+
+    fn render_rows(
+        &mut self,
+        range: std::ops::Range<usize>,
+        cx: &mut ViewContext<Self>,
+    ) -> Vec<Div> {
+        let theme = cx.global::<Theme>();
+        let today = cx.global::<Collection>().timing.days_elapsed;
+        let mut selected_bg = theme.overlay0;
+        selected_bg.fade_out(0.5);
+
+        range
+            .filter_map(|ix| self.rows.get(ix))
+            .map(|row| {
+                let card_id = row.card_id;
+
+                div()
+                    .flex()
+                    .py_1()
+                    .text_sm()
+                    .rounded_md()
+                    .when(self.selection.contains(&card_id), |this| {
+                        this.bg(selected_bg)
+                    })
+                    .when(row.suspended, |this| this.text_color(theme.overlay1))
+                    .hover(|this| this.bg(theme.surface0))
+                    .on_mouse_down(
+                        MouseButton::Left,
+                        cx.listener(move |this, event, cx| this.row_click(card_id, event, cx)),
+                    )
+                    .child(
+                        div()
+                            .w(px(28.0))
+                            .flex_none()
+                            .text_color(theme.red)
+                            .when(row.flags > 0, |this| this.child(format!("⚑{}", row.flags))),
+                    )
+                    .children(Column::ALL.into_iter().map(|column| {
+                        let highlighted =
+                            self.highlights
+                                .get(&card_id)
+                                .and_then(|highlight| match column {
+                                    Column::Question => Some(&highlight.question),
+                                    Column::Answer => Some(&highlight.answer),
+                                    _ => None,
+                                });
+
+                        match highlighted {
+                            Some(text) => Self::highlighted_cell(column, text, theme),
+                            None => Self::cell(column).child(row.cell(column, today)),
+                        }
+                    }))
+            })
+            .collect()
+    }
+}
+
+impl Render for BrowseView {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let theme = cx.global::<Theme>();
+
+        let header = div()
+            .flex()
+            .pb_2()
+            .mb_1()
+            .border_b_1()
+            .border_color(theme.crust)
+            .text_sm()
+            .font_weight(FontWeight::BOLD)
+            .child(div().w(px(28.0)).flex_none())
+            .children(Column::ALL.into_iter().map(|column| {
+                let arrow = match (self.sort_column == column, self.ascending) {
+                    (true, true) => " ▲",
+                    (true, false) => " ▼",
+                    (false, _) => "",
+                };
+
+                Self::cell(column)
+                    .cursor_pointer()
+                    .hover(|this| this.text_color(theme.subtext1))
+                    .child(format!("{}{}", column.title(), arrow))
+                    .on_mouse_down(
+                        MouseButton::Left,
+                        cx.listener(move |this, _event, cx| this.sort_by(column, cx)),
+                    )
+            }));
+
+        let body = if self.rows.is_empty() {
+            div().p_2().text_sm().text_color(theme.subtext0).child(
+                self.load_error
+                    .clone()
+                    .unwrap_or_else(|| "No cards match the search".to_string()),
+            )
+        } else {
+            div().flex_1().child(
+                uniform_list(
+                    cx.view().clone(),
+                    "browse-rows",
+                    self.rows.len(),
+                    Self::render_rows,
+                )
+                .h_full()
+                .track_scroll(self.scroll_handle.clone()),
+            )
+        };
+
+        let side_panel: Option<(AnyView, f32)> = if let Some(find_replace) = &self.find_replace {
+            Some((find_replace.clone().into(), 480.0))
+        } else if let Some(bulk_actions) = &self.bulk_actions {
+            Some((bulk_actions.clone().into(), 420.0))
+        } else {
+            self.editor.clone().map(|editor| (editor.into(), 560.0))
+        };
+
+        div()
+            .size_full()
+            .flex()
+            .text_color(theme.text)
+            .child(self.sidebar.clone())
+            .child(
+                div()
+                    .flex_1()
+                    .h_full()
+                    .flex()
+                    .flex_col()
+                    .p_4()
+                    .child(
+                        div()
+                            .flex()
+                            .items_center()
+                            .justify_between()
+                            .pb_4()
+                            .child(
+                                div()
+                                    .text_xl()
+                                    .font_weight(FontWeight::EXTRA_BOLD)
+                                    .child("Browse"),
+                            )
+                            .child(
+                                div()
+                                    .flex()
+                                    .items_center()
+                                    .gap_2()
+                                    .text_sm()
+                                    .child(
+                                        div()
+                                            .text_color(theme.subtext0)
+                                            .child(format!("{} cards", self.rows.len())),
+                                    )
+                                    .child(div().w(Pixels(320.0)).child(self.search_input.clone()))
+                                    .children(cx.global::<Collection>().undo_name().map(|name| {
+                                        Button::new("undo", format!("Undo {}", name), None)
+                                            .on_click(cx.listener(|this, _e, cx| this.undo(cx)))
+                                    }))
+                                    .child(
+                                        Button::new("find-replace", "Find and replace", None)
+                                            .on_click(cx.listener(|this, _e, cx| {
+                                                this.open_find_replace(cx)
+                                            })),
+                                    )
+                                    .child(
+                                        Button::new("rebuild-index", "Rebuild index", None)
+                                            .on_click(
+                                                cx.listener(|this, _e, cx| this.rebuild_index(cx)),
+                                            ),
+                                    ),
+                            ),
+                    )
+                    .child(
+                        div()
+                            .track_focus(&self.focus_handle)
+                            .on_key_down(cx.listener(Self::key_down))
+                            .flex_1()
+                            .flex()
+                            .flex_col()
+                            .child(header)
+                            .child(body),
+                    ),
+            )
+            .children(side_panel.map(|(panel, width)| {
+                div()
+                    .w(Pixels(width))
+                    .h_full()
+                    .border_l_1()
+                    .border_color(theme.crust)
+                    .bg(theme.mantle)
+                    .child(panel)
+            }))
+    }
+}
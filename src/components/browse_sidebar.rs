@@ -0,0 +1,295 @@
+use gpui::{
+    div, prelude::*, px, AnchorCorner, Div, EventEmitter, FontWeight, KeyDownEvent, Render, View,
+    ViewContext, WindowContext,
+};
+
+use crate::{
+    errors::{ErrorState, Result},
+    models::{
+        collection::Collection,
+        deck::{self, get_decks},
+        saved_search::SavedSearch,
+        tag::TagNode,
+    },
+    search::exact_term,
+    theme::Theme,
+    ui::{
+        button::button::Button, clickable::Clickable, context_menu::ContextMenu,
+        list::list_item::ListItem, popover_menu::popover_menu, text_field::text_field::TextField,
+    },
+};
+
+/// The browser's sidebar: saved searches, then the decks and tags, each
+/// filtering the table with one click. The current search can be saved
+/// under a name, and saved searches renamed, reordered and deleted.
+pub struct BrowseSidebar {
+    /// The browser's search, which "Save" saves.
+    query: String,
+    name_input: TextField,
+    /// The saved search whose new name is being typed, if any.
+    renaming: Option<u32>,
+}
+
+pub enum SidebarEvent {
+    Search(String),
+}
+
+impl EventEmitter<SidebarEvent> for BrowseSidebar {}
+
+/// What the sidebar lists, loaded again on every render so that it follows
+/// changes made elsewhere.
+struct Loaded {
+    saved: Vec<SavedSearch>,
+    decks: Vec<String>,
+    tags: Vec<TagNode>,
+}
+
+impl BrowseSidebar {
+    pub fn view(cx: &mut WindowContext) -> View<Self> {
+        cx.new_view(|cx: &mut ViewContext<Self>| Self {
+            query: String::new(),
+            name_input: TextField::new(cx, "Name for this search".to_string(), false),
+            renaming: None,
+        })
+    }
+
+    pub fn set_query(&mut self, query: String, cx: &mut ViewContext<Self>) {
+        self.query = query;
+        cx.notify();
+    }
+
+    fn load(collection: &Collection) -> Result<Loaded> {
+        let decks = deck::sorted(get_decks(&collection.storage.conn)?)
+            .into_iter()
+            .map(|deck| deck.name)
+            .collect();
+
+        Ok(Loaded {
+            saved: collection.saved_searches()?,
+            decks,
+            tags: collection.tag_tree()?,
+        })
+    }
+
+    /// Saves the browser's search under the typed name, or renames the saved
+    /// search being renamed.
+    fn save(&mut self, cx: &mut ViewContext<Self>) {
+        let name = self.name_input.view.read(cx).text.clone();
+        let collection = cx.global::<Collection>();
+
+        let result = match self.renaming {
+            Some(id) => collection.rename_saved_search(id, &name),
+            None => collection.save_search(&name, &self.query).map(|_| ()),
+        };
+
+        match result {
+            Ok(()) => self.stop_renaming(cx),
+            Err(e) => ErrorState::report("Error saving search", e, cx),
+        }
+    }
+
+    fn start_renaming(&mut self, saved: &SavedSearch, cx: &mut ViewContext<Self>) {
+        let name = saved.name.clone();
+        self.name_input.view.update(cx, |view, _| view.text = name);
+        self.name_input.focus(cx);
+        self.renaming = Some(saved.id);
+        cx.notify();
+    }
+
+    fn stop_renaming(&mut self, cx: &mut ViewContext<Self>) {
+        self.name_input.view.update(cx, |view, _| view.text.clear());
+        self.renaming = None;
+        cx.notify();
+    }
+
+    fn key_down(&mut self, event: &KeyDownEvent, cx: &mut ViewContext<Self>) {
+        match event.keystroke.key.as_str() {
+            "enter" => self.save(cx),
+            "escape" => self.stop_renaming(cx),
+            _ => {}
+        }
+    }
+
+    fn build_saved_menu(
+        view: View<Self>,
+        saved: SavedSearch,
+        index: usize,
+        cx: &mut WindowContext,
+    ) -> View<ContextMenu> {
+        ContextMenu::build(cx, move |menu, _wc| {
+            let id = saved.id;
+            let rename_view = view.clone();
+            let rename = saved.clone();
+            let (up_view, down_view) = (view.clone(), view.clone());
+
+            menu.entry("Rename", None, move |wc| {
+                rename_view.update(wc, |this, cx| this.start_renaming(&rename, cx));
+            })
+            .when(index > 0, |menu| {
+                menu.entry("Move up", None, move |wc| {
+                    Self::move_saved(&up_view, id, index - 1, wc)
+                })
+            })
+            .entry("Move down", None, move |wc| {
+                Self::move_saved(&down_view, id, index + 1, wc)
+            })
+            .entry("Delete", None, move |wc| {
+                if let Err(e) = wc.global::<Collection>().delete_saved_search(id) {
+                    ErrorState::report("Error deleting saved search", e, wc);
+                }
+                view.update(wc, |_, cx| cx.notify());
+            })
+        })
+    }
+
+    fn move_saved(view: &View<Self>, id: u32, index: usize, cx: &mut WindowContext) {
+        if let Err(e) = cx.global::<Collection>().move_saved_search(id, index) {
+            ErrorState::report("Error moving saved search", e, cx);
+        }
+        view.update(cx, |_, cx| cx.notify());
+    }
+
+    fn heading(title: &str, theme: &Theme) -> Div {
+        div()
+            .mt_3()
+            .pb_1()
+            .mb_1()
+            .border_b_1()
+            .border_color(theme.crust)
+            .text_sm()
+            .font_weight(FontWeight::BOLD)
+            .child(title.to_string())
+    }
+
+    /// Lists the tags of the tree with their depth, parents first.
+    fn flatten<'a>(nodes: &'a [TagNode], depth: usize, out: &mut Vec<(usize, &'a TagNode)>) {
+        for node in nodes {
+            out.push((depth, node));
+            Self::flatten(&node.children, depth + 1, out);
+        }
+    }
+}
+
+impl Render for BrowseSidebar {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let view = cx.view().clone();
+
+        let (loaded, load_error) = match Self::load(cx.global::<Collection>()) {
+            Ok(loaded) => (loaded, None),
+            Err(e) => {
+                log::error!("Error loading the sidebar: {}", e);
+                let empty = Loaded {
+                    saved: Vec::new(),
+                    decks: Vec::new(),
+                    tags: Vec::new(),
+                };
+                (empty, Some(format!("Could not load the sidebar: {}", e)))
+            }
+        };
+
+        let theme = cx.global::<Theme>();
+        let search_item = |id: (&'static str, usize), label: String, query: String| {
+            ListItem::new(id)
+                .child(div().text_sm().child(label))
+                .on_click(cx.listener(move |_this, _event, cx| {
+                    cx.emit(SidebarEvent::Search(query.clone()))
+                }))
+        };
+
+        let saved_count = loaded.saved.len();
+        let saved = loaded
+            .saved
+            .into_iter()
+            .enumerate()
+            .map(|(ix, saved)| {
+                let menu_view = view.clone();
+                let menu_saved = saved.clone();
+
+                search_item(("saved", ix), saved.name.clone(), saved.query.clone()).end_slot(
+                    popover_menu(("saved-menu", ix))
+                        .menu(move |cx| {
+                            Some(Self::build_saved_menu(
+                                menu_view.clone(),
+                                menu_saved.clone(),
+                                ix,
+                                cx,
+                            ))
+                        })
+                        .anchor(AnchorCorner::TopRight)
+                        .trigger(Button::new(("saved-menu-btn", ix), "…", None)),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let decks = loaded
+            .decks
+            .into_iter()
+            .enumerate()
+            .map(|(ix, name)| {
+                let depth = name.matches(deck::SEPARATOR).count();
+                search_item(
+                    ("deck", ix),
+                    deck::base_name(&name).to_string(),
+                    exact_term("deck", &name),
+                )
+                .indent_level(depth)
+            })
+            .collect::<Vec<_>>();
+
+        let mut tag_rows = Vec::new();
+        Self::flatten(&loaded.tags, 0, &mut tag_rows);
+        let tags = tag_rows
+            .into_iter()
+            .enumerate()
+            .map(|(ix, (depth, node))| {
+                search_item(("tag", ix), node.name.clone(), exact_term("tag", &node.tag))
+                    .indent_level(depth)
+            })
+            .collect::<Vec<_>>();
+
+        let save_label = if self.renaming.is_some() {
+            "Rename"
+        } else {
+            "Save"
+        };
+
+        div()
+            .w(px(220.0))
+            .h_full()
+            .flex()
+            .flex_col()
+            .p_2()
+            .bg(theme.mantle)
+            .border_r_1()
+            .border_color(theme.crust)
+            .text_color(theme.text)
+            .child(Self::heading("Saved searches", theme).mt_0())
+            .children(load_error.map(|error| div().text_xs().text_color(theme.red).child(error)))
+            .children(saved)
+            .when(saved_count == 0, |this| {
+                this.child(
+                    div()
+                        .text_xs()
+                        .text_color(theme.subtext0)
+                        .child("Save a search to list it here"),
+                )
+            })
+            .child(
+                div()
+                    .mt_2()
+                    .flex()
+                    .items_center()
+                    .gap_1()
+                    .on_key_down(cx.listener(Self::key_down))
+                    .child(div().flex_1().child(self.name_input.clone()))
+                    .child(
+                        Button::new("save-search", save_label, None)
+                            .on_click(cx.listener(|this, _e, cx| this.save(cx))),
+                    ),
+            )
+            .child(Self::heading("Decks", theme))
+            .children(decks)
+            .child(Self::heading("Tags", theme))
+            .children(tags)
+    }
+}
@@ -0,0 +1,280 @@
+use gpui::{
+    div, prelude::*, Div, EventEmitter, FontWeight, Render, View, ViewContext, WindowContext,
+};
+
+use crate::{
+    errors::{ErrorState, NgurraError, Result},
+    models::{
+        bulk::{DueDays, MAX_FLAG},
+        collection::Collection,
+        tag,
+    },
+    theme::Theme,
+    ui::{button::button::Button, clickable::Clickable, text_field::text_field::TextField},
+};
+
+/// Changes every card selected in the browser at once. Each change can be
+/// taken back with the browser's undo button.
+pub struct BulkActionsView {
+    card_ids: Vec<u32>,
+    deck_input: TextField,
+    tags_input: TextField,
+    due_input: TextField,
+    position_input: TextField,
+    flag_input: TextField,
+    /// What the last change did, as in "Suspended 3 cards".
+    status: Option<String>,
+}
+
+pub enum BulkActionEvent {
+    Changed,
+}
+
+impl EventEmitter<BulkActionEvent> for BulkActionsView {}
+
+impl BulkActionsView {
+    pub fn view(card_ids: Vec<u32>, cx: &mut WindowContext) -> View<Self> {
+        cx.new_view(|cx: &mut ViewContext<Self>| Self {
+            card_ids,
+            deck_input: TextField::new(cx, "Deck name".to_string(), false),
+            tags_input: TextField::new(cx, "Tags, separated by spaces".to_string(), false),
+            due_input: TextField::new(cx, "Days, e.g. 3, 1-7 or 5!".to_string(), false),
+            position_input: TextField::new(cx, "Start, e.g. 0 or 10+5".to_string(), false),
+            flag_input: TextField::new(cx, format!("0 to {}", MAX_FLAG), false),
+            status: None,
+        })
+    }
+
+    pub fn set_cards(&mut self, card_ids: Vec<u32>, cx: &mut ViewContext<Self>) {
+        self.card_ids = card_ids;
+        cx.notify();
+    }
+
+    /// Runs a change on the selected cards and reports how many it changed,
+    /// as in "Moved 3 cards".
+    fn apply(
+        &mut self,
+        done: &str,
+        change: impl FnOnce(&mut Collection, &[u32]) -> Result<usize>,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let card_ids = self.card_ids.clone();
+        let result =
+            cx.update_global(|collection: &mut Collection, _| change(collection, &card_ids));
+
+        match result {
+            Ok(changed) => {
+                let noun = if changed == 1 { "card" } else { "cards" };
+                self.status = Some(format!("{} {} {}", done, changed, noun));
+                cx.emit(BulkActionEvent::Changed);
+            }
+            Err(e) => ErrorState::report("Error changing cards", e, cx),
+        }
+
+        cx.notify();
+    }
+
+    fn move_cards(&mut self, cx: &mut ViewContext<Self>) {
+        let deck_name = self.deck_input.view.read(cx).text.clone();
+        self.apply(
+            "Moved",
+            |collection, ids| collection.move_cards(ids, &deck_name),
+            cx,
+        );
+    }
+
+    fn add_tags(&mut self, cx: &mut ViewContext<Self>) {
+        let tags = tag::parse(&self.tags_input.view.read(cx).text);
+        self.apply(
+            "Tagged",
+            |collection, ids| collection.add_tags_to_cards(ids, &tags),
+            cx,
+        );
+    }
+
+    fn remove_tags(&mut self, cx: &mut ViewContext<Self>) {
+        let tags = tag::parse(&self.tags_input.view.read(cx).text);
+        self.apply(
+            "Untagged",
+            |collection, ids| collection.remove_tags_from_cards(ids, &tags),
+            cx,
+        );
+    }
+
+    fn set_suspended(&mut self, suspended: bool, cx: &mut ViewContext<Self>) {
+        let done = if suspended {
+            "Suspended"
+        } else {
+            "Unsuspended"
+        };
+        self.apply(
+            done,
+            |collection, ids| collection.set_suspended(ids, suspended),
+            cx,
+        );
+    }
+
+    fn reset(&mut self, cx: &mut ViewContext<Self>) {
+        self.apply("Reset", |collection, ids| collection.reset_cards(ids), cx);
+    }
+
+    fn set_due_date(&mut self, cx: &mut ViewContext<Self>) {
+        let input = self.due_input.view.read(cx).text.clone();
+        self.apply(
+            "Rescheduled",
+            |collection, ids| collection.set_due_date(ids, DueDays::parse(&input)?),
+            cx,
+        );
+    }
+
+    /// Reads `10` as positions 10, 11, 12 and so on, and `10+5` as 10, 15,
+    /// 20. The other new cards move back to make room.
+    fn reposition(&mut self, cx: &mut ViewContext<Self>) {
+        let input = self.position_input.view.read(cx).text.clone();
+        self.apply(
+            "Repositioned",
+            |collection, ids| {
+                let (start, step) = parse_position(&input)?;
+                collection.reposition_cards(ids, start, step, true)
+            },
+            cx,
+        );
+    }
+
+    fn set_flag(&mut self, cx: &mut ViewContext<Self>) {
+        let input = self.flag_input.view.read(cx).text.clone();
+        self.apply(
+            "Flagged",
+            |collection, ids| {
+                let flag = input.trim().parse::<u8>().map_err(|_| {
+                    NgurraError::Parse(format!("flag '{}': expected 0 to {}", input, MAX_FLAG))
+                })?;
+                collection.set_flag(ids, flag)
+            },
+            cx,
+        );
+    }
+
+    fn delete(&mut self, cx: &mut ViewContext<Self>) {
+        self.apply(
+            "Deleted",
+            |collection, ids| collection.delete_cards(ids),
+            cx,
+        );
+    }
+
+    fn section(title: &str, theme: &Theme) -> Div {
+        div()
+            .mt_4()
+            .mb_1()
+            .text_sm()
+            .font_weight(FontWeight::BOLD)
+            .text_color(theme.subtext1)
+            .child(title.to_string())
+    }
+
+    fn row(input: Option<TextField>) -> Div {
+        div()
+            .flex()
+            .items_center()
+            .gap_2()
+            .children(input.map(|input| div().flex_1().child(input)))
+    }
+}
+
+fn parse_position(input: &str) -> Result<(u32, u32)> {
+    let (start, step) = input.split_once('+').unwrap_or((input, "1"));
+
+    match (start.trim().parse(), step.trim().parse()) {
+        (Ok(start), Ok(step)) if step > 0 => Ok((start, step)),
+        _ => Err(NgurraError::Parse(format!(
+            "position '{}': expected a start like 10, or a start and step like 10+5",
+            input
+        ))),
+    }
+}
+
+impl Render for BulkActionsView {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let theme = cx.global::<Theme>();
+        let button = |id: &'static str, label: &'static str| Button::new(id, label, None);
+
+        div()
+            .flex()
+            .flex_col()
+            .p_4()
+            .text_color(theme.text)
+            .child(
+                div()
+                    .text_xl()
+                    .font_weight(FontWeight::EXTRA_BOLD)
+                    .child(format!("{} cards selected", self.card_ids.len())),
+            )
+            .children(
+                self.status
+                    .clone()
+                    .map(|status| div().mt_2().text_sm().text_color(theme.green).child(status)),
+            )
+            .child(Self::section("Deck", theme))
+            .child(
+                Self::row(Some(self.deck_input.clone())).child(
+                    button("bulk-move", "Move")
+                        .on_click(cx.listener(|this, _e, cx| this.move_cards(cx))),
+                ),
+            )
+            .child(Self::section("Tags", theme))
+            .child(
+                Self::row(Some(self.tags_input.clone()))
+                    .child(
+                        button("bulk-add-tags", "Add")
+                            .on_click(cx.listener(|this, _e, cx| this.add_tags(cx))),
+                    )
+                    .child(
+                        button("bulk-remove-tags", "Remove")
+                            .on_click(cx.listener(|this, _e, cx| this.remove_tags(cx))),
+                    ),
+            )
+            .child(Self::section("Scheduling", theme))
+            .child(
+                Self::row(None)
+                    .child(
+                        button("bulk-suspend", "Suspend")
+                            .on_click(cx.listener(|this, _e, cx| this.set_suspended(true, cx))),
+                    )
+                    .child(
+                        button("bulk-unsuspend", "Unsuspend")
+                            .on_click(cx.listener(|this, _e, cx| this.set_suspended(false, cx))),
+                    )
+                    .child(
+                        button("bulk-reset", "Reset to new")
+                            .on_click(cx.listener(|this, _e, cx| this.reset(cx))),
+                    ),
+            )
+            .child(
+                Self::row(Some(self.due_input.clone())).mt_2().child(
+                    button("bulk-due", "Set due date")
+                        .on_click(cx.listener(|this, _e, cx| this.set_due_date(cx))),
+                ),
+            )
+            .child(
+                Self::row(Some(self.position_input.clone())).mt_2().child(
+                    button("bulk-reposition", "Reposition")
+                        .on_click(cx.listener(|this, _e, cx| this.reposition(cx))),
+                ),
+            )
+            .child(Self::section("Flag", theme))
+            .child(
+                Self::row(Some(self.flag_input.clone())).child(
+                    button("bulk-flag", "Set flag")
+                        .on_click(cx.listener(|this, _e, cx| this.set_flag(cx))),
+                ),
+            )
+            .child(Self::section("Delete", theme))
+            .child(
+                Self::row(None).child(
+                    button("bulk-delete", "Delete cards")
+                        .on_click(cx.listener(|this, _e, cx| this.delete(cx))),
+                ),
+            )
+    }
+}
@@ -3,6 +3,7 @@ use gpui::{div, prelude::*, MouseButton, Render, View, WindowContext};
 pub mod deck_detail;
 pub mod deck_list;
 pub mod flash_card;
+pub mod move_deck_form;
 pub mod new_deck_form;
 
 use crate::state::{StackableViewState, ViewState};
@@ -4,9 +4,11 @@ use gpui::{
 };
 
 use crate::{
+    errors::Result,
     models::{
         builder::Builder,
         collection::{self, Collection, CollectionBuilder},
+        deck::get_decks_tree_stats,
         queue::{self, Queue, QueueBuilder},
     },
     repositories::deck::DeckStat,
@@ -31,7 +33,10 @@ impl DeckDetail {
 
         let mut queue_builder = QueueBuilder::new(deck_id);
         queue_builder.collect_cards(&col);
-        let queue = queue_builder.build().unwrap();
+        let queue = queue_builder.build().unwrap_or_else(|e| {
+            log::error!("Error building queue for deck {}: {}", deck_id, e);
+            Queue::default()
+        });
 
         cx.new_view(|_vc| Self {
             deck_id,
@@ -41,8 +46,8 @@ impl DeckDetail {
         .into()
     }
 
-    fn get_deck(&self, collection: &Collection) -> Deck {
-        Deck::load(self.deck_id, &collection.storage.conn).unwrap()
+    fn get_deck(&self, collection: &Collection) -> Result<Deck> {
+        Ok(Deck::load(self.deck_id, &collection.storage.conn)?)
     }
 
     fn key_down(&mut self, event: &KeyDownEvent, cx: &mut ViewContext<Self>) {
@@ -76,19 +81,37 @@ impl Render for DeckDetail {
         cx.focus(&self.focus_handle);
         let theme = cx.global::<Theme>();
         let col = cx.global::<collection::Collection>();
-        let mut deck = self.get_deck(col);
+        let mut deck = match self.get_deck(col) {
+            Ok(deck) => deck,
+            Err(e) => {
+                log::error!("Error loading deck {}: {}", self.deck_id, e);
+
+                return div()
+                    .track_focus(&self.focus_handle)
+                    .flex()
+                    .w_full()
+                    .justify_center()
+                    .pt_20()
+                    .text_color(theme.red)
+                    .on_key_down(cx.listener(Self::key_down))
+                    .child(format!("Could not load this deck: {}", e));
+            }
+        };
 
         let timing_at_stamp = CollectionBuilder::timing_for_timestamp(
             &col.storage.conn,
             chrono::Local::now().timestamp(),
         );
 
-        let decks_stats =
-            Deck::get_decks_stats(&col.storage.conn, timing_at_stamp.days_elapsed).unwrap();
+        let decks_stats = get_decks_tree_stats(&col.storage.conn, timing_at_stamp.days_elapsed)
+            .unwrap_or_else(|e| {
+                log::error!("Error loading deck stats: {}", e);
+                Default::default()
+            });
 
-        if let Some(st) = decks_stats.get(&deck.id.unwrap()) {
+        if let Some(st) = decks_stats.get(&self.deck_id) {
             deck.stats = Some(DeckStat {
-                id: Some(deck.id.unwrap()),
+                id: Some(self.deck_id),
                 new: st.new,
                 learning: st.learning,
                 due: st.due,
@@ -98,7 +121,7 @@ impl Render for DeckDetail {
         let stats = match deck.stats {
             Some(stats) => stats,
             None => DeckStat {
-                id: Some(deck.id.unwrap()),
+                id: Some(self.deck_id),
                 new: 0,
                 learning: 0,
                 due: 0,
@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 use gpui::{
     div, AnchorCorner, AnyView, AppContext, Context, EventEmitter, FocusHandle, InteractiveElement,
     IntoElement, KeyDownEvent, Model, ParentElement, Pixels, Render, RenderOnce, SharedString,
@@ -5,26 +7,45 @@ use gpui::{
 };
 
 use crate::{
-    components::{add_card::AddCardBuilder, shared::icon::Icon},
+    components::{
+        add_card::AddCardBuilder, backup_list::BackupListBuilder, export::ExportBuilder,
+        import::ImportBuilder, media_check::MediaCheckBuilder, shared::icon::Icon,
+        sync_view::SyncBuilder,
+    },
+    errors::{ErrorState, Result},
     models::{
         collection::{Collection, CollectionBuilder},
-        deck::get_decks,
+        deck::{self, deck_rows, get_decks, DeckRow},
+        states::state_context::StateContext,
     },
     repositories::deck::DeckStat,
     state::{StackableView, StackableViewState},
     theme::Theme,
     ui::{
-        button::button::Button, clickable::Clickable, context_menu::ContextMenu,
-        popover_menu::popover_menu, text_field::text_field::TextEvent,
+        button::button::Button,
+        clickable::Clickable,
+        context_menu::ContextMenu,
+        list::list_item,
+        popover_menu::popover_menu,
+        text_field::text_field::{TextEvent, TextField},
     },
     Deck,
 };
 
-use super::{deck_detail::DeckDetailBuilder, new_deck_form::NewDeckFormBuilder};
+use super::{
+    deck_detail::DeckDetailBuilder, move_deck_form::MoveDeckBuilder,
+    new_deck_form::NewDeckFormBuilder,
+};
 
 pub struct DeckListView {
     selected: Model<u32>,
-    items: Vec<Deck>,
+    /// Decks whose subdecks are hidden.
+    collapsed: Model<HashSet<u32>>,
+    /// The rows that are shown: every deck not inside a collapsed one.
+    items: Vec<DeckRow>,
+    /// The deck being renamed in place, set from its menu.
+    renaming: Model<Option<u32>>,
+    rename_input: Option<TextField>,
     focus_handle: FocusHandle,
 }
 
@@ -40,21 +61,26 @@ impl EventEmitter<TextEvent> for DeckListView {}
 impl DeckListView {
     pub fn view(cx: &mut WindowContext) -> View<Self> {
         let collection = cx.global::<crate::Collection>();
-        let items = Self::get_all_decks_and_stats(collection);
+        let items = Self::get_all_decks_and_stats(collection, &HashSet::new()).unwrap_or_default();
         let selected = cx.new_model(|_| {
             if items.len() > 0 {
-                items[0].id.unwrap_or(0)
+                items[0].deck.id.unwrap_or(0)
             } else {
                 0
             }
         });
+        let collapsed = cx.new_model(|_| HashSet::new());
+        let renaming = cx.new_model(|_| None);
         let focus_handle = cx.focus_handle();
 
         focus_handle.focus(cx);
 
         let list = Self {
             selected,
+            collapsed,
             items,
+            renaming,
+            rename_input: None,
             focus_handle,
         };
 
@@ -63,6 +89,17 @@ impl DeckListView {
                 cx.notify();
             })
             .detach();
+            cx.observe(&list.collapsed, move |_this: &mut DeckListView, _, cx| {
+                cx.notify();
+            })
+            .detach();
+            cx.observe(
+                &list.renaming,
+                move |this: &mut DeckListView, renaming, cx| {
+                    this.start_rename(*renaming.read(cx), cx);
+                },
+            )
+            .detach();
 
             list
         });
@@ -73,13 +110,80 @@ impl DeckListView {
         let id = self.selected.read(cx);
 
         self.items
-            .clone()
-            .into_iter()
+            .iter()
+            .map(|row| row.deck.clone())
             .enumerate()
-            .find(|(_, item)| item.id.unwrap().eq(id))
+            .find(|(_, item)| item.id == Some(*id))
+    }
+
+    /// Shows or hides the subdecks of the selected deck.
+    fn set_selected_expanded(&mut self, expanded: bool, cx: &mut ViewContext<Self>) {
+        let Some(deck_id) = self.selected(cx).and_then(|(_, deck)| deck.id) else {
+            return;
+        };
+
+        self.collapsed.update(cx, |collapsed, cx| {
+            if expanded {
+                collapsed.remove(&deck_id);
+            } else {
+                collapsed.insert(deck_id);
+            }
+            cx.notify();
+        });
+    }
+
+    /// Swaps the name of the deck for an input holding its full name, or
+    /// drops the input when `deck_id` is `None`.
+    fn start_rename(&mut self, deck_id: Option<u32>, cx: &mut ViewContext<Self>) {
+        let name = deck_id.and_then(|id| {
+            self.items
+                .iter()
+                .find(|row| row.deck.id == Some(id))
+                .map(|row| row.deck.name.clone())
+        });
+
+        self.rename_input = name.map(|name| {
+            let input = TextField::new(cx, "Deck name".to_string(), false);
+            input.view.update(cx, |view, _| view.text = name);
+            input
+        });
+        cx.notify();
+    }
+
+    fn finish_rename(&mut self, save: bool, cx: &mut ViewContext<Self>) {
+        let deck_id = *self.renaming.read(cx);
+
+        if let (true, Some(deck_id), Some(input)) = (save, deck_id, &self.rename_input) {
+            let name = input.view.read(cx).text.clone();
+
+            let result = cx.update_global(|collection: &mut Collection, _| {
+                collection.rename_deck(deck_id, &name)
+            });
+
+            if let Err(e) = result {
+                ErrorState::report("Error renaming deck", e, cx);
+                return;
+            }
+        }
+
+        self.renaming.update(cx, |renaming, cx| {
+            *renaming = None;
+            cx.notify();
+        });
     }
 
     fn key_down(&mut self, event: &KeyDownEvent, cx: &mut ViewContext<Self>) {
+        // Keys typed into the name input bubble up here; only enter and
+        // escape are handled while renaming.
+        if self.rename_input.is_some() {
+            match event.keystroke.key.as_str() {
+                "enter" => self.finish_rename(true, cx),
+                "escape" => self.finish_rename(false, cx),
+                _ => {}
+            }
+            return;
+        }
+
         match event.keystroke.key.as_str() {
             "up" => {
                 let index = if let Some((index, _)) = self.selected(cx) {
@@ -93,7 +197,7 @@ impl DeckListView {
                 };
 
                 self.selected.update(cx, |this, cx| {
-                    *this = self.items[index].id.unwrap();
+                    *this = self.items[index].deck.id.unwrap_or(0);
 
                     cx.notify();
                 });
@@ -111,7 +215,7 @@ impl DeckListView {
                 };
 
                 self.selected.update(cx, |this, cx| {
-                    *this = self.items[index].id.unwrap();
+                    *this = self.items[index].deck.id.unwrap_or(0);
 
                     cx.notify();
                 });
@@ -129,22 +233,17 @@ impl DeckListView {
                 };
 
                 self.selected.update(cx, |this, cx| {
-                    *this = self.items[index].id.unwrap();
+                    *this = self.items[index].deck.id.unwrap_or(0);
 
                     cx.notify();
                 });
             }
+            "left" => self.set_selected_expanded(false, cx),
+            "right" => self.set_selected_expanded(true, cx),
             "enter" | "space" => {
-                if let Some((_index, deck)) = self.selected(cx) {
+                if let Some(deck_id) = self.selected(cx).and_then(|(_, deck)| deck.id) {
                     StackableViewState::update(
-                        |state, cx| {
-                            state.push(
-                                DeckDetailBuilder {
-                                    deck_id: deck.id.unwrap(),
-                                },
-                                cx,
-                            )
-                        },
+                        |state, cx| state.push(DeckDetailBuilder { deck_id }, cx),
                         cx,
                     );
 
@@ -155,53 +254,90 @@ impl DeckListView {
         }
     }
 
-    fn get_all_decks_and_stats(collection: &Collection) -> Vec<Deck> {
-        let decks = get_decks(&collection.storage.conn);
+    /// Loads the decks in tree order with their counts, leaving out the
+    /// decks inside collapsed ones.
+    fn get_all_decks_and_stats(
+        collection: &Collection,
+        collapsed: &HashSet<u32>,
+    ) -> Result<Vec<DeckRow>> {
+        let decks = get_decks(&collection.storage.conn)?;
         let timing_at_stamp = CollectionBuilder::timing_for_timestamp(
             &collection.storage.conn,
             chrono::Local::now().timestamp(),
         );
 
-        let decks_stats =
-            Deck::get_decks_stats(&collection.storage.conn, timing_at_stamp.days_elapsed).unwrap();
-
-        decks
-            .into_iter()
-            .map(|mut deck| {
-                let st = decks_stats.get(&deck.id.unwrap());
-
-                if let Some(st) = st {
-                    deck.stats = Some(DeckStat {
-                        id: Some(deck.id.unwrap()),
-                        new: st.new,
-                        learning: st.learning,
-                        due: st.due,
-                    });
+        let own_stats =
+            Deck::get_decks_stats(&collection.storage.conn, timing_at_stamp.days_elapsed)?;
+        let decks_stats = deck::aggregate_stats(&decks, &own_stats, &StateContext::default());
+
+        let mut hidden_under: Option<String> = None;
+        let mut rows = Vec::new();
+
+        for mut row in deck_rows(decks) {
+            if let Some(parent) = &hidden_under {
+                if deck::is_within(&row.deck.name, parent) {
+                    continue;
                 }
+                hidden_under = None;
+            }
 
-                deck
-            })
-            .collect()
+            if let Some(st) = row.deck.id.and_then(|id| decks_stats.get(&id)) {
+                row.deck.stats = Some(st.clone());
+            }
+
+            if row.deck.id.is_some_and(|id| collapsed.contains(&id)) {
+                hidden_under = Some(row.deck.name.clone());
+            }
+            rows.push(row);
+        }
+
+        Ok(rows)
     }
 
     fn new_deck_click(&mut self, _event: &gpui::ClickEvent, cx: &mut gpui::ViewContext<Self>) {
         StackableViewState::update(|state, cx| state.push(NewDeckFormBuilder {}, cx), cx);
     }
+
+    fn backups_click(&mut self, _event: &gpui::ClickEvent, cx: &mut gpui::ViewContext<Self>) {
+        StackableViewState::update(|state, cx| state.push(BackupListBuilder, cx), cx);
+    }
+
+    fn media_click(&mut self, _event: &gpui::ClickEvent, cx: &mut gpui::ViewContext<Self>) {
+        StackableViewState::update(|state, cx| state.push(MediaCheckBuilder, cx), cx);
+    }
+
+    fn import_click(&mut self, _event: &gpui::ClickEvent, cx: &mut gpui::ViewContext<Self>) {
+        StackableViewState::update(|state, cx| state.push(ImportBuilder, cx), cx);
+    }
+
+    fn sync_click(&mut self, _event: &gpui::ClickEvent, cx: &mut gpui::ViewContext<Self>) {
+        StackableViewState::update(|state, cx| state.push(SyncBuilder, cx), cx);
+    }
 }
 
 impl Render for DeckListView {
     fn render(&mut self, cx: &mut gpui::ViewContext<Self>) -> impl gpui::prelude::IntoElement {
-        cx.focus(&self.focus_handle);
-
-        self.items = Self::get_all_decks_and_stats(cx.global::<Collection>());
-
-        if self.items.len() == 0 {
-            self.items.push(Deck::new(""));
+        match &self.rename_input {
+            Some(input) => input.focus(cx),
+            None => cx.focus(&self.focus_handle),
         }
 
+        let collapsed = self.collapsed.read(cx).clone();
+        let load_error = match Self::get_all_decks_and_stats(cx.global::<Collection>(), &collapsed)
+        {
+            Ok(items) => {
+                self.items = items;
+                None
+            }
+            Err(e) => {
+                log::error!("Error loading decks: {}", e);
+                Some(format!("Could not load decks: {}", e))
+            }
+        };
+
         let theme = cx.global::<Theme>();
-        let collection = cx.global::<crate::Collection>();
         let selected = self.selected.read(cx);
+        let renaming = *self.renaming.read(cx);
 
         div()
             .track_focus(&self.focus_handle)
@@ -233,11 +369,25 @@ impl Render for DeckListView {
                                 .mb_2(),
                         )
                         .children(
-                            Self::get_all_decks_and_stats(collection)
-                                .into_iter()
-                                .map(|deck| {
-                                    let deck_id = deck.id.unwrap();
-                                    HocListItem::init(deck, deck_id, deck_id.eq(selected))
+                            load_error.map(|error| {
+                                div().px_2().text_sm().text_color(theme.red).child(error)
+                            }),
+                        )
+                        .children(
+                            self.items
+                                .iter()
+                                .filter_map(|row| {
+                                    let deck_id = row.deck.id?;
+                                    Some(HocListItem::init(
+                                        row.clone(),
+                                        deck_id,
+                                        deck_id.eq(selected),
+                                        self.collapsed.clone(),
+                                        self.renaming.clone(),
+                                        self.rename_input
+                                            .clone()
+                                            .filter(|_| renaming == Some(deck_id)),
+                                    ))
                                 })
                                 .collect::<Vec<_>>(),
                         ),
@@ -245,12 +395,41 @@ impl Render for DeckListView {
             )
             .child(
                 div().mb_16().flex().justify_center().child(
-                    div().w(Pixels(300.0)).flex().justify_center().child(
-                        div().child(
-                            Button::new("create_deck", "Create Deck", None)
-                                .on_click(cx.listener(Self::new_deck_click)),
+                    div()
+                        .w(Pixels(400.0))
+                        .flex()
+                        .justify_center()
+                        .gap_2()
+                        .child(
+                            div().child(
+                                Button::new("create_deck", "Create Deck", None)
+                                    .on_click(cx.listener(Self::new_deck_click)),
+                            ),
+                        )
+                        .child(
+                            div().child(
+                                Button::new("backups", "Backups", None)
+                                    .on_click(cx.listener(Self::backups_click)),
+                            ),
+                        )
+                        .child(
+                            div().child(
+                                Button::new("media", "Check media", None)
+                                    .on_click(cx.listener(Self::media_click)),
+                            ),
+                        )
+                        .child(
+                            div().child(
+                                Button::new("import", "Import", None)
+                                    .on_click(cx.listener(Self::import_click)),
+                            ),
+                        )
+                        .child(
+                            div().child(
+                                Button::new("sync", "Sync", None)
+                                    .on_click(cx.listener(Self::sync_click)),
+                            ),
                         ),
-                    ),
                 ),
             )
     }
@@ -258,22 +437,52 @@ impl Render for DeckListView {
 
 #[derive(IntoElement, Clone)]
 pub struct HocListItem {
-    deck: Deck,
+    row: DeckRow,
+    deck_id: u32,
     menu_item: String,
     selected: bool,
+    collapsed: Model<HashSet<u32>>,
+    renaming: Model<Option<u32>>,
+    /// The name input, when this deck is being renamed.
+    rename_input: Option<TextField>,
 }
 
 impl HocListItem {
-    pub fn init(deck: Deck, deck_id: u32, selected: bool) -> Self {
+    pub fn init(
+        row: DeckRow,
+        deck_id: u32,
+        selected: bool,
+        collapsed: Model<HashSet<u32>>,
+        renaming: Model<Option<u32>>,
+        rename_input: Option<TextField>,
+    ) -> Self {
         let menu_item = format!("menu-item-{}", deck_id);
         Self {
-            deck,
+            row,
+            deck_id,
             menu_item,
             selected,
+            collapsed,
+            renaming,
+            rename_input,
         }
     }
 
-    fn build_deck_menu(cx: &mut WindowContext, deck_id: u32) -> View<ContextMenu> {
+    fn duplicate(deck_id: u32, reset: bool, cx: &mut WindowContext) {
+        let result = cx.update_global(|collection: &mut Collection, _| {
+            collection.duplicate_deck(deck_id, reset)
+        });
+
+        if let Err(e) = result {
+            ErrorState::report("Error duplicating deck", e, cx);
+        }
+    }
+
+    fn build_deck_menu(
+        cx: &mut WindowContext,
+        deck_id: u32,
+        renaming: Model<Option<u32>>,
+    ) -> View<ContextMenu> {
         ContextMenu::build(cx, move |menu, _wc| {
             menu.entry("Add new card", None, move |wc| {
                 StackableViewState::update(
@@ -281,9 +490,44 @@ impl HocListItem {
                     wc,
                 );
             })
+            .entry("Rename", None, move |wc| {
+                renaming.update(wc, |renaming, cx| {
+                    *renaming = Some(deck_id);
+                    cx.notify();
+                });
+            })
+            .entry("Move", None, move |wc| {
+                StackableViewState::update(
+                    |state, cx| state.push(MoveDeckBuilder { deck_id }, cx),
+                    wc,
+                );
+            })
+            .entry("Duplicate", None, move |wc| {
+                Self::duplicate(deck_id, false, wc)
+            })
+            .entry("Duplicate as new cards", None, move |wc| {
+                Self::duplicate(deck_id, true, wc)
+            })
+            .entry("Export", None, move |wc| {
+                StackableViewState::update(
+                    |state, cx| {
+                        state.push(
+                            ExportBuilder {
+                                deck_id: Some(deck_id),
+                            },
+                            cx,
+                        )
+                    },
+                    wc,
+                );
+            })
             .entry("Delete", None, move |wc| {
-                let collection = wc.global::<Collection>();
-                Deck::delete(deck_id, &collection.storage.conn).unwrap();
+                let result = wc.update_global(|collection: &mut Collection, _| {
+                    collection.delete_deck(deck_id)
+                });
+                if let Err(e) = result {
+                    ErrorState::report("Error deleting deck", e, wc);
+                }
             })
         })
     }
@@ -292,10 +536,13 @@ impl HocListItem {
 impl RenderOnce for HocListItem {
     fn render(self, cx: &mut WindowContext) -> impl IntoElement {
         let menu_id = SharedString::from(self.menu_item);
-        let menu_btn = SharedString::from(format!("btn-{}", self.deck.id.unwrap()));
+        let menu_btn = SharedString::from(format!("btn-{}", self.deck_id));
 
-        let deck_id = self.deck.id.unwrap();
-        let inner = cx.new_view(|_| ListItem::new(self.deck, self.selected));
+        let deck_id = self.deck_id;
+        let renaming = self.renaming;
+        let inner = cx.new_view(|_| {
+            ListItem::new(self.row, self.selected, self.collapsed, self.rename_input)
+        });
 
         div()
             .flex()
@@ -306,7 +553,7 @@ impl RenderOnce for HocListItem {
             .child(
                 div().child(
                     popover_menu(menu_id)
-                        .menu(move |cx| Some(Self::build_deck_menu(cx, deck_id)))
+                        .menu(move |cx| Some(Self::build_deck_menu(cx, deck_id, renaming.clone())))
                         .anchor(AnchorCorner::TopLeft)
                         .trigger(Button::new(menu_btn, "M", Some(Icon::Settings))),
                 ),
@@ -316,12 +563,24 @@ impl RenderOnce for HocListItem {
 
 pub struct ListItem {
     selected: bool,
-    deck: Deck,
+    row: DeckRow,
+    collapsed: Model<HashSet<u32>>,
+    rename_input: Option<TextField>,
 }
 
 impl ListItem {
-    pub fn new(deck: Deck, selected: bool) -> Self {
-        Self { deck, selected }
+    pub fn new(
+        row: DeckRow,
+        selected: bool,
+        collapsed: Model<HashSet<u32>>,
+        rename_input: Option<TextField>,
+    ) -> Self {
+        Self {
+            row,
+            selected,
+            collapsed,
+            rename_input,
+        }
     }
 }
 
@@ -331,13 +590,26 @@ impl Render for ListItem {
         let mut bg_hover = theme.overlay0;
         bg_hover.fade_out(0.5);
 
-        if let None = self.deck.id {
+        if let None = self.row.deck.id {
             return div().child("No decks found");
         }
 
-        let deck_id = self.deck.id.unwrap();
+        let deck_id = self.row.deck.id.unwrap();
+        let expanded = self
+            .row
+            .has_children
+            .then(|| !self.collapsed.read(cx).contains(&deck_id));
+        let collapsed = self.collapsed.clone();
+
+        // Clicks in the name input must not open the deck.
+        let name = match self.rename_input.clone() {
+            Some(input) => div()
+                .on_mouse_down(gpui::MouseButton::Left, |_e, cx| cx.stop_propagation())
+                .child(input),
+            None => div().child(deck::base_name(&self.row.deck.name).to_string()),
+        };
 
-        let stats = match self.deck.stats.as_ref() {
+        let stats = match self.row.deck.stats.as_ref() {
             Some(stats) => stats,
             None => &DeckStat {
                 id: None,
@@ -363,11 +635,20 @@ impl Render for ListItem {
             );
         })
         .child(
-            div()
-                .min_w_80()
-                .text_sm()
-                .pl_2()
-                .child(self.deck.name.clone()),
+            div().min_w_80().text_sm().child(
+                list_item::ListItem::new(("deck", deck_id as usize))
+                    .indent_level(self.row.depth)
+                    .toggle(expanded)
+                    .on_toggle(move |_event, cx| {
+                        collapsed.update(cx, |collapsed, cx| {
+                            if !collapsed.remove(&deck_id) {
+                                collapsed.insert(deck_id);
+                            }
+                            cx.notify();
+                        })
+                    })
+                    .child(name),
+            ),
         )
         .child(
             div()
@@ -3,60 +3,126 @@ use std::collections::VecDeque;
 use gpui::*;
 
 use crate::{
+    components::{edit_card::EditCardBuilder, shared::field::Field},
+    errors::ErrorState,
     models::{
         answer::Answer,
         collection::Collection,
         queue::{Queue, QueueEntry},
+        type_answer,
     },
     repositories::flash_card::{self, CardQueue},
     state::{StackableView, StackableViewState},
     theme::Theme,
-    ui::{button::button::Button, clickable::Clickable},
+    ui::{button::button::Button, clickable::Clickable, text_field::text_field::TextField},
 };
 
 pub struct FlashCard {
     pub focus_handle: FocusHandle,
     queue: VecDeque<QueueEntry>,
     show_answer: bool,
+    answer_input: TextField,
+    /// Whether the current card asks for its answer to be typed in.
+    expects_typing: bool,
+    /// What was typed for the current card, once its answer is shown.
+    typed: Option<String>,
+    /// The rating the typed answer suggests.
+    suggested: Option<Answer>,
 }
 
 impl FlashCard {
     pub fn view(cx: &mut WindowContext, card_queue: &Queue) -> AnyView {
         let focus_handle = cx.focus_handle();
-        cx.new_view(|_| Self {
+        cx.new_view(|cx| Self {
             focus_handle,
             queue: card_queue.core.clone(),
             show_answer: false,
+            answer_input: TextField::new(cx, "Type the answer".to_string(), false),
+            expects_typing: false,
+            typed: None,
+            suggested: None,
         })
         .into()
     }
 
-    pub fn answer(&mut self, answer: Answer, collection: &Collection) {
+    pub fn answer(&mut self, answer: Answer, cx: &mut ViewContext<Self>) {
         if self.show_answer {
             if let Some(current_card) = self.queue.pop_back() {
                 self.show_answer = false;
+                self.typed = None;
+                self.suggested = None;
 
-                let card = collection.answer_card(current_card.card_id as u32, answer);
-                if let CardQueue::Learning = card.get_queue() {
-                    self.queue.push_front(current_card);
+                let card_id = current_card.card_id as u32;
+                let result = cx.update_global(|collection: &mut Collection, _| {
+                    collection.answer_card(card_id, answer)
+                });
+
+                match result {
+                    Ok((card, buried)) => {
+                        self.queue.retain(|entry| !buried.contains(&entry.card_id));
+
+                        if let CardQueue::Learning = card.get_queue() {
+                            self.queue.push_front(current_card);
+                        }
+                    }
+                    Err(e) => {
+                        // Keep the card in the queue so the answer can be retried.
+                        self.queue.push_back(current_card);
+                        ErrorState::report("Error saving answer", e, cx);
+                    }
                 }
             }
         } else {
-            self.show_answer = true;
+            self.reveal(cx);
         }
     }
 
+    /// Shows the answer, keeping what was typed to compare with it.
+    fn reveal(&mut self, cx: &mut ViewContext<Self>) {
+        if self.expects_typing {
+            let typed = self.answer_input.view.read(cx).text.clone();
+            self.answer_input
+                .view
+                .update(cx, |view, _| view.text.clear());
+            self.typed = Some(typed);
+        }
+
+        self.show_answer = true;
+    }
+
     fn key_down(&mut self, event: &KeyDownEvent, cx: &mut ViewContext<Self>) {
-        let collection = cx.global::<crate::Collection>();
+        // Keys belong to the answer input until the answer is shown.
+        if self.expects_typing && !self.show_answer {
+            if event.keystroke.key == "enter" {
+                self.reveal(cx);
+                cx.notify();
+            }
+            return;
+        }
 
         match event.keystroke.key.as_str() {
-            "enter" | "space" | "3" => {
-                self.answer(Answer::Good, collection);
+            "enter" | "space" => {
+                self.answer(self.suggested.unwrap_or(Answer::Good), cx);
+                cx.notify();
+            }
+            "3" => {
+                self.answer(Answer::Good, cx);
                 cx.notify();
             }
             "1" => self.again(cx),
             "2" => self.hard(cx),
             "4" => self.easy(cx),
+            // The card is loaded again on every render, so the edits show
+            // up in place once the editor is closed.
+            "e" => {
+                if let Some(entry) = self.queue.back() {
+                    let card_id = entry.card_id as u32;
+                    StackableViewState::update(
+                        |state, cx| state.push(EditCardBuilder { card_id }, cx),
+                        cx,
+                    );
+                }
+            }
             "backspace" => {
                 StackableViewState::update(|state, cx| state.pop(cx), cx);
                 cx.notify();
@@ -70,8 +136,11 @@ impl FlashCard {
 
     fn again(&mut self, cx: &mut ViewContext<Self>) {
         self.show_answer = false;
-        let card = self.queue.pop_back().unwrap();
-        self.queue.push_front(card);
+        self.typed = None;
+        self.suggested = None;
+        if let Some(card) = self.queue.pop_back() {
+            self.queue.push_front(card);
+        }
 
         cx.notify();
     }
@@ -81,8 +150,7 @@ impl FlashCard {
     }
 
     fn easy(&mut self, cx: &mut ViewContext<Self>) {
-        let collection = cx.global::<crate::Collection>();
-        self.answer(Answer::Easy, collection);
+        self.answer(Answer::Easy, cx);
         cx.notify();
     }
 
@@ -91,8 +159,7 @@ impl FlashCard {
     }
 
     fn hard(&mut self, cx: &mut ViewContext<Self>) {
-        let collection = cx.global::<crate::Collection>();
-        self.answer(Answer::Hard, collection);
+        self.answer(Answer::Hard, cx);
         cx.notify();
     }
 
@@ -101,8 +168,7 @@ impl FlashCard {
     }
 
     fn good(&mut self, cx: &mut ViewContext<Self>) {
-        let collection = cx.global::<crate::Collection>();
-        self.answer(Answer::Good, collection);
+        self.answer(Answer::Good, cx);
         cx.notify();
     }
 
@@ -143,11 +209,11 @@ impl FlashCard {
 
 impl Render for FlashCard {
     fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
-        cx.focus(&self.focus_handle);
         let collection = cx.global::<crate::Collection>();
         let theme: &Theme = cx.global::<Theme>();
 
         if self.queue.is_empty() {
+            cx.focus(&self.focus_handle);
             return div()
                 .flex()
                 .track_focus(&self.focus_handle)
@@ -157,34 +223,96 @@ impl Render for FlashCard {
                 .child(div().mt_20().child(self.render_congrats(cx)));
         }
 
-        let id = self.queue.back().unwrap().card_id;
+        let id = self
+            .queue
+            .back()
+            .map(|entry| entry.card_id)
+            .unwrap_or_default();
+
+        let card = match flash_card::FlashCard::load(id, &collection.storage.conn) {
+            Ok(card) => card,
+            Err(e) => {
+                log::error!("Error loading card {}: {}", id, e);
+                cx.focus(&self.focus_handle);
+
+                return div()
+                    .flex()
+                    .track_focus(&self.focus_handle)
+                    .size_full()
+                    .justify_center()
+                    .on_key_down(cx.listener(Self::key_down))
+                    .child(
+                        div()
+                            .mt_20()
+                            .text_color(theme.red)
+                            .child(format!("Could not load this card: {}", e)),
+                    );
+            }
+        };
+        let media_folder = collection.media_folder();
+
+        self.expects_typing = card.get_question().contains(type_answer::PROMPT);
+        self.suggested = match (&self.typed, type_answer::expected(card.get_answer())) {
+            (Some(typed), Some((expected, ignore))) => {
+                Some(type_answer::compare(typed, expected, ignore).suggested_answer())
+            }
+            _ => None,
+        };
+
+        let typing = self.expects_typing && !self.show_answer;
+        if typing {
+            self.answer_input.focus(cx);
+        } else {
+            cx.focus(&self.focus_handle);
+        }
 
-        let card = flash_card::FlashCard::load(id, &collection.storage.conn).unwrap();
         let answer = if self.show_answer {
-            div().pt_5().child(card.get_answer().to_string())
+            div().pt_5().child(
+                Field::new(card.get_answer(), media_folder.clone()).typed(self.typed.clone()),
+            )
+        } else if typing {
+            div().pt_5().child(self.answer_input.clone())
         } else {
             div()
         };
 
         let actions = if self.show_answer {
-            div().absolute().bottom_16().max_w(Pixels(500.0)).child(
-                div()
-                    .flex()
-                    .justify_between()
-                    .child(
-                        Button::new("again", "Again", None)
-                            .on_click(cx.listener(Self::again_click)),
-                    )
-                    .child(
-                        Button::new("hard", "Hard", None).on_click(cx.listener(Self::hard_click)),
-                    )
-                    .child(
-                        Button::new("good", "Good", None).on_click(cx.listener(Self::good_click)),
-                    )
-                    .child(
-                        Button::new("easy", "Easy", None).on_click(cx.listener(Self::easy_click)),
-                    ),
-            )
+            let mut buttons = [
+                (Answer::Again, Button::new("again", "Again", None)),
+                (Answer::Hard, Button::new("hard", "Hard", None)),
+                (Answer::Good, Button::new("good", "Good", None)),
+                (Answer::Easy, Button::new("easy", "Easy", None)),
+            ];
+            for (answer, button) in buttons.iter_mut() {
+                if Some(*answer) == self.suggested {
+                    button.focus();
+                }
+            }
+            let [(_, again), (_, hard), (_, good), (_, easy)] = buttons;
+
+            let suggestion = match self.suggested {
+                Some(answer) => div()
+                    .pb_2()
+                    .text_sm()
+                    .text_color(theme.subtext0)
+                    .child(format!("Suggested: {:?}", answer)),
+                None => div(),
+            };
+
+            div()
+                .absolute()
+                .bottom_16()
+                .max_w(Pixels(500.0))
+                .child(suggestion)
+                .child(
+                    div()
+                        .flex()
+                        .justify_between()
+                        .child(again.on_click(cx.listener(Self::again_click)))
+                        .child(hard.on_click(cx.listener(Self::hard_click)))
+                        .child(good.on_click(cx.listener(Self::good_click)))
+                        .child(easy.on_click(cx.listener(Self::easy_click))),
+                )
         } else {
             div()
         };
@@ -214,7 +342,7 @@ impl Render for FlashCard {
                                         .pb_5()
                                         .border_b_1()
                                         .border_color(theme.crust)
-                                        .child(card.get_question().to_string()),
+                                        .child(Field::new(card.get_question(), media_folder)),
                                 )
                                 .child(answer),
                         )
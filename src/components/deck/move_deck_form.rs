@@ -0,0 +1,143 @@
+use gpui::{
+    div, AnyView, FocusHandle, FontWeight, InteractiveElement, ParentElement, Pixels, Render,
+    Styled, View, ViewContext, VisualContext, WindowContext,
+};
+
+use crate::{
+    errors::ErrorState,
+    models::{
+        collection::Collection,
+        deck::{self, deck_rows, get_decks},
+    },
+    state::{StackableView, StackableViewState},
+    theme::Theme,
+    ui::list::list_item::ListItem,
+};
+
+/// Picks a new parent for a deck. The deck's subdecks move along with it.
+struct MoveDeckForm {
+    deck_id: u32,
+    focus_handle: FocusHandle,
+}
+
+impl MoveDeckForm {
+    pub fn view(deck_id: u32, cx: &mut WindowContext) -> View<Self> {
+        cx.new_view(|cx: &mut ViewContext<Self>| {
+            let focus_handle = cx.focus_handle();
+            focus_handle.focus(cx);
+
+            Self {
+                deck_id,
+                focus_handle,
+            }
+        })
+    }
+
+    fn move_to(&mut self, parent_id: Option<u32>, cx: &mut ViewContext<Self>) {
+        let deck_id = self.deck_id;
+        let result = cx.update_global(|collection: &mut Collection, _| {
+            collection.move_deck(deck_id, parent_id)
+        });
+
+        match result {
+            Ok(_) => {
+                StackableViewState::update(|state, cx| state.pop(cx), cx);
+                cx.notify();
+            }
+            Err(e) => ErrorState::report("Error moving deck", e, cx),
+        }
+    }
+}
+
+impl Render for MoveDeckForm {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl gpui::prelude::IntoElement {
+        let decks = get_decks(&cx.global::<Collection>().storage.conn).unwrap_or_else(|e| {
+            log::error!("Error loading decks: {}", e);
+            Vec::new()
+        });
+        let name = decks
+            .iter()
+            .find(|deck| deck.id == Some(self.deck_id))
+            .map(|deck| deck.name.clone())
+            .unwrap_or_default();
+
+        // A deck cannot go inside itself, so its own tree is left out.
+        let parents = deck_rows(decks)
+            .into_iter()
+            .filter(|row| !deck::is_within(&row.deck.name, &name))
+            .collect::<Vec<_>>();
+
+        let theme = cx.global::<Theme>();
+
+        let top_level = ListItem::new("move-top-level")
+            .on_click(cx.listener(|form, _event, cx| form.move_to(None, cx)))
+            .child(div().text_sm().child("Top level"));
+
+        let items = parents
+            .into_iter()
+            .filter_map(|row| {
+                let parent_id = row.deck.id?;
+
+                Some(
+                    ListItem::new(("move-to", parent_id as usize))
+                        .indent_level(row.depth + 1)
+                        .on_click(
+                            cx.listener(move |form, _event, cx| form.move_to(Some(parent_id), cx)),
+                        )
+                        .child(
+                            div()
+                                .text_sm()
+                                .child(deck::base_name(&row.deck.name).to_string()),
+                        ),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        div().flex().size_full().justify_center().child(
+            div().mt_20().child(
+                div()
+                    .track_focus(&self.focus_handle)
+                    .flex()
+                    .w_full()
+                    .flex_col()
+                    .text_color(theme.text)
+                    .on_key_down(|event, wc| {
+                        if event.keystroke.key == "escape" {
+                            StackableViewState::update(|state, cx| state.pop(cx), wc);
+                        }
+                    })
+                    .child(
+                        div()
+                            .w(Pixels(500.0))
+                            .child(
+                                div()
+                                    .text_xl()
+                                    .font_weight(FontWeight::EXTRA_BOLD)
+                                    .pb_5()
+                                    .border_b_1()
+                                    .border_color(theme.crust)
+                                    .child(format!("Move {}", name)),
+                            )
+                            .child(
+                                div()
+                                    .mt_6()
+                                    .text_sm()
+                                    .text_color(theme.subtext0)
+                                    .child("Choose the deck to move it under."),
+                            )
+                            .child(div().mt_2().child(top_level).children(items)),
+                    ),
+            ),
+        )
+    }
+}
+
+pub struct MoveDeckBuilder {
+    pub deck_id: u32,
+}
+
+impl StackableView for MoveDeckBuilder {
+    fn build(&self, cx: &mut WindowContext) -> AnyView {
+        MoveDeckForm::view(self.deck_id, cx).into()
+    }
+}
@@ -4,7 +4,7 @@ use gpui::{
 };
 
 use crate::{
-    repositories::{self},
+    errors::ErrorState,
     state::{StackableView, StackableViewState},
     theme::Theme,
     ui::{button::button::Button, clickable::Clickable, text_field::text_field::TextField},
@@ -19,7 +19,11 @@ struct NewDeckForm {
 impl NewDeckForm {
     pub fn view(cx: &mut WindowContext) -> View<Self> {
         cx.new_view(|cx: &mut gpui::ViewContext<'_, NewDeckForm>| {
-            let text_input = TextField::new(cx, "Input deck name".to_string(), false);
+            let text_input = TextField::new(
+                cx,
+                "Input deck name, with Parent::Child for a subdeck".to_string(),
+                false,
+            );
             text_input.focus(cx);
 
             let focus_handle = cx.focus_handle();
@@ -41,17 +45,17 @@ impl NewDeckForm {
     }
 
     fn save(&mut self, cx: &mut ViewContext<Self>) {
-        let collection = cx.global::<crate::Collection>();
-        let text = &self.text_input.view.read(&cx).text;
+        let text = self.text_input.view.read(cx).text.clone();
+        let result =
+            cx.update_global(|collection: &mut crate::Collection, _| collection.create_deck(&text));
 
-        let mut deck = repositories::deck::Deck::new(text);
-        match deck.save(&collection.storage.conn) {
+        match result {
             Ok(_) => {
                 StackableViewState::update(|state, cx| state.pop(cx), cx);
                 cx.notify();
             }
             Err(e) => {
-                log::error!("Error saving deck: {:?}", e);
+                ErrorState::report("Error saving deck", e, cx);
             }
         }
     }
@@ -0,0 +1,284 @@
+use gpui::{
+    div, prelude::*, AnyView, ClickEvent, EventEmitter, FocusHandle, Render, View, ViewContext,
+    WindowContext,
+};
+
+use crate::{
+    components::shared::{
+        card_form::{attach_media, CardForm},
+        tag_editor::TagEditor,
+    },
+    errors::{ErrorState, Result},
+    models::collection::Collection,
+    repositories::{deck::Deck, note::Note, notetype::Notetype},
+    state::{StackableView, StackableViewState},
+    theme::Theme,
+    ui::{button::button::Button, clickable::Clickable, text_field::text_field::TextField},
+    FlashCard,
+};
+
+/// Edits a saved card: the fields and tags of its note, and its deck. The
+/// note type cannot be changed here.
+pub struct EditCardView {
+    card_id: u32,
+    /// Whether the editor was pushed onto the view stack, and pops itself
+    /// when done, or is shown inside another view, which gets an
+    /// `EditCardEvent` instead.
+    stacked: bool,
+    notetype_name: String,
+    field_names: Vec<String>,
+    field_inputs: Vec<TextField>,
+    /// `None` for a card without a note, which has nowhere to keep tags.
+    tag_editor: Option<View<TagEditor>>,
+    deck_input: TextField,
+    media_input: TextField,
+    focus_handle: FocusHandle,
+    focused_at: usize,
+}
+
+/// What the form starts with: the note type's name and fields, the field
+/// values, the tags and the name of the card's deck.
+struct Loaded {
+    notetype_name: String,
+    fields: Vec<(String, String)>,
+    tags: Option<Vec<String>>,
+    deck_name: String,
+}
+
+pub enum EditCardEvent {
+    Saved,
+    Closed,
+}
+
+impl EventEmitter<EditCardEvent> for EditCardView {}
+
+impl EditCardView {
+    pub fn view(card_id: u32, cx: &mut WindowContext) -> View<Self> {
+        Self::build(card_id, true, cx)
+    }
+
+    /// An editor to show next to a list of cards.
+    pub fn embedded(card_id: u32, cx: &mut WindowContext) -> View<Self> {
+        Self::build(card_id, false, cx)
+    }
+
+    fn build(card_id: u32, stacked: bool, cx: &mut WindowContext) -> View<Self> {
+        cx.new_view(|cx: &mut ViewContext<Self>| {
+            let loaded = Self::load(card_id, cx.global::<Collection>()).unwrap_or_else(|e| {
+                ErrorState::report("Error loading card", e, cx);
+                Loaded {
+                    notetype_name: String::new(),
+                    fields: Vec::new(),
+                    tags: None,
+                    deck_name: String::new(),
+                }
+            });
+
+            let deck_input = TextField::new(cx, "Deck name".to_string(), false);
+            deck_input
+                .view
+                .update(cx, |view, _| view.text = loaded.deck_name);
+
+            let (field_names, texts): (Vec<_>, Vec<_>) = loaded.fields.into_iter().unzip();
+            let field_inputs = texts
+                .into_iter()
+                .map(|text| {
+                    let input = TextField::new(cx, "".to_string(), false);
+                    input.view.update(cx, |view, _| view.text = text);
+                    input
+                })
+                .collect::<Vec<_>>();
+
+            if let Some(first) = field_inputs.first() {
+                first.focus(cx);
+            }
+
+            Self {
+                card_id,
+                stacked,
+                notetype_name: loaded.notetype_name,
+                field_names,
+                field_inputs,
+                tag_editor: loaded.tags.map(|tags| TagEditor::view(&tags, cx)),
+                deck_input,
+                media_input: TextField::new(
+                    cx,
+                    "Path to an image or audio file".to_string(),
+                    false,
+                ),
+                focus_handle: cx.focus_handle(),
+                focused_at: 0,
+            }
+        })
+    }
+
+    fn load(card_id: u32, collection: &Collection) -> Result<Loaded> {
+        let conn = &collection.storage.conn;
+        let card = FlashCard::load(card_id, conn)?;
+        let deck_name = Deck::load(card.get_deck_id(), conn)?.name;
+
+        if card.note_id == 0 {
+            return Ok(Loaded {
+                notetype_name: "Basic".to_string(),
+                fields: vec![
+                    ("Front".to_string(), card.get_question().to_string()),
+                    ("Back".to_string(), card.get_answer().to_string()),
+                ],
+                tags: None,
+                deck_name,
+            });
+        }
+
+        let note = Note::load(card.note_id, conn)?;
+        let notetype = Notetype::load(note.notetype_id, conn)?;
+
+        Ok(Loaded {
+            notetype_name: notetype.name,
+            fields: notetype.fields.into_iter().zip(note.fields).collect(),
+            tags: Some(note.tags),
+            deck_name,
+        })
+    }
+
+    /// The fields, the tags if the card has a note, the deck, then the
+    /// submit button.
+    fn focus_next(&mut self, cx: &mut ViewContext<Self>) {
+        let tags_at = self.field_inputs.len();
+        let tabbable = tags_at + 3;
+
+        let mut next = (self.focused_at + 1) % tabbable;
+        if next == tags_at && self.tag_editor.is_none() {
+            next += 1;
+        }
+
+        match self.field_inputs.get(next) {
+            Some(input) => input.focus(cx),
+            None if next == tags_at => {
+                if let Some(tag_editor) = &self.tag_editor {
+                    let input = tag_editor.read(cx).input.clone();
+                    input.focus(cx);
+                }
+            }
+            None if next == tags_at + 1 => self.deck_input.focus(cx),
+            None => cx.focus(&self.focus_handle),
+        }
+
+        self.focused_at = next;
+        cx.notify();
+    }
+
+    fn save_click(&mut self, _event: &ClickEvent, cx: &mut ViewContext<Self>) {
+        self.save(cx);
+    }
+
+    fn save(&mut self, cx: &mut ViewContext<Self>) {
+        let fields = self
+            .field_inputs
+            .iter()
+            .map(|input| input.view.read(cx).text.clone())
+            .collect::<Vec<_>>();
+        let tags = self
+            .tag_editor
+            .as_ref()
+            .map(|tag_editor| tag_editor.read(cx).tags(cx))
+            .unwrap_or_default();
+        let deck_name = self.deck_input.view.read(cx).text.clone();
+
+        let card_id = self.card_id;
+        let result = cx.update_global(|collection: &mut Collection, _| {
+            collection.edit_card(card_id, &fields, &tags, &deck_name)
+        });
+
+        match result {
+            Ok(_) => self.close(EditCardEvent::Saved, cx),
+            Err(e) => ErrorState::report("Error saving card", e, cx),
+        }
+    }
+
+    fn close(&mut self, event: EditCardEvent, cx: &mut ViewContext<Self>) {
+        if self.stacked {
+            StackableViewState::update(|state, cx| state.pop(cx), cx);
+        } else {
+            cx.emit(event);
+        }
+        cx.notify();
+    }
+
+    fn attach_click(&mut self, _event: &ClickEvent, cx: &mut ViewContext<Self>) {
+        attach_media(&self.media_input, self.field_inputs.last(), cx);
+        cx.notify();
+    }
+}
+
+impl Render for EditCardView {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let theme = cx.global::<Theme>();
+
+        let mut submit_btn =
+            Button::new("save", "Save card", None).on_click(cx.listener(Self::save_click));
+        if self.focused_at == self.field_inputs.len() + 2 {
+            submit_btn.focus();
+        }
+
+        let fields = self
+            .field_names
+            .iter()
+            .cloned()
+            .zip(self.field_inputs.iter().cloned())
+            .collect::<Vec<_>>();
+
+        let mut form = CardForm::new("Edit card", self.deck_input.clone(), submit_btn)
+            .notetype(
+                div()
+                    .text_color(theme.subtext0)
+                    .child(self.notetype_name.clone()),
+            )
+            .fields(fields)
+            .media(
+                self.media_input.clone(),
+                Button::new("attach", "Attach", None).on_click(cx.listener(Self::attach_click)),
+            );
+        if let Some(tag_editor) = &self.tag_editor {
+            form = form.tags(tag_editor.clone());
+        }
+
+        // Next to a card list there is no title bar to leave room for.
+        let top = if self.stacked {
+            div().mt_20()
+        } else {
+            div().mt_4()
+        };
+
+        div().flex().size_full().justify_center().child(
+            top.child(
+                div()
+                    .track_focus(&self.focus_handle)
+                    .flex()
+                    .w_full()
+                    .flex_col()
+                    .text_color(theme.text)
+                    .relative()
+                    .h_full()
+                    .on_key_down(cx.listener(|this, event: &gpui::KeyDownEvent, cx| {
+                        match event.keystroke.key.as_str() {
+                            "tab" => this.focus_next(cx),
+                            "enter" => this.save(cx),
+                            "escape" => this.close(EditCardEvent::Closed, cx),
+                            _ => {}
+                        }
+                    }))
+                    .child(form),
+            ),
+        )
+    }
+}
+
+pub struct EditCardBuilder {
+    pub card_id: u32,
+}
+
+impl StackableView for EditCardBuilder {
+    fn build(&self, cx: &mut WindowContext) -> AnyView {
+        EditCardView::view(self.card_id, cx).into()
+    }
+}
@@ -0,0 +1,179 @@
+use std::path::PathBuf;
+
+use gpui::{
+    div, AnyView, FontWeight, ParentElement, Pixels, Render, Styled, View, ViewContext,
+    VisualContext, WindowContext,
+};
+
+use crate::{
+    errors::ErrorState,
+    import_export::apkg_export::ExportOptions,
+    models::collection::Collection,
+    state::StackableView,
+    theme::Theme,
+    ui::{
+        button::button::Button, clickable::Clickable, selectable::Selectable,
+        text_field::text_field::TextField,
+    },
+    Deck,
+};
+
+/// Writes a deck, or the whole collection, to an Anki package.
+pub struct ExportView {
+    /// The deck the screen was opened from, with its name.
+    deck: Option<(u32, String)>,
+    options: ExportOptions,
+    path_input: TextField,
+    /// What the last export wrote, as in "12 cards exported".
+    status: Option<String>,
+}
+
+impl ExportView {
+    pub fn view(deck_id: Option<u32>, cx: &mut WindowContext) -> View<Self> {
+        let conn = &cx.global::<Collection>().storage.conn;
+        let deck = deck_id.and_then(|id| match Deck::load(id, conn) {
+            Ok(deck) => Some((id, deck.name)),
+            Err(e) => {
+                log::error!("Error loading deck {}: {}", id, e);
+                None
+            }
+        });
+
+        cx.new_view(|cx| {
+            let path_input =
+                TextField::new(cx, "Path of the .apkg file to write".to_string(), false);
+            path_input.focus(cx);
+
+            Self {
+                options: ExportOptions {
+                    deck_id: deck.as_ref().map(|(id, _)| *id),
+                    ..ExportOptions::default()
+                },
+                deck,
+                path_input,
+                status: None,
+            }
+        })
+    }
+
+    fn toggle(&mut self, toggle: impl FnOnce(&mut ExportOptions), cx: &mut ViewContext<Self>) {
+        toggle(&mut self.options);
+        self.status = None;
+        cx.notify();
+    }
+
+    fn export(&mut self, cx: &mut ViewContext<Self>) {
+        let path = self.path_input.view.read(cx).text.trim().to_string();
+        if path.is_empty() {
+            self.status = Some("Enter the path of the file to write first".to_string());
+            cx.notify();
+            return;
+        }
+
+        let result = cx
+            .global::<Collection>()
+            .export_anki_package(&PathBuf::from(&path), &self.options);
+
+        match result {
+            Ok(summary) => self.status = Some(format!("Wrote {}: {}", path, summary)),
+            Err(e) => {
+                self.status = None;
+                ErrorState::report("Error exporting", e, cx);
+            }
+        }
+
+        cx.notify();
+    }
+}
+
+impl Render for ExportView {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl gpui::prelude::IntoElement {
+        let theme = cx.global::<Theme>();
+
+        let scope = div()
+            .mt_5()
+            .flex()
+            .gap_2()
+            .children(self.deck.clone().map(|(id, name)| {
+                Button::new("export-deck", name, None)
+                    .selected(self.options.deck_id.is_some())
+                    .on_click(cx.listener(move |this, _e, cx| {
+                        this.toggle(|options| options.deck_id = Some(id), cx)
+                    }))
+            }))
+            .child(
+                Button::new("export-all", "All decks", None)
+                    .selected(self.options.deck_id.is_none())
+                    .on_click(cx.listener(|this, _e, cx| {
+                        this.toggle(|options| options.deck_id = None, cx)
+                    })),
+            );
+
+        let options = div()
+            .mt_3()
+            .flex()
+            .gap_2()
+            .child(
+                Button::new("export-scheduling", "Include scheduling", None)
+                    .selected(self.options.include_scheduling)
+                    .on_click(cx.listener(|this, _e, cx| {
+                        this.toggle(
+                            |options| options.include_scheduling = !options.include_scheduling,
+                            cx,
+                        )
+                    })),
+            )
+            .child(
+                Button::new("export-media", "Include media", None)
+                    .selected(self.options.include_media)
+                    .on_click(cx.listener(|this, _e, cx| {
+                        this.toggle(|options| options.include_media = !options.include_media, cx)
+                    })),
+            );
+
+        div().flex().size_full().justify_center().child(
+            div().mt_20().child(
+                div()
+                    .w(Pixels(500.0))
+                    .flex()
+                    .flex_col()
+                    .text_color(theme.text)
+                    .child(
+                        div()
+                            .text_xl()
+                            .font_weight(FontWeight::EXTRA_BOLD)
+                            .pb_5()
+                            .border_b_1()
+                            .border_color(theme.crust)
+                            .child("Export"),
+                    )
+                    .child(scope)
+                    .child(options)
+                    .child(div().mt_5().text_sm().child("Anki package (.apkg)"))
+                    .child(div().mt_2().child(self.path_input.clone()))
+                    .children(
+                        self.status
+                            .clone()
+                            .map(|status| div().mt_5().text_sm().child(status)),
+                    )
+                    .child(
+                        div().mt_5().flex().justify_end().child(
+                            Button::new("export-now", "Export", None)
+                                .on_click(cx.listener(|this, _e, cx| this.export(cx))),
+                        ),
+                    ),
+            ),
+        )
+    }
+}
+
+pub struct ExportBuilder {
+    /// The deck to offer exporting; every deck can be exported either way.
+    pub deck_id: Option<u32>,
+}
+
+impl StackableView for ExportBuilder {
+    fn build(&self, cx: &mut WindowContext) -> AnyView {
+        ExportView::view(self.deck_id, cx).into()
+    }
+}
@@ -0,0 +1,339 @@
+use gpui::{
+    div, prelude::*, px, Div, EventEmitter, FontWeight, Render, View, ViewContext, WindowContext,
+};
+
+use crate::{
+    errors::{ErrorState, Result},
+    models::{
+        browse::one_line,
+        collection::Collection,
+        find_replace::{FieldChange, FindReplace},
+    },
+    theme::Theme,
+    ui::{
+        button::button::Button,
+        clickable::Clickable,
+        selectable::Selectable,
+        text_field::text_field::{TextEvent, TextField},
+    },
+};
+
+/// The most changes the preview lists; the count above it covers them all.
+const PREVIEW_LIMIT: usize = 50;
+
+/// Replaces text in the fields of the cards selected in the browser, or of
+/// the cards matching a search. Nothing changes until the preview has been
+/// shown.
+pub struct FindReplaceView {
+    selected: Vec<u32>,
+    /// Whether the search below picks the cards rather than the selection.
+    use_search: bool,
+    search_input: TextField,
+    find_input: TextField,
+    replace_input: TextField,
+    fields_input: TextField,
+    regex: bool,
+    match_case: bool,
+    /// The changes shown, and the cards they were worked out for.
+    preview: Option<(Vec<u32>, Vec<FieldChange>)>,
+    status: Option<String>,
+}
+
+pub enum FindReplaceEvent {
+    Replaced,
+    Closed,
+}
+
+impl EventEmitter<FindReplaceEvent> for FindReplaceView {}
+
+impl FindReplaceView {
+    /// Starts out on the selected cards, or on the browser's search if none
+    /// are selected.
+    pub fn view(selected: Vec<u32>, search: String, cx: &mut WindowContext) -> View<Self> {
+        cx.new_view(|cx: &mut ViewContext<Self>| {
+            let search_input = TextField::new(cx, "Search, e.g. deck:Spanish".to_string(), false);
+            search_input.view.update(cx, |view, _| view.text = search);
+
+            let find_input = TextField::new(cx, "Find".to_string(), false);
+            find_input.focus(cx);
+            let replace_input = TextField::new(cx, "Replace with".to_string(), false);
+            let fields_input = TextField::new(
+                cx,
+                "Fields, e.g. Front Back; all if empty".to_string(),
+                false,
+            );
+
+            // A preview only holds for the text it was made with.
+            for input in [&search_input, &find_input, &replace_input, &fields_input] {
+                cx.subscribe(&input.view, |this: &mut Self, _, event, cx| {
+                    if let TextEvent::Input { .. } = event {
+                        this.preview = None;
+                        cx.notify();
+                    }
+                })
+                .detach();
+            }
+
+            Self {
+                use_search: selected.is_empty(),
+                selected,
+                search_input,
+                find_input,
+                replace_input,
+                fields_input,
+                regex: false,
+                match_case: false,
+                preview: None,
+                status: None,
+            }
+        })
+    }
+
+    pub fn set_selected(&mut self, selected: Vec<u32>, cx: &mut ViewContext<Self>) {
+        self.selected = selected;
+        self.preview = None;
+        cx.notify();
+    }
+
+    fn find_replace(&self, cx: &ViewContext<Self>) -> FindReplace {
+        FindReplace {
+            find: self.find_input.view.read(cx).text.clone(),
+            replace: self.replace_input.view.read(cx).text.clone(),
+            regex: self.regex,
+            match_case: self.match_case,
+            fields: self
+                .fields_input
+                .view
+                .read(cx)
+                .text
+                .split_whitespace()
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+
+    /// The cards in scope: the selection, or the cards matching the search.
+    fn card_ids(&self, cx: &ViewContext<Self>) -> Result<Vec<u32>> {
+        if self.use_search {
+            let search = self.search_input.view.read(cx).text.clone();
+            cx.global::<Collection>().search_cards(&search)
+        } else {
+            Ok(self.selected.clone())
+        }
+    }
+
+    fn toggle(&mut self, toggle: impl FnOnce(&mut Self), cx: &mut ViewContext<Self>) {
+        toggle(self);
+        self.preview = None;
+        cx.notify();
+    }
+
+    fn show_preview(&mut self, cx: &mut ViewContext<Self>) {
+        let find_replace = self.find_replace(cx);
+        let result = self.card_ids(cx).and_then(|card_ids| {
+            let changes = cx
+                .global::<Collection>()
+                .preview_find_replace(&card_ids, &find_replace)?;
+            Ok((card_ids, changes))
+        });
+
+        match result {
+            Ok(preview) => {
+                self.preview = Some(preview);
+                self.status = None;
+            }
+            Err(e) => ErrorState::report("Error finding text", e, cx),
+        }
+
+        cx.notify();
+    }
+
+    /// Makes the previewed changes, to the cards they were previewed for.
+    fn replace(&mut self, cx: &mut ViewContext<Self>) {
+        let Some((card_ids, _)) = self.preview.take() else {
+            return;
+        };
+
+        let find_replace = self.find_replace(cx);
+        let result = cx.update_global(|collection: &mut Collection, _| {
+            collection.find_and_replace(&card_ids, &find_replace)
+        });
+
+        match result {
+            Ok(changed) => {
+                let noun = if changed == 1 { "card" } else { "cards" };
+                self.status = Some(format!("Changed {} {}", changed, noun));
+                cx.emit(FindReplaceEvent::Replaced);
+            }
+            Err(e) => ErrorState::report("Error replacing text", e, cx),
+        }
+
+        cx.notify();
+    }
+
+    fn render_change(change: &FieldChange, theme: &Theme) -> Div {
+        div()
+            .py_1()
+            .border_b_1()
+            .border_color(theme.crust)
+            .text_sm()
+            .child(
+                div()
+                    .text_color(theme.subtext0)
+                    .child(format!("Card {} · {}", change.card_id, change.field)),
+            )
+            .child(
+                div()
+                    .text_color(theme.red)
+                    .child(format!("− {}", one_line(&change.before))),
+            )
+            .child(
+                div()
+                    .text_color(theme.green)
+                    .child(format!("+ {}", one_line(&change.after))),
+            )
+    }
+}
+
+impl Render for FindReplaceView {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let theme = cx.global::<Theme>();
+        let label = |text: &str| {
+            div()
+                .mt_3()
+                .mb_1()
+                .text_sm()
+                .text_color(theme.subtext1)
+                .child(text.to_string())
+        };
+
+        let scope = div()
+            .flex()
+            .gap_2()
+            .child(
+                Button::new(
+                    "fr-selected",
+                    format!("Selected cards ({})", self.selected.len()),
+                    None,
+                )
+                .selected(!self.use_search)
+                .on_click(
+                    cx.listener(|this, _e, cx| this.toggle(|this| this.use_search = false, cx)),
+                ),
+            )
+            .child(
+                Button::new("fr-search", "Search", None)
+                    .selected(self.use_search)
+                    .on_click(
+                        cx.listener(|this, _e, cx| this.toggle(|this| this.use_search = true, cx)),
+                    ),
+            );
+
+        let options =
+            div()
+                .flex()
+                .gap_2()
+                .mt_3()
+                .child(
+                    Button::new("fr-regex", "Regex", None)
+                        .selected(self.regex)
+                        .on_click(cx.listener(|this, _e, cx| {
+                            this.toggle(|this| this.regex = !this.regex, cx)
+                        })),
+                )
+                .child(
+                    Button::new("fr-case", "Match case", None)
+                        .selected(self.match_case)
+                        .on_click(cx.listener(|this, _e, cx| {
+                            this.toggle(|this| this.match_case = !this.match_case, cx)
+                        })),
+                );
+
+        let actions = div()
+            .flex()
+            .gap_2()
+            .mt_4()
+            .child(
+                Button::new("fr-preview", "Preview", None)
+                    .on_click(cx.listener(|this, _e, cx| this.show_preview(cx))),
+            )
+            .when(
+                self.preview
+                    .as_ref()
+                    .is_some_and(|(_, changes)| !changes.is_empty()),
+                |this| {
+                    this.child(
+                        Button::new("fr-replace", "Replace", None)
+                            .on_click(cx.listener(|this, _e, cx| this.replace(cx))),
+                    )
+                },
+            )
+            .child(
+                Button::new("fr-close", "Close", None)
+                    .on_click(cx.listener(|_this, _e, cx| cx.emit(FindReplaceEvent::Closed))),
+            );
+
+        let preview = self.preview.as_ref().map(|(card_ids, changes)| {
+            let summary = if changes.is_empty() {
+                format!("Nothing to change in {} cards", card_ids.len())
+            } else {
+                format!(
+                    "{} fields will change, in {} cards searched",
+                    changes.len(),
+                    card_ids.len()
+                )
+            };
+
+            div()
+                .mt_4()
+                .flex()
+                .flex_col()
+                .child(div().text_sm().font_weight(FontWeight::BOLD).child(summary))
+                .children(
+                    changes
+                        .iter()
+                        .take(PREVIEW_LIMIT)
+                        .map(|change| Self::render_change(change, theme)),
+                )
+                .when(changes.len() > PREVIEW_LIMIT, |this| {
+                    this.child(
+                        div()
+                            .text_sm()
+                            .text_color(theme.subtext0)
+                            .child(format!("and {} more", changes.len() - PREVIEW_LIMIT)),
+                    )
+                })
+        });
+
+        div()
+            .flex()
+            .flex_col()
+            .p_4()
+            .text_color(theme.text)
+            .child(
+                div()
+                    .text_xl()
+                    .font_weight(FontWeight::EXTRA_BOLD)
+                    .child("Find and replace"),
+            )
+            .children(
+                self.status
+                    .clone()
+                    .map(|status| div().mt_2().text_sm().text_color(theme.green).child(status)),
+            )
+            .child(label("Cards"))
+            .child(scope)
+            .when(self.use_search, |this| {
+                this.child(div().mt_2().child(self.search_input.clone()))
+            })
+            .child(label("Find"))
+            .child(self.find_input.clone())
+            .child(label("Replace with"))
+            .child(self.replace_input.clone())
+            .child(label("In fields"))
+            .child(div().w(px(360.0)).child(self.fields_input.clone()))
+            .child(options)
+            .child(actions)
+            .children(preview)
+    }
+}
@@ -0,0 +1,431 @@
+use std::path::{Path, PathBuf};
+
+use gpui::{
+    div, prelude::*, px, AnyView, Div, FontWeight, Pixels, Render, View, ViewContext, WindowContext,
+};
+
+use crate::{
+    errors::ErrorState,
+    import_export::delimited::{self, DelimitedFile, DelimitedImport, DuplicateMode},
+    models::{browse::one_line, collection::Collection},
+    state::StackableView,
+    theme::Theme,
+    ui::{
+        button::button::Button, clickable::Clickable, selectable::Selectable,
+        text_field::text_field::TextField,
+    },
+};
+
+/// The rows of a CSV or TSV file shown before importing it.
+const PREVIEW_ROWS: usize = 5;
+
+/// What a column of a CSV or TSV file can be mapped to.
+#[derive(Clone, Copy)]
+enum Target {
+    Question,
+    Answer,
+    Tags,
+    Deck,
+}
+
+impl Target {
+    fn label(self) -> &'static str {
+        match self {
+            Target::Question => "Question",
+            Target::Answer => "Answer",
+            Target::Tags => "Tags",
+            Target::Deck => "Deck",
+        }
+    }
+
+    fn column(self, options: &DelimitedImport) -> Option<usize> {
+        let columns = &options.columns;
+        match self {
+            Target::Question => Some(columns.question),
+            Target::Answer => Some(columns.answer),
+            Target::Tags => columns.tags,
+            Target::Deck => columns.deck,
+        }
+    }
+
+    /// Maps the target to `column`; only tags and deck can be left unmapped.
+    fn set(self, options: &mut DelimitedImport, column: Option<usize>) {
+        let columns = &mut options.columns;
+        match (self, column) {
+            (Target::Question, Some(column)) => columns.question = column,
+            (Target::Answer, Some(column)) => columns.answer = column,
+            (Target::Tags, column) => columns.tags = column,
+            (Target::Deck, column) => columns.deck = column,
+            _ => {}
+        }
+    }
+}
+
+/// Imports a file into the collection, named by its path: an Anki package
+/// straight away, or a CSV or TSV file once its columns are mapped.
+pub struct ImportView {
+    path_input: TextField,
+    /// The deck of CSV rows without one.
+    deck_input: TextField,
+    /// The CSV or TSV file being mapped, with its path.
+    delimited: Option<(String, DelimitedFile)>,
+    options: DelimitedImport,
+    /// What the last import did, as in "3 notes added, 0 updated, 0 skipped".
+    status: Option<String>,
+}
+
+impl ImportView {
+    pub fn view(cx: &mut WindowContext) -> View<Self> {
+        cx.new_view(|cx| {
+            let path_input = TextField::new(
+                cx,
+                "Path to an .apkg, .colpkg, .csv or .tsv file".to_string(),
+                false,
+            );
+            path_input.focus(cx);
+
+            Self {
+                path_input,
+                deck_input: TextField::new(cx, "Deck for rows without one".to_string(), false),
+                delimited: None,
+                options: DelimitedImport::default(),
+                status: None,
+            }
+        })
+    }
+
+    fn is_package(path: &str) -> bool {
+        Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| {
+                ["apkg", "colpkg"].contains(&extension.to_ascii_lowercase().as_str())
+            })
+    }
+
+    fn import(&mut self, cx: &mut ViewContext<Self>) {
+        let path = self.path_input.view.read(cx).text.trim().to_string();
+        if path.is_empty() {
+            self.status = Some("Enter the path of the file to import first".to_string());
+            cx.notify();
+            return;
+        }
+
+        if !Self::is_package(&path) {
+            self.load_delimited(path, cx);
+            return;
+        }
+
+        // An import can change many cards at once, so keep a copy.
+        if let Err(e) = cx.global::<Collection>().maybe_backup(true) {
+            ErrorState::report("Error backing up collection", e, cx);
+            return;
+        }
+
+        let result = cx.update_global(|collection: &mut Collection, _| {
+            collection.import_anki_package(&PathBuf::from(&path))
+        });
+
+        match result {
+            Ok(summary) => {
+                self.status = Some(format!("Imported {}: {}", path, summary));
+                cx.refresh();
+            }
+            Err(e) => {
+                self.status = None;
+                ErrorState::report("Error importing", e, cx);
+            }
+        }
+
+        cx.notify();
+    }
+
+    /// Reads a CSV or TSV file to show its first rows and map its columns.
+    fn load_delimited(&mut self, path: String, cx: &mut ViewContext<Self>) {
+        match delimited::read(Path::new(&path), None) {
+            Ok(file) => {
+                self.options = DelimitedImport {
+                    duplicates: self.options.duplicates,
+                    ..DelimitedImport::default()
+                };
+                self.status = None;
+                self.delimited = Some((path, file));
+                self.deck_input.focus(cx);
+            }
+            Err(e) => {
+                self.delimited = None;
+                ErrorState::report("Error reading file", e, cx);
+            }
+        }
+
+        cx.notify();
+    }
+
+    fn import_delimited(&mut self, cx: &mut ViewContext<Self>) {
+        let Some((path, file)) = self.delimited.take() else {
+            return;
+        };
+
+        self.options.deck = self.deck_input.view.read(cx).text.trim().to_string();
+        if self.options.deck.is_empty() {
+            self.status = Some("Enter the deck for rows without one first".to_string());
+            self.delimited = Some((path, file));
+            cx.notify();
+            return;
+        }
+
+        if let Err(e) = cx.global::<Collection>().maybe_backup(true) {
+            ErrorState::report("Error backing up collection", e, cx);
+            self.delimited = Some((path, file));
+            return;
+        }
+
+        let options = self.options.clone();
+        let result = cx.update_global(|collection: &mut Collection, _| {
+            collection.import_delimited(&file, &options)
+        });
+
+        match result {
+            Ok(summary) => {
+                self.status = Some(format!("Imported {}: {}", path, summary));
+                cx.refresh();
+            }
+            Err(e) => {
+                self.status = None;
+                self.delimited = Some((path, file));
+                ErrorState::report("Error importing", e, cx);
+            }
+        }
+
+        cx.notify();
+    }
+
+    fn toggle(&mut self, toggle: impl FnOnce(&mut DelimitedImport), cx: &mut ViewContext<Self>) {
+        toggle(&mut self.options);
+        cx.notify();
+    }
+
+    fn delimiter_name(delimiter: char) -> String {
+        match delimiter {
+            '\t' => "tab".to_string(),
+            ',' => "comma".to_string(),
+            ';' => "semicolon".to_string(),
+            other => format!("\"{}\"", other),
+        }
+    }
+
+    /// The name of a column: its header when the first row is one.
+    fn column_name(&self, file: &DelimitedFile, column: usize) -> String {
+        let header = file
+            .rows
+            .first()
+            .and_then(|row| row.get(column))
+            .map(|name| one_line(name))
+            .filter(|name| self.options.skip_header && !name.is_empty());
+
+        header.unwrap_or_else(|| format!("Column {}", column + 1))
+    }
+
+    /// A row of buttons picking the column a target is read from.
+    fn mapping_row(&self, file: &DelimitedFile, target: Target, cx: &mut ViewContext<Self>) -> Div {
+        let mapped = target.column(&self.options);
+        let optional = matches!(target, Target::Tags | Target::Deck);
+        let id = target.label();
+
+        div()
+            .mt_2()
+            .flex()
+            .flex_wrap()
+            .items_center()
+            .gap_1()
+            .child(div().w(px(80.0)).text_sm().child(target.label()))
+            .when(optional, |this| {
+                this.child(
+                    Button::new((id, usize::MAX), "None", None)
+                        .selected(mapped.is_none())
+                        .on_click(cx.listener(move |this, _e, cx| {
+                            this.toggle(|options| target.set(options, None), cx)
+                        })),
+                )
+            })
+            .children((0..file.columns()).map(|column| {
+                Button::new((id, column), self.column_name(file, column), None)
+                    .selected(mapped == Some(column))
+                    .on_click(cx.listener(move |this, _e, cx| {
+                        this.toggle(|options| target.set(options, Some(column)), cx)
+                    }))
+            }))
+    }
+
+    fn preview_table(&self, file: &DelimitedFile, theme: &Theme) -> Div {
+        let columns = file.columns();
+        let mapped_label = |column: usize| {
+            [Target::Question, Target::Answer, Target::Tags, Target::Deck]
+                .into_iter()
+                .filter(|target| target.column(&self.options) == Some(column))
+                .map(Target::label)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let cell = || {
+            div()
+                .flex_1()
+                .min_w(px(60.0))
+                .px_2()
+                .overflow_hidden()
+                .whitespace_nowrap()
+        };
+
+        let header = div()
+            .flex()
+            .py_1()
+            .border_b_1()
+            .border_color(theme.crust)
+            .font_weight(FontWeight::BOLD)
+            .children((0..columns).map(|column| cell().child(mapped_label(column))));
+
+        let rows = file
+            .rows
+            .iter()
+            .take(PREVIEW_ROWS)
+            .enumerate()
+            .map(|(ix, row)| {
+                div()
+                    .flex()
+                    .py_1()
+                    .when(ix == 0 && self.options.skip_header, |this| {
+                        this.text_color(theme.overlay1)
+                    })
+                    .children((0..columns).map(|column| {
+                        cell().child(one_line(row.get(column).map_or("", String::as_str)))
+                    }))
+            });
+
+        div().mt_3().text_sm().child(header).children(rows)
+    }
+
+    fn render_delimited(&self, cx: &mut ViewContext<Self>) -> Option<Div> {
+        let (path, file) = self.delimited.as_ref()?;
+        let theme = cx.global::<Theme>();
+        let table = self.preview_table(file, theme);
+        let subtext = theme.subtext0;
+        let rows = file.rows.len() - usize::from(self.options.skip_header && !file.rows.is_empty());
+
+        let duplicates = [
+            (DuplicateMode::Skip, "Skip"),
+            (DuplicateMode::Update, "Update"),
+            (DuplicateMode::Create, "Create"),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(ix, (mode, label))| {
+            Button::new(("import-duplicates", ix), label, None)
+                .selected(self.options.duplicates == mode)
+                .on_click(cx.listener(move |this, _e, cx| {
+                    this.toggle(|options| options.duplicates = mode, cx)
+                }))
+        })
+        .collect::<Vec<_>>();
+
+        let mapping = [Target::Question, Target::Answer, Target::Tags, Target::Deck]
+            .into_iter()
+            .map(|target| self.mapping_row(file, target, cx))
+            .collect::<Vec<_>>();
+
+        Some(
+            div()
+                .mt_5()
+                .flex()
+                .flex_col()
+                .child(div().text_sm().text_color(subtext).child(format!(
+                    "{}: {} rows separated by {}",
+                    path,
+                    rows,
+                    Self::delimiter_name(file.delimiter)
+                )))
+                .child(table)
+                .child(
+                    div().mt_3().child(
+                        Button::new("import-header", "First row is a header", None)
+                            .selected(self.options.skip_header)
+                            .on_click(cx.listener(|this, _e, cx| {
+                                this.toggle(
+                                    |options| options.skip_header = !options.skip_header,
+                                    cx,
+                                )
+                            })),
+                    ),
+                )
+                .children(mapping)
+                .child(
+                    div()
+                        .mt_3()
+                        .flex()
+                        .items_center()
+                        .gap_1()
+                        .child(div().w(px(80.0)).text_sm().child("Duplicates"))
+                        .children(duplicates),
+                )
+                .child(div().mt_3().child(self.deck_input.clone()))
+                .child(
+                    div().mt_5().flex().justify_end().child(
+                        Button::new("import-rows", format!("Import {} rows", rows), None)
+                            .on_click(cx.listener(|this, _e, cx| this.import_delimited(cx))),
+                    ),
+                ),
+        )
+    }
+}
+
+impl Render for ImportView {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let delimited = self.render_delimited(cx);
+        let theme = cx.global::<Theme>();
+
+        div().flex().size_full().justify_center().child(
+            div().mt_20().child(
+                div()
+                    .w(Pixels(600.0))
+                    .flex()
+                    .flex_col()
+                    .text_color(theme.text)
+                    .child(
+                        div()
+                            .text_xl()
+                            .font_weight(FontWeight::EXTRA_BOLD)
+                            .pb_5()
+                            .border_b_1()
+                            .border_color(theme.crust)
+                            .child("Import"),
+                    )
+                    .child(
+                        div()
+                            .mt_5()
+                            .text_sm()
+                            .child("Anki package (.apkg or .colpkg), or CSV or TSV file"),
+                    )
+                    .child(div().mt_2().child(self.path_input.clone()))
+                    .children(
+                        self.status
+                            .clone()
+                            .map(|status| div().mt_5().text_sm().child(status)),
+                    )
+                    .child(
+                        div().mt_5().flex().justify_end().child(
+                            Button::new("import-now", "Import", None)
+                                .on_click(cx.listener(|this, _e, cx| this.import(cx))),
+                        ),
+                    )
+                    .children(delimited),
+            ),
+        )
+    }
+}
+
+pub struct ImportBuilder;
+
+impl StackableView for ImportBuilder {
+    fn build(&self, cx: &mut WindowContext) -> AnyView {
+        ImportView::view(cx).into()
+    }
+}
@@ -0,0 +1,156 @@
+use gpui::{
+    div, AnyView, FontWeight, ParentElement, Pixels, Render, Styled, View, ViewContext,
+    VisualContext, WindowContext,
+};
+
+use crate::{
+    errors::{ErrorState, Result},
+    models::collection::Collection,
+    state::StackableView,
+    storage::media::MediaCheck,
+    theme::Theme,
+    ui::{button::button::Button, clickable::Clickable},
+};
+
+pub struct MediaCheckView {
+    report: Result<MediaCheck>,
+    status: Option<String>,
+}
+
+impl MediaCheckView {
+    pub fn view(cx: &mut WindowContext) -> View<Self> {
+        let report = cx.global::<Collection>().check_media();
+
+        cx.new_view(|_cx| Self {
+            report,
+            status: None,
+        })
+    }
+
+    fn check(&mut self, cx: &mut ViewContext<Self>) {
+        self.report = cx.global::<Collection>().check_media();
+        self.status = None;
+        cx.notify();
+    }
+
+    fn delete_unused(&mut self, cx: &mut ViewContext<Self>) {
+        let Ok(report) = &self.report else {
+            return;
+        };
+
+        match cx.global::<Collection>().delete_media(&report.unused) {
+            Ok(deleted) => {
+                self.report = cx.global::<Collection>().check_media();
+                self.status = Some(format!("Deleted {} unused files", deleted));
+            }
+            Err(e) => ErrorState::report("Error deleting media", e, cx),
+        }
+
+        cx.notify();
+    }
+
+    fn render_section(title: &str, files: &[String], empty: &str) -> gpui::Div {
+        let rows = files
+            .iter()
+            .map(|file| div().py_1().text_sm().child(file.clone()))
+            .collect::<Vec<_>>();
+
+        let body = if files.is_empty() {
+            div().text_sm().child(empty.to_string())
+        } else {
+            div().children(rows)
+        };
+
+        div()
+            .mt_5()
+            .child(div().text_lg().font_weight(FontWeight::BOLD).child(format!(
+                "{} ({})",
+                title,
+                files.len()
+            )))
+            .child(body)
+    }
+}
+
+impl Render for MediaCheckView {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl gpui::prelude::IntoElement {
+        let theme = cx.global::<Theme>();
+
+        let body = match &self.report {
+            Ok(report) => {
+                let delete = if report.unused.is_empty() {
+                    div()
+                } else {
+                    div().mt_5().flex().justify_end().child(
+                        Button::new("delete-unused", "Delete unused files", None)
+                            .on_click(cx.listener(|this, _e, cx| this.delete_unused(cx))),
+                    )
+                };
+
+                div()
+                    .child(Self::render_section(
+                        "Missing",
+                        &report.missing,
+                        "Every file the cards use is in the media folder",
+                    ))
+                    .child(Self::render_section(
+                        "Unused",
+                        &report.unused,
+                        "Every file in the media folder is used by a card",
+                    ))
+                    .child(delete)
+            }
+            Err(e) => {
+                log::error!("Error checking media: {}", e);
+
+                div()
+                    .mt_5()
+                    .text_color(theme.red)
+                    .child(format!("Could not check media: {}", e))
+            }
+        };
+
+        div().flex().size_full().justify_center().child(
+            div().mt_20().child(
+                div()
+                    .w(Pixels(500.0))
+                    .flex()
+                    .flex_col()
+                    .text_color(theme.text)
+                    .child(
+                        div()
+                            .flex()
+                            .justify_between()
+                            .items_center()
+                            .pb_5()
+                            .border_b_1()
+                            .border_color(theme.crust)
+                            .child(
+                                div()
+                                    .text_xl()
+                                    .font_weight(FontWeight::EXTRA_BOLD)
+                                    .child("Media"),
+                            )
+                            .child(
+                                Button::new("check-media", "Check again", None)
+                                    .on_click(cx.listener(|this, _e, cx| this.check(cx))),
+                            ),
+                    )
+                    .children(
+                        self.status
+                            .clone()
+                            .map(|status| div().mt_5().text_sm().child(status)),
+                    )
+                    .child(body),
+            ),
+        )
+    }
+}
+
+pub struct MediaCheckBuilder;
+
+impl StackableView for MediaCheckBuilder {
+    fn build(&self, cx: &mut WindowContext) -> AnyView {
+        MediaCheckView::view(cx).into()
+    }
+}
@@ -1,5 +1,18 @@
 pub mod add_card;
+pub mod backup_list;
+pub mod browse;
+pub mod browse_sidebar;
+pub mod bulk_actions;
 pub mod deck;
+pub mod edit_card;
+pub mod export;
+pub mod find_replace;
+pub mod import;
+pub mod media_check;
+pub mod profile_switcher;
+pub mod rename_tag_form;
 pub mod shared;
+pub mod sync_view;
 pub mod tab_bar_container;
 pub mod tab_panel;
+pub mod tag_sidebar;
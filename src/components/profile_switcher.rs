@@ -0,0 +1,102 @@
+use gpui::{
+    div, EventEmitter, FontWeight, ParentElement, Pixels, Render, Styled, View, ViewContext,
+    VisualContext, WindowContext,
+};
+
+use crate::{
+    storage::profile::Profiles,
+    theme::Theme,
+    ui::{button::button::Button, clickable::Clickable, text_field::text_field::TextField},
+};
+
+pub enum ProfileEvent {
+    Selected(String),
+}
+
+/// Shown at startup when several profiles exist, to pick which collection to
+/// open or to create a new profile.
+pub struct ProfileSwitcher {
+    profiles: Vec<String>,
+    name_input: TextField,
+    pub error: Option<String>,
+}
+
+impl EventEmitter<ProfileEvent> for ProfileSwitcher {}
+
+impl ProfileSwitcher {
+    pub fn view(cx: &mut WindowContext) -> View<Self> {
+        let profiles = cx.global::<Profiles>().list().unwrap_or_else(|e| {
+            log::error!("Error listing profiles: {:?}", e);
+            vec![]
+        });
+
+        cx.new_view(|cx| Self {
+            profiles,
+            name_input: TextField::new(cx, "New profile name".to_string(), false),
+            error: None,
+        })
+    }
+
+    fn create(&mut self, cx: &mut ViewContext<Self>) {
+        let name = self.name_input.view.read(cx).text.trim().to_string();
+
+        if !name.is_empty() {
+            cx.emit(ProfileEvent::Selected(name));
+        }
+    }
+}
+
+impl Render for ProfileSwitcher {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl gpui::prelude::IntoElement {
+        let theme = cx.global::<Theme>();
+
+        let profiles =
+            self.profiles
+                .iter()
+                .enumerate()
+                .map(|(ix, name)| {
+                    let name = name.clone();
+
+                    div()
+                        .mt_2()
+                        .child(Button::new(("profile", ix), name.clone(), None).on_click(
+                            cx.listener(move |_this, _e, cx| {
+                                cx.emit(ProfileEvent::Selected(name.clone()));
+                            }),
+                        ))
+                })
+                .collect::<Vec<_>>();
+
+        div().flex().size_full().justify_center().child(
+            div().mt_20().child(
+                div()
+                    .w(Pixels(500.0))
+                    .flex()
+                    .flex_col()
+                    .text_color(theme.text)
+                    .child(
+                        div()
+                            .text_xl()
+                            .font_weight(FontWeight::EXTRA_BOLD)
+                            .pb_5()
+                            .border_b_1()
+                            .border_color(theme.crust)
+                            .child("Choose a profile"),
+                    )
+                    .children(
+                        self.error
+                            .clone()
+                            .map(|error| div().mt_5().text_sm().text_color(theme.red).child(error)),
+                    )
+                    .child(div().mt_5().flex().flex_col().children(profiles))
+                    .child(div().mt_6().child(self.name_input.clone()))
+                    .child(
+                        div().mt_5().flex().justify_end().child(
+                            Button::new("create-profile", "Create profile", None)
+                                .on_click(cx.listener(|this, _e, cx| this.create(cx))),
+                        ),
+                    ),
+            ),
+        )
+    }
+}
@@ -0,0 +1,118 @@
+use gpui::{
+    div, AnyView, ClickEvent, FocusHandle, FontWeight, InteractiveElement, ParentElement, Pixels,
+    Render, Styled, View, ViewContext, VisualContext, WindowContext,
+};
+
+use crate::{
+    errors::ErrorState,
+    models::collection::Collection,
+    state::{StackableView, StackableViewState},
+    theme::Theme,
+    ui::{button::button::Button, clickable::Clickable, text_field::text_field::TextField},
+};
+
+/// Renames a tag across the collection. Giving it the name of another tag
+/// merges the two.
+struct RenameTagForm {
+    tag: String,
+    name_input: TextField,
+    focus_handle: FocusHandle,
+}
+
+impl RenameTagForm {
+    pub fn view(tag: String, cx: &mut WindowContext) -> View<Self> {
+        cx.new_view(|cx: &mut ViewContext<Self>| {
+            let name_input = TextField::new(cx, "New name".to_string(), false);
+            let text = tag.clone();
+            name_input.view.update(cx, |view, _| view.text = text);
+            name_input.focus(cx);
+
+            Self {
+                tag,
+                name_input,
+                focus_handle: cx.focus_handle(),
+            }
+        })
+    }
+
+    fn save_click(&mut self, _event: &ClickEvent, cx: &mut ViewContext<Self>) {
+        self.save(cx);
+    }
+
+    fn save(&mut self, cx: &mut ViewContext<Self>) {
+        let name = self.name_input.view.read(cx).text.clone();
+
+        let tag = self.tag.clone();
+        let result =
+            cx.update_global(|collection: &mut Collection, _| collection.rename_tag(&tag, &name));
+
+        match result {
+            Ok(_) => {
+                StackableViewState::update(|state, cx| state.pop(cx), cx);
+                cx.notify();
+            }
+            Err(e) => ErrorState::report("Error renaming tag", e, cx),
+        }
+    }
+}
+
+impl Render for RenameTagForm {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl gpui::prelude::IntoElement {
+        let view = cx.view().clone();
+        let theme = cx.global::<Theme>();
+
+        div().flex().size_full().justify_center().child(
+            div().mt_20().child(
+                div()
+                    .track_focus(&self.focus_handle)
+                    .flex()
+                    .w_full()
+                    .flex_col()
+                    .text_color(theme.text)
+                    .relative()
+                    .on_key_down(move |event, wc| {
+                        view.update(wc, |form, vc| match event.keystroke.key.as_str() {
+                            "enter" => form.save(vc),
+                            "escape" => StackableViewState::update(|state, cx| state.pop(cx), vc),
+                            _ => {}
+                        })
+                    })
+                    .h_full()
+                    .child(
+                        div()
+                            .w(Pixels(500.0))
+                            .child(
+                                div()
+                                    .text_xl()
+                                    .font_weight(FontWeight::EXTRA_BOLD)
+                                    .pb_5()
+                                    .border_b_1()
+                                    .border_color(theme.crust)
+                                    .child(format!("Rename {}", self.tag)),
+                            )
+                            .child(div().mt_6().text_sm().text_color(theme.subtext0).child(
+                                "Tags below it are renamed too. Use the name of an \
+                                        existing tag to merge them.",
+                            ))
+                            .child(div().mt_2().child(self.name_input.clone()))
+                            .child(
+                                div().mt_6().justify_end().flex().child(
+                                    Button::new("btn-rename", "Rename", None)
+                                        .on_click(cx.listener(Self::save_click)),
+                                ),
+                            ),
+                    ),
+            ),
+        )
+    }
+}
+
+pub struct RenameTagBuilder {
+    pub tag: String,
+}
+
+impl StackableView for RenameTagBuilder {
+    fn build(&self, cx: &mut WindowContext) -> AnyView {
+        RenameTagForm::view(self.tag.clone(), cx).into()
+    }
+}
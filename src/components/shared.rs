@@ -1 +1,4 @@
+pub mod card_form;
+pub mod field;
 pub mod icon;
+pub mod tag_editor;
@@ -0,0 +1,145 @@
+use std::path::PathBuf;
+
+use gpui::{div, prelude::*, AnyElement, FontWeight, Pixels, SharedString, View, WindowContext};
+
+use crate::{
+    components::shared::tag_editor::TagEditor,
+    errors::ErrorState,
+    models::{collection::Collection, field::media_reference},
+    ui::{button::button::Button, text_field::text_field::TextField},
+};
+
+/// The layout of the forms that add and edit cards: the deck, the note type,
+/// an input per field, the tags, a media picker and the submit button.
+#[derive(IntoElement)]
+pub struct CardForm {
+    title: SharedString,
+    deck_input: TextField,
+    notetype: Option<AnyElement>,
+    fields: Vec<(String, TextField)>,
+    tag_editor: Option<View<TagEditor>>,
+    media: Option<(TextField, Button)>,
+    submit: Button,
+}
+
+impl CardForm {
+    pub fn new(title: impl Into<SharedString>, deck_input: TextField, submit: Button) -> Self {
+        Self {
+            title: title.into(),
+            deck_input,
+            notetype: None,
+            fields: Vec::new(),
+            tag_editor: None,
+            media: None,
+            submit,
+        }
+    }
+
+    /// Shown next to the "Type" heading: the note type, or a button to
+    /// change it.
+    pub fn notetype(mut self, notetype: impl IntoElement) -> Self {
+        self.notetype = Some(notetype.into_any_element());
+        self
+    }
+
+    /// The name and input of each field.
+    pub fn fields(mut self, fields: Vec<(String, TextField)>) -> Self {
+        self.fields = fields;
+        self
+    }
+
+    pub fn tags(mut self, tag_editor: View<TagEditor>) -> Self {
+        self.tag_editor = Some(tag_editor);
+        self
+    }
+
+    /// The path input and the button that attaches the file, see
+    /// `attach_media`.
+    pub fn media(mut self, media_input: TextField, attach: Button) -> Self {
+        self.media = Some((media_input, attach));
+        self
+    }
+}
+
+fn heading(text: impl Into<SharedString>) -> impl IntoElement {
+    div()
+        .mt_5()
+        .text_lg()
+        .font_weight(FontWeight::BOLD)
+        .child(text.into())
+}
+
+impl RenderOnce for CardForm {
+    fn render(self, _cx: &mut WindowContext) -> impl IntoElement {
+        let fields = self
+            .fields
+            .into_iter()
+            .map(|(name, input)| div().child(heading(name)).child(input));
+
+        div()
+            .w(Pixels(500.0))
+            .child(
+                div()
+                    .text_xl()
+                    .font_weight(FontWeight::EXTRA_BOLD)
+                    .child(self.title),
+            )
+            .child(
+                div()
+                    .child(heading("Deck"))
+                    .child(self.deck_input)
+                    .children(self.notetype.map(|notetype| {
+                        div()
+                            .mt_5()
+                            .flex()
+                            .justify_between()
+                            .items_center()
+                            .child(div().text_lg().font_weight(FontWeight::BOLD).child("Type"))
+                            .child(notetype)
+                    }))
+                    .children(fields)
+                    .children(
+                        self.tag_editor
+                            .map(|tag_editor| div().child(heading("Tags")).child(tag_editor)),
+                    )
+                    .children(self.media.map(|(media_input, attach)| {
+                        div().child(heading("Media")).child(
+                            div()
+                                .flex()
+                                .gap_2()
+                                .items_center()
+                                .child(div().flex_1().child(media_input))
+                                .child(attach),
+                        )
+                    }))
+                    .child(div().mt_5().flex().justify_end().child(self.submit)),
+            )
+    }
+}
+
+/// Copies the file named in `media_input` into the media folder and
+/// references it at the end of `field`.
+pub fn attach_media(media_input: &TextField, field: Option<&TextField>, cx: &mut WindowContext) {
+    let path = media_input.view.read(cx).text.trim().to_string();
+    if path.is_empty() {
+        return;
+    }
+
+    let Some(field) = field else {
+        return;
+    };
+
+    let collection = cx.global::<Collection>();
+    match collection.add_media(&PathBuf::from(path)) {
+        Ok(filename) => {
+            field.view.update(cx, |view, _| {
+                if !view.text.is_empty() {
+                    view.text.push(' ');
+                }
+                view.text.push_str(&media_reference(&filename));
+            });
+            media_input.view.update(cx, |view, _| view.text.clear());
+        }
+        Err(e) => ErrorState::report("Error adding media", e, cx),
+    }
+}
@@ -0,0 +1,183 @@
+use std::path::PathBuf;
+
+use gpui::{
+    div, img, Div, FontWeight, HighlightStyle, IntoElement, ParentElement, Pixels, RenderOnce,
+    SharedUrl, Styled, StyledText, TextStyle, WindowContext,
+};
+
+use crate::{
+    models::{
+        field::{segments, Segment},
+        type_answer::{compare, DiffKind},
+    },
+    theme::Theme,
+};
+
+/// Renders a card field, showing the images it references from the media
+/// folder, highlighting the active cloze and comparing a typed answer.
+#[derive(IntoElement)]
+pub struct Field {
+    text: String,
+    media_folder: PathBuf,
+    typed: Option<String>,
+}
+
+impl Field {
+    pub fn new(text: impl Into<String>, media_folder: PathBuf) -> Self {
+        Field {
+            text: text.into(),
+            media_folder,
+            typed: None,
+        }
+    }
+
+    /// What was typed in, to compare with the answer the field expects.
+    pub fn typed(mut self, typed: Option<String>) -> Self {
+        self.typed = typed;
+        self
+    }
+}
+
+impl Field {
+    /// Lays out a run of text in one paragraph, so that a highlighted cloze
+    /// stays inline with the text around it.
+    fn render_text(run: &[Segment], theme: &Theme) -> Div {
+        let mut text = String::new();
+        let mut highlights = Vec::new();
+
+        for segment in run {
+            match segment {
+                Segment::Text(part) => text.push_str(part),
+                Segment::Cloze(part) => {
+                    let start = text.len();
+                    text.push_str(part);
+
+                    let mut style = HighlightStyle::default();
+                    style.color = Some(theme.blue);
+                    style.font_weight = Some(FontWeight::BOLD);
+                    highlights.push((start..text.len(), style));
+                }
+                _ => {}
+            }
+        }
+
+        div().child(StyledText::new(text).with_highlights(&Self::text_style(theme), highlights))
+    }
+
+    /// Shows the expected answer merged with what was typed: correct
+    /// characters in green, missing and extra ones in red, with the missing
+    /// ones underlaid.
+    fn render_comparison(typed: &str, expected: &str, ignore: bool, theme: &Theme) -> Div {
+        let comparison = compare(typed, expected, ignore);
+        let mut text = String::new();
+        let mut highlights = Vec::new();
+
+        for part in &comparison.parts {
+            let start = text.len();
+            text.push_str(&part.text);
+
+            let mut style = HighlightStyle::default();
+            match part.kind {
+                DiffKind::Correct => style.color = Some(theme.green),
+                DiffKind::Extra => style.color = Some(theme.red),
+                DiffKind::Missing => {
+                    let mut background = theme.red;
+                    background.fade_out(0.7);
+                    style.color = Some(theme.red);
+                    style.background_color = Some(background);
+                }
+            }
+            highlights.push((start..text.len(), style));
+        }
+
+        div()
+            .py_2()
+            .font_family(theme.font_mono.clone())
+            .child(StyledText::new(text).with_highlights(&Self::text_style(theme), highlights))
+    }
+
+    fn text_style(theme: &Theme) -> TextStyle {
+        let mut style = TextStyle::default();
+        style.color = theme.text;
+        style.font_family = theme.font_sans.clone();
+        style
+    }
+}
+
+impl RenderOnce for Field {
+    fn render(self, cx: &mut WindowContext) -> impl IntoElement {
+        let theme = cx.global::<Theme>();
+
+        let mut children = Vec::new();
+        let mut run = Vec::new();
+
+        for segment in segments(&self.text) {
+            match segment {
+                Segment::Text(_) | Segment::Cloze(_) => run.push(segment),
+                // The study view shows its own input.
+                Segment::TypePrompt => {}
+                Segment::TypedAnswer {
+                    expected,
+                    ignore_case_and_diacritics,
+                } => match &self.typed {
+                    Some(typed) => {
+                        if !run.is_empty() {
+                            children.push(Self::render_text(&run, theme));
+                            run.clear();
+                        }
+
+                        children.push(Self::render_comparison(
+                            typed,
+                            expected,
+                            ignore_case_and_diacritics,
+                            theme,
+                        ));
+                    }
+                    None => run.push(Segment::Text(expected)),
+                },
+                Segment::Image(name) => {
+                    if !run.is_empty() {
+                        children.push(Self::render_text(&run, theme));
+                        run.clear();
+                    }
+
+                    let path = self.media_folder.join(name);
+
+                    if path.exists() {
+                        children.push(
+                            div().py_2().child(
+                                img(SharedUrl::file(path.to_string_lossy().to_string()))
+                                    .w(Pixels(500.0))
+                                    .h(Pixels(250.0)),
+                            ),
+                        );
+                    } else {
+                        children.push(
+                            div()
+                                .text_color(theme.red)
+                                .child(format!("Missing image: {}", name)),
+                        );
+                    }
+                }
+                Segment::Sound(name) => {
+                    if !run.is_empty() {
+                        children.push(Self::render_text(&run, theme));
+                        run.clear();
+                    }
+
+                    children.push(
+                        div()
+                            .text_color(theme.subtext0)
+                            .child(format!("Audio: {}", name)),
+                    );
+                }
+            }
+        }
+
+        if !run.is_empty() {
+            children.push(Self::render_text(&run, theme));
+        }
+
+        div().flex().flex_col().children(children)
+    }
+}
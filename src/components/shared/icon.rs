@@ -10,6 +10,7 @@ use crate::{
 #[derive(Debug, IntoElement, Clone)]
 pub enum Icon {
     BookText,
+    FileSearch,
     Settings,
     MoveLeft,
 }
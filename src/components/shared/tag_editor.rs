@@ -0,0 +1,96 @@
+use gpui::{div, prelude::*, AppContext, MouseButton, Render, View, ViewContext, WindowContext};
+
+use crate::{
+    errors::ErrorState,
+    models::{collection::Collection, tag},
+    theme::Theme,
+    ui::text_field::text_field::{TextEvent, TextField},
+};
+
+/// How many existing tags are suggested for the tag being typed.
+const SUGGESTIONS: usize = 5;
+
+/// An input for a note's tags, separated by spaces, that suggests existing
+/// tags for the one being typed.
+pub struct TagEditor {
+    pub input: TextField,
+    known: Vec<String>,
+}
+
+impl TagEditor {
+    pub fn view(tags: &[String], cx: &mut WindowContext) -> View<Self> {
+        cx.new_view(|cx: &mut ViewContext<Self>| {
+            let known = cx.global::<Collection>().all_tags().unwrap_or_else(|e| {
+                ErrorState::report("Error loading tags", e, cx);
+                Vec::new()
+            });
+
+            let input = TextField::new(cx, "Tags, separated by spaces".to_string(), false);
+            let text = tags.join(" ");
+            input.view.update(cx, |view, _| view.text = text);
+
+            cx.subscribe(&input.view, |_, _, event, cx| {
+                if let TextEvent::Input { .. } = event {
+                    cx.notify();
+                }
+            })
+            .detach();
+
+            Self { input, known }
+        })
+    }
+
+    pub fn tags(&self, cx: &AppContext) -> Vec<String> {
+        tag::parse(&self.input.view.read(cx).text)
+    }
+
+    /// The tag being typed: the text after the last space.
+    fn typed(text: &str) -> &str {
+        text.rsplit(' ').next().unwrap_or_default()
+    }
+
+    /// Replaces the tag being typed with a suggestion.
+    fn complete(&mut self, suggestion: &str, cx: &mut ViewContext<Self>) {
+        self.input.view.update(cx, |view, _| {
+            let start = view.text.len() - Self::typed(&view.text).len();
+            view.text.replace_range(start.., suggestion);
+            view.text.push(' ');
+
+            let end = view.text.chars().count();
+            view.selection = end..end;
+        });
+
+        self.input.focus(cx);
+        cx.notify();
+    }
+}
+
+impl Render for TagEditor {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let theme = cx.global::<Theme>();
+        let text = self.input.view.read(cx).text.clone();
+        let suggestions = tag::complete(Self::typed(&text), &self.known, SUGGESTIONS);
+
+        div()
+            .flex()
+            .flex_col()
+            .gap_1()
+            .child(self.input.clone())
+            .child(div().flex().flex_wrap().gap_1().text_xs().children(
+                suggestions.into_iter().map(|suggestion| {
+                    div()
+                        .px_2()
+                        .py_0p5()
+                        .rounded_md()
+                        .bg(theme.surface0)
+                        .text_color(theme.subtext1)
+                        .hover(|s| s.bg(theme.surface1))
+                        .child(suggestion.clone())
+                        .on_mouse_down(
+                            MouseButton::Left,
+                            cx.listener(move |this, _event, cx| this.complete(&suggestion, cx)),
+                        )
+                }),
+            ))
+    }
+}
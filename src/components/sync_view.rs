@@ -0,0 +1,291 @@
+use std::path::PathBuf;
+
+use gpui::{
+    div, AnyView, FontWeight, ParentElement, Pixels, Render, Styled, View, ViewContext,
+    VisualContext, WindowContext,
+};
+
+use crate::{
+    errors::{ErrorState, Result},
+    models::collection::Collection,
+    state::StackableView,
+    sync::{
+        client::{sync_collection_at, SyncMode, SyncOutcome},
+        journal::{sync_folder_at, FolderSyncOutcome},
+    },
+    theme::Theme,
+    ui::{button::button::Button, clickable::Clickable, text_field::text_field::TextField},
+};
+
+pub struct SyncView {
+    endpoint_input: TextField,
+    folder_input: TextField,
+    status: Option<String>,
+    running: bool,
+    full_sync_required: bool,
+}
+
+impl SyncView {
+    pub fn view(cx: &mut WindowContext) -> View<Self> {
+        let collection = cx.global::<Collection>();
+        let endpoint = collection.sync_endpoint().unwrap_or_else(|e| {
+            log::error!("Error reading sync endpoint: {}", e);
+            None
+        });
+        let folder = collection.sync_folder().unwrap_or_else(|e| {
+            log::error!("Error reading sync folder: {}", e);
+            None
+        });
+
+        cx.new_view(|cx| {
+            let endpoint_input = TextField::new(cx, "http://127.0.0.1:27701".to_string(), false);
+
+            if let Some(endpoint) = endpoint {
+                endpoint_input
+                    .view
+                    .update(cx, |view, _| view.text = endpoint);
+            }
+
+            let folder_input = TextField::new(cx, "Shared folder".to_string(), false);
+            if let Some(folder) = folder {
+                folder_input.view.update(cx, |view, _| {
+                    view.text = folder.to_string_lossy().to_string()
+                });
+            }
+
+            Self {
+                endpoint_input,
+                folder_input,
+                status: None,
+                running: false,
+                full_sync_required: false,
+            }
+        })
+    }
+
+    fn start(&mut self, mode: SyncMode, cx: &mut ViewContext<Self>) {
+        if self.running {
+            return;
+        }
+
+        let endpoint = self.endpoint_input.view.read(cx).text.trim().to_string();
+        if endpoint.is_empty() {
+            self.status = Some("Enter the address of a sync server first".to_string());
+            cx.notify();
+            return;
+        }
+
+        let collection = cx.global::<Collection>();
+
+        if let Err(e) = collection.set_sync_endpoint(&endpoint) {
+            ErrorState::report("Error saving sync server", e, cx);
+            return;
+        }
+
+        // A full download replaces the local collection, so keep a copy.
+        if mode == SyncMode::FullDownload {
+            if let Err(e) = collection.maybe_backup(true) {
+                ErrorState::report("Error backing up collection", e, cx);
+                return;
+            }
+        }
+
+        let path = collection.col_path.clone();
+
+        self.running = true;
+        self.status = Some("Syncing...".to_string());
+        cx.notify();
+
+        let task = cx
+            .background_executor()
+            .spawn(async move { sync_collection_at(&path, &endpoint, mode) });
+
+        cx.spawn(|this, mut cx| async move {
+            let result = task.await;
+            this.update(&mut cx, |this, cx| this.finish(result, cx))
+                .ok();
+        })
+        .detach();
+    }
+
+    fn start_folder(&mut self, cx: &mut ViewContext<Self>) {
+        if self.running {
+            return;
+        }
+
+        let folder = self.folder_input.view.read(cx).text.trim().to_string();
+        if folder.is_empty() {
+            self.status = Some("Enter the path of a shared folder first".to_string());
+            cx.notify();
+            return;
+        }
+
+        let collection = cx.global::<Collection>();
+
+        if let Err(e) = collection.set_sync_folder(&PathBuf::from(folder)) {
+            ErrorState::report("Error saving sync folder", e, cx);
+            return;
+        }
+
+        let path = collection.col_path.clone();
+
+        self.running = true;
+        self.full_sync_required = false;
+        self.status = Some("Syncing...".to_string());
+        cx.notify();
+
+        let task = cx
+            .background_executor()
+            .spawn(async move { sync_folder_at(&path) });
+
+        cx.spawn(|this, mut cx| async move {
+            let result = task.await;
+            this.update(&mut cx, |this, cx| this.finish_folder(result, cx))
+                .ok();
+        })
+        .detach();
+    }
+
+    fn finish_folder(
+        &mut self,
+        result: Result<Option<FolderSyncOutcome>>,
+        cx: &mut ViewContext<Self>,
+    ) {
+        self.running = false;
+
+        match result {
+            Ok(outcome) => {
+                cx.update_global(|collection: &mut Collection, _| collection.reload());
+
+                let outcome = outcome.unwrap_or_default();
+                self.status = Some(format!(
+                    "Wrote {} changes to the shared folder, read {}",
+                    outcome.sent, outcome.received
+                ));
+
+                cx.refresh();
+            }
+            Err(e) => {
+                self.status = None;
+                ErrorState::report("Error syncing with the shared folder", e, cx);
+            }
+        }
+
+        cx.notify();
+    }
+
+    fn finish(&mut self, result: Result<SyncOutcome>, cx: &mut ViewContext<Self>) {
+        self.running = false;
+        self.full_sync_required = false;
+
+        match result {
+            Ok(outcome) => {
+                cx.update_global(|collection: &mut Collection, _| collection.reload());
+
+                self.status = Some(match outcome {
+                    SyncOutcome::NoChanges => "Already up to date".to_string(),
+                    SyncOutcome::Synced { sent, received } => {
+                        format!("Sent {} changes, received {}", sent, received)
+                    }
+                    SyncOutcome::Uploaded => "Uploaded the collection to the server".to_string(),
+                    SyncOutcome::Downloaded => {
+                        "Downloaded the collection from the server".to_string()
+                    }
+                    SyncOutcome::FullSyncRequired => {
+                        self.full_sync_required = true;
+                        "This collection and the server's cannot be merged. Choose which one to keep; the other will be replaced.".to_string()
+                    }
+                });
+
+                cx.refresh();
+            }
+            Err(e) => {
+                self.status = None;
+                ErrorState::report("Error syncing", e, cx);
+            }
+        }
+
+        cx.notify();
+    }
+}
+
+impl Render for SyncView {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl gpui::prelude::IntoElement {
+        let theme = cx.global::<Theme>();
+
+        let full_sync_actions = if self.full_sync_required {
+            div()
+                .mt_5()
+                .flex()
+                .justify_end()
+                .gap_2()
+                .child(
+                    Button::new("full-upload", "Keep this collection", None)
+                        .on_click(cx.listener(|this, _e, cx| this.start(SyncMode::FullUpload, cx))),
+                )
+                .child(
+                    Button::new("full-download", "Keep the server's", None).on_click(
+                        cx.listener(|this, _e, cx| this.start(SyncMode::FullDownload, cx)),
+                    ),
+                )
+        } else {
+            div()
+        };
+
+        div().flex().size_full().justify_center().child(
+            div().mt_20().child(
+                div()
+                    .w(Pixels(500.0))
+                    .flex()
+                    .flex_col()
+                    .text_color(theme.text)
+                    .child(
+                        div()
+                            .text_xl()
+                            .font_weight(FontWeight::EXTRA_BOLD)
+                            .pb_5()
+                            .border_b_1()
+                            .border_color(theme.crust)
+                            .child("Sync"),
+                    )
+                    .child(div().mt_5().text_sm().child("Sync server"))
+                    .child(div().mt_2().child(self.endpoint_input.clone()))
+                    .children(
+                        self.status
+                            .clone()
+                            .map(|status| div().mt_5().text_sm().child(status)),
+                    )
+                    .child(
+                        div().mt_5().flex().justify_end().child(
+                            Button::new("sync-now", "Sync now", None).on_click(
+                                cx.listener(|this, _e, cx| this.start(SyncMode::Normal, cx)),
+                            ),
+                        ),
+                    )
+                    .child(full_sync_actions)
+                    .child(div().mt_10().text_sm().child("Shared folder"))
+                    .child(
+                        div()
+                            .mt_2()
+                            .text_xs()
+                            .child("Sync through a network drive or a folder kept in step by a tool such as Syncthing, instead of a server."),
+                    )
+                    .child(div().mt_2().child(self.folder_input.clone()))
+                    .child(
+                        div().mt_5().flex().justify_end().child(
+                            Button::new("sync-folder", "Sync folder", None)
+                                .on_click(cx.listener(|this, _e, cx| this.start_folder(cx))),
+                        ),
+                    ),
+            ),
+        )
+    }
+}
+
+pub struct SyncBuilder;
+
+impl StackableView for SyncBuilder {
+    fn build(&self, cx: &mut WindowContext) -> AnyView {
+        SyncView::view(cx).into()
+    }
+}
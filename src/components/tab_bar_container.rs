@@ -32,9 +32,10 @@ impl TabBarView {
     }
 }
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy)]
 pub enum TabEvent {
     Deck,
+    Browse,
 }
 
 impl EventEmitter<TabEvent> for TabBarView {}
@@ -47,34 +48,39 @@ impl RenderOnce for TabBarContainer {
     fn render(self, cx: &mut WindowContext) -> impl IntoElement {
         let theme = cx.global::<Theme>();
 
-        let view_clone1 = Rc::clone(&self.view);
-
         let bg_hover = theme.overlay0;
-        let selected_deck_clone = Rc::clone(&self.selected);
+        let selected = *self.selected.borrow();
+
+        let tab = |event: TabEvent, icon: Icon| {
+            let view = Rc::clone(&self.view);
+            let selected_tab = Rc::clone(&self.selected);
+
+            div()
+                .group("tab_bar")
+                .px_2()
+                .py_2()
+                .when(selected == event, |this| this.bg(bg_hover))
+                .rounded_md()
+                .hover(|s| s.rounded_md().bg(bg_hover))
+                .text_color(theme.text)
+                .child(icon)
+                .on_mouse_down(gpui::MouseButton::Left, move |_ev, cx| {
+                    view.borrow_mut().update(cx, |_e, cx| {
+                        cx.emit(event);
+                        cx.notify();
+                        *selected_tab.borrow_mut() = event;
+                    })
+                })
+        };
 
         div()
             .bg(theme.mantle)
             .border_t_1()
             .flex()
             .flex_col()
+            .gap_1()
             .p_2()
-            .child(
-                div()
-                    .group("tab_bar")
-                    .px_2()
-                    .py_2()
-                    .bg(bg_hover)
-                    .rounded_md()
-                    .hover(|s| s.rounded_md().bg(bg_hover))
-                    .text_color(theme.text)
-                    .child(Icon::BookText)
-                    .on_mouse_down(gpui::MouseButton::Left, move |_ev, cx| {
-                        view_clone1.borrow_mut().update(cx, |_e, cx| {
-                            cx.emit(TabEvent::Deck);
-                            cx.notify();
-                            *selected_deck_clone.borrow_mut() = TabEvent::Deck;
-                        })
-                    }),
-            )
+            .child(tab(TabEvent::Deck, Icon::BookText))
+            .child(tab(TabEvent::Browse, Icon::FileSearch))
     }
 }
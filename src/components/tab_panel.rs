@@ -4,6 +4,7 @@ use crate::state::TabView;
 use gpui::{div, prelude::*, AnyView, ViewContext};
 
 use super::{
+    browse::BrowseView,
     deck::DeckView,
     tab_bar_container::{TabBarContainer, TabEvent},
 };
@@ -35,6 +36,9 @@ impl TabView for TabPanelBuilder {
                     TabEvent::Deck => {
                         subscriber.content = DeckView::view(cx).into();
                     }
+                    TabEvent::Browse => {
+                        subscriber.content = BrowseView::view(cx).into();
+                    }
                 },
             )
             .detach();
@@ -0,0 +1,162 @@
+use std::collections::HashSet;
+
+use gpui::{div, prelude::*, AnchorCorner, Pixels, Render, View, ViewContext, WindowContext};
+
+use crate::{
+    components::rename_tag_form::RenameTagBuilder,
+    errors::ErrorState,
+    models::{collection::Collection, tag::TagNode},
+    state::StackableViewState,
+    theme::Theme,
+    ui::{
+        button::button::Button, context_menu::ContextMenu, list::list_item::ListItem,
+        popover_menu::popover_menu,
+    },
+};
+
+/// The tags of the collection as a tree, with the number of cards under each
+/// tag, and menus to rename, merge and delete them.
+pub struct TagSidebar {
+    /// Tags whose children are hidden.
+    collapsed: HashSet<String>,
+}
+
+impl TagSidebar {
+    pub fn view(cx: &mut WindowContext) -> View<Self> {
+        cx.new_view(|_| Self {
+            collapsed: HashSet::new(),
+        })
+    }
+
+    fn toggle(&mut self, tag: &str, cx: &mut ViewContext<Self>) {
+        if !self.collapsed.remove(tag) {
+            self.collapsed.insert(tag.to_string());
+        }
+        cx.notify();
+    }
+
+    /// Lists the nodes that are not inside a collapsed tag, with their depth.
+    fn visible<'a>(&self, nodes: &'a [TagNode], depth: usize, out: &mut Vec<(usize, &'a TagNode)>) {
+        for node in nodes {
+            out.push((depth, node));
+
+            if !self.collapsed.contains(&node.tag) {
+                self.visible(&node.children, depth + 1, out);
+            }
+        }
+    }
+
+    fn build_tag_menu(view: View<Self>, tag: String, cx: &mut WindowContext) -> View<ContextMenu> {
+        ContextMenu::build(cx, move |menu, _wc| {
+            let rename_tag = tag.clone();
+
+            menu.entry("Rename or merge", None, move |wc| {
+                let tag = rename_tag.clone();
+                StackableViewState::update(
+                    |state, cx| state.push(RenameTagBuilder { tag }, cx),
+                    wc,
+                );
+            })
+            .entry("Delete", None, move |wc| {
+                let result =
+                    wc.update_global(|collection: &mut Collection, _| collection.delete_tag(&tag));
+                if let Err(e) = result {
+                    ErrorState::report("Error deleting tag", e, wc);
+                }
+                view.update(wc, |_, cx| cx.notify());
+            })
+        })
+    }
+}
+
+impl Render for TagSidebar {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let view = cx.view().clone();
+
+        let (tree, load_error) = match cx.global::<Collection>().tag_tree() {
+            Ok(tree) => (tree, None),
+            Err(e) => {
+                log::error!("Error loading tags: {}", e);
+                (Vec::new(), Some(format!("Could not load tags: {}", e)))
+            }
+        };
+
+        let mut rows = Vec::new();
+        self.visible(&tree, 0, &mut rows);
+
+        let theme = cx.global::<Theme>();
+
+        let items = rows
+            .into_iter()
+            .enumerate()
+            .map(|(ix, (depth, node))| {
+                let toggle_tag = node.tag.clone();
+                let menu_tag = node.tag.clone();
+                let menu_view = view.clone();
+
+                ListItem::new(("tag", ix))
+                    .indent_level(depth)
+                    .toggle(
+                        (!node.children.is_empty()).then(|| !self.collapsed.contains(&node.tag)),
+                    )
+                    .on_toggle(cx.listener(move |this, _event, cx| this.toggle(&toggle_tag, cx)))
+                    .child(div().text_sm().child(node.name.clone()))
+                    .end_slot(
+                        div()
+                            .flex()
+                            .items_center()
+                            .gap_1()
+                            .child(
+                                div()
+                                    .text_xs()
+                                    .text_color(theme.subtext0)
+                                    .child(node.cards.to_string()),
+                            )
+                            .child(
+                                popover_menu(("tag-menu", ix))
+                                    .menu(move |cx| {
+                                        Some(Self::build_tag_menu(
+                                            menu_view.clone(),
+                                            menu_tag.clone(),
+                                            cx,
+                                        ))
+                                    })
+                                    .anchor(AnchorCorner::TopRight)
+                                    .trigger(Button::new(("tag-menu-btn", ix), "…", None)),
+                            ),
+                    )
+            })
+            .collect::<Vec<_>>();
+
+        div()
+            .w(Pixels(240.0))
+            .h_full()
+            .flex()
+            .flex_col()
+            .p_2()
+            .bg(theme.mantle)
+            .border_l_1()
+            .border_color(theme.crust)
+            .text_color(theme.text)
+            .child(
+                div()
+                    .pb_2()
+                    .mb_2()
+                    .border_b_1()
+                    .border_color(theme.crust)
+                    .text_sm()
+                    .font_weight(gpui::FontWeight::BOLD)
+                    .child("Tags"),
+            )
+            .children(load_error.map(|error| div().text_xs().text_color(theme.red).child(error)))
+            .when(tree.is_empty(), |this| {
+                this.child(
+                    div()
+                        .text_xs()
+                        .text_color(theme.subtext0)
+                        .child("No tags yet"),
+                )
+            })
+            .children(items)
+    }
+}
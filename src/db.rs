@@ -1,11 +1,28 @@
-use rusqlite::{Connection, Result};
+use std::collections::HashSet;
+
+use rusqlite::{params, Connection, Result};
+
+use crate::{
+    repositories::{
+        guid::new_guid,
+        notetype::{Notetype, BASIC_GUID},
+        timestamps::mtime_now,
+    },
+    search::fts,
+};
+
+/// Bumped whenever `upgrade` learns a new step.
+pub const SCHEMA_VERSION: u32 = 8;
 
 pub fn init_db(conn: &Connection) -> Result<()> {
     conn.execute(
         "CREATE TABLE IF NOT EXISTS decks (
             id INTEGER PRIMARY KEY,
             name TEXT NOT NULL,
-            creation_time DateTime NOT NULL
+            creation_time DateTime NOT NULL,
+            guid TEXT NOT NULL DEFAULT '',
+            mtime INTEGER NOT NULL DEFAULT 0,
+            usn INTEGER NOT NULL DEFAULT -1
         )",
         [],
     )?;
@@ -22,7 +39,43 @@ pub fn init_db(conn: &Connection) -> Result<()> {
             interval INTEGER NOT NULL,
             queue integer NOT NULL,
             due integer NOT NULL,
-            data text NOT NULL
+            data text NOT NULL,
+            guid TEXT NOT NULL DEFAULT '',
+            mtime INTEGER NOT NULL DEFAULT 0,
+            usn INTEGER NOT NULL DEFAULT -1,
+            note_id INTEGER NOT NULL DEFAULT 0,
+            ord INTEGER NOT NULL DEFAULT 0,
+            buried_until INTEGER NOT NULL DEFAULT 0,
+            suspended INTEGER NOT NULL DEFAULT 0,
+            flags INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS notetypes (
+            id INTEGER PRIMARY KEY,
+            guid TEXT NOT NULL UNIQUE,
+            name TEXT NOT NULL,
+            kind INTEGER NOT NULL,
+            fields TEXT NOT NULL,
+            templates TEXT NOT NULL,
+            mtime INTEGER NOT NULL,
+            usn INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Notetype::add_stock(conn)?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS notes (
+            id INTEGER PRIMARY KEY,
+            guid TEXT NOT NULL UNIQUE,
+            notetype_id INTEGER NOT NULL,
+            fields TEXT NOT NULL,
+            tags TEXT NOT NULL DEFAULT '',
+            mtime INTEGER NOT NULL,
+            usn INTEGER NOT NULL
         )",
         [],
     )?;
@@ -35,5 +88,359 @@ pub fn init_db(conn: &Connection) -> Result<()> {
         [],
     )?;
 
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS revlog (
+            id INTEGER PRIMARY KEY,
+            card_id INTEGER NOT NULL,
+            usn INTEGER NOT NULL,
+            ease INTEGER NOT NULL,
+            interval INTEGER NOT NULL,
+            last_interval INTEGER NOT NULL
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS graves (
+            guid TEXT NOT NULL,
+            kind INTEGER NOT NULL,
+            usn INTEGER NOT NULL,
+            mtime INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS sync_state (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            endpoint TEXT,
+            last_usn INTEGER NOT NULL DEFAULT 0,
+            server_schema INTEGER NOT NULL DEFAULT 0,
+            full_sync_required INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )?;
+    conn.execute("INSERT OR IGNORE INTO sync_state (id) VALUES (1)", [])?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS media (
+            hash TEXT PRIMARY KEY,
+            filename TEXT NOT NULL UNIQUE,
+            size INTEGER NOT NULL
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS folder_sync (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            folder TEXT,
+            device TEXT NOT NULL DEFAULT '',
+            exported_until INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )?;
+    conn.execute("INSERT OR IGNORE INTO folder_sync (id) VALUES (1)", [])?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS journal_progress (
+            device TEXT PRIMARY KEY,
+            position INTEGER NOT NULL
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS saved_searches (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            query TEXT NOT NULL,
+            position INTEGER NOT NULL
+        )",
+        [],
+    )?;
+
+    fts::create_index(conn)?;
+
+    upgrade(conn)
+}
+
+/// Brings a collection created by an older version up to `SCHEMA_VERSION`.
+fn upgrade(conn: &Connection) -> Result<()> {
+    let version: u32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+    if version < 1 {
+        for table in ["decks", "cards"] {
+            add_column_if_missing(conn, table, "guid", "TEXT NOT NULL DEFAULT ''")?;
+            add_column_if_missing(conn, table, "mtime", "INTEGER NOT NULL DEFAULT 0")?;
+            add_column_if_missing(conn, table, "usn", "INTEGER NOT NULL DEFAULT -1")?;
+            backfill_guids(conn, table)?;
+
+            conn.execute(
+                &format!(
+                    "CREATE UNIQUE INDEX IF NOT EXISTS ix_{table}_guid ON {table} (guid)",
+                    table = table
+                ),
+                [],
+            )?;
+        }
+    }
+
+    if version < 2 {
+        add_column_if_missing(conn, "graves", "mtime", "INTEGER NOT NULL DEFAULT 0")?;
+    }
+
+    if version < 3 {
+        add_column_if_missing(conn, "cards", "note_id", "INTEGER NOT NULL DEFAULT 0")?;
+        add_column_if_missing(conn, "cards", "ord", "INTEGER NOT NULL DEFAULT 0")?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS ix_cards_note_id ON cards (note_id)",
+            [],
+        )?;
+        move_cards_to_notes(conn)?;
+    }
+
+    if version < 4 {
+        add_column_if_missing(conn, "cards", "buried_until", "INTEGER NOT NULL DEFAULT 0")?;
+    }
+
+    if version < 5 {
+        add_column_if_missing(conn, "notes", "tags", "TEXT NOT NULL DEFAULT ''")?;
+    }
+
+    if version < 6 {
+        fts::rebuild_index(conn)?;
+    }
+
+    if version < 7 {
+        add_column_if_missing(conn, "cards", "suspended", "INTEGER NOT NULL DEFAULT 0")?;
+        add_column_if_missing(conn, "cards", "flags", "INTEGER NOT NULL DEFAULT 0")?;
+    }
+
+    if version < 8 {
+        rename_clashing_decks(conn)?;
+        conn.execute(
+            "CREATE UNIQUE INDEX IF NOT EXISTS ix_decks_name ON decks (name COLLATE NOCASE)",
+            [],
+        )?;
+    }
+
+    if version < SCHEMA_VERSION {
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+    }
+
     Ok(())
 }
+
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, decl: &str) -> Result<()> {
+    let exists: bool = conn.query_row(
+        &format!(
+            "SELECT COUNT(1) > 0 FROM pragma_table_info('{}') WHERE name = ?",
+            table
+        ),
+        [column],
+        |row| row.get(0),
+    )?;
+
+    if !exists {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, decl),
+            [],
+        )?;
+    }
+
+    Ok(())
+}
+
+fn backfill_guids(conn: &Connection, table: &str) -> Result<()> {
+    let ids = conn
+        .prepare(&format!("SELECT id FROM {} WHERE guid = ''", table))?
+        .query_map([], |row| row.get::<_, u32>(0))?
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut stmt = conn.prepare(&format!("UPDATE {} SET guid = ? WHERE id = ?", table))?;
+    for id in ids {
+        stmt.execute(params![new_guid(), id])?;
+    }
+
+    Ok(())
+}
+
+/// Deck names are unique regardless of case. Of the decks whose names only
+/// differ in case, the oldest keeps its name and the others get a number, as
+/// in `french 2`. The renamed decks are sent at the next sync.
+fn rename_clashing_decks(conn: &Connection) -> Result<()> {
+    let decks = conn
+        .prepare("SELECT id, name FROM decks ORDER BY id")?
+        .query_map([], |row| {
+            Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut taken: HashSet<String> = decks
+        .iter()
+        .map(|(_, name)| name.to_ascii_lowercase())
+        .collect();
+    let mut kept = HashSet::new();
+
+    for (id, name) in decks {
+        if kept.insert(name.to_ascii_lowercase()) {
+            continue;
+        }
+
+        let renamed = (2..)
+            .map(|n| format!("{} {}", name, n))
+            .find(|candidate| !taken.contains(&candidate.to_ascii_lowercase()))
+            .unwrap_or_default();
+        taken.insert(renamed.to_ascii_lowercase());
+
+        conn.execute(
+            "UPDATE decks SET name = ?, mtime = ?, usn = -1 WHERE id = ?",
+            params![renamed, mtime_now(), id],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Gives every card that predates notes a "Basic" note holding its question
+/// and answer. The note takes the card's guid, so that collections synced
+/// before the upgrade end up with the same notes.
+fn move_cards_to_notes(conn: &Connection) -> Result<()> {
+    let basic_id: u32 = conn.query_row(
+        "SELECT id FROM notetypes WHERE guid = ?",
+        [BASIC_GUID],
+        |row| row.get(0),
+    )?;
+
+    conn.execute(
+        "INSERT INTO notes (guid, notetype_id, fields, mtime, usn)
+        SELECT guid, ?, question || char(31) || answer, mtime, -1 FROM cards WHERE note_id = 0",
+        [basic_id],
+    )?;
+
+    // The cards are sent again so that the other side learns their notes.
+    conn.execute(
+        "UPDATE cards SET note_id = (SELECT id FROM notes WHERE notes.guid = cards.guid), ord = 0, usn = -1
+        WHERE note_id = 0",
+        [],
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn upgrade_adds_guids_to_existing_rows() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE decks (
+                id INTEGER PRIMARY KEY,
+                name TEXT NOT NULL,
+                creation_time DateTime NOT NULL
+            );
+            INSERT INTO decks (name, creation_time) VALUES ('a', '2024-01-01T00:00:00Z');
+            INSERT INTO decks (name, creation_time) VALUES ('b', '2024-01-01T00:00:00Z');",
+        )
+        .unwrap();
+
+        init_db(&conn).unwrap();
+
+        let guids = conn
+            .prepare("SELECT guid FROM decks ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get::<_, String>(0))
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(guids.len(), 2);
+        assert!(guids.iter().all(|guid| !guid.is_empty()));
+        assert_ne!(guids[0], guids[1]);
+
+        let version: u32 = conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, SCHEMA_VERSION);
+
+        // Running it again must leave the existing guids alone.
+        init_db(&conn).unwrap();
+        let first: String = conn
+            .query_row("SELECT guid FROM decks WHERE name = 'a'", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(first, guids[0]);
+    }
+
+    #[test]
+    fn upgrade_renames_decks_that_differ_in_case() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+        conn.execute_batch(
+            "DROP INDEX ix_decks_name;
+            PRAGMA user_version = 7;
+            INSERT INTO decks (name, creation_time, guid) VALUES ('French', '2024-01-01T00:00:00Z', 'a');
+            INSERT INTO decks (name, creation_time, guid) VALUES ('french', '2024-01-01T00:00:00Z', 'b');
+            INSERT INTO decks (name, creation_time, guid) VALUES ('FRENCH', '2024-01-01T00:00:00Z', 'c');
+            INSERT INTO decks (name, creation_time, guid) VALUES ('french 2', '2024-01-01T00:00:00Z', 'd');",
+        )
+        .unwrap();
+
+        init_db(&conn).unwrap();
+
+        let names = conn
+            .prepare("SELECT name FROM decks ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get::<_, String>(0))
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(names, vec!["French", "french 3", "FRENCH 4", "french 2"]);
+
+        assert!(conn
+            .execute(
+                "INSERT INTO decks (name, creation_time, guid) VALUES ('FRENCH 2', '2024-01-01T00:00:00Z', 'e')",
+                [],
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn upgrade_moves_cards_to_basic_notes() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE cards (
+                id INTEGER PRIMARY KEY,
+                question TEXT NOT NULL,
+                answer TEXT NOT NULL,
+                creation_time DateTime NOT NULL,
+                last_studied_time DateTime,
+                deck_id INTEGER NOT NULL,
+                ef FLOAT NOT NULL,
+                interval INTEGER NOT NULL,
+                queue integer NOT NULL,
+                due integer NOT NULL,
+                data text NOT NULL
+            );
+            INSERT INTO cards (question, answer, creation_time, deck_id, ef, interval, queue, due, data)
+            VALUES ('Capital of France?', 'Paris', '2024-01-01T00:00:00Z', 1, 2.5, 1, 0, 0, '{}');",
+        )
+        .unwrap();
+
+        init_db(&conn).unwrap();
+
+        let (fields, notetype): (String, String) = conn
+            .query_row(
+                "SELECT n.fields, t.name FROM cards c
+                JOIN notes n ON n.id = c.note_id JOIN notetypes t ON t.id = n.notetype_id",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(fields, "Capital of France?\x1fParis");
+        assert_eq!(notetype, "Basic");
+    }
+}
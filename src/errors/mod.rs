@@ -1,6 +1,146 @@
+use std::fmt;
+
+use gpui::{AppContext, Global, SharedString};
+
 pub type Result<T, E = NgurraError> = std::result::Result<T, E>;
 
 #[derive(Debug)]
 pub enum NgurraError {
+    Sqlite(rusqlite::Error),
+    Io(std::io::Error),
+    Parse(String),
+    Import(String),
+    Scheduling(String),
+    Sync(String),
+    /// A note that would not make any card, or whose cards could not be made.
+    Note(String),
+    /// A deck that cannot be created or renamed, such as one without a name.
+    Deck(String),
+    /// A tag that cannot be used, such as an empty one.
+    Tag(String),
+    /// A search that cannot be parsed.
+    Search(crate::search::SearchError),
+    /// A saved search that cannot be saved, such as one without a name.
+    SavedSearch(String),
+    /// The collection was opened read-only because another process holds it.
+    CollectionReadOnly,
+}
+
+impl fmt::Display for NgurraError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NgurraError::Sqlite(e) => write!(f, "database error: {}", e),
+            NgurraError::Io(e) => write!(f, "file error: {}", e),
+            NgurraError::Parse(msg) => write!(f, "could not parse {}", msg),
+            NgurraError::Import(msg) => write!(f, "import failed: {}", msg),
+            NgurraError::Scheduling(msg) => write!(f, "scheduling failed: {}", msg),
+            NgurraError::Sync(msg) => write!(f, "sync failed: {}", msg),
+            NgurraError::Note(msg) => write!(f, "could not save note: {}", msg),
+            NgurraError::Deck(msg) => write!(f, "invalid deck: {}", msg),
+            NgurraError::Tag(msg) => write!(f, "invalid tag: {}", msg),
+            NgurraError::Search(e) => write!(f, "invalid search: {}", e),
+            NgurraError::SavedSearch(msg) => write!(f, "invalid saved search: {}", msg),
+            NgurraError::CollectionReadOnly => {
+                write!(f, "the collection is open in another process")
+            }
+        }
+    }
+}
+
+impl std::error::Error for NgurraError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NgurraError::Sqlite(e) => Some(e),
+            NgurraError::Io(e) => Some(e),
+            NgurraError::Search(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for NgurraError {
+    fn from(e: rusqlite::Error) -> Self {
+        NgurraError::Sqlite(e)
+    }
+}
+
+impl From<std::io::Error> for NgurraError {
+    fn from(e: std::io::Error) -> Self {
+        NgurraError::Io(e)
+    }
+}
+
+impl From<crate::search::SearchError> for NgurraError {
+    fn from(e: crate::search::SearchError) -> Self {
+        NgurraError::Search(e)
+    }
+}
+
+impl From<chrono::ParseError> for NgurraError {
+    fn from(e: chrono::ParseError) -> Self {
+        NgurraError::Parse(format!("date: {}", e))
+    }
+}
+
+impl From<regex::Error> for NgurraError {
+    fn from(e: regex::Error) -> Self {
+        NgurraError::Parse(format!("regex: {}", e))
+    }
+}
+
+impl From<serde_json::Error> for NgurraError {
+    fn from(e: serde_json::Error) -> Self {
+        NgurraError::Parse(format!("JSON: {}", e))
+    }
+}
+
+impl From<zip::result::ZipError> for NgurraError {
+    fn from(e: zip::result::ZipError) -> Self {
+        match e {
+            zip::result::ZipError::Io(e) => NgurraError::Io(e),
+            e => NgurraError::Parse(format!("zip file: {}", e)),
+        }
+    }
+}
+
+impl From<ureq::Error> for NgurraError {
+    fn from(e: ureq::Error) -> Self {
+        match e {
+            ureq::Error::Status(status, response) => NgurraError::Sync(format!(
+                "server replied {}: {}",
+                status,
+                response.into_string().unwrap_or_default()
+            )),
+            ureq::Error::Transport(transport) => NgurraError::Sync(transport.to_string()),
+        }
+    }
+}
+
+/// The most recent failure, shown to the user until it is dismissed.
+#[derive(Default)]
+pub struct ErrorState {
+    pub message: Option<SharedString>,
+}
+
+impl Global for ErrorState {}
+
+impl ErrorState {
+    pub fn init(cx: &mut AppContext) {
+        cx.set_global(ErrorState::default());
+    }
+
+    pub fn report(context: &str, error: impl fmt::Display, cx: &mut AppContext) {
+        let message = format!("{}: {}", context, error);
+        log::error!("{}", message);
+
+        cx.set_global(ErrorState {
+            message: Some(message.into()),
+        });
+        cx.refresh();
+    }
 
+    pub fn dismiss(cx: &mut AppContext) {
+        cx.set_global(ErrorState::default());
+        cx.refresh();
+    }
 }
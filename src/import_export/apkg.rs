@@ -0,0 +1,1011 @@
+//! Importing Anki packages. An `.apkg` file holds some decks and a `.colpkg`
+//! file a whole collection; both are zip files holding the collection's
+//! SQLite database, a `media` file naming the numbered media entries, and
+//! those entries.
+//!
+//! Notes are matched to the collection's by guid. A note that is already
+//! there only changes if the package's copy was edited since, and the same
+//! goes for the scheduling of each of its cards, so importing a package again
+//! leaves cards studied since the first import alone.
+
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use serde::Deserialize;
+use zip::{result::ZipError, ZipArchive};
+
+use crate::{
+    errors::{NgurraError, Result},
+    models::{
+        deck,
+        field::rename_media_references,
+        note::{render_card, update_note},
+    },
+    repositories::{
+        flash_card::CardQueue,
+        guid::{new_guid, PENDING_USN},
+        note::{split_fields, split_tags, Note},
+        notetype::{CardTemplate, Notetype, NotetypeKind},
+        timestamps::mtime_now,
+    },
+    storage::media,
+};
+
+use super::ImportSummary;
+
+/// The database of packages written by Anki 2.1 and earlier, in the order
+/// they are looked for. When both are present, the first one is the real
+/// collection and the second a stub for older versions.
+const LEGACY_COLLECTION: &str = "collection.anki21";
+const OLDEST_COLLECTION: &str = "collection.anki2";
+/// The compressed database of newer packages, which this importer cannot
+/// read. Such packages carry a stub `collection.anki2` too.
+const NEWER_COLLECTION: &str = "collection.anki21b";
+
+/// Where Anki puts cards whose deck is missing from the package.
+const DEFAULT_DECK: &str = "Default";
+
+/// Note types are given this guid prefix and the Anki id, so that the next
+/// import of the same note type finds it.
+const NOTETYPE_GUID_PREFIX: &str = "anki-";
+
+#[derive(Deserialize)]
+struct AnkiNotetype {
+    name: String,
+    /// 1 for cloze note types.
+    #[serde(rename = "type", default)]
+    kind: u8,
+    flds: Vec<AnkiField>,
+    tmpls: Vec<AnkiTemplate>,
+}
+
+#[derive(Deserialize)]
+struct AnkiField {
+    name: String,
+    #[serde(default)]
+    ord: u32,
+}
+
+#[derive(Deserialize)]
+struct AnkiTemplate {
+    name: String,
+    qfmt: String,
+    afmt: String,
+    #[serde(default)]
+    ord: u32,
+}
+
+#[derive(Deserialize)]
+struct AnkiDeck {
+    name: String,
+}
+
+struct AnkiNote {
+    id: i64,
+    guid: String,
+    notetype_id: i64,
+    mtime: i64,
+    tags: String,
+    fields: String,
+}
+
+struct AnkiCard {
+    /// Milliseconds since the epoch at which the card was created.
+    id: i64,
+    note_id: i64,
+    deck_id: i64,
+    ord: u32,
+    mtime: i64,
+    /// 0 new, 1 learning, 2 review, 3 relearning.
+    kind: i64,
+    /// As `kind`, or -1 when suspended and -2 or -3 when buried.
+    queue: i64,
+    /// The position of a new card, or the day a review card is due on.
+    due: i64,
+    interval: i64,
+    /// The ease in permille.
+    factor: i64,
+    /// The deck and due date to return to when in a filtered deck.
+    original_due: i64,
+    original_deck_id: i64,
+    flags: i64,
+}
+
+struct AnkiReview {
+    id: i64,
+    card_id: i64,
+    ease: u8,
+    interval: i64,
+    last_interval: i64,
+}
+
+/// What a package's database holds.
+struct Package {
+    /// Days since the package's collection was created, which review cards
+    /// count their due date in.
+    today: i64,
+    notetypes: HashMap<i64, AnkiNotetype>,
+    decks: HashMap<i64, String>,
+    notes: Vec<AnkiNote>,
+    cards: Vec<AnkiCard>,
+    reviews: Vec<AnkiReview>,
+}
+
+/// A card's scheduling, converted to the collection's queues and days.
+struct Scheduling {
+    queue: CardQueue,
+    due: u32,
+    interval: u32,
+    ef: f32,
+    suspended: bool,
+    flags: u8,
+}
+
+impl AnkiCard {
+    /// The deck the card belongs in, outside of any filtered deck.
+    fn home_deck_id(&self) -> i64 {
+        if self.original_deck_id != 0 {
+            self.original_deck_id
+        } else {
+            self.deck_id
+        }
+    }
+
+    /// `package_today` and `today` are the current day of the package's
+    /// collection and of this one, which review due dates are moved between.
+    fn scheduling(&self, package_today: i64, today: u32) -> Scheduling {
+        let due = if self.original_deck_id != 0 && self.original_due != 0 {
+            self.original_due
+        } else {
+            self.due
+        };
+
+        let (queue, due) = match self.kind {
+            0 => (CardQueue::New, due),
+            2 => (CardQueue::Review, today as i64 + due - package_today),
+            // Anki times learning steps in seconds; here they are due again
+            // right away.
+            _ => (CardQueue::Learning, today as i64),
+        };
+
+        Scheduling {
+            queue,
+            due: due.max(0) as u32,
+            interval: self.interval.max(1) as u32,
+            ef: if self.factor > 0 {
+                self.factor as f32 / 1000.0
+            } else {
+                2.5
+            },
+            suspended: self.queue == -1,
+            flags: (self.flags & 0b111) as u8,
+        }
+    }
+}
+
+/// A database outside the collection, removed once the import or export is
+/// done with it.
+pub(super) struct TempFile(pub(super) PathBuf);
+
+impl TempFile {
+    pub(super) fn new(purpose: &str) -> Self {
+        Self(std::env::temp_dir().join(format!("ngurra-{}-{}.anki2", purpose, new_guid())))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.0) {
+            log::warn!("Error removing {:?}: {}", self.0, e);
+        }
+    }
+}
+
+/// Copies the package's database out of the zip file, since SQLite can only
+/// open files.
+fn extract_collection(archive: &mut ZipArchive<File>) -> Result<TempFile> {
+    let names = archive.file_names().collect::<Vec<_>>();
+    let entry = if names.contains(&LEGACY_COLLECTION) {
+        LEGACY_COLLECTION
+    } else if names.contains(&NEWER_COLLECTION) {
+        return Err(NgurraError::Import(
+            "the package uses the format of Anki 2.1.50 and later; export it again with \
+            \"Support older Anki versions\" ticked"
+                .to_string(),
+        ));
+    } else if names.contains(&OLDEST_COLLECTION) {
+        OLDEST_COLLECTION
+    } else {
+        return Err(NgurraError::Import(
+            "the file is not an Anki package: it holds no collection".to_string(),
+        ));
+    };
+
+    let temp = TempFile::new("import");
+    io::copy(&mut archive.by_name(entry)?, &mut File::create(&temp.0)?)?;
+
+    Ok(temp)
+}
+
+fn read_package(db: &Connection) -> Result<Package> {
+    let (created, notetypes, decks): (i64, String, String) =
+        db.query_row("SELECT crt, models, decks FROM col", [], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?;
+
+    let keyed_by_id = |json: &str| -> Result<Vec<(i64, serde_json::Value)>> {
+        let map: HashMap<String, serde_json::Value> = serde_json::from_str(json)?;
+        Ok(map
+            .into_iter()
+            .filter_map(|(id, value)| Some((id.parse().ok()?, value)))
+            .collect())
+    };
+
+    let notetypes = keyed_by_id(&notetypes)?
+        .into_iter()
+        .map(|(id, value)| Ok((id, serde_json::from_value(value)?)))
+        .collect::<Result<HashMap<_, _>>>()?;
+    let decks = keyed_by_id(&decks)?
+        .into_iter()
+        .map(|(id, value)| Ok((id, serde_json::from_value::<AnkiDeck>(value)?.name)))
+        .collect::<Result<HashMap<_, _>>>()?;
+
+    let notes = db
+        .prepare("SELECT id, guid, mid, mod, tags, flds FROM notes ORDER BY id")?
+        .query_map([], |row| {
+            Ok(AnkiNote {
+                id: row.get(0)?,
+                guid: row.get(1)?,
+                notetype_id: row.get(2)?,
+                mtime: row.get(3)?,
+                tags: row.get(4)?,
+                fields: row.get(5)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let cards = db
+        .prepare(
+            "SELECT id, nid, did, ord, mod, type, queue, due, ivl, factor, odue, odid, flags
+            FROM cards ORDER BY nid, ord",
+        )?
+        .query_map([], |row| {
+            Ok(AnkiCard {
+                id: row.get(0)?,
+                note_id: row.get(1)?,
+                deck_id: row.get(2)?,
+                ord: row.get(3)?,
+                mtime: row.get(4)?,
+                kind: row.get(5)?,
+                queue: row.get(6)?,
+                due: row.get(7)?,
+                interval: row.get(8)?,
+                factor: row.get(9)?,
+                original_due: row.get(10)?,
+                original_deck_id: row.get(11)?,
+                flags: row.get(12)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let reviews = db
+        .prepare("SELECT id, cid, ease, ivl, lastIvl FROM revlog ORDER BY id")?
+        .query_map([], |row| {
+            Ok(AnkiReview {
+                id: row.get(0)?,
+                card_id: row.get(1)?,
+                ease: row.get(2)?,
+                interval: row.get(3)?,
+                last_interval: row.get(4)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(Package {
+        today: (Utc::now().timestamp() - created).max(0) / 86_400,
+        notetypes,
+        decks,
+        notes,
+        cards,
+        reviews,
+    })
+}
+
+/// Copies the package's media files into the media folder.
+///
+/// # Returns
+///
+/// The files that had to be stored under another name, because a different
+/// file already has theirs, with their new name.
+fn import_media(
+    conn: &Connection,
+    folder: &Path,
+    archive: &mut ZipArchive<File>,
+    summary: &mut ImportSummary,
+) -> Result<HashMap<String, String>> {
+    let map: HashMap<String, String> = match archive.by_name("media") {
+        Ok(mut entry) => {
+            let mut json = String::new();
+            entry.read_to_string(&mut json)?;
+            serde_json::from_str(&json)?
+        }
+        Err(ZipError::FileNotFound) => return Ok(HashMap::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut renamed = HashMap::new();
+    for (entry, name) in map {
+        let mut bytes = Vec::new();
+        match archive.by_name(&entry) {
+            Ok(mut file) => file.read_to_end(&mut bytes)?,
+            Err(ZipError::FileNotFound) => {
+                log::warn!("Package is missing media file {} ({})", name, entry);
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let known = conn
+            .query_row(
+                "SELECT 1 FROM media WHERE hash = ?",
+                [media::checksum(&bytes)],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some();
+
+        let stored = media::add_bytes(conn, folder, &name, &bytes)?;
+        if !known {
+            summary.media += 1;
+        }
+        if stored != name {
+            renamed.insert(name, stored);
+        }
+    }
+
+    Ok(renamed)
+}
+
+/// The note type the package's notes of a note type are imported as: the one
+/// made by an earlier import, or one with the same fields and number of
+/// cards, such as the built-in "Basic" for Anki's "Basic". Otherwise a copy
+/// of the Anki note type is added.
+fn find_or_add_notetype(conn: &Connection, id: i64, anki: &AnkiNotetype) -> Result<Notetype> {
+    let guid = format!("{}{}", NOTETYPE_GUID_PREFIX, id);
+    if let Some(notetype) = Notetype::load_by_guid(&guid, conn)? {
+        return Ok(notetype);
+    }
+
+    let mut fields = anki.flds.iter().collect::<Vec<_>>();
+    fields.sort_by_key(|field| field.ord);
+    let fields = fields
+        .into_iter()
+        .map(|field| field.name.as_str())
+        .collect::<Vec<_>>();
+
+    let mut templates = anki.tmpls.iter().collect::<Vec<_>>();
+    templates.sort_by_key(|template| template.ord);
+    let templates = templates
+        .into_iter()
+        .map(|template| CardTemplate::new(&template.name, &template.qfmt, &template.afmt))
+        .collect::<Vec<_>>();
+
+    let kind = if anki.kind == 1 {
+        NotetypeKind::Cloze
+    } else {
+        NotetypeKind::Normal
+    };
+
+    let same = Notetype::get_all(conn)?.into_iter().find(|notetype| {
+        notetype.kind == kind
+            && notetype.fields == fields
+            && notetype.templates.len() == templates.len()
+    });
+    if let Some(notetype) = same {
+        return Ok(notetype);
+    }
+
+    let mut notetype = Notetype {
+        guid,
+        ..Notetype::new(&anki.name, kind, &fields, templates)
+    };
+    notetype.save(conn)?;
+
+    Ok(notetype)
+}
+
+fn insert_card(
+    conn: &Connection,
+    notetype: &Notetype,
+    note: &Note,
+    deck_id: u32,
+    card: &AnkiCard,
+    scheduling: &Scheduling,
+    last_review: Option<i64>,
+) -> Result<Option<u32>> {
+    let Some((question, answer)) = render_card(notetype, note, card.ord) else {
+        return Ok(None);
+    };
+
+    let time = |millis: i64| {
+        DateTime::<Utc>::from_timestamp_millis(millis)
+            .unwrap_or_else(Utc::now)
+            .to_rfc3339()
+    };
+
+    conn.execute(
+        "INSERT INTO cards (question, answer, creation_time, last_studied_time, deck_id, ef, interval, queue, due, data, guid, mtime, usn, note_id, ord, suspended, flags)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, '{}', ?, ?, ?, ?, ?, ?, ?)",
+        params![
+            question,
+            answer,
+            time(card.id),
+            last_review.map(time),
+            deck_id,
+            scheduling.ef,
+            scheduling.interval,
+            scheduling.queue.clone() as i8,
+            scheduling.due,
+            new_guid(),
+            mtime_now(),
+            PENDING_USN,
+            note.id,
+            card.ord,
+            scheduling.suspended,
+            scheduling.flags,
+        ],
+    )?;
+
+    Ok(Some(conn.last_insert_rowid() as u32))
+}
+
+fn update_scheduling(
+    conn: &Connection,
+    card_id: u32,
+    deck_id: u32,
+    scheduling: &Scheduling,
+) -> Result<()> {
+    conn.execute(
+        "UPDATE cards SET deck_id = ?, ef = ?, interval = ?, queue = ?, due = ?, suspended = ?, flags = ?, mtime = ?, usn = ?
+        WHERE id = ?",
+        params![
+            deck_id,
+            scheduling.ef,
+            scheduling.interval,
+            scheduling.queue.clone() as i8,
+            scheduling.due,
+            scheduling.suspended,
+            scheduling.flags,
+            mtime_now(),
+            PENDING_USN,
+            card_id,
+        ],
+    )?;
+
+    Ok(())
+}
+
+/// The note's cards by template, with when they were last saved.
+fn cards_by_ord(conn: &Connection, note_id: u32) -> Result<HashMap<u32, (u32, i64)>> {
+    let cards = conn
+        .prepare("SELECT ord, id, mtime FROM cards WHERE note_id = ?")?
+        .query_map([note_id], |row| {
+            Ok((row.get(0)?, (row.get(1)?, row.get(2)?)))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+
+    Ok(cards)
+}
+
+/// Imports the package at `path`, copying its media into `media_folder`.
+/// `today` is the collection's current day, which review cards keep the same
+/// number of days until their due date from.
+pub fn import(
+    conn: &Connection,
+    media_folder: &Path,
+    path: &Path,
+    today: u32,
+) -> Result<ImportSummary> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let package = {
+        let temp = extract_collection(&mut archive)?;
+        let db = Connection::open_with_flags(&temp.0, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        read_package(&db)?
+    };
+
+    let mut summary = ImportSummary::default();
+    let renamed = import_media(conn, media_folder, &mut archive, &mut summary)?;
+
+    let mut cards_by_note: HashMap<i64, Vec<&AnkiCard>> = HashMap::new();
+    for card in &package.cards {
+        cards_by_note.entry(card.note_id).or_default().push(card);
+    }
+
+    let mut last_reviews = HashMap::new();
+    for review in &package.reviews {
+        last_reviews.insert(review.card_id, review.id);
+    }
+
+    let mut notetypes = HashMap::new();
+    let mut deck_ids = HashMap::new();
+    // The collection's id of each card of the package.
+    let mut card_ids = HashMap::new();
+
+    for anki_note in &package.notes {
+        let Some(anki_notetype) = package.notetypes.get(&anki_note.notetype_id) else {
+            log::warn!(
+                "Skipping note {}: its note type is missing from the package",
+                anki_note.guid
+            );
+            continue;
+        };
+
+        let notetype: &Notetype = match notetypes.entry(anki_note.notetype_id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(find_or_add_notetype(
+                conn,
+                anki_note.notetype_id,
+                anki_notetype,
+            )?),
+        };
+
+        let mut fields = split_fields(&anki_note.fields);
+        if !renamed.is_empty() {
+            for field in fields.iter_mut() {
+                *field =
+                    rename_media_references(field, |name| renamed.get(name).map(String::as_str));
+            }
+        }
+        let tags = split_tags(&anki_note.tags);
+        let cards = cards_by_note
+            .get(&anki_note.id)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+
+        let existing: Option<u32> = conn
+            .query_row(
+                "SELECT id FROM notes WHERE guid = ?",
+                [&anki_note.guid],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        let mut deck_id = |card: &AnkiCard| -> Result<u32> {
+            let anki_deck_id = card.home_deck_id();
+            if let Some(deck_id) = deck_ids.get(&anki_deck_id) {
+                return Ok(*deck_id);
+            }
+
+            let name = package
+                .decks
+                .get(&anki_deck_id)
+                .map(String::as_str)
+                .unwrap_or(DEFAULT_DECK);
+            let deck_id = deck::add_deck(conn, name)?.id.unwrap_or_default();
+            deck_ids.insert(anki_deck_id, deck_id);
+            Ok(deck_id)
+        };
+
+        match existing {
+            None => {
+                let mut note = Note {
+                    guid: anki_note.guid.clone(),
+                    fields,
+                    tags,
+                    ..Note::new(notetype)
+                };
+                note.fields.resize(notetype.fields.len(), String::new());
+                note.save(conn)?;
+
+                for card in cards {
+                    let scheduling = card.scheduling(package.today, today);
+                    let last_review = last_reviews.get(&card.id).copied();
+                    let inserted = insert_card(
+                        conn,
+                        notetype,
+                        &note,
+                        deck_id(card)?,
+                        card,
+                        &scheduling,
+                        last_review,
+                    )?;
+                    if let Some(id) = inserted {
+                        card_ids.insert(card.id, id);
+                    }
+                }
+
+                summary.added += 1;
+            }
+            Some(note_id) => {
+                let mut note = Note::load(note_id, conn)?;
+                let before = cards_by_ord(conn, note_id)?;
+
+                let mut changed = anki_note.mtime > note.mtime;
+                if changed {
+                    let notetype = Notetype::load(note.notetype_id, conn)?;
+                    note.fields = fields;
+                    note.fields.resize(notetype.fields.len(), String::new());
+                    note.tags = tags;
+                    update_note(conn, &mut note)?;
+                }
+
+                // Editing the note may have added cards for new templates.
+                let after = cards_by_ord(conn, note_id)?;
+                let notetype = Notetype::load(note.notetype_id, conn)?;
+
+                for card in cards {
+                    let scheduling = card.scheduling(package.today, today);
+                    let id = match (before.get(&card.ord), after.get(&card.ord)) {
+                        (Some(&(id, mtime)), _) => {
+                            if card.mtime > mtime {
+                                update_scheduling(conn, id, deck_id(card)?, &scheduling)?;
+                                changed = true;
+                            }
+                            Some(id)
+                        }
+                        (None, Some(&(id, _))) => {
+                            update_scheduling(conn, id, deck_id(card)?, &scheduling)?;
+                            Some(id)
+                        }
+                        (None, None) => {
+                            let last_review = last_reviews.get(&card.id).copied();
+                            let inserted = insert_card(
+                                conn,
+                                &notetype,
+                                &note,
+                                deck_id(card)?,
+                                card,
+                                &scheduling,
+                                last_review,
+                            )?;
+                            changed |= inserted.is_some();
+                            inserted
+                        }
+                    };
+
+                    if let Some(id) = id {
+                        card_ids.insert(card.id, id);
+                    }
+                }
+
+                if changed {
+                    summary.updated += 1;
+                } else {
+                    summary.skipped += 1;
+                }
+            }
+        }
+    }
+
+    // Reviews keep their Anki ids, so one imported before is not added
+    // again. An ease of 0 marks a card rescheduled by hand, not an answer.
+    let mut stmt = conn.prepare(
+        "INSERT OR IGNORE INTO revlog (id, card_id, usn, ease, interval, last_interval)
+        VALUES (?, ?, ?, ?, ?, ?)",
+    )?;
+    for review in &package.reviews {
+        let Some(card_id) = card_ids.get(&review.card_id) else {
+            continue;
+        };
+        if review.ease == 0 {
+            continue;
+        }
+
+        // Negative intervals are learning steps, in seconds.
+        summary.reviews += stmt.execute(params![
+            review.id,
+            card_id,
+            PENDING_USN,
+            review.ease.min(4),
+            review.interval.max(0),
+            review.last_interval.max(0),
+        ])?;
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use zip::{write::FileOptions, ZipWriter};
+
+    use crate::{
+        db::init_db, import_export::apkg_export::ANKI_SCHEMA, repositories::notetype::BASIC_GUID,
+    };
+
+    use super::*;
+
+    const MODELS: &str = r#"{
+        "1001": {"name": "Basic", "type": 0,
+            "flds": [{"name": "Front", "ord": 0}, {"name": "Back", "ord": 1}],
+            "tmpls": [{"name": "Card 1", "ord": 0, "qfmt": "{{Front}}", "afmt": "{{FrontSide}}<hr id=answer>{{Back}}"}]},
+        "1002": {"name": "Vocabulary", "type": 0,
+            "flds": [{"name": "Word", "ord": 0}, {"name": "Meaning", "ord": 1}, {"name": "Example", "ord": 2}],
+            "tmpls": [{"name": "Recognise", "ord": 0, "qfmt": "{{Word}}", "afmt": "{{Meaning}}"},
+                {"name": "Recall", "ord": 1, "qfmt": "{{Meaning}}", "afmt": "{{Word}}"}]}
+    }"#;
+
+    const DECKS: &str = r#"{
+        "1": {"name": "Default"},
+        "20": {"name": "Languages::French"},
+        "30": {"name": "Filtered"}
+    }"#;
+
+    /// The package's collection was created 100 days ago; the collection it
+    /// is imported into is on day 50.
+    const TODAY: u32 = 50;
+
+    fn temp_folder() -> PathBuf {
+        std::env::temp_dir().join(format!("ngurra-test-{}", uuid::Uuid::new_v4()))
+    }
+
+    /// Writes a package with a basic note with one review card, and a
+    /// vocabulary note with a suspended new card and a review card in a
+    /// filtered deck. `edits` is run on its collection before zipping it.
+    fn write_package(folder: &Path, edits: &str) -> PathBuf {
+        fs::create_dir_all(folder).unwrap();
+        let db_path = folder.join(format!("{}.anki2", uuid::Uuid::new_v4()));
+
+        let db = Connection::open(&db_path).unwrap();
+        db.execute_batch(ANKI_SCHEMA).unwrap();
+        db.execute(
+            "INSERT INTO col VALUES (1, ?, 0, 0, 11, 0, 0, 0, '{}', ?, ?, '{}', '{}')",
+            params![Utc::now().timestamp() - 100 * 86_400, MODELS, DECKS],
+        )
+        .unwrap();
+        db.execute_batch(
+            "INSERT INTO notes VALUES (1, 'guid-bonjour', 1001, 1000, 0, ' greetings ',
+                'bonjour' || char(31) || 'hello <img src=\"cat.jpg\">', 'bonjour', 0, 0, '');
+            INSERT INTO notes VALUES (2, 'guid-chat', 1002, 1000, 0, '',
+                'chat' || char(31) || 'cat' || char(31) || 'Le chat dort.', 'chat', 0, 0, '');
+            INSERT INTO cards VALUES (1700000000000, 1, 20, 0, 1000, 0, 2, 2, 105, 10, 2300, 3, 0, 0, 0, 0, 0, '');
+            INSERT INTO cards VALUES (1700000000001, 2, 20, 0, 1000, 0, 0, -1, 7, 0, 0, 0, 0, 0, 0, 0, 2, '');
+            INSERT INTO cards VALUES (1700000000002, 2, 30, 1, 1000, 0, 2, 2, 0, 4, 2500, 5, 1, 0, 98, 20, 0, '');
+            INSERT INTO revlog VALUES (1700000100000, 1700000000000, 0, 3, 10, 4, 2300, 5000, 1);
+            INSERT INTO revlog VALUES (1700000200000, 1700000000000, 0, 0, 12, 10, 2300, 0, 4);
+            INSERT INTO revlog VALUES (1700000300000, 1700000000002, 0, 1, -600, 3, 2500, 8000, 2);",
+        )
+        .unwrap();
+        db.execute_batch(edits).unwrap();
+        drop(db);
+
+        let path = folder.join(format!("{}.apkg", uuid::Uuid::new_v4()));
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        for (name, bytes) in [
+            (LEGACY_COLLECTION, fs::read(&db_path).unwrap()),
+            ("media", br#"{"0": "cat.jpg"}"#.to_vec()),
+            ("0", b"meow".to_vec()),
+        ] {
+            zip.start_file(name, FileOptions::default()).unwrap();
+            zip.write_all(&bytes).unwrap();
+        }
+        zip.finish().unwrap();
+
+        path
+    }
+
+    fn setup() -> (Connection, PathBuf) {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+        (conn, temp_folder())
+    }
+
+    /// The question, answer, queue, due day and deck of each card, by template.
+    fn cards(conn: &Connection, guid: &str) -> Vec<(String, String, i8, u32, String)> {
+        conn.prepare(
+            "SELECT c.question, c.answer, c.queue, c.due, d.name FROM cards c
+            JOIN notes n ON n.id = c.note_id JOIN decks d ON d.id = c.deck_id
+            WHERE n.guid = ? ORDER BY c.ord",
+        )
+        .unwrap()
+        .query_map([guid], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+            ))
+        })
+        .unwrap()
+        .collect::<rusqlite::Result<_>>()
+        .unwrap()
+    }
+
+    #[test]
+    fn imports_notes_scheduling_reviews_and_media() {
+        let (conn, folder) = setup();
+        let package = write_package(&folder, "");
+        let media_folder = folder.join("media");
+
+        let summary = import(&conn, &media_folder, &package, TODAY).unwrap();
+        assert_eq!(
+            summary,
+            ImportSummary {
+                added: 2,
+                reviews: 2,
+                media: 1,
+                ..ImportSummary::default()
+            }
+        );
+
+        let french = "Languages::French".to_string();
+        assert_eq!(
+            cards(&conn, "guid-bonjour"),
+            vec![(
+                "bonjour".to_string(),
+                "hello <img src=\"cat.jpg\">".to_string(),
+                CardQueue::Review as i8,
+                55,
+                french.clone()
+            )]
+        );
+        assert_eq!(
+            cards(&conn, "guid-chat"),
+            vec![
+                (
+                    "chat".to_string(),
+                    "cat".to_string(),
+                    CardQueue::New as i8,
+                    7,
+                    french.clone()
+                ),
+                // Back from the filtered deck, and two days overdue.
+                (
+                    "cat".to_string(),
+                    "chat".to_string(),
+                    CardQueue::Review as i8,
+                    48,
+                    french
+                ),
+            ]
+        );
+
+        let (ef, interval, suspended, flags): (f32, u32, bool, u8) = conn
+            .query_row(
+                "SELECT ef, interval, suspended, flags FROM cards WHERE ord = 0 ORDER BY id",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!((ef, interval, suspended, flags), (2.3, 10, false, 0));
+        let (suspended, flags): (bool, u8) = conn
+            .query_row(
+                "SELECT suspended, flags FROM cards WHERE due = 7",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((suspended, flags), (true, 2));
+
+        // Anki's "Basic" is the built-in one; the other note type is copied.
+        let basic = Notetype::load_by_guid(BASIC_GUID, &conn).unwrap().unwrap();
+        let notetype_ids = conn
+            .prepare("SELECT notetype_id FROM notes ORDER BY guid")
+            .unwrap()
+            .query_map([], |row| row.get::<_, u32>(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(notetype_ids[0], basic.id.unwrap());
+        let vocabulary = Notetype::load(notetype_ids[1], &conn).unwrap();
+        assert_eq!(vocabulary.name, "Vocabulary");
+        assert_eq!(vocabulary.fields, ["Word", "Meaning", "Example"]);
+        assert_eq!(vocabulary.templates[1].front, "{{Meaning}}");
+
+        let intervals = conn
+            .prepare("SELECT interval FROM revlog ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get::<_, u32>(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(intervals, [10, 0]);
+
+        assert_eq!(fs::read(media_folder.join("cat.jpg")).unwrap(), b"meow");
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn reimporting_updates_notes_edited_since() {
+        let (conn, folder) = setup();
+        let media_folder = folder.join("media");
+        import(&conn, &media_folder, &write_package(&folder, ""), TODAY).unwrap();
+
+        let again = import(&conn, &media_folder, &write_package(&folder, ""), TODAY).unwrap();
+        assert_eq!(
+            again,
+            ImportSummary {
+                skipped: 2,
+                ..ImportSummary::default()
+            }
+        );
+
+        let later = Utc::now().timestamp() + 100;
+        let edited = write_package(
+            &folder,
+            &format!(
+                "UPDATE notes SET mod = {later}, flds = 'bonjour' || char(31) || 'hello!' WHERE id = 1;
+                UPDATE cards SET mod = {later}, due = 110 WHERE nid = 1;",
+                later = later
+            ),
+        );
+        let summary = import(&conn, &media_folder, &edited, TODAY).unwrap();
+        assert_eq!((summary.updated, summary.skipped), (1, 1));
+
+        let count: u32 = conn
+            .query_row("SELECT COUNT(*) FROM cards", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 3);
+        let bonjour = &cards(&conn, "guid-bonjour")[0];
+        assert_eq!((bonjour.1.as_str(), bonjour.3), ("hello!", 60));
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn renamed_media_is_only_replaced_where_referenced() {
+        let (conn, folder) = setup();
+        let media_folder = folder.join("media");
+        media::add_bytes(&conn, &media_folder, "cat.jpg", b"purr").unwrap();
+
+        let package = write_package(
+            &folder,
+            "UPDATE notes SET flds = 'cat.jpg' || char(31) ||
+                '<img src=\"bobcat.jpg\"> <img src=\"cat.jpg\"> [sound:cat.jpg]' WHERE id = 1",
+        );
+        import(&conn, &media_folder, &package, TODAY).unwrap();
+
+        let stored = format!("cat-{}.jpg", &media::checksum(b"meow")[..8]);
+        let fields: String = conn
+            .query_row(
+                "SELECT fields FROM notes WHERE guid = 'guid-bonjour'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(
+            split_fields(&fields),
+            [
+                "cat.jpg".to_string(),
+                format!(
+                    "<img src=\"bobcat.jpg\"> <img src=\"{0}\"> [sound:{0}]",
+                    stored
+                )
+            ]
+        );
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn newer_packages_are_refused() {
+        let (conn, folder) = setup();
+        fs::create_dir_all(&folder).unwrap();
+        let path = folder.join("new.apkg");
+
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        for name in [NEWER_COLLECTION, OLDEST_COLLECTION] {
+            zip.start_file(name, FileOptions::default()).unwrap();
+            zip.write_all(b"not read").unwrap();
+        }
+        zip.finish().unwrap();
+
+        let result = import(&conn, &folder, &path, TODAY);
+        assert!(matches!(result, Err(NgurraError::Import(_))));
+        assert!(matches!(
+            import(&conn, &folder, &folder.join("missing.apkg"), TODAY),
+            Err(NgurraError::Io(_))
+        ));
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+}
@@ -0,0 +1,846 @@
+//! Exporting decks as an Anki `.apkg` package, in the format every Anki
+//! version since 2.1 imports: a zip file holding a `collection.anki2`
+//! database, the numbered media files and a `media` file naming them.
+//!
+//! Each card becomes a note of Anki's "Basic" note type, with the card's
+//! question on the front and its answer on the back. The package's
+//! collection is dated like this one, so review due days are written
+//! unchanged.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::Write,
+    path::Path,
+};
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+use serde_json::json;
+use sha1::{Digest, Sha1};
+use zip::{write::FileOptions, ZipWriter};
+
+use crate::{
+    errors::Result,
+    models::{
+        deck::{self, get_decks},
+        field::{media_references, segments, Segment},
+    },
+    repositories::{flash_card::CardQueue, guid::new_guid, session::Session},
+    storage::media,
+    Deck,
+};
+
+use super::{apkg::TempFile, ExportSummary};
+
+/// The tables and indexes of an Anki collection at schema version 11.
+pub(super) const ANKI_SCHEMA: &str = "
+    CREATE TABLE col (id INTEGER PRIMARY KEY, crt INTEGER NOT NULL, mod INTEGER NOT NULL, scm INTEGER NOT NULL,
+        ver INTEGER NOT NULL, dty INTEGER NOT NULL, usn INTEGER NOT NULL, ls INTEGER NOT NULL, conf TEXT NOT NULL,
+        models TEXT NOT NULL, decks TEXT NOT NULL, dconf TEXT NOT NULL, tags TEXT NOT NULL);
+    CREATE TABLE notes (id INTEGER PRIMARY KEY, guid TEXT NOT NULL, mid INTEGER NOT NULL, mod INTEGER NOT NULL,
+        usn INTEGER NOT NULL, tags TEXT NOT NULL, flds TEXT NOT NULL, sfld TEXT NOT NULL, csum INTEGER NOT NULL,
+        flags INTEGER NOT NULL, data TEXT NOT NULL);
+    CREATE TABLE cards (id INTEGER PRIMARY KEY, nid INTEGER NOT NULL, did INTEGER NOT NULL, ord INTEGER NOT NULL,
+        mod INTEGER NOT NULL, usn INTEGER NOT NULL, type INTEGER NOT NULL, queue INTEGER NOT NULL,
+        due INTEGER NOT NULL, ivl INTEGER NOT NULL, factor INTEGER NOT NULL, reps INTEGER NOT NULL,
+        lapses INTEGER NOT NULL, left INTEGER NOT NULL, odue INTEGER NOT NULL, odid INTEGER NOT NULL,
+        flags INTEGER NOT NULL, data TEXT NOT NULL);
+    CREATE TABLE revlog (id INTEGER PRIMARY KEY, cid INTEGER NOT NULL, usn INTEGER NOT NULL, ease INTEGER NOT NULL,
+        ivl INTEGER NOT NULL, lastIvl INTEGER NOT NULL, factor INTEGER NOT NULL, time INTEGER NOT NULL,
+        type INTEGER NOT NULL);
+    CREATE TABLE graves (usn INTEGER NOT NULL, oid INTEGER NOT NULL, type INTEGER NOT NULL);
+    CREATE INDEX ix_notes_usn ON notes (usn);
+    CREATE INDEX ix_cards_usn ON cards (usn);
+    CREATE INDEX ix_revlog_usn ON revlog (usn);
+    CREATE INDEX ix_cards_nid ON cards (nid);
+    CREATE INDEX ix_cards_sched ON cards (did, queue, due);
+    CREATE INDEX ix_revlog_cid ON revlog (cid);
+    CREATE INDEX ix_notes_csum ON notes (csum);";
+
+/// The entry the database is stored under.
+const COLLECTION: &str = "collection.anki2";
+const SCHEMA_VERSION: u32 = 11;
+
+/// The id of the "Basic" note type in the package.
+const BASIC_MODEL_ID: i64 = 1_342_697_561_419;
+/// Anki's "Default" deck and options group, which every collection has.
+const DEFAULT_ID: i64 = 1;
+
+/// What to put in the package.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportOptions {
+    /// The deck to export, with the decks below it. `None` exports every
+    /// card.
+    pub deck_id: Option<u32>,
+    /// Keeps each card's due date, interval and ease and the review history.
+    /// Without it, every card is new in the package.
+    pub include_scheduling: bool,
+    /// Bundles the media files the cards reference.
+    pub include_media: bool,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            deck_id: None,
+            include_scheduling: true,
+            include_media: true,
+        }
+    }
+}
+
+struct ExportedCard {
+    id: u32,
+    guid: String,
+    question: String,
+    answer: String,
+    created: i64,
+    mtime: i64,
+    deck_id: u32,
+    ef: f32,
+    interval: u32,
+    queue: CardQueue,
+    due: u32,
+    suspended: bool,
+    flags: u8,
+    tags: String,
+}
+
+/// The answers given to a card, as Anki counts them.
+#[derive(Default)]
+struct ReviewCounts {
+    reps: u32,
+    lapses: u32,
+}
+
+/// Anki's sort field: the text of the question, without markup.
+fn sort_field(question: &str) -> String {
+    let text = segments(question)
+        .into_iter()
+        .filter_map(|segment| match segment {
+            Segment::Text(text) | Segment::Cloze(text) => Some(text),
+            _ => None,
+        })
+        .collect::<String>();
+
+    let mut plain = String::new();
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => plain.push(c),
+            _ => {}
+        }
+    }
+
+    plain.trim().to_string()
+}
+
+/// The first 32 bits of the sort field's SHA-1, which Anki finds duplicates
+/// with.
+fn field_checksum(sort_field: &str) -> i64 {
+    let digest = Sha1::digest(sort_field.as_bytes());
+    u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]) as i64
+}
+
+/// Anki has no deck 0 and keeps 1 for "Default".
+fn anki_deck_id(deck_id: u32) -> i64 {
+    deck_id as i64 + 1
+}
+
+fn load_cards(conn: &Connection, deck_ids: Option<&HashSet<u32>>) -> Result<Vec<ExportedCard>> {
+    let cards = conn
+        .prepare(
+            "SELECT c.id, c.guid, c.question, c.answer, c.creation_time, c.mtime, c.deck_id, c.ef,
+                c.interval, c.queue, c.due, c.suspended, c.flags, COALESCE(n.tags, '')
+            FROM cards c LEFT JOIN notes n ON n.id = c.note_id ORDER BY c.id",
+        )?
+        .query_map([], |row| {
+            let created: String = row.get(4)?;
+            Ok(ExportedCard {
+                id: row.get(0)?,
+                guid: row.get(1)?,
+                question: row.get(2)?,
+                answer: row.get(3)?,
+                created: DateTime::parse_from_rfc3339(&created)
+                    .map(|time| time.timestamp_millis())
+                    .unwrap_or_default(),
+                mtime: row.get(5)?,
+                deck_id: row.get(6)?,
+                ef: row.get(7)?,
+                interval: row.get(8)?,
+                queue: row.get(9)?,
+                due: row.get(10)?,
+                suspended: row.get(11)?,
+                flags: row.get(12)?,
+                tags: row.get(13)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(cards
+        .into_iter()
+        .filter(|card| match deck_ids {
+            Some(ids) => ids.contains(&card.deck_id),
+            None => true,
+        })
+        .collect())
+}
+
+/// The decks of the package, by id: the exported ones and the decks above
+/// them, so that Anki rebuilds the same hierarchy.
+fn exported_decks(conn: &Connection, deck_id: Option<u32>) -> Result<Vec<(u32, String)>> {
+    let decks = deck::sorted(get_decks(conn)?)
+        .into_iter()
+        .filter_map(|deck| Some((deck.id?, deck.name)));
+
+    let Some(deck_id) = deck_id else {
+        return Ok(decks.collect());
+    };
+
+    let root = Deck::load(deck_id, conn)?.name;
+    Ok(decks
+        .filter(|(_, name)| deck::is_within(name, &root) || deck::is_within(&root, name))
+        .collect())
+}
+
+fn basic_model(mtime: i64) -> serde_json::Value {
+    let field = |name: &str, ord: u32| {
+        json!({
+            "name": name, "ord": ord, "sticky": false, "rtl": false,
+            "font": "Arial", "size": 20, "media": []
+        })
+    };
+
+    json!({
+        "id": BASIC_MODEL_ID,
+        "name": "Basic",
+        "type": 0,
+        "mod": mtime,
+        "usn": 0,
+        "sortf": 0,
+        "did": DEFAULT_ID,
+        "flds": [field("Front", 0), field("Back", 1)],
+        "tmpls": [{
+            "name": "Card 1",
+            "ord": 0,
+            "qfmt": "{{Front}}",
+            "afmt": "{{FrontSide}}\n\n<hr id=answer>\n\n{{Back}}",
+            "bqfmt": "",
+            "bafmt": "",
+            "did": null,
+            "bfont": "",
+            "bsize": 0
+        }],
+        "css": ".card {\n    font-family: arial;\n    font-size: 20px;\n    text-align: center;\n    \
+            color: black;\n    background-color: white;\n}\n",
+        "latexPre": "\\documentclass[12pt]{article}\n\\special{papersize=3in,5in}\n\
+            \\usepackage[utf8]{inputenc}\n\\usepackage{amssymb,amsmath}\n\\pagestyle{empty}\n\
+            \\setlength{\\parindent}{0in}\n\\begin{document}\n",
+        "latexPost": "\\end{document}",
+        "latexsvg": false,
+        "req": [[0, "any", [0]]],
+        "tags": [],
+        "vers": []
+    })
+}
+
+fn anki_deck(id: i64, name: &str, mtime: i64) -> serde_json::Value {
+    json!({
+        "id": id,
+        "name": name,
+        "mod": mtime,
+        "usn": 0,
+        "desc": "",
+        "dyn": 0,
+        "conf": DEFAULT_ID,
+        "collapsed": false,
+        "browserCollapsed": false,
+        "extendNew": 0,
+        "extendRev": 0,
+        "newToday": [0, 0],
+        "revToday": [0, 0],
+        "lrnToday": [0, 0],
+        "timeToday": [0, 0]
+    })
+}
+
+fn deck_options(mtime: i64) -> serde_json::Value {
+    json!({
+        "id": DEFAULT_ID,
+        "name": "Default",
+        "mod": mtime,
+        "usn": 0,
+        "maxTaken": 60,
+        "autoplay": true,
+        "timer": 0,
+        "replayq": true,
+        "dyn": false,
+        "new": {
+            "bury": false, "delays": [1.0, 10.0], "initialFactor": 2500,
+            "ints": [1, 4, 0], "order": 1, "perDay": 20
+        },
+        "rev": {
+            "bury": false, "ease4": 1.3, "ivlFct": 1.0, "maxIvl": 36500,
+            "perDay": 200, "hardFactor": 1.2
+        },
+        "lapse": {
+            "delays": [10.0], "leechAction": 1, "leechFails": 8, "minInt": 1, "mult": 0.0
+        }
+    })
+}
+
+/// Writes the package's collection to `db`.
+fn write_collection(
+    conn: &Connection,
+    db: &Connection,
+    options: &ExportOptions,
+    summary: &mut ExportSummary,
+) -> Result<Vec<String>> {
+    let decks = exported_decks(conn, options.deck_id)?;
+    let deck_ids = match options.deck_id {
+        Some(id) => Some(deck::deck_tree_ids(conn, id)?.into_iter().collect()),
+        None => None,
+    };
+    let cards = load_cards(conn, deck_ids.as_ref())?;
+    let now = Utc::now();
+    let mtime = now.timestamp();
+
+    let mut deck_json = serde_json::Map::new();
+    deck_json.insert(
+        DEFAULT_ID.to_string(),
+        anki_deck(DEFAULT_ID, "Default", mtime),
+    );
+    for (id, name) in &decks {
+        let id = anki_deck_id(*id);
+        deck_json.insert(id.to_string(), anki_deck(id, name, mtime));
+    }
+
+    let conf = json!({
+        "nextPos": cards.len() + 1,
+        "estTimes": true,
+        "activeDecks": [DEFAULT_ID],
+        "sortType": "noteFld",
+        "timeLim": 0,
+        "sortBackwards": false,
+        "addToCur": true,
+        "curDeck": DEFAULT_ID,
+        "newSpread": 0,
+        "dueCounts": true,
+        "curModel": BASIC_MODEL_ID,
+        "collapseTime": 1200
+    });
+
+    db.execute_batch(ANKI_SCHEMA)?;
+    db.execute(
+        "INSERT INTO col VALUES (1, ?, ?, ?, ?, 0, 0, 0, ?, ?, ?, ?, '{}')",
+        params![
+            Session::get_creation_stamp(conn)?,
+            now.timestamp_millis(),
+            now.timestamp_millis(),
+            SCHEMA_VERSION,
+            conf.to_string(),
+            json!({ BASIC_MODEL_ID.to_string(): basic_model(mtime) }).to_string(),
+            serde_json::Value::Object(deck_json).to_string(),
+            json!({ DEFAULT_ID.to_string(): deck_options(mtime) }).to_string(),
+        ],
+    )?;
+
+    // Anki ids are creation times in milliseconds, and must be unique.
+    let mut card_ids = HashMap::new();
+    let mut last_id = 0;
+    for card in &cards {
+        last_id = card.created.max(last_id + 1);
+        card_ids.insert(card.id, last_id);
+    }
+
+    let mut counts: HashMap<u32, ReviewCounts> = HashMap::new();
+    if options.include_scheduling {
+        let reviews = conn
+            .prepare("SELECT id, card_id, ease, interval, last_interval FROM revlog ORDER BY id")?
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, u32>(1)?,
+                    row.get::<_, u8>(2)?,
+                    row.get::<_, i64>(3)?,
+                    row.get::<_, i64>(4)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let factors = cards
+            .iter()
+            .map(|card| (card.id, (card.ef * 1000.0).round() as i64))
+            .collect::<HashMap<_, _>>();
+
+        let mut stmt = db.prepare("INSERT INTO revlog VALUES (?, ?, 0, ?, ?, ?, ?, 0, ?)")?;
+        for (id, card_id, ease, interval, last_interval) in reviews {
+            let Some(anki_id) = card_ids.get(&card_id) else {
+                continue;
+            };
+
+            let count = counts.entry(card_id).or_default();
+            count.reps += 1;
+            if ease == 1 && last_interval > 0 {
+                count.lapses += 1;
+            }
+
+            // 0 for a card answered while still being learned, 1 for a review.
+            let kind = if last_interval == 0 { 0 } else { 1 };
+            stmt.execute(params![
+                id,
+                anki_id,
+                ease,
+                interval,
+                last_interval,
+                factors[&card_id],
+                kind
+            ])?;
+            summary.reviews += 1;
+        }
+    }
+
+    let mut media = Vec::new();
+    let mut note_stmt =
+        db.prepare("INSERT INTO notes VALUES (?, ?, ?, ?, 0, ?, ?, ?, ?, 0, '')")?;
+    let mut card_stmt = db.prepare(
+        "INSERT INTO cards VALUES (?, ?, ?, 0, ?, 0, ?, ?, ?, ?, ?, ?, ?, ?, 0, 0, ?, '')",
+    )?;
+    for (position, card) in cards.iter().enumerate() {
+        let id = card_ids[&card.id];
+        let guid = if card.guid.is_empty() {
+            new_guid()
+        } else {
+            card.guid.clone()
+        };
+        let sort_field = sort_field(&card.question);
+
+        note_stmt.execute(params![
+            id,
+            guid,
+            BASIC_MODEL_ID,
+            card.mtime,
+            card.tags,
+            format!("{}\x1f{}", card.question, card.answer),
+            sort_field,
+            field_checksum(&sort_field),
+        ])?;
+
+        // (type, queue, due, interval, factor, steps left)
+        let scheduling = if options.include_scheduling {
+            let (kind, queue, due, left) = match card.queue {
+                CardQueue::New => (0, 0, position as i64 + 1, 0),
+                // Learning cards here are due on a day, like Anki's cards
+                // whose learning step is a day or more.
+                CardQueue::Learning => (1, 3, card.due as i64, 1),
+                CardQueue::Review => (2, 2, card.due as i64, 0),
+            };
+            let queue = if card.suspended { -1 } else { queue };
+            let factor = if kind == 0 {
+                0
+            } else {
+                (card.ef * 1000.0).round() as i64
+            };
+            (kind, queue, due, card.interval, factor, left)
+        } else {
+            (0, 0, position as i64 + 1, 0, 0, 0)
+        };
+        let count = counts.remove(&card.id).unwrap_or_default();
+
+        card_stmt.execute(params![
+            id,
+            id,
+            anki_deck_id(card.deck_id),
+            card.mtime,
+            scheduling.0,
+            scheduling.1,
+            scheduling.2,
+            scheduling.3,
+            scheduling.4,
+            count.reps,
+            count.lapses,
+            scheduling.5,
+            card.flags,
+        ])?;
+        summary.cards += 1;
+
+        if options.include_media {
+            for name in media_references(&card.question)
+                .into_iter()
+                .chain(media_references(&card.answer))
+            {
+                if !media.contains(&name) {
+                    media.push(name);
+                }
+            }
+        }
+    }
+
+    Ok(media)
+}
+
+/// Writes the cards chosen by `options` to an `.apkg` file at `path`,
+/// replacing any file there.
+pub fn export(
+    conn: &Connection,
+    media_folder: &Path,
+    path: &Path,
+    options: &ExportOptions,
+) -> Result<ExportSummary> {
+    let mut summary = ExportSummary::default();
+    let temp = TempFile::new("export");
+
+    let media = {
+        let db = Connection::open(&temp.0)?;
+        write_collection(conn, &db, options, &mut summary)?
+    };
+
+    let mut zip = ZipWriter::new(File::create(path)?);
+    zip.start_file(COLLECTION, FileOptions::default())?;
+    zip.write_all(&fs::read(&temp.0)?)?;
+
+    // Media entries are numbered; the `media` entry maps them to names.
+    let mut names = serde_json::Map::new();
+    for name in media {
+        if !media::is_plain_name(&name) {
+            log::warn!(
+                "Not exporting media file outside the media folder: {}",
+                name
+            );
+            continue;
+        }
+        let bytes = match fs::read(media_folder.join(&name)) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                log::warn!("Not exporting missing media file {}: {}", name, e);
+                continue;
+            }
+        };
+
+        let entry = names.len().to_string();
+        zip.start_file(entry.as_str(), FileOptions::default())?;
+        zip.write_all(&bytes)?;
+        names.insert(entry, name.into());
+        summary.media += 1;
+    }
+
+    zip.start_file("media", FileOptions::default())?;
+    zip.write_all(serde_json::Value::Object(names).to_string().as_bytes())?;
+    zip.finish()?;
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{io::Read, path::PathBuf};
+
+    use zip::ZipArchive;
+
+    use crate::{
+        db::init_db,
+        models::deck::add_deck,
+        repositories::{
+            note::Note,
+            notetype::{Notetype, BASIC_GUID},
+        },
+        FlashCard,
+    };
+
+    use super::*;
+
+    fn temp_folder() -> PathBuf {
+        std::env::temp_dir().join(format!("ngurra-test-{}", uuid::Uuid::new_v4()))
+    }
+
+    /// A collection with a review card in `Languages::French`, which has an
+    /// image and a review, and a new card in `Maths`.
+    fn setup(folder: &Path) -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+        Session::set_creation_stamp(&conn, 1_700_000_000).unwrap();
+
+        let media_folder = folder.join("media");
+        media::add_bytes(&conn, &media_folder, "cat.jpg", b"meow").unwrap();
+
+        let french = add_deck(&conn, "Languages::French").unwrap().id.unwrap();
+        let basic = Notetype::load_by_guid(BASIC_GUID, &conn).unwrap().unwrap();
+        let mut note = Note::new(&basic);
+        note.fields = vec![
+            "chat".to_string(),
+            "<b>cat</b> <img src=\"cat.jpg\">".to_string(),
+        ];
+        note.tags = vec!["animals".to_string()];
+        note.save(&conn).unwrap();
+
+        let mut card = FlashCard::new(
+            french,
+            "chat",
+            "<b>cat</b> <img src=\"cat.jpg\">",
+            Some(2.3),
+        );
+        card.note_id = note.id.unwrap();
+        card.set_queue(CardQueue::Review);
+        card.due = 40;
+        card.interval = 12;
+        card.save(&conn).unwrap();
+        conn.execute(
+            "INSERT INTO revlog VALUES (1700000100000, ?, -1, 3, 12, 5)",
+            [card.id.unwrap()],
+        )
+        .unwrap();
+
+        let maths = add_deck(&conn, "Maths").unwrap().id.unwrap();
+        FlashCard::new(maths, "1 + 1", "2", None)
+            .save(&conn)
+            .unwrap();
+
+        conn
+    }
+
+    /// Opens the database of the package at `path` and returns it with the
+    /// names of the zip entries and the `media` map.
+    fn open_package(
+        path: &Path,
+        folder: &Path,
+    ) -> (Connection, Vec<String>, HashMap<String, String>) {
+        let mut archive = ZipArchive::new(File::open(path).unwrap()).unwrap();
+        let names = archive.file_names().map(str::to_string).collect();
+
+        let db_path = folder.join("exported.anki2");
+        let mut bytes = Vec::new();
+        archive
+            .by_name(COLLECTION)
+            .unwrap()
+            .read_to_end(&mut bytes)
+            .unwrap();
+        fs::write(&db_path, bytes).unwrap();
+
+        let mut json = String::new();
+        archive
+            .by_name("media")
+            .unwrap()
+            .read_to_string(&mut json)
+            .unwrap();
+
+        (
+            Connection::open(db_path).unwrap(),
+            names,
+            serde_json::from_str(&json).unwrap(),
+        )
+    }
+
+    #[test]
+    fn exports_a_deck_with_scheduling_and_media() {
+        let folder = temp_folder();
+        let conn = setup(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        let path = folder.join("languages.apkg");
+        let languages = deck::find_deck_id(&conn, "Languages").unwrap();
+
+        let options = ExportOptions {
+            deck_id: Some(languages),
+            ..ExportOptions::default()
+        };
+        let summary = export(&conn, &folder.join("media"), &path, &options).unwrap();
+        assert_eq!(
+            summary,
+            ExportSummary {
+                cards: 1,
+                reviews: 1,
+                media: 1
+            }
+        );
+
+        let (db, names, media) = open_package(&path, &folder);
+        assert_eq!(
+            media,
+            HashMap::from([("0".to_string(), "cat.jpg".to_string())])
+        );
+        assert!(names.contains(&"0".to_string()));
+
+        let tables = db
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'table' ORDER BY name")
+            .unwrap()
+            .query_map([], |row| row.get::<_, String>(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(tables, ["cards", "col", "graves", "notes", "revlog"]);
+
+        let (crt, ver, models, decks): (i64, u32, String, String) = db
+            .query_row("SELECT crt, ver, models, decks FROM col", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })
+            .unwrap();
+        assert_eq!((crt, ver), (1_700_000_000, 11));
+
+        let models: serde_json::Value = serde_json::from_str(&models).unwrap();
+        let basic = &models[BASIC_MODEL_ID.to_string()];
+        assert_eq!(basic["name"], "Basic");
+        assert_eq!(basic["flds"][0]["name"], "Front");
+        assert_eq!(basic["flds"][1]["name"], "Back");
+
+        let decks: HashMap<String, serde_json::Value> = serde_json::from_str(&decks).unwrap();
+        let mut deck_names = decks
+            .values()
+            .map(|deck| deck["name"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        deck_names.sort();
+        assert_eq!(deck_names, ["Default", "Languages", "Languages::French"]);
+
+        let french_id = decks
+            .iter()
+            .find(|(_, deck)| deck["name"] == "Languages::French")
+            .map(|(id, _)| id.parse::<i64>().unwrap())
+            .unwrap();
+        let (guid, tags, fields, sfld, csum): (String, String, String, String, i64) = db
+            .query_row(
+                "SELECT guid, tags, flds, sfld, csum FROM notes WHERE mid = ?",
+                [BASIC_MODEL_ID],
+                |row| {
+                    Ok((
+                        row.get(0)?,
+                        row.get(1)?,
+                        row.get(2)?,
+                        row.get(3)?,
+                        row.get(4)?,
+                    ))
+                },
+            )
+            .unwrap();
+        assert!(!guid.is_empty());
+        assert_eq!(tags, " animals ");
+        assert_eq!(fields, "chat\x1f<b>cat</b> <img src=\"cat.jpg\">");
+        assert_eq!((sfld.as_str(), csum), ("chat", field_checksum("chat")));
+
+        let card: (i64, i64, i64, i64, i64, i64, i64) = db
+            .query_row(
+                "SELECT did, type, queue, due, ivl, factor, reps FROM cards",
+                [],
+                |row| {
+                    Ok((
+                        row.get(0)?,
+                        row.get(1)?,
+                        row.get(2)?,
+                        row.get(3)?,
+                        row.get(4)?,
+                        row.get(5)?,
+                        row.get(6)?,
+                    ))
+                },
+            )
+            .unwrap();
+        assert_eq!(card, (french_id, 2, 2, 40, 12, 2300, 1));
+
+        let review: (i64, i64, i64, i64) = db
+            .query_row(
+                "SELECT r.ease, r.ivl, r.lastIvl, r.factor FROM revlog r JOIN cards c ON c.id = r.cid",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!(review, (3, 12, 5, 2300));
+    }
+
+    #[test]
+    fn exports_without_scheduling_as_new_cards() {
+        let folder = temp_folder();
+        let conn = setup(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        let path = folder.join("all.apkg");
+
+        let options = ExportOptions {
+            deck_id: None,
+            include_scheduling: false,
+            include_media: false,
+        };
+        let summary = export(&conn, &folder.join("media"), &path, &options).unwrap();
+        assert_eq!(
+            summary,
+            ExportSummary {
+                cards: 2,
+                ..ExportSummary::default()
+            }
+        );
+
+        let (db, names, media) = open_package(&path, &folder);
+        assert!(media.is_empty());
+        assert_eq!(names.len(), 2);
+
+        let cards = db
+            .prepare("SELECT type, queue, due, ivl, reps FROM cards ORDER BY due")
+            .unwrap()
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, i64>(3)?,
+                    row.get::<_, i64>(4)?,
+                ))
+            })
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(cards, [(0, 0, 1, 0, 0), (0, 0, 2, 0, 0)]);
+
+        let reviews: u32 = db
+            .query_row("SELECT COUNT(*) FROM revlog", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(reviews, 0);
+    }
+
+    #[test]
+    fn media_outside_the_media_folder_is_not_exported() {
+        let folder = temp_folder();
+        let conn = setup(&folder);
+        fs::write(folder.join("secret.txt"), "hunter2").unwrap();
+        let path = folder.join("maths.apkg");
+
+        let maths = deck::find_deck_id(&conn, "Maths").unwrap();
+        FlashCard::new(maths, "<img src=\"../secret.txt\">", "?", None)
+            .save(&conn)
+            .unwrap();
+
+        let options = ExportOptions {
+            deck_id: Some(maths),
+            ..ExportOptions::default()
+        };
+        let summary = export(&conn, &folder.join("media"), &path, &options).unwrap();
+        assert_eq!(summary.media, 0);
+
+        let (_, names, media) = open_package(&path, &folder);
+        assert!(media.is_empty());
+        assert_eq!(names.len(), 2);
+    }
+
+    #[test]
+    fn exported_packages_import_back() {
+        let folder = temp_folder();
+        let conn = setup(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        let path = folder.join("all.apkg");
+        export(
+            &conn,
+            &folder.join("media"),
+            &path,
+            &ExportOptions::default(),
+        )
+        .unwrap();
+
+        let other = Connection::open_in_memory().unwrap();
+        init_db(&other).unwrap();
+        let summary = super::super::apkg::import(&other, &folder.join("other"), &path, 0).unwrap();
+        assert_eq!((summary.added, summary.reviews, summary.media), (2, 1, 1));
+
+        let decks = deck::sorted(get_decks(&other).unwrap())
+            .into_iter()
+            .map(|deck| deck.name)
+            .collect::<Vec<_>>();
+        assert!(decks.contains(&"Languages::French".to_string()));
+        assert!(decks.contains(&"Maths".to_string()));
+    }
+}
@@ -0,0 +1,465 @@
+//! Importing spreadsheets saved as delimited text: comma or semicolon
+//! separated `.csv` files, tab separated `.tsv` files and the like. Fields
+//! may be quoted as spreadsheets write them, with `""` for a quote inside a
+//! quoted field, which can then also hold delimiters and line breaks.
+//!
+//! Each row becomes a Basic note. A row is a duplicate of a Basic note whose
+//! front is the same as the row's question.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use rusqlite::Connection;
+
+use crate::{
+    errors::{NgurraError, Result},
+    models::{
+        deck,
+        note::{add_note, update_note},
+        tag,
+    },
+    repositories::{
+        note::{split_fields, Note},
+        notetype::{Notetype, BASIC_GUID},
+    },
+    FlashCard,
+};
+
+use super::ImportSummary;
+
+/// The delimiters looked for, preferred in this order when several fit.
+const DELIMITERS: [char; 4] = ['\t', ',', ';', '|'];
+/// How many rows the delimiter is guessed from.
+const SAMPLE_ROWS: usize = 20;
+
+/// A file split into rows of fields.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DelimitedFile {
+    pub delimiter: char,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl DelimitedFile {
+    /// The number of columns of the widest row.
+    pub fn columns(&self) -> usize {
+        self.rows.iter().map(Vec::len).max().unwrap_or(0)
+    }
+}
+
+/// Which column holds what, counted from 0.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnMap {
+    pub question: usize,
+    pub answer: usize,
+    /// Tags separated by spaces or commas.
+    pub tags: Option<usize>,
+    /// The deck to put the row's note in, instead of the default deck.
+    pub deck: Option<usize>,
+}
+
+impl Default for ColumnMap {
+    fn default() -> Self {
+        Self {
+            question: 0,
+            answer: 1,
+            tags: None,
+            deck: None,
+        }
+    }
+}
+
+/// What to do with a row whose question is already in the collection.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DuplicateMode {
+    /// Leave the note as it is.
+    #[default]
+    Skip,
+    /// Give the note the row's answer, and its tags and deck if those are
+    /// mapped.
+    Update,
+    /// Add the row as another note.
+    Create,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DelimitedImport {
+    pub columns: ColumnMap,
+    /// Whether the first row names the columns rather than holding a card.
+    pub skip_header: bool,
+    pub duplicates: DuplicateMode,
+    /// The deck of rows without one, created if missing.
+    pub deck: String,
+}
+
+/// Splits `text` into rows, leaving out blank lines.
+pub fn parse(text: &str, delimiter: char) -> Vec<Vec<String>> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    // Whether the field was quoted, which makes an empty line a row.
+    let mut was_quoted = false;
+
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => quoted = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+
+        match c {
+            '"' if field.is_empty() && !was_quoted => {
+                quoted = true;
+                was_quoted = true;
+            }
+            '\r' | '\n' => {
+                if c == '\r' && chars.peek() == Some(&'\n') {
+                    chars.next();
+                }
+                row.push(std::mem::take(&mut field));
+                if row.len() > 1 || !row[0].is_empty() || was_quoted {
+                    rows.push(std::mem::take(&mut row));
+                }
+                row.clear();
+                was_quoted = false;
+            }
+            _ if c == delimiter => {
+                row.push(std::mem::take(&mut field));
+                was_quoted = false;
+            }
+            _ => field.push(c),
+        }
+    }
+
+    if !field.is_empty() || !row.is_empty() || was_quoted {
+        row.push(field);
+        rows.push(row);
+    }
+
+    rows
+}
+
+/// Guesses the delimiter of `text`: the one that splits the first rows into
+/// the same number of fields, and into the most fields if several do.
+pub fn detect_delimiter(text: &str) -> char {
+    let mut best = (DELIMITERS[1], false, 1);
+
+    for delimiter in DELIMITERS {
+        let rows = parse(text, delimiter);
+        let sample = &rows[..rows.len().min(SAMPLE_ROWS)];
+        let Some(first) = sample.first() else {
+            continue;
+        };
+
+        let consistent = sample.iter().all(|row| row.len() == first.len());
+        if first.len() > 1 && (consistent, first.len()) > (best.1, best.2) {
+            best = (delimiter, consistent, first.len());
+        }
+    }
+
+    best.0
+}
+
+/// Reads the file at `path`, guessing its delimiter unless one is given.
+pub fn read(path: &Path, delimiter: Option<char>) -> Result<DelimitedFile> {
+    let text = fs::read_to_string(path)?;
+    let delimiter = delimiter.unwrap_or_else(|| detect_delimiter(&text));
+
+    Ok(DelimitedFile {
+        delimiter,
+        rows: parse(&text, delimiter),
+    })
+}
+
+fn column(row: &[String], index: usize) -> &str {
+    row.get(index).map_or("", |field| field.trim())
+}
+
+/// Reads a tags column, where tags are separated by spaces or commas, the
+/// way the tag editor reads typed tags.
+fn row_tags(tags: &str) -> Vec<String> {
+    tag::parse(&tags.replace(',', " "))
+}
+
+/// Basic notes by their front, to find duplicates with.
+fn basic_notes_by_front(conn: &Connection, notetype_id: u32) -> Result<HashMap<String, u32>> {
+    let notes = conn
+        .prepare("SELECT id, fields FROM notes WHERE notetype_id = ? ORDER BY id")?
+        .query_map([notetype_id], |row| {
+            Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut by_front = HashMap::new();
+    for (id, fields) in notes {
+        let front = split_fields(&fields).swap_remove(0);
+        by_front.entry(front.trim().to_string()).or_insert(id);
+    }
+
+    Ok(by_front)
+}
+
+/// Changes a duplicate note to match its row.
+///
+/// # Returns
+///
+/// Whether anything changed.
+fn update_duplicate(
+    conn: &Connection,
+    note_id: u32,
+    answer: &str,
+    tags: Option<Vec<String>>,
+    deck_id: Option<u32>,
+) -> Result<bool> {
+    let mut note = Note::load(note_id, conn)?;
+    let mut changed = false;
+
+    if note.fields.get(1).map(String::as_str) != Some(answer) {
+        note.fields.resize(2.max(note.fields.len()), String::new());
+        note.fields[1] = answer.to_string();
+        changed = true;
+    }
+    if let Some(tags) = tags.filter(|tags| *tags != note.tags) {
+        note.tags = tags;
+        changed = true;
+    }
+    if changed {
+        update_note(conn, &mut note)?;
+    }
+
+    if let Some(deck_id) = deck_id {
+        for id in note.card_ids(conn)? {
+            let mut card = FlashCard::load(id, conn)?;
+            if card.get_deck_id() != deck_id {
+                card.set_deck_id(deck_id);
+                card.save(conn)?;
+                changed = true;
+            }
+        }
+    }
+
+    Ok(changed)
+}
+
+/// Adds the rows of `file` to the collection as Basic notes.
+pub fn import(
+    conn: &Connection,
+    file: &DelimitedFile,
+    options: &DelimitedImport,
+) -> Result<ImportSummary> {
+    let columns = &options.columns;
+    let notetype = Notetype::load_by_guid(BASIC_GUID, conn)?
+        .ok_or_else(|| NgurraError::Import("the Basic note type is missing".to_string()))?;
+    let notetype_id = notetype.id.unwrap_or_default();
+
+    let mut existing = basic_notes_by_front(conn, notetype_id)?;
+    let mut deck_ids: HashMap<String, u32> = HashMap::new();
+    let mut deck_id = |name: &str| -> Result<u32> {
+        if let Some(id) = deck_ids.get(name) {
+            return Ok(*id);
+        }
+        let id = deck::add_deck(conn, name)?.id.unwrap_or_default();
+        deck_ids.insert(name.to_string(), id);
+        Ok(id)
+    };
+
+    let mut summary = ImportSummary::default();
+    let rows = file.rows.iter().skip(usize::from(options.skip_header));
+    for row in rows {
+        let question = column(row, columns.question);
+        if question.is_empty() {
+            summary.skipped += 1;
+            continue;
+        }
+
+        let answer = column(row, columns.answer);
+        let tags = columns.tags.map(|index| row_tags(column(row, index)));
+        let row_deck = columns
+            .deck
+            .map(|index| column(row, index))
+            .filter(|name| !name.is_empty());
+
+        let duplicate = existing.get(question).copied();
+        match (duplicate, options.duplicates) {
+            (Some(_), DuplicateMode::Skip) => summary.skipped += 1,
+            (Some(note_id), DuplicateMode::Update) => {
+                let row_deck_id = row_deck.map(&mut deck_id).transpose()?;
+                if update_duplicate(conn, note_id, answer, tags, row_deck_id)? {
+                    summary.updated += 1;
+                } else {
+                    summary.skipped += 1;
+                }
+            }
+            (None, _) | (Some(_), DuplicateMode::Create) => {
+                let deck_name = row_deck.unwrap_or(&options.deck);
+                let mut note = Note::new(&notetype);
+                note.fields = vec![question.to_string(), answer.to_string()];
+                note.tags = tags.unwrap_or_default();
+                add_note(conn, &mut note, deck_id(deck_name)?)?;
+
+                existing
+                    .entry(question.to_string())
+                    .or_insert(note.id.unwrap_or_default());
+                summary.added += 1;
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use crate::db::init_db;
+
+    use super::*;
+
+    fn temp_file(name: &str, text: &str) -> PathBuf {
+        let folder = std::env::temp_dir().join(format!("ngurra-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&folder).unwrap();
+        let path = folder.join(name);
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    fn setup() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+        conn
+    }
+
+    /// The question, answer, tags and deck of every card, by question.
+    fn cards(conn: &Connection) -> Vec<(String, String, String, String)> {
+        conn.prepare(
+            "SELECT c.question, c.answer, n.tags, d.name FROM cards c
+            JOIN notes n ON n.id = c.note_id JOIN decks d ON d.id = c.deck_id
+            ORDER BY c.question, c.id",
+        )
+        .unwrap()
+        .query_map([], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })
+        .unwrap()
+        .collect::<rusqlite::Result<_>>()
+        .unwrap()
+    }
+
+    #[test]
+    fn quoted_fields_keep_delimiters_quotes_and_line_breaks() {
+        let text = "\u{feff}front,back\r\n\"a, b\",\"say \"\"hi\"\"\"\n\n\"two\nlines\",\n";
+
+        assert_eq!(
+            parse(text, ','),
+            vec![
+                vec!["front".to_string(), "back".to_string()],
+                vec!["a, b".to_string(), "say \"hi\"".to_string()],
+                vec!["two\nlines".to_string(), String::new()],
+            ]
+        );
+    }
+
+    #[test]
+    fn delimiter_is_guessed_from_the_first_rows() {
+        assert_eq!(detect_delimiter("chat\tcat, the animal\nchien\tdog"), '\t');
+        assert_eq!(detect_delimiter("chat,\"cat; the animal\"\nchien,dog"), ',');
+        assert_eq!(detect_delimiter("chat;cat;animals\nchien;dog;animals"), ';');
+        assert_eq!(detect_delimiter("just one column"), ',');
+
+        let path = temp_file("words.tsv", "chat\tcat\nchien\tdog\n");
+        let file = read(&path, None).unwrap();
+        assert_eq!(file.delimiter, '\t');
+        assert_eq!(file.columns(), 2);
+    }
+
+    #[test]
+    fn rows_become_notes_in_their_decks() {
+        let conn = setup();
+        let file = DelimitedFile {
+            delimiter: ',',
+            rows: parse(
+                "Deck,Front,Back,Tags\n\
+                Languages::French,chat,cat,\"animals, nouns Animals,lang::::fr\"\n\
+                ,chien,dog,\n\
+                ,,no question,\n",
+                ',',
+            ),
+        };
+        let options = DelimitedImport {
+            columns: ColumnMap {
+                question: 1,
+                answer: 2,
+                tags: Some(3),
+                deck: Some(0),
+            },
+            skip_header: true,
+            deck: "Imported".to_string(),
+            ..DelimitedImport::default()
+        };
+
+        let summary = import(&conn, &file, &options).unwrap();
+        assert_eq!(
+            summary,
+            ImportSummary {
+                added: 2,
+                skipped: 1,
+                ..ImportSummary::default()
+            }
+        );
+        assert_eq!(
+            cards(&conn),
+            vec![
+                (
+                    "chat".to_string(),
+                    "cat".to_string(),
+                    " animals nouns lang::fr ".to_string(),
+                    "Languages::French".to_string()
+                ),
+                (
+                    "chien".to_string(),
+                    "dog".to_string(),
+                    String::new(),
+                    "Imported".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicates_are_skipped_updated_or_created() {
+        let conn = setup();
+        let first = DelimitedFile {
+            delimiter: '\t',
+            rows: parse("chat\tcat\nchien\tdog", '\t'),
+        };
+        let second = DelimitedFile {
+            delimiter: '\t',
+            rows: parse("chat\ta cat\nchien\tdog\noiseau\tbird", '\t'),
+        };
+        let options = |duplicates| DelimitedImport {
+            duplicates,
+            deck: "French".to_string(),
+            ..DelimitedImport::default()
+        };
+        import(&conn, &first, &options(DuplicateMode::Skip)).unwrap();
+
+        let skipped = import(&conn, &second, &options(DuplicateMode::Skip)).unwrap();
+        assert_eq!((skipped.added, skipped.updated, skipped.skipped), (1, 0, 2));
+
+        let updated = import(&conn, &second, &options(DuplicateMode::Update)).unwrap();
+        assert_eq!((updated.added, updated.updated, updated.skipped), (0, 1, 2));
+        assert_eq!(cards(&conn)[0].1, "a cat");
+
+        let created = import(&conn, &first, &options(DuplicateMode::Create)).unwrap();
+        assert_eq!((created.added, created.updated, created.skipped), (2, 0, 0));
+        assert_eq!(cards(&conn).len(), 5);
+    }
+}
@@ -0,0 +1,65 @@
+//! Moving cards between Ngurra and files other apps read and write.
+//!
+//! Imports match what is already in the collection, so that importing the
+//! same file twice updates the cards from the first import rather than adding
+//! them again.
+
+pub mod apkg;
+pub mod apkg_export;
+pub mod delimited;
+
+/// What an import did, counted in notes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportSummary {
+    pub added: usize,
+    /// Notes that were already in the collection and changed.
+    pub updated: usize,
+    /// Notes that were already in the collection and were left alone.
+    pub skipped: usize,
+    /// Answers added to the review history.
+    pub reviews: usize,
+    /// Media files copied into the media folder.
+    pub media: usize,
+}
+
+impl std::fmt::Display for ImportSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} notes added, {} updated, {} skipped",
+            self.added, self.updated, self.skipped
+        )?;
+
+        if self.reviews > 0 {
+            write!(f, ", {} reviews", self.reviews)?;
+        }
+        if self.media > 0 {
+            write!(f, ", {} media files", self.media)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// What an export wrote.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExportSummary {
+    pub cards: usize,
+    pub reviews: usize,
+    pub media: usize,
+}
+
+impl std::fmt::Display for ExportSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} cards exported", self.cards)?;
+
+        if self.reviews > 0 {
+            write!(f, ", {} reviews", self.reviews)?;
+        }
+        if self.media > 0 {
+            write!(f, ", {} media files", self.media)?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,13 +1,17 @@
 mod action;
 mod assets;
+mod cli;
 mod components;
 mod db;
 mod errors;
+mod import_export;
 mod models;
 mod ngurra;
 mod repositories;
+mod search;
 mod state;
 mod storage;
+mod sync;
 mod theme;
 mod ui;
 
@@ -16,34 +20,116 @@ use ngurra::Ngurra;
 pub use repositories::deck::Deck;
 pub use repositories::flash_card::FlashCard;
 
-use std::{
-    io::{IsTerminal, Write},
-    path::PathBuf,
-};
+use std::io::{IsTerminal, Write};
 
 use crate::{
     assets::Assets,
+    errors::ErrorState,
     models::{
+        browse::{load_rows, Column},
         builder::Builder,
         collection::{Collection, CollectionBuilder},
     },
+    storage::profile::{Profiles, LEGACY_COLLECTION},
     theme::Theme,
 };
 
 fn main() {
-    let collection = CollectionBuilder::new(PathBuf::from("ngurra.db"))
-        .build()
-        .unwrap_or_else(|e| {
-            panic!("Error opening collection: {:?}", e);
-        });
+    let args = cli::Args::parse().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    });
 
     init_logger();
 
+    let profiles = Profiles::new(args.base.unwrap_or_else(Profiles::default_base));
+    match profiles.adopt_legacy(std::path::Path::new(LEGACY_COLLECTION)) {
+        Ok(Some(path)) => log::info!(
+            "Copied the collection from {} into the default profile at {:?}",
+            LEGACY_COLLECTION,
+            path
+        ),
+        Ok(None) => {}
+        Err(e) => log::error!(
+            "Error copying {} into the default profile: {}",
+            LEGACY_COLLECTION,
+            e
+        ),
+    }
+    let profile = args.profile.or_else(|| profiles.sole_profile());
+
+    let mut collection = profile.map(|name| {
+        CollectionBuilder::for_profile(&profiles, &name)
+            .unwrap_or_else(|e| {
+                eprintln!("Error creating profile {}: {}", name, e);
+                std::process::exit(1);
+            })
+            .build()
+            .unwrap_or_else(|e| {
+                eprintln!("Error opening collection: {}", e);
+                std::process::exit(1);
+            })
+    });
+
+    if let Some(backup_path) = args.restore {
+        let Some(collection) = collection.as_mut() else {
+            eprintln!("--restore needs --profile when there are several profiles");
+            std::process::exit(2);
+        };
+
+        match collection.restore_backup(&backup_path) {
+            Ok(previous) => log::info!(
+                "Restored {:?}, previous collection saved to {:?}",
+                backup_path,
+                previous
+            ),
+            Err(e) => {
+                eprintln!("Error restoring backup: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(package_path) = args.import {
+        let Some(collection) = collection.as_mut() else {
+            eprintln!("--import needs --profile when there are several profiles");
+            std::process::exit(2);
+        };
+
+        match collection.import_anki_package(&package_path) {
+            Ok(summary) => log::info!("Imported {:?}: {}", package_path, summary),
+            Err(e) => {
+                eprintln!("Error importing {:?}: {}", package_path, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(query) = args.search {
+        let Some(collection) = collection.as_ref() else {
+            eprintln!("--search needs --profile when there are several profiles");
+            std::process::exit(2);
+        };
+
+        if let Err(e) = print_search(collection, &query) {
+            eprintln!("Error searching cards: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     log::info!("========== starting Ngurra ==========");
     App::new().with_assets(Assets).run(|cx: &mut AppContext| {
         ngurra::init(cx);
         Theme::init(cx);
-        Collection::init(collection, cx);
+        ErrorState::init(cx);
+        cx.set_global(profiles);
+
+        if let Some(collection) = collection {
+            Collection::init(collection, cx);
+            Collection::schedule_backups(cx);
+            Collection::sync_folder_on_start(cx);
+        }
 
         cx.open_window(
             WindowOptions {
@@ -75,6 +161,23 @@ fn main() {
     });
 }
 
+/// Prints the id, deck and question of each card matching the search, one
+/// card per line separated by tabs.
+fn print_search(collection: &Collection, query: &str) -> errors::Result<()> {
+    let rows = load_rows(&collection.storage.conn, query, &collection.timing)?;
+
+    for row in rows {
+        println!(
+            "{}\t{}\t{}",
+            row.card_id,
+            row.deck,
+            row.cell(Column::Question, collection.timing.days_elapsed)
+        );
+    }
+
+    Ok(())
+}
+
 fn init_logger() {
     if stdout_is_pty() {
         env_logger::builder()
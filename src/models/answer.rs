@@ -1,6 +1,19 @@
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Answer {
     Again,
     Hard,
     Good,
     Easy,
 }
+
+impl Answer {
+    /// The button number recorded in the review log, 1 (again) to 4 (easy).
+    pub fn ease(&self) -> u8 {
+        match self {
+            Answer::Again => 1,
+            Answer::Hard => 2,
+            Answer::Good => 3,
+            Answer::Easy => 4,
+        }
+    }
+}
@@ -0,0 +1,273 @@
+//! The rows of the card browser: one per card, with the values of every
+//! column, and the ways they can be sorted and filtered.
+
+use std::{cmp::Ordering, time::SystemTime};
+
+use chrono::{DateTime, Local};
+use rusqlite::{params_from_iter, Connection};
+
+use crate::{
+    errors::Result,
+    models::timing::SchedTimingToday,
+    repositories::{flash_card::CardQueue, timestamps::time_from_sql},
+    search,
+};
+
+const ROWS_QUERY: &str = "SELECT c.id, c.question, c.answer, COALESCE(d.name, ''), c.queue, c.due, c.interval, c.ef, c.creation_time,
+    (SELECT MAX(r.id) FROM revlog r WHERE r.card_id = c.id), c.suspended, c.flags
+FROM cards c LEFT JOIN decks d ON d.id = c.deck_id";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Column {
+    Question,
+    Answer,
+    Deck,
+    Due,
+    Interval,
+    Ease,
+    Queue,
+    Created,
+    LastReview,
+}
+
+impl Column {
+    pub const ALL: [Column; 9] = [
+        Column::Question,
+        Column::Answer,
+        Column::Deck,
+        Column::Due,
+        Column::Interval,
+        Column::Ease,
+        Column::Queue,
+        Column::Created,
+        Column::LastReview,
+    ];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Column::Question => "Question",
+            Column::Answer => "Answer",
+            Column::Deck => "Deck",
+            Column::Due => "Due",
+            Column::Interval => "Interval",
+            Column::Ease => "Ease",
+            Column::Queue => "Queue",
+            Column::Created => "Created",
+            Column::LastReview => "Last review",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CardRow {
+    pub card_id: u32,
+    pub question: String,
+    pub answer: String,
+    pub deck: String,
+    pub queue: CardQueue,
+    /// The position of a new card, or the day a review card is due on.
+    pub due: u32,
+    pub interval: u32,
+    pub ease: f32,
+    pub created: SystemTime,
+    /// Milliseconds since the epoch of the latest answer, if any.
+    pub last_review: Option<i64>,
+    pub suspended: bool,
+    /// From 1 to `bulk::MAX_FLAG`, or 0 without a flag.
+    pub flags: u8,
+}
+
+impl CardRow {
+    /// The text shown in a column, with `today` counted like
+    /// `SchedTimingToday::days_elapsed`.
+    pub fn cell(&self, column: Column, today: u32) -> String {
+        match column {
+            Column::Question => one_line(&self.question),
+            Column::Answer => one_line(&self.answer),
+            Column::Deck => self.deck.clone(),
+            Column::Due => match self.queue {
+                CardQueue::New => format!("New #{}", self.due),
+                CardQueue::Learning => "Learning".to_string(),
+                CardQueue::Review => match self.due as i64 - today as i64 {
+                    0 => "Today".to_string(),
+                    1 => "Tomorrow".to_string(),
+                    days if days > 0 => format!("In {} days", days),
+                    days => format!("{} days ago", -days),
+                },
+            },
+            Column::Interval => match self.queue {
+                CardQueue::Review => format!("{}d", self.interval),
+                _ => String::new(),
+            },
+            Column::Ease => format!("{:.0}%", self.ease * 100.0),
+            Column::Queue if self.suspended => "Suspended".to_string(),
+            Column::Queue => format!("{:?}", self.queue),
+            Column::Created => DateTime::<Local>::from(self.created)
+                .format("%Y-%m-%d")
+                .to_string(),
+            Column::LastReview => self
+                .last_review
+                .and_then(DateTime::from_timestamp_millis)
+                .map(|time| time.with_timezone(&Local).format("%Y-%m-%d").to_string())
+                .unwrap_or_default(),
+        }
+    }
+
+    fn compare(&self, other: &CardRow, column: Column) -> Ordering {
+        match column {
+            Column::Question => self.question.to_lowercase().cmp(&other.question.to_lowercase()),
+            Column::Answer => self.answer.to_lowercase().cmp(&other.answer.to_lowercase()),
+            Column::Deck => self.deck.to_lowercase().cmp(&other.deck.to_lowercase()),
+            Column::Due | Column::Queue => (self.queue.clone() as u8, self.due)
+                .cmp(&(other.queue.clone() as u8, other.due)),
+            Column::Interval => self.interval.cmp(&other.interval),
+            Column::Ease => self.ease.total_cmp(&other.ease),
+            Column::Created => self.created.cmp(&other.created),
+            Column::LastReview => self.last_review.cmp(&other.last_review),
+        }
+    }
+}
+
+/// Fields can span several lines; a table cell shows them on one.
+pub fn one_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Loads a row for every card matching a search, see `crate::search`. An
+/// empty search matches every card.
+pub fn load_rows(
+    conn: &Connection,
+    query: &str,
+    timing: &SchedTimingToday,
+) -> Result<Vec<CardRow>> {
+    let filter = search::compile(query, timing)?;
+    let sql = format!("{} WHERE {} ORDER BY c.id", ROWS_QUERY, filter.clause);
+
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt
+        .query_map(params_from_iter(filter.params), |row| {
+            Ok(CardRow {
+                card_id: row.get(0)?,
+                question: row.get(1)?,
+                answer: row.get(2)?,
+                deck: row.get(3)?,
+                queue: row.get(4)?,
+                due: row.get(5)?,
+                interval: row.get(6)?,
+                ease: row.get(7)?,
+                created: time_from_sql(row, 8)?,
+                last_review: row.get(9)?,
+                suspended: row.get(10)?,
+                flags: row.get(11)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(rows)
+}
+
+/// Sorts the rows by a column. Rows that compare equal keep their order.
+pub fn sort_rows(rows: &mut [CardRow], column: Column, ascending: bool) {
+    rows.sort_by(|a, b| {
+        let ordering = a.compare(b, column);
+        if ascending {
+            ordering
+        } else {
+            ordering.reverse()
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use rusqlite::Connection;
+
+    use crate::{db::init_db, repositories::revlog::RevlogEntry, Deck, FlashCard};
+
+    use super::*;
+
+    const TIMING: SchedTimingToday = SchedTimingToday {
+        now: 1_700_000_000,
+        days_elapsed: 10,
+        next_day_at: 11 * 86_400,
+    };
+
+    fn setup() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+
+        let mut spanish = Deck::new("Spanish");
+        spanish.save(&conn).unwrap();
+        let mut french = Deck::new("French");
+        french.save(&conn).unwrap();
+
+        FlashCard::new(spanish.id.unwrap(), "perro", "dog", None)
+            .save(&conn)
+            .unwrap();
+        let mut review = FlashCard::new(french.id.unwrap(), "chat\nnoir", "black cat", None);
+        review.set_queue(CardQueue::Review);
+        review.interval = 30;
+        review.due = 12;
+        review.save(&conn).unwrap();
+        RevlogEntry::new(review.id.unwrap(), 3, 30, 1)
+            .save(&conn)
+            .unwrap();
+
+        conn
+    }
+
+    #[test]
+    fn rows_carry_deck_and_last_review() {
+        let conn = setup();
+        let rows = load_rows(&conn, "", &TIMING).unwrap();
+
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].deck, "Spanish");
+        assert_eq!(rows[0].last_review, None);
+        assert!(rows[1].last_review.is_some());
+        assert_eq!(rows[1].cell(Column::Question, 10), "chat noir");
+        assert_eq!(rows[1].cell(Column::Due, 10), "In 2 days");
+        assert_eq!(rows[1].cell(Column::Interval, 10), "30d");
+        assert_eq!(rows[0].cell(Column::Ease, 10), "250%");
+
+        conn.execute("UPDATE cards SET suspended = 1, flags = 2", [])
+            .unwrap();
+        let rows = load_rows(&conn, "", &TIMING).unwrap();
+        assert_eq!(rows[0].flags, 2);
+        assert_eq!(rows[1].cell(Column::Queue, 10), "Suspended");
+    }
+
+    #[test]
+    fn rows_match_the_search() {
+        let conn = setup();
+
+        let matching = |query: &str| {
+            load_rows(&conn, query, &TIMING)
+                .unwrap()
+                .into_iter()
+                .map(|row| row.question)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(matching("DOG"), vec!["perro"]);
+        assert_eq!(matching("deck:french"), vec!["chat\nnoir"]);
+        assert_eq!(matching("prop:ivl>7 OR perro").len(), 2);
+        assert!(matching("100%").is_empty());
+        assert!(load_rows(&conn, "is:", &TIMING).is_err());
+    }
+
+    #[test]
+    fn rows_sort_by_any_column() {
+        let conn = setup();
+        let mut rows = load_rows(&conn, "", &TIMING).unwrap();
+
+        sort_rows(&mut rows, Column::Deck, true);
+        assert_eq!(rows[0].deck, "French");
+
+        sort_rows(&mut rows, Column::Interval, false);
+        assert_eq!(rows[0].interval, 30);
+
+        sort_rows(&mut rows, Column::Due, true);
+        assert!(matches!(rows[0].queue, CardQueue::New));
+    }
+}
@@ -0,0 +1,510 @@
+//! Changes made to many cards at once, such as the cards selected in the
+//! browser. Each returns how many cards it changed; cards the change would
+//! leave as they are, like suspending a suspended card, are not counted.
+
+use std::collections::BTreeMap;
+
+use rusqlite::{named_params, Connection, ToSql};
+
+use crate::{
+    errors::{NgurraError, Result},
+    repositories::{
+        flash_card::CardQueue,
+        guid::PENDING_USN,
+        note::{split_tags, Note},
+        timestamps::mtime_now,
+    },
+    FlashCard,
+};
+
+use super::{deck, tag};
+
+/// The highest flag; 0 means no flag.
+pub const MAX_FLAG: u8 = 7;
+
+/// The furthest a card can be pushed into the future, about a hundred years.
+pub const MAX_DUE_DAYS: u32 = 36_500;
+
+/// When cards should next be due: a number of days from today picked in
+/// `min..=max`, spreading the cards over the range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DueDays {
+    pub min: u32,
+    pub max: u32,
+    /// Whether the interval becomes the number of days too. Cards that were
+    /// not in review yet always take it.
+    pub set_interval: bool,
+}
+
+impl DueDays {
+    /// Reads `3` for in three days, `0` for today, or `1-7` for a day in the
+    /// coming week. A trailing `!` sets the interval as well.
+    pub fn parse(input: &str) -> Result<DueDays> {
+        let input = input.trim();
+        let (days, set_interval) = match input.strip_suffix('!') {
+            Some(days) => (days, true),
+            None => (input, false),
+        };
+
+        let number = |text: &str| text.trim().parse::<u32>().ok();
+        let range = match days.split_once('-') {
+            Some((min, max)) => number(min).zip(number(max)),
+            None => number(days).map(|day| (day, day)),
+        };
+
+        match range {
+            Some((_, max)) if max > MAX_DUE_DAYS => Err(NgurraError::Parse(format!(
+                "due days '{}': at most {} days",
+                input, MAX_DUE_DAYS
+            ))),
+            Some((min, max)) if min <= max => Ok(DueDays {
+                min,
+                max,
+                set_interval,
+            }),
+            _ => Err(NgurraError::Parse(format!(
+                "due days '{}': expected a number of days like 3 or a range like 1-7",
+                input
+            ))),
+        }
+    }
+}
+
+/// Runs `UPDATE cards SET {change}` on every card for which `only` holds,
+/// bumping its mtime. `change` and `only` can use the named `params`.
+fn update_cards(
+    conn: &Connection,
+    card_ids: &[u32],
+    change: &str,
+    only: &str,
+    params: &[(&str, &dyn ToSql)],
+) -> Result<usize> {
+    let mut stmt = conn.prepare(&format!(
+        "UPDATE cards SET {}, mtime = :mtime, usn = :usn WHERE id = :id AND ({})",
+        change, only
+    ))?;
+    let mtime = mtime_now();
+
+    let mut changed = 0;
+    for id in card_ids {
+        let mut all = params.to_vec();
+        all.extend([
+            (":mtime", &mtime as &dyn ToSql),
+            (":usn", &PENDING_USN as &dyn ToSql),
+            (":id", id as &dyn ToSql),
+        ]);
+        changed += stmt.execute(all.as_slice())?;
+    }
+
+    Ok(changed)
+}
+
+/// Moves the cards to the deck with the given name.
+pub fn set_deck(conn: &Connection, card_ids: &[u32], deck_name: &str) -> Result<usize> {
+    let deck_id = deck::find_deck_id(conn, deck_name)?;
+
+    update_cards(
+        conn,
+        card_ids,
+        "deck_id = :deck_id",
+        "deck_id != :deck_id",
+        named_params! {":deck_id": deck_id},
+    )
+}
+
+/// Changes the tags of the notes of the cards, saving the notes whose tags
+/// changed.
+///
+/// # Returns
+///
+/// How many of the cards had their note changed.
+fn retag_cards(
+    conn: &Connection,
+    card_ids: &[u32],
+    change: impl Fn(&[String]) -> Vec<String>,
+) -> Result<usize> {
+    let mut stmt = conn.prepare("SELECT note_id FROM cards WHERE id = ? AND note_id != 0")?;
+    let mut cards_by_note = BTreeMap::<u32, usize>::new();
+    for id in card_ids {
+        let note_ids = stmt
+            .query_map([id], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<u32>>>()?;
+        for note_id in note_ids {
+            *cards_by_note.entry(note_id).or_default() += 1;
+        }
+    }
+
+    let mut changed = 0;
+    for (note_id, cards) in cards_by_note {
+        let tags: String =
+            conn.query_row("SELECT tags FROM notes WHERE id = ?", [note_id], |row| {
+                row.get(0)
+            })?;
+        let old = split_tags(&tags);
+        let new = change(&old);
+
+        if new != old {
+            let mut note = Note::load(note_id, conn)?;
+            note.tags = new;
+            note.save(conn)?;
+            changed += cards;
+        }
+    }
+
+    Ok(changed)
+}
+
+pub fn add_tags(conn: &Connection, card_ids: &[u32], tags: &[String]) -> Result<usize> {
+    retag_cards(conn, card_ids, |old| {
+        let mut new = old.to_vec();
+        tag::add(&mut new, tags.iter().cloned());
+        new
+    })
+}
+
+/// Removes the tags, and the tags below them, from the notes of the cards.
+pub fn remove_tags(conn: &Connection, card_ids: &[u32], tags: &[String]) -> Result<usize> {
+    retag_cards(conn, card_ids, |old| {
+        old.iter()
+            .filter(|kept| !tags.iter().any(|removed| tag::is_within(kept, removed)))
+            .cloned()
+            .collect()
+    })
+}
+
+/// Keeps the cards out of study, or lets them back in, without touching
+/// their scheduling.
+pub fn set_suspended(conn: &Connection, card_ids: &[u32], suspended: bool) -> Result<usize> {
+    update_cards(
+        conn,
+        card_ids,
+        "suspended = :suspended",
+        "suspended != :suspended",
+        named_params! {":suspended": suspended},
+    )
+}
+
+pub fn set_flag(conn: &Connection, card_ids: &[u32], flag: u8) -> Result<usize> {
+    if flag > MAX_FLAG {
+        return Err(NgurraError::Parse(format!(
+            "flag {}: expected 0 to {}",
+            flag, MAX_FLAG
+        )));
+    }
+
+    update_cards(
+        conn,
+        card_ids,
+        "flags = :flag",
+        "flags != :flag",
+        named_params! {":flag": flag},
+    )
+}
+
+/// The position after the last new card.
+fn next_new_position(conn: &Connection) -> Result<u32> {
+    Ok(conn.query_row(
+        "SELECT COALESCE(MAX(due), -1) + 1 FROM cards WHERE queue = ?",
+        [CardQueue::New as u8],
+        |row| row.get(0),
+    )?)
+}
+
+/// Makes the cards new again, after the cards that are new already. Their
+/// review history is kept.
+pub fn reset_to_new(conn: &Connection, card_ids: &[u32]) -> Result<usize> {
+    let mut position = next_new_position(conn)?;
+    let mut changed = 0;
+
+    for id in card_ids {
+        let reset = update_cards(
+            conn,
+            &[*id],
+            "queue = :new, due = :position, interval = 1, ef = 2.5, data = '{}', buried_until = 0",
+            "queue != :new",
+            named_params! {":new": CardQueue::New as u8, ":position": position},
+        )?;
+        position += reset as u32;
+        changed += reset;
+    }
+
+    Ok(changed)
+}
+
+/// Makes the cards review cards due on a day picked from `days`, counted
+/// from `today` like `SchedTimingToday::days_elapsed`.
+pub fn set_due_date(
+    conn: &Connection,
+    card_ids: &[u32],
+    days: DueDays,
+    today: u32,
+) -> Result<usize> {
+    let span = days.max - days.min + 1;
+    let mut changed = 0;
+
+    for (ix, id) in card_ids.iter().enumerate() {
+        let offset = days.min + ix as u32 % span;
+
+        changed += update_cards(
+            conn,
+            &[*id],
+            "interval = CASE WHEN :set_interval OR queue != :review THEN :interval ELSE interval END,
+                queue = :review, due = :due, buried_until = 0",
+            "1",
+            named_params! {
+                ":set_interval": days.set_interval,
+                ":review": CardQueue::Review as u8,
+                ":interval": offset.max(1),
+                ":due": today + offset,
+            },
+        )?;
+    }
+
+    Ok(changed)
+}
+
+/// Gives the new cards among the cards the positions `start`, `start +
+/// step` and so on, keeping their order. With `shift`, the other new cards
+/// from `start` on move back to make room.
+pub fn reposition(
+    conn: &Connection,
+    card_ids: &[u32],
+    start: u32,
+    step: u32,
+    shift: bool,
+) -> Result<usize> {
+    let step = step.max(1);
+
+    let mut stmt = conn.prepare("SELECT due FROM cards WHERE id = ? AND queue = ?")?;
+    let mut new_cards = Vec::new();
+    for id in card_ids {
+        let due = stmt
+            .query_map((id, CardQueue::New as u8), |row| row.get::<_, u32>(0))?
+            .next()
+            .transpose()?;
+        if let Some(due) = due {
+            new_cards.push((due, *id));
+        }
+    }
+    new_cards.sort();
+
+    if shift && !new_cards.is_empty() {
+        conn.execute(
+            "UPDATE cards SET due = due + ?, mtime = ?, usn = ? WHERE queue = ? AND due >= ?",
+            (
+                new_cards.len() as u32 * step,
+                mtime_now(),
+                PENDING_USN,
+                CardQueue::New as u8,
+                start,
+            ),
+        )?;
+    }
+
+    for (ix, (_, id)) in new_cards.iter().enumerate() {
+        update_cards(
+            conn,
+            &[*id],
+            "due = :position",
+            "1",
+            named_params! {":position": start + ix as u32 * step},
+        )?;
+    }
+
+    Ok(new_cards.len())
+}
+
+/// Deletes the cards, and the notes left without any card.
+pub fn delete_cards(conn: &Connection, card_ids: &[u32]) -> Result<usize> {
+    let mut note_ids = Vec::new();
+    let mut deleted = 0;
+
+    for id in card_ids {
+        let card = match FlashCard::load(*id, conn) {
+            Ok(card) => card,
+            Err(rusqlite::Error::QueryReturnedNoRows) => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        card.delete(conn)?;
+        deleted += 1;
+        if card.note_id != 0 {
+            note_ids.push(card.note_id);
+        }
+    }
+
+    note_ids.sort();
+    note_ids.dedup();
+    for note_id in note_ids {
+        let note = Note::load(note_id, conn)?;
+        if note.card_ids(conn)?.is_empty() {
+            note.delete(conn)?;
+        }
+    }
+
+    Ok(deleted)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        db::init_db,
+        models::note::add_note,
+        repositories::notetype::{Notetype, BASIC_AND_REVERSED_GUID},
+        Deck,
+    };
+
+    use super::*;
+
+    /// A deck with a "Basic (and reversed card)" note, whose two cards come
+    /// first, and a card without a note.
+    fn setup() -> (Connection, Vec<u32>) {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+
+        let mut deck = Deck::new("Spanish");
+        deck.save(&conn).unwrap();
+        Deck::new("French").save(&conn).unwrap();
+        let deck_id = deck.id.unwrap();
+
+        let reversed = Notetype::load_by_guid(BASIC_AND_REVERSED_GUID, &conn)
+            .unwrap()
+            .unwrap();
+        let mut note = Note::new(&reversed);
+        note.fields = vec!["perro".to_string(), "dog".to_string()];
+        note.tags = vec!["animals".to_string(), "lang::es".to_string()];
+        let mut ids = add_note(&conn, &mut note, deck_id)
+            .unwrap()
+            .into_iter()
+            .map(|card| card.id.unwrap())
+            .collect::<Vec<_>>();
+
+        let mut card = FlashCard::new(deck_id, "gato", "cat", None);
+        card.set_queue(CardQueue::Review);
+        card.interval = 20;
+        card.due = 30;
+        card.save(&conn).unwrap();
+        ids.push(card.id.unwrap());
+
+        (conn, ids)
+    }
+
+    fn column(conn: &Connection, column: &str, id: u32) -> i64 {
+        conn.query_row(
+            &format!("SELECT {} FROM cards WHERE id = ?", column),
+            [id],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn due_days_are_parsed() {
+        let days = |min, max, set_interval| DueDays {
+            min,
+            max,
+            set_interval,
+        };
+
+        assert_eq!(DueDays::parse("0").unwrap(), days(0, 0, false));
+        assert_eq!(DueDays::parse(" 1-7 ").unwrap(), days(1, 7, false));
+        assert_eq!(DueDays::parse("3!").unwrap(), days(3, 3, true));
+        assert!(DueDays::parse("7-1").is_err());
+        assert!(DueDays::parse("soon").is_err());
+        assert!(DueDays::parse("36500").is_ok());
+        assert!(DueDays::parse("36501").is_err());
+        assert!(DueDays::parse("0-4294967295").is_err());
+    }
+
+    #[test]
+    fn moving_suspending_and_flagging_count_changed_cards() {
+        let (conn, ids) = setup();
+
+        assert_eq!(set_deck(&conn, &ids[..2], "Spanish").unwrap(), 0);
+        assert_eq!(set_deck(&conn, &ids[..2], "French").unwrap(), 2);
+        assert!(matches!(
+            set_deck(&conn, &ids, "German"),
+            Err(NgurraError::Deck(_))
+        ));
+
+        assert_eq!(set_suspended(&conn, &ids[..1], true).unwrap(), 1);
+        assert_eq!(set_suspended(&conn, &ids, true).unwrap(), 2);
+        assert_eq!(column(&conn, "suspended", ids[2]), 1);
+        assert_eq!(set_suspended(&conn, &ids, false).unwrap(), 3);
+
+        assert_eq!(set_flag(&conn, &ids, 2).unwrap(), 3);
+        assert_eq!(column(&conn, "flags", ids[0]), 2);
+        assert!(set_flag(&conn, &ids, 8).is_err());
+    }
+
+    #[test]
+    fn tags_change_on_the_notes_of_the_cards() {
+        let (conn, ids) = setup();
+        let tags = |conn: &Connection| Note::load(1, conn).unwrap().tags;
+
+        assert_eq!(add_tags(&conn, &ids, &["Animals".to_string()]).unwrap(), 0);
+        assert_eq!(add_tags(&conn, &ids, &["verbs".to_string()]).unwrap(), 2);
+        assert_eq!(tags(&conn), vec!["animals", "lang::es", "verbs"]);
+
+        assert_eq!(
+            remove_tags(&conn, &ids[..1], &["lang".to_string()]).unwrap(),
+            1
+        );
+        assert_eq!(tags(&conn), vec!["animals", "verbs"]);
+    }
+
+    #[test]
+    fn reset_and_due_dates_change_scheduling() {
+        let (conn, ids) = setup();
+
+        assert_eq!(reset_to_new(&conn, &ids).unwrap(), 1);
+        assert_eq!(column(&conn, "queue", ids[2]), 0);
+        assert_eq!(column(&conn, "interval", ids[2]), 1);
+        assert_eq!(column(&conn, "due", ids[2]), 1);
+
+        let days = DueDays::parse("1-2").unwrap();
+        assert_eq!(set_due_date(&conn, &ids, days, 10).unwrap(), 3);
+        assert_eq!(column(&conn, "queue", ids[0]), 2);
+        assert_eq!(column(&conn, "due", ids[0]), 11);
+        assert_eq!(column(&conn, "due", ids[1]), 12);
+        assert_eq!(column(&conn, "due", ids[2]), 11);
+        assert_eq!(column(&conn, "interval", ids[1]), 2);
+
+        let days = DueDays::parse("5").unwrap();
+        set_due_date(&conn, &ids[..1], days, 10).unwrap();
+        assert_eq!(column(&conn, "interval", ids[0]), 1);
+        set_due_date(&conn, &ids[..1], DueDays::parse("5!").unwrap(), 10).unwrap();
+        assert_eq!(column(&conn, "interval", ids[0]), 5);
+    }
+
+    #[test]
+    fn reposition_orders_new_cards() {
+        let (conn, ids) = setup();
+        let mut other = FlashCard::new(1, "casa", "house", None);
+        other.due = 10;
+        other.save(&conn).unwrap();
+        let other = other.id.unwrap();
+
+        assert_eq!(reposition(&conn, &ids, 10, 5, true).unwrap(), 2);
+        assert_eq!(column(&conn, "due", ids[0]), 10);
+        assert_eq!(column(&conn, "due", ids[1]), 15);
+        assert_eq!(column(&conn, "due", other), 20);
+        assert_eq!(column(&conn, "due", ids[2]), 30);
+    }
+
+    #[test]
+    fn deleting_the_last_card_deletes_the_note() {
+        let (conn, ids) = setup();
+
+        assert_eq!(delete_cards(&conn, &ids[..1]).unwrap(), 1);
+        assert!(Note::load(1, &conn).is_ok());
+
+        assert_eq!(delete_cards(&conn, &ids).unwrap(), 2);
+        assert!(Note::load(1, &conn).is_err());
+
+        let graves: u32 = conn
+            .query_row("SELECT COUNT(*) FROM graves", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(graves, 4);
+    }
+}
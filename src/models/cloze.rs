@@ -0,0 +1,236 @@
+//! Cloze deletions: in `{{c1::Canberra}} is the capital of {{c2::Australia}}`
+//! each number makes its own card, which hides that number's text on the front
+//! and reveals it on the back. A hint can follow the answer, as in
+//! `{{c1::Canberra::city}}`.
+//!
+//! Clozes can be nested, so that the same text belongs to several cards:
+//! `{{c1::Canberra is {{c2::the capital}}}}`. Markers that are never closed, or
+//! closing braces without a marker, are kept as plain text.
+
+use std::collections::BTreeSet;
+
+use super::template::Side;
+
+/// Wraps the active cloze in a rendered card, so that the study view can
+/// highlight it. See `models::field::segments`.
+pub const ACTIVE_OPEN: &str = "<span class=\"cloze\">";
+pub const ACTIVE_CLOSE: &str = "</span>";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node<'a> {
+    Text(&'a str),
+    Cloze {
+        number: u32,
+        content: Vec<Node<'a>>,
+        hint: Option<&'a str>,
+    },
+}
+
+/// Reads the number of a `{{cN::` marker at the start of `text`, and the
+/// length of the marker.
+fn open_marker(text: &str) -> Option<(u32, usize)> {
+    let rest = text.strip_prefix("{{c")?;
+    let digits = rest.chars().take_while(char::is_ascii_digit).count();
+    let number = rest[..digits].parse::<u32>().ok().filter(|n| *n > 0)?;
+
+    rest[digits..]
+        .starts_with("::")
+        .then_some((number, "{{c".len() + digits + "::".len()))
+}
+
+/// Splits a hint off the cloze's own trailing text, leaving nested clozes in
+/// the answer.
+fn split_hint<'a>(mut content: Vec<Node<'a>>) -> (Vec<Node<'a>>, Option<&'a str>) {
+    if let Some(Node::Text(text)) = content.last() {
+        if let Some((answer, hint)) = text.split_once("::") {
+            let last = content.len() - 1;
+            content[last] = Node::Text(answer);
+            return (content, Some(hint));
+        }
+    }
+
+    (content, None)
+}
+
+fn parse(text: &str) -> Vec<Node<'_>> {
+    // Each open cloze keeps its number, its marker and the nodes that came
+    // before it.
+    let mut stack: Vec<(u32, &str, Vec<Node>)> = Vec::new();
+    let mut nodes = Vec::new();
+    let mut text_start = 0;
+    let mut at = 0;
+
+    while at < text.len() {
+        let rest = &text[at..];
+
+        if let Some((number, len)) = open_marker(rest) {
+            if at > text_start {
+                nodes.push(Node::Text(&text[text_start..at]));
+            }
+            stack.push((number, &rest[..len], std::mem::take(&mut nodes)));
+            at += len;
+            text_start = at;
+        } else if rest.starts_with("}}") && !stack.is_empty() {
+            if at > text_start {
+                nodes.push(Node::Text(&text[text_start..at]));
+            }
+            let (number, _, parent) = stack.pop().unwrap();
+            let (content, hint) = split_hint(std::mem::replace(&mut nodes, parent));
+            nodes.push(Node::Cloze {
+                number,
+                content,
+                hint,
+            });
+            at += "}}".len();
+            text_start = at;
+        } else {
+            at += rest.chars().next().map_or(1, char::len_utf8);
+        }
+    }
+
+    if text.len() > text_start {
+        nodes.push(Node::Text(&text[text_start..]));
+    }
+
+    while let Some((_, marker, parent)) = stack.pop() {
+        let content = std::mem::replace(&mut nodes, parent);
+        nodes.push(Node::Text(marker));
+        nodes.extend(content);
+    }
+
+    nodes
+}
+
+fn collect_numbers(nodes: &[Node], numbers: &mut BTreeSet<u32>) {
+    for node in nodes {
+        if let Node::Cloze {
+            number, content, ..
+        } = node
+        {
+            numbers.insert(*number);
+            collect_numbers(content, numbers);
+        }
+    }
+}
+
+/// The cloze numbers used in the text, in order.
+pub fn numbers(text: &str) -> BTreeSet<u32> {
+    let mut numbers = BTreeSet::new();
+    collect_numbers(&parse(text), &mut numbers);
+    numbers
+}
+
+/// Writes the text with every cloze revealed.
+fn reveal(nodes: &[Node], out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Cloze { content, .. } => reveal(content, out),
+        }
+    }
+}
+
+fn render_nodes(nodes: &[Node], active: u32, side: Side, out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Cloze {
+                number,
+                content,
+                hint,
+            } if *number == active => {
+                out.push_str(ACTIVE_OPEN);
+                match side {
+                    Side::Question => {
+                        out.push('[');
+                        out.push_str(hint.unwrap_or("..."));
+                        out.push(']');
+                    }
+                    Side::Answer => reveal(content, out),
+                }
+                out.push_str(ACTIVE_CLOSE);
+            }
+            Node::Cloze { content, .. } => render_nodes(content, active, side, out),
+        }
+    }
+}
+
+/// Renders the text for the card of cloze number `active`: that cloze is
+/// hidden on the question and revealed on the answer, and marked on both
+/// sides. Other clozes show their text.
+pub fn render(text: &str, active: u32, side: Side) -> String {
+    let mut out = String::new();
+    render_nodes(&parse(text), active, side, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn marked(text: &str) -> String {
+        format!("{}{}{}", ACTIVE_OPEN, text, ACTIVE_CLOSE)
+    }
+
+    #[test]
+    fn each_number_hides_its_own_text() {
+        let text = "{{c1::Canberra}} is the capital of {{c2::Australia}}";
+
+        assert_eq!(numbers(text).into_iter().collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(
+            render(text, 1, Side::Question),
+            format!("{} is the capital of Australia", marked("[...]"))
+        );
+        assert_eq!(
+            render(text, 2, Side::Answer),
+            format!("Canberra is the capital of {}", marked("Australia"))
+        );
+    }
+
+    #[test]
+    fn hints_replace_the_placeholder() {
+        let text = "{{c1::Canberra::city}} is a capital";
+
+        assert_eq!(
+            render(text, 1, Side::Question),
+            format!("{} is a capital", marked("[city]"))
+        );
+        assert_eq!(
+            render(text, 1, Side::Answer),
+            format!("{} is a capital", marked("Canberra"))
+        );
+    }
+
+    #[test]
+    fn nested_clozes_belong_to_several_cards() {
+        let text = "{{c1::Canberra is {{c2::the capital::what}}}} of Australia";
+
+        assert_eq!(numbers(text).into_iter().collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(
+            render(text, 1, Side::Question),
+            format!("{} of Australia", marked("[...]"))
+        );
+        assert_eq!(
+            render(text, 1, Side::Answer),
+            format!("{} of Australia", marked("Canberra is the capital"))
+        );
+        assert_eq!(
+            render(text, 2, Side::Question),
+            format!("Canberra is {} of Australia", marked("[what]"))
+        );
+    }
+
+    #[test]
+    fn unbalanced_markers_are_text() {
+        assert_eq!(render("{{c1::open", 1, Side::Question), "{{c1::open");
+        assert_eq!(
+            render("close}} {{c0::zero}}", 1, Side::Question),
+            "close}} {{c0::zero}}"
+        );
+        assert_eq!(
+            render("{{c1::a {{c2::b}} c", 2, Side::Answer),
+            format!("{{{{c1::a {} c", marked("b"))
+        );
+        assert!(numbers("{{c1::open").is_empty());
+    }
+}
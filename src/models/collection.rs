@@ -1,4 +1,8 @@
-use std::path::PathBuf;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use chrono::Local;
 use gpui::{AppContext, Global};
@@ -6,15 +10,45 @@ use rusqlite::Connection;
 
 use crate::{
     db::init_db,
-    errors::Result,
-    repositories::{flash_card::CardQueue, session::Session},
-    storage::{self, sqlite::SqliteStorage},
-    FlashCard,
+    errors::{ErrorState, NgurraError, Result},
+    import_export::{
+        apkg,
+        apkg_export::{self, ExportOptions},
+        delimited::{self, DelimitedFile, DelimitedImport},
+        ExportSummary, ImportSummary,
+    },
+    repositories::{
+        flash_card::CardQueue, note::Note, notetype::Notetype, revlog::RevlogEntry,
+        session::Session,
+    },
+    search::{
+        self,
+        fts::{self, Highlight},
+    },
+    storage::{
+        backup::{self, BackupLimits},
+        media::{self, MediaCheck},
+        profile::Profiles,
+        sqlite::SqliteStorage,
+    },
+    sync::{changes, journal},
+    Deck, FlashCard,
 };
 
 use super::{
-    answer::Answer, builder::Builder, card::get_current_card_state, queue::Queue,
-    states::card_state::CardState, timing::SchedTimingToday,
+    answer::Answer,
+    builder::Builder,
+    bulk::{self, DueDays},
+    card::get_current_card_state,
+    deck,
+    find_replace::{self, FieldChange, FindReplace},
+    note,
+    queue::Queue,
+    saved_search::{self, SavedSearch},
+    states::card_state::CardState,
+    tag::{self, TagNode},
+    timing::SchedTimingToday,
+    undo::{self, UndoStep},
 };
 
 pub struct CollectionBuilder {
@@ -28,16 +62,26 @@ impl CollectionBuilder {
         }
     }
 
+    /// Builds the collection stored in the given profile's folder.
+    pub fn for_profile(profiles: &Profiles, name: &str) -> std::io::Result<Self> {
+        Ok(Self::new(profiles.collection_path(name)?))
+    }
+
     pub(crate) fn timing_for_timestamp(conn: &Connection, now: i64) -> SchedTimingToday {
         // Get current utc offset from the system
         let creation_stamp_res = Session::get_creation_stamp(conn);
 
-        let creation_stamp = if let Err(e) = creation_stamp_res {
-            println!("Error getting creation stamp: {:?}", e);
-            Session::set_creation_stamp(conn, now).unwrap();
-            now
-        } else {
-            creation_stamp_res.unwrap()
+        let creation_stamp = match creation_stamp_res {
+            Ok(creation_stamp) => creation_stamp,
+            Err(e) => {
+                log::warn!("Error getting creation stamp: {:?}", e);
+
+                if let Err(e) = Session::set_creation_stamp(conn, now) {
+                    log::error!("Error saving creation stamp: {:?}", e);
+                }
+
+                now
+            }
         };
 
         let days_elapsed = (now - creation_stamp) / 86_400;
@@ -64,9 +108,9 @@ impl Builder for CollectionBuilder {
 
         let storage = SqliteStorage::open_or_create(&col_path)?;
 
-        init_db(&storage.conn).unwrap_or_else(|e| {
-            panic!("Error initializing database: {:?}", e);
-        });
+        if !storage.read_only {
+            init_db(&storage.conn)?;
+        }
 
         let timing = Self::timing_for_timestamp(&storage.conn, Local::now().timestamp());
 
@@ -75,6 +119,8 @@ impl Builder for CollectionBuilder {
             col_path,
             card_queues: None,
             timing,
+            backup_limits: BackupLimits::default(),
+            undo: None,
         };
 
         Ok(col)
@@ -86,6 +132,9 @@ pub struct Collection {
     pub col_path: PathBuf,
     pub card_queues: Option<Queue>,
     pub timing: SchedTimingToday,
+    pub backup_limits: BackupLimits,
+    /// How to take back the last change made to many cards at once.
+    undo: Option<UndoStep>,
 }
 
 impl Collection {
@@ -93,6 +142,499 @@ impl Collection {
         cx.set_global(col);
     }
 
+    /// Backs up the collection periodically while the app is running, and
+    /// once more when it quits.
+    pub fn schedule_backups(cx: &mut AppContext) {
+        let interval = cx
+            .global::<Collection>()
+            .backup_limits
+            .minimum_interval_mins as u64;
+
+        cx.spawn(|cx| async move {
+            loop {
+                cx.background_executor()
+                    .timer(Duration::from_secs(interval * 60))
+                    .await;
+
+                if cx.update(|cx| Self::backup_global(false, cx)).is_err() {
+                    break;
+                }
+            }
+        })
+        .detach();
+
+        cx.on_app_quit(|cx| {
+            Self::backup_global(true, cx);
+            async {}
+        })
+        .detach();
+    }
+
+    /// Catches up with the other devices' journals when a sync folder is set
+    /// up. This runs in the background, through a connection of its own.
+    pub fn sync_folder_on_start(cx: &mut AppContext) {
+        let collection = cx.global::<Collection>();
+        if collection.is_read_only() {
+            return;
+        }
+
+        let path = collection.col_path.clone();
+        let task = cx
+            .background_executor()
+            .spawn(async move { journal::sync_folder_at(&path) });
+
+        cx.spawn(|cx| async move {
+            let result = task.await;
+
+            cx.update(|cx| match result {
+                Ok(Some(_)) => {
+                    cx.update_global(|collection: &mut Collection, _| collection.reload());
+                    cx.refresh();
+                }
+                Ok(None) => {}
+                Err(e) => ErrorState::report("Error syncing with the shared folder", e, cx),
+            })
+            .ok();
+        })
+        .detach();
+    }
+
+    fn backup_global(force: bool, cx: &mut AppContext) {
+        if let Err(e) = cx.global::<Collection>().maybe_backup(force) {
+            ErrorState::report("Error backing up collection", e, cx);
+        }
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.storage.read_only
+    }
+
+    pub fn backups_folder(&self) -> PathBuf {
+        backup::backups_folder(&self.col_path)
+    }
+
+    pub fn media_folder(&self) -> PathBuf {
+        media::media_folder(&self.col_path)
+    }
+
+    /// Copies a file into the media folder and returns the name cards should
+    /// reference it by.
+    pub fn add_media(&self, source: &Path) -> Result<String> {
+        if self.is_read_only() {
+            return Err(NgurraError::CollectionReadOnly);
+        }
+
+        media::add_file(&self.storage.conn, &self.media_folder(), source)
+    }
+
+    pub fn check_media(&self) -> Result<MediaCheck> {
+        media::check(&self.storage.conn, &self.media_folder())
+    }
+
+    /// Deletes media files, e.g. the unused ones found by `check_media`.
+    pub fn delete_media(&self, filenames: &[String]) -> Result<usize> {
+        if self.is_read_only() {
+            return Err(NgurraError::CollectionReadOnly);
+        }
+
+        media::delete_files(&self.storage.conn, &self.media_folder(), filenames)
+    }
+
+    pub fn notetypes(&self) -> Result<Vec<Notetype>> {
+        Ok(Notetype::get_all(&self.storage.conn)?)
+    }
+
+    /// Saves a new note and adds the cards it generates to the deck.
+    pub fn add_note(&mut self, note: &mut Note, deck_id: u32) -> Result<Vec<FlashCard>> {
+        self.change(|conn| note::add_note(conn, note, deck_id))
+    }
+
+    /// Saves an edited note and re-renders its cards.
+    pub fn update_note(&mut self, note: &mut Note) -> Result<usize> {
+        self.change(|conn| note::update_note(conn, note))
+    }
+
+    /// Saves the edits made to a card and its note, and moves the card to
+    /// the named deck.
+    pub fn edit_card(
+        &mut self,
+        card_id: u32,
+        fields: &[String],
+        tags: &[String],
+        deck_name: &str,
+    ) -> Result<FlashCard> {
+        self.change(|conn| note::edit_card(conn, card_id, fields, tags, deck_name))
+    }
+
+    /// The ids of the cards matching a search, see `crate::search`.
+    pub fn search_cards(&self, query: &str) -> Result<Vec<u32>> {
+        search::search_cards(&self.storage.conn, query, &self.timing)
+    }
+
+    /// The question and answer of each card matching the text of a search,
+    /// with the matched words marked, see `fts::highlights`.
+    pub fn search_highlights(&self, query: &str) -> Result<HashMap<u32, Highlight>> {
+        let node = search::parse(query)?;
+        Ok(fts::highlights(&self.storage.conn, &node)?)
+    }
+
+    /// Indexes the text of every card again for searching.
+    pub fn rebuild_search_index(&self) -> Result<()> {
+        self.write(|conn| Ok(fts::rebuild_index(conn)?))
+    }
+
+    pub fn saved_searches(&self) -> Result<Vec<SavedSearch>> {
+        saved_search::all(&self.storage.conn)
+    }
+
+    /// Saves a search under a name, at the end of the saved searches.
+    pub fn save_search(&self, name: &str, query: &str) -> Result<SavedSearch> {
+        self.write(|conn| saved_search::add(conn, name, query))
+    }
+
+    pub fn rename_saved_search(&self, id: u32, name: &str) -> Result<()> {
+        self.write(|conn| saved_search::rename(conn, id, name))
+    }
+
+    /// Moves a saved search to the given place in the list.
+    pub fn move_saved_search(&self, id: u32, index: usize) -> Result<()> {
+        self.write(|conn| saved_search::move_to(conn, id, index))
+    }
+
+    pub fn delete_saved_search(&self, id: u32) -> Result<()> {
+        self.write(|conn| saved_search::delete(conn, id))
+    }
+
+    pub fn all_tags(&self) -> Result<Vec<String>> {
+        tag::all_tags(&self.storage.conn)
+    }
+
+    pub fn tag_tree(&self) -> Result<Vec<TagNode>> {
+        tag::tag_tree(&self.storage.conn)
+    }
+
+    /// Renames a tag, and the tags below it, on every note. Returns how many
+    /// notes changed.
+    pub fn rename_tag(&mut self, from: &str, to: &str) -> Result<usize> {
+        self.change(|conn| tag::rename_tag(conn, from, to))
+    }
+
+    /// Merges tags into another one on every note. Returns how many notes
+    /// changed.
+    pub fn merge_tags(&mut self, tags: &[String], into: &str) -> Result<usize> {
+        self.change(|conn| tag::merge_tags(conn, tags, into))
+    }
+
+    /// Removes a tag, and the tags below it, from every note. Returns how
+    /// many notes changed.
+    pub fn delete_tag(&mut self, tag: &str) -> Result<usize> {
+        self.change(|conn| tag::delete_tag(conn, tag))
+    }
+
+    /// Creates a deck, and any of its parents that do not exist yet. Fails if
+    /// a deck with that name exists.
+    pub fn create_deck(&mut self, name: &str) -> Result<Deck> {
+        self.change(|conn| deck::create_deck(conn, name))
+    }
+
+    /// Deletes a deck together with the decks below it.
+    pub fn delete_deck(&mut self, deck_id: u32) -> Result<()> {
+        self.change(|conn| deck::delete_deck_tree(conn, deck_id))?;
+        self.card_queues = None;
+
+        Ok(())
+    }
+
+    /// Renames a deck and the decks below it.
+    pub fn rename_deck(&mut self, deck_id: u32, name: &str) -> Result<Deck> {
+        self.change(|conn| deck::rename_deck(conn, deck_id, name))
+    }
+
+    /// Moves a deck and the decks below it under another deck, or to the top
+    /// level.
+    pub fn move_deck(&mut self, deck_id: u32, parent_id: Option<u32>) -> Result<Deck> {
+        self.change(|conn| deck::move_deck(conn, deck_id, parent_id))
+    }
+
+    /// Copies a deck with its cards, optionally as new cards.
+    pub fn duplicate_deck(&mut self, deck_id: u32, reset: bool) -> Result<Deck> {
+        self.change(|conn| deck::duplicate_deck(conn, deck_id, reset))
+    }
+
+    /// Imports the notes, decks, scheduling and media of an Anki `.apkg` or
+    /// `.colpkg` package, updating the notes an earlier import added.
+    pub fn import_anki_package(&mut self, path: &Path) -> Result<ImportSummary> {
+        let folder = self.media_folder();
+        let today = self.timing.days_elapsed;
+        let summary = self.change(|conn| apkg::import(conn, &folder, path, today))?;
+        self.card_queues = None;
+
+        Ok(summary)
+    }
+
+    /// Adds the rows of a CSV or TSV file as notes, handling rows already in
+    /// the collection as `options` asks.
+    pub fn import_delimited(
+        &mut self,
+        file: &DelimitedFile,
+        options: &DelimitedImport,
+    ) -> Result<ImportSummary> {
+        let summary = self.change(|conn| delimited::import(conn, file, options))?;
+        self.card_queues = None;
+
+        Ok(summary)
+    }
+
+    /// Writes the cards chosen by `options` to an Anki `.apkg` package.
+    pub fn export_anki_package(
+        &self,
+        path: &Path,
+        options: &ExportOptions,
+    ) -> Result<ExportSummary> {
+        apkg_export::export(&self.storage.conn, &self.media_folder(), path, options)
+    }
+
+    pub fn move_cards(&mut self, card_ids: &[u32], deck_name: &str) -> Result<usize> {
+        self.bulk("Change deck", card_ids, |conn| {
+            bulk::set_deck(conn, card_ids, deck_name)
+        })
+    }
+
+    pub fn add_tags_to_cards(&mut self, card_ids: &[u32], tags: &[String]) -> Result<usize> {
+        self.bulk("Add tags", card_ids, |conn| {
+            bulk::add_tags(conn, card_ids, tags)
+        })
+    }
+
+    pub fn remove_tags_from_cards(&mut self, card_ids: &[u32], tags: &[String]) -> Result<usize> {
+        self.bulk("Remove tags", card_ids, |conn| {
+            bulk::remove_tags(conn, card_ids, tags)
+        })
+    }
+
+    pub fn set_suspended(&mut self, card_ids: &[u32], suspended: bool) -> Result<usize> {
+        let name = if suspended { "Suspend" } else { "Unsuspend" };
+        self.bulk(name, card_ids, |conn| {
+            bulk::set_suspended(conn, card_ids, suspended)
+        })
+    }
+
+    pub fn set_flag(&mut self, card_ids: &[u32], flag: u8) -> Result<usize> {
+        self.bulk("Flag", card_ids, |conn| {
+            bulk::set_flag(conn, card_ids, flag)
+        })
+    }
+
+    pub fn reset_cards(&mut self, card_ids: &[u32]) -> Result<usize> {
+        self.bulk("Reset to new", card_ids, |conn| {
+            bulk::reset_to_new(conn, card_ids)
+        })
+    }
+
+    pub fn set_due_date(&mut self, card_ids: &[u32], days: DueDays) -> Result<usize> {
+        let today = self.timing.days_elapsed;
+        self.bulk("Set due date", card_ids, |conn| {
+            bulk::set_due_date(conn, card_ids, days, today)
+        })
+    }
+
+    /// Gives the selected new cards positions from `start` on, see
+    /// `bulk::reposition`.
+    pub fn reposition_cards(
+        &mut self,
+        card_ids: &[u32],
+        start: u32,
+        step: u32,
+        shift: bool,
+    ) -> Result<usize> {
+        // Shifting moves the other new cards as well, so undo needs them too.
+        let mut touched = card_ids.to_vec();
+        if shift {
+            touched.extend(self.search_cards("is:new")?);
+        }
+
+        self.bulk("Reposition", &touched, |conn| {
+            bulk::reposition(conn, card_ids, start, step, shift)
+        })
+    }
+
+    /// Deletes the cards, and the notes left without cards.
+    pub fn delete_cards(&mut self, card_ids: &[u32]) -> Result<usize> {
+        self.bulk("Delete cards", card_ids, |conn| {
+            bulk::delete_cards(conn, card_ids)
+        })
+    }
+
+    /// The changes `find_and_replace` would make to the cards' fields.
+    pub fn preview_find_replace(
+        &self,
+        card_ids: &[u32],
+        find_replace: &FindReplace,
+    ) -> Result<Vec<FieldChange>> {
+        find_replace::preview(&self.storage.conn, card_ids, find_replace)
+    }
+
+    /// Replaces text in the fields of the cards. Returns how many cards
+    /// changed, including other cards of the changed notes.
+    pub fn find_and_replace(
+        &mut self,
+        card_ids: &[u32],
+        find_replace: &FindReplace,
+    ) -> Result<usize> {
+        let affected = find_replace::affected_cards(&self.storage.conn, card_ids)?;
+        self.bulk("Find and replace", &affected, |conn| {
+            find_replace::replace(conn, card_ids, find_replace)
+        })
+    }
+
+    /// The name of the change `undo` would take back, if any.
+    pub fn undo_name(&self) -> Option<&str> {
+        self.undo.as_ref().map(|step| step.name.as_str())
+    }
+
+    /// Takes back the last change made to many cards at once.
+    ///
+    /// # Returns
+    ///
+    /// The name of the change, or `None` if there was nothing to undo.
+    pub fn undo(&mut self) -> Result<Option<String>> {
+        let Some(step) = self.undo.take() else {
+            return Ok(None);
+        };
+
+        self.write(|conn| undo::restore(conn, &step))?;
+        self.card_queues = None;
+
+        Ok(Some(step.name))
+    }
+
+    /// Runs a change to many cards as one transaction that `undo` can take
+    /// back. `card_ids` are the cards the change may touch.
+    fn bulk(
+        &mut self,
+        name: &str,
+        card_ids: &[u32],
+        change: impl FnOnce(&Connection) -> Result<usize>,
+    ) -> Result<usize> {
+        let (step, changed) = self.write(|conn| {
+            let step = undo::snapshot(conn, name, card_ids)?;
+            Ok((step, change(conn)?))
+        })?;
+
+        self.undo = Some(step);
+        self.card_queues = None;
+
+        Ok(changed)
+    }
+
+    /// Runs a change that `undo` cannot take back. Restoring the last undo
+    /// step afterwards would throw this change away, so the step is dropped.
+    fn change<T>(&mut self, change: impl FnOnce(&Connection) -> Result<T>) -> Result<T> {
+        let changed = self.write(change)?;
+        self.undo = None;
+
+        Ok(changed)
+    }
+
+    /// Runs a change that touches many rows as a single transaction.
+    fn write<T>(&self, change: impl FnOnce(&Connection) -> Result<T>) -> Result<T> {
+        if self.is_read_only() {
+            return Err(NgurraError::CollectionReadOnly);
+        }
+
+        let tx = self.storage.conn.unchecked_transaction()?;
+        let changed = change(&tx)?;
+        tx.commit()?;
+
+        Ok(changed)
+    }
+
+    /// Takes a new backup and prunes old ones. Unless `force` is set, nothing
+    /// happens if the newest backup is younger than the minimum interval.
+    pub fn maybe_backup(&self, force: bool) -> Result<Option<PathBuf>> {
+        let folder = self.backups_folder();
+
+        if !force {
+            if let Some(newest) = backup::list_backups(&folder)?.first() {
+                let age = Local::now().naive_local() - newest.created;
+                let minimum_interval =
+                    chrono::Duration::minutes(self.backup_limits.minimum_interval_mins as i64);
+
+                if age < minimum_interval {
+                    return Ok(None);
+                }
+            }
+        }
+
+        let path = backup::backup(&self.storage.conn, &folder)?;
+        backup::prune(&folder, &self.backup_limits)?;
+
+        Ok(Some(path))
+    }
+
+    /// Swaps the given backup in as the live collection. The current state is
+    /// backed up first, and the path of that backup is returned.
+    pub fn restore_backup(&mut self, backup_path: &Path) -> Result<PathBuf> {
+        if self.is_read_only() {
+            return Err(NgurraError::CollectionReadOnly);
+        }
+
+        let current = backup::backup(&self.storage.conn, &self.backups_folder())?;
+
+        backup::restore(&mut self.storage.conn, backup_path)?;
+
+        // The restored collection no longer matches what the sync server
+        // holds, so the next sync has to replace one side with the other.
+        let mut sync_state = changes::sync_state(&self.storage.conn)?;
+        sync_state.full_sync_required = true;
+        changes::save_sync_state(&self.storage.conn, &sync_state)?;
+
+        self.reload();
+
+        Ok(current)
+    }
+
+    /// Drops cached state after the collection was changed behind our back,
+    /// by a restore or a sync.
+    pub fn reload(&mut self) {
+        self.card_queues = None;
+        self.undo = None;
+        self.timing =
+            CollectionBuilder::timing_for_timestamp(&self.storage.conn, Local::now().timestamp());
+    }
+
+    pub fn sync_endpoint(&self) -> Result<Option<String>> {
+        Ok(changes::sync_state(&self.storage.conn)?.endpoint)
+    }
+
+    pub fn set_sync_endpoint(&self, endpoint: &str) -> Result<()> {
+        if self.is_read_only() {
+            return Err(NgurraError::CollectionReadOnly);
+        }
+
+        let mut sync_state = changes::sync_state(&self.storage.conn)?;
+        sync_state.endpoint = Some(endpoint.to_string());
+        changes::save_sync_state(&self.storage.conn, &sync_state)?;
+
+        Ok(())
+    }
+
+    pub fn sync_folder(&self) -> Result<Option<PathBuf>> {
+        Ok(journal::folder_state(&self.storage.conn)?.folder)
+    }
+
+    pub fn set_sync_folder(&self, folder: &Path) -> Result<()> {
+        if self.is_read_only() {
+            return Err(NgurraError::CollectionReadOnly);
+        }
+
+        let mut folder_state = journal::folder_state(&self.storage.conn)?;
+        folder_state.folder = Some(folder.to_path_buf());
+        journal::save_folder_state(&self.storage.conn, &folder_state)?;
+
+        Ok(())
+    }
+
     pub fn apply_state(&self, card: &mut FlashCard, next: CardState) {
         match next {
             CardState::New(next_new_state) => {
@@ -113,8 +655,15 @@ impl Collection {
         }
     }
 
-    pub fn answer_card(&self, card_id: u32, answer: Answer) -> FlashCard {
-        let mut card = FlashCard::load(card_id, &self.storage.conn).unwrap();
+    /// Schedules the card according to the answer and buries its siblings
+    /// until tomorrow.
+    ///
+    /// Returns the updated card and the IDs of the buried siblings, which
+    /// should be dropped from the study queue.
+    pub fn answer_card(&mut self, card_id: u32, answer: Answer) -> Result<(FlashCard, Vec<u32>)> {
+        let mut card = FlashCard::load(card_id, &self.storage.conn)?;
+        let last_interval = card.interval;
+        let ease = answer.ease();
 
         let current_card_state = get_current_card_state(&card);
         let next_state = current_card_state.next_states();
@@ -138,8 +687,14 @@ impl Collection {
             }
         }
 
-        card.save(&self.storage.conn).unwrap();
-        card
+        let tomorrow = self.timing.days_elapsed + 1;
+        let buried = self.change(|conn| {
+            card.save(conn)?;
+            RevlogEntry::new(card_id, ease, card.interval, last_interval).save(conn)?;
+            Ok(card.bury_siblings(conn, tomorrow)?)
+        })?;
+
+        Ok((card, buried))
     }
 }
 
@@ -153,3 +708,76 @@ impl Global for Collection {}
 //         }
 //     }
 // }
+
+#[cfg(test)]
+mod test {
+    use crate::repositories::notetype::BASIC_GUID;
+
+    use super::*;
+
+    fn open_collection() -> Collection {
+        let folder = std::env::temp_dir().join(format!("ngurra-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&folder).unwrap();
+        CollectionBuilder::new(folder.join("collection.db"))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn later_edits_drop_the_undo_step() {
+        let mut collection = open_collection();
+        let deck = deck::add_deck(&collection.storage.conn, "Spanish").unwrap();
+        let basic = Notetype::load_by_guid(BASIC_GUID, &collection.storage.conn)
+            .unwrap()
+            .unwrap();
+        let mut note = Note::new(&basic);
+        note.fields = vec!["perro".to_string(), "dog".to_string()];
+        let card_id = collection.add_note(&mut note, deck.id.unwrap()).unwrap()[0]
+            .id
+            .unwrap();
+
+        collection.set_suspended(&[card_id], true).unwrap();
+        assert_eq!(collection.undo_name(), Some("Suspend"));
+
+        let fields = vec!["el perro".to_string(), "the dog".to_string()];
+        collection
+            .edit_card(card_id, &fields, &[], "Spanish")
+            .unwrap();
+        assert_eq!(collection.undo_name(), None);
+        assert_eq!(collection.undo().unwrap(), None);
+
+        let (question, suspended): (String, bool) = collection
+            .storage
+            .conn
+            .query_row(
+                "SELECT question, suspended FROM cards WHERE id = ?",
+                [card_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(question, "el perro");
+        assert!(suspended);
+    }
+
+    #[test]
+    fn deleting_a_deck_drops_the_undo_step() {
+        let mut collection = open_collection();
+        let deck_id = collection.create_deck("Spanish").unwrap().id.unwrap();
+        let basic = Notetype::load_by_guid(BASIC_GUID, &collection.storage.conn)
+            .unwrap()
+            .unwrap();
+        let mut note = Note::new(&basic);
+        note.fields = vec!["perro".to_string(), "dog".to_string()];
+        let card_id = collection.add_note(&mut note, deck_id).unwrap()[0]
+            .id
+            .unwrap();
+
+        collection.set_suspended(&[card_id], true).unwrap();
+        assert_eq!(collection.undo_name(), Some("Suspend"));
+
+        collection.delete_deck(deck_id).unwrap();
+        assert_eq!(collection.undo_name(), None);
+        assert_eq!(collection.undo().unwrap(), None);
+        assert!(Deck::load(deck_id, &collection.storage.conn).is_err());
+    }
+}
@@ -1,38 +1,648 @@
+//! Decks nest through their names: `Languages::French` is a subdeck of
+//! `Languages`. A parent deck's counts include its subdecks, and studying it
+//! studies them too.
+
+use std::collections::HashMap;
+
 use rusqlite::Connection;
 
-use crate::Deck;
+use crate::{
+    errors::{NgurraError, Result},
+    repositories::{deck::DeckStat, guid::new_guid, note::Note},
+    Deck, FlashCard,
+};
+
+use super::states::state_context::StateContext;
+
+pub const SEPARATOR: &str = "::";
+
+pub fn get_decks(conn: &Connection) -> Result<Vec<Deck>> {
+    Ok(Deck::get_all_decks(conn)?)
+}
+
+/// Cleans up a deck name as typed, trimming each level and dropping blank
+/// ones, so that ` Languages :: French ` becomes `Languages::French`.
+pub fn normalize_name(name: &str) -> Option<String> {
+    let levels = name
+        .split(SEPARATOR)
+        .map(str::trim)
+        .filter(|level| !level.is_empty())
+        .collect::<Vec<_>>();
 
-pub fn get_decks(_conn: &Connection) -> Vec<Deck> {
-    let conn = Connection::open("ngurra.db").unwrap();
-    let decks_res = Deck::get_all_decks(&conn);
+    (!levels.is_empty()).then(|| levels.join(SEPARATOR))
+}
+
+/// The last level of the name, shown in the deck list.
+pub fn base_name(name: &str) -> &str {
+    name.rsplit(SEPARATOR).next().unwrap_or(name)
+}
 
-    match decks_res {
-        Ok(decks) => decks,
-        Err(e) => {
-            eprintln!("Error getting decks: {}", e);
-            vec![]
+pub fn parent_name(name: &str) -> Option<&str> {
+    name.rsplit_once(SEPARATOR).map(|(parent, _)| parent)
+}
+
+/// Whether two deck names are the same deck. Like the index on the `decks`
+/// table, this ignores the case of ASCII letters.
+pub fn same_name(name: &str, other: &str) -> bool {
+    name.eq_ignore_ascii_case(other)
+}
+
+/// Whether `name` is `ancestor` or one of the decks below it.
+pub fn is_within(name: &str, ancestor: &str) -> bool {
+    match (name.get(..ancestor.len()), name.get(ancestor.len()..)) {
+        (Some(prefix), Some(rest)) => {
+            same_name(prefix, ancestor) && (rest.is_empty() || rest.starts_with(SEPARATOR))
         }
+        _ => false,
     }
 }
 
+/// Spells the levels of `name` the way the existing decks spell them, so that
+/// `languages::Verbs` becomes `Languages::Verbs` when `Languages` exists.
+fn existing_spelling(decks: &[Deck], name: &str) -> String {
+    let mut spelled = String::new();
+
+    for level in name.split(SEPARATOR) {
+        if !spelled.is_empty() {
+            spelled.push_str(SEPARATOR);
+        }
+        spelled.push_str(level);
+
+        if let Some(deck) = decks.iter().find(|deck| same_name(&deck.name, &spelled)) {
+            spelled.clone_from(&deck.name);
+        }
+    }
+
+    spelled
+}
+
+/// Creates a deck, and any of its parents that do not exist yet.
+///
+/// # Returns
+///
+/// The new deck, or the existing one if a deck with that name exists.
+pub fn add_deck(conn: &Connection, name: &str) -> Result<Deck> {
+    let name =
+        normalize_name(name).ok_or_else(|| NgurraError::Deck("the name is empty".to_string()))?;
+    let decks = get_decks(conn)?;
+
+    let name = existing_spelling(&decks, &name);
+
+    let mut path = Vec::new();
+    let mut added = None;
+    for level in name.split(SEPARATOR) {
+        path.push(level);
+        let name = path.join(SEPARATOR);
+
+        added = match decks.iter().find(|deck| same_name(&deck.name, &name)) {
+            Some(deck) => Some(deck.clone()),
+            None => {
+                let mut deck = Deck::new(&name);
+                deck.save(conn)?;
+                Some(deck)
+            }
+        };
+    }
+
+    added.ok_or_else(|| NgurraError::Deck("the name is empty".to_string()))
+}
+
+/// Creates a deck like `add_deck`, but fails if a deck with that name exists.
+pub fn create_deck(conn: &Connection, name: &str) -> Result<Deck> {
+    let name =
+        normalize_name(name).ok_or_else(|| NgurraError::Deck("the name is empty".to_string()))?;
+
+    if get_decks(conn)?
+        .iter()
+        .any(|deck| same_name(&deck.name, &name))
+    {
+        return Err(NgurraError::Deck(format!(
+            "a deck named {} already exists",
+            name
+        )));
+    }
+
+    add_deck(conn, &name)
+}
+
+/// The ID of the deck and of every deck below it, parents first.
+pub fn deck_tree_ids(conn: &Connection, deck_id: u32) -> Result<Vec<u32>> {
+    let root = Deck::load(deck_id, conn)?;
+
+    Ok(sorted(get_decks(conn)?)
+        .into_iter()
+        .filter(|deck| is_within(&deck.name, &root.name))
+        .filter_map(|deck| deck.id)
+        .collect())
+}
+
+/// The id of the deck with the given name, as typed.
+pub fn find_deck_id(conn: &Connection, name: &str) -> Result<u32> {
+    let name =
+        normalize_name(name).ok_or_else(|| NgurraError::Deck("the name is empty".to_string()))?;
+
+    get_decks(conn)?
+        .into_iter()
+        .find(|deck| same_name(&deck.name, &name))
+        .and_then(|deck| deck.id)
+        .ok_or_else(|| NgurraError::Deck(format!("there is no deck named {}", name)))
+}
+
+/// Deletes a deck together with the decks below it.
+pub fn delete_deck_tree(conn: &Connection, deck_id: u32) -> Result<()> {
+    for id in deck_tree_ids(conn, deck_id)? {
+        Deck::delete(id, conn)?;
+    }
+
+    Ok(())
+}
+
+/// Gives a deck, and the decks below it, a new name. Parents of the new name
+/// that do not exist yet are created.
+///
+/// Fails if another deck already has one of the new names, or if the deck
+/// would end up inside itself.
+pub fn rename_deck(conn: &Connection, deck_id: u32, name: &str) -> Result<Deck> {
+    let name =
+        normalize_name(name).ok_or_else(|| NgurraError::Deck("the name is empty".to_string()))?;
+    let root = Deck::load(deck_id, conn)?;
+
+    if name == root.name {
+        return Ok(root);
+    }
+    // A rename that only changes the case is fine.
+    if is_within(&name, &root.name) && !same_name(&name, &root.name) {
+        return Err(NgurraError::Deck(format!(
+            "{} cannot be moved inside itself",
+            root.name
+        )));
+    }
+
+    let (mut tree, others): (Vec<_>, Vec<_>) = get_decks(conn)?
+        .into_iter()
+        .partition(|deck| is_within(&deck.name, &root.name));
+    let name = existing_spelling(&others, &name);
+
+    for deck in tree.iter_mut() {
+        deck.name = format!("{}{}", name, &deck.name[root.name.len()..]);
+
+        if others
+            .iter()
+            .any(|other| same_name(&other.name, &deck.name))
+        {
+            return Err(NgurraError::Deck(format!(
+                "a deck named {} already exists",
+                deck.name
+            )));
+        }
+    }
+
+    if let Some(parent) = parent_name(&name) {
+        add_deck(conn, parent)?;
+    }
+
+    let mut renamed = None;
+    for mut deck in tree {
+        deck.save(conn)?;
+        if deck.id == Some(deck_id) {
+            renamed = Some(deck);
+        }
+    }
+
+    renamed.ok_or_else(|| NgurraError::Deck(format!("{} was not found", root.name)))
+}
+
+/// Moves a deck, with the decks below it, under another deck, or to the top
+/// level when `parent_id` is `None`.
+pub fn move_deck(conn: &Connection, deck_id: u32, parent_id: Option<u32>) -> Result<Deck> {
+    let deck = Deck::load(deck_id, conn)?;
+    let name = match parent_id {
+        Some(parent_id) => {
+            let parent = Deck::load(parent_id, conn)?;
+            format!("{}{}{}", parent.name, SEPARATOR, base_name(&deck.name))
+        }
+        None => base_name(&deck.name).to_string(),
+    };
+
+    rename_deck(conn, deck_id, &name)
+}
+
+/// The first of `name`, `name 2`, `name 3`... that no deck has.
+pub fn unused_name(decks: &[Deck], name: &str) -> String {
+    let taken = |candidate: &str| decks.iter().any(|deck| same_name(&deck.name, candidate));
+
+    if !taken(name) {
+        return name.to_string();
+    }
+
+    (2..)
+        .map(|n| format!("{} {}", name, n))
+        .find(|candidate| !taken(candidate))
+        .unwrap_or_else(|| name.to_string())
+}
+
+/// Copies a deck and its cards into a new deck next to it. The cards' notes
+/// are copied too, so that editing a copy leaves the original alone. Decks
+/// below it are not copied.
+///
+/// With `reset` the copied cards start over as new cards; otherwise they keep
+/// their scheduling.
+pub fn duplicate_deck(conn: &Connection, deck_id: u32, reset: bool) -> Result<Deck> {
+    let source = Deck::load(deck_id, conn)?;
+    let name = unused_name(&get_decks(conn)?, &format!("{} (copy)", source.name));
+
+    let mut copy = Deck::new(&name);
+    copy.save(conn)?;
+    let copy_id = copy.id.unwrap_or_default();
+
+    let card_ids = conn
+        .prepare("SELECT id FROM cards WHERE deck_id = ? ORDER BY id")?
+        .query_map([deck_id], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<u32>>>()?;
+
+    let mut copied_notes: HashMap<u32, u32> = HashMap::new();
+    for card_id in card_ids {
+        let card = FlashCard::load(card_id, conn)?;
+
+        let note_id = match (card.note_id, copied_notes.get(&card.note_id)) {
+            (0, _) => 0,
+            (_, Some(copied)) => *copied,
+            (note_id, None) => {
+                let mut note = Note::load(note_id, conn)?;
+                note.id = None;
+                note.guid = new_guid();
+                note.save(conn)?;
+
+                let copied = note.id.unwrap_or_default();
+                copied_notes.insert(note_id, copied);
+                copied
+            }
+        };
+
+        card.duplicate(copy_id, note_id, reset).save(conn)?;
+    }
+
+    Ok(copy)
+}
+
+/// Sorts decks level by level, so that each deck is directly followed by the
+/// decks below it.
+pub fn sorted(mut decks: Vec<Deck>) -> Vec<Deck> {
+    decks.sort_by_cached_key(|deck| {
+        deck.name
+            .split(SEPARATOR)
+            .map(str::to_lowercase)
+            .collect::<Vec<_>>()
+    });
+    decks
+}
+
+/// Caps a deck's counts by the daily limits. Learning cards are always shown.
+fn capped(stat: &DeckStat, limits: &StateContext) -> DeckStat {
+    DeckStat {
+        id: stat.id,
+        new: stat.new.min(limits.new_per_day),
+        learning: stat.learning,
+        due: stat.due.min(limits.reviews_per_day),
+    }
+}
+
+/// Adds up the counts of every deck and the decks below it. Each subdeck
+/// contributes the counts it would show on its own, and the total is capped
+/// by the deck's own limits.
+pub fn aggregate_stats(
+    decks: &[Deck],
+    own: &HashMap<u32, DeckStat>,
+    limits: &StateContext,
+) -> HashMap<u32, DeckStat> {
+    decks
+        .iter()
+        .filter_map(|deck| {
+            let id = deck.id?;
+            let mut total = DeckStat {
+                id: Some(id),
+                new: 0,
+                learning: 0,
+                due: 0,
+            };
+
+            for stat in decks
+                .iter()
+                .filter(|other| is_within(&other.name, &deck.name))
+                .filter_map(|other| own.get(&other.id?))
+            {
+                let stat = capped(stat, limits);
+                total.new += stat.new;
+                total.learning += stat.learning;
+                total.due += stat.due;
+            }
+
+            Some((id, capped(&total, limits)))
+        })
+        .collect()
+}
+
+/// Each deck's counts, including the decks below it.
+pub fn get_decks_tree_stats(
+    conn: &Connection,
+    days_elapsed: u32,
+) -> Result<HashMap<u32, DeckStat>> {
+    let own = Deck::get_decks_stats(conn, days_elapsed)?;
+
+    Ok(aggregate_stats(
+        &get_decks(conn)?,
+        &own,
+        &StateContext::default(),
+    ))
+}
+
+/// A deck in the deck list: how deep it is nested and whether it has
+/// subdecks. A deck whose parent is missing is shown under its nearest
+/// existing ancestor.
+#[derive(Clone)]
+pub struct DeckRow {
+    pub deck: Deck,
+    pub depth: usize,
+    pub has_children: bool,
+}
+
+pub fn deck_rows(decks: Vec<Deck>) -> Vec<DeckRow> {
+    let decks = sorted(decks);
+
+    decks
+        .iter()
+        .enumerate()
+        .map(|(ix, deck)| {
+            let depth = decks
+                .iter()
+                .filter(|other| other.name != deck.name && is_within(&deck.name, &other.name))
+                .count();
+            let has_children = decks
+                .get(ix + 1)
+                .is_some_and(|next| next.name != deck.name && is_within(&next.name, &deck.name));
+
+            DeckRow {
+                deck: deck.clone(),
+                depth,
+                has_children,
+            }
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod test {
     use rusqlite::Connection;
 
-    use crate::{db::init_db, Deck};
+    use crate::{
+        db::init_db,
+        models::note::add_note,
+        repositories::{
+            flash_card::CardQueue,
+            notetype::{Notetype, BASIC_GUID},
+        },
+        Deck, FlashCard,
+    };
 
-    use super::get_decks;
+    use super::*;
 
-    #[test]
-    fn test_get_decks() {
+    fn setup() -> Connection {
         let conn = Connection::open_in_memory().unwrap();
         init_db(&conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_get_decks() {
+        let conn = setup();
 
         let mut deck = Deck::new("Test Deck");
         deck.save(&conn).unwrap();
 
-        let decks = get_decks(&conn);
+        let decks = get_decks(&conn).unwrap();
 
         assert_eq!(decks.len(), 1);
     }
+
+    #[test]
+    fn names_nest_with_separators() {
+        assert_eq!(
+            normalize_name(" Languages ::  :: French "),
+            Some("Languages::French".to_string())
+        );
+        assert_eq!(normalize_name(":: "), None);
+        assert_eq!(base_name("Languages::French"), "French");
+        assert_eq!(parent_name("Languages::French"), Some("Languages"));
+        assert!(is_within("Languages::French", "Languages"));
+        assert!(!is_within("Languages2", "Languages"));
+    }
+
+    #[test]
+    fn add_deck_creates_missing_parents() {
+        let conn = setup();
+
+        let deck = add_deck(&conn, "Languages::French::Verbs").unwrap();
+        assert_eq!(deck.name, "Languages::French::Verbs");
+        assert_eq!(add_deck(&conn, "Languages").unwrap().id, Some(1));
+
+        let rows = deck_rows(get_decks(&conn).unwrap());
+        assert_eq!(
+            rows.iter()
+                .map(|row| (row.deck.name.as_str(), row.depth, row.has_children))
+                .collect::<Vec<_>>(),
+            vec![
+                ("Languages", 0, true),
+                ("Languages::French", 1, true),
+                ("Languages::French::Verbs", 2, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn parents_count_and_study_their_subdecks() {
+        let conn = setup();
+        let parent = add_deck(&conn, "Languages").unwrap();
+        let child = add_deck(&conn, "Languages::French").unwrap();
+        let other = add_deck(&conn, "Languages Extra").unwrap();
+
+        for deck in [&parent, &child, &child, &other] {
+            FlashCard::new(deck.id.unwrap(), "Front", "Back", None)
+                .save(&conn)
+                .unwrap();
+        }
+        let mut review = FlashCard::new(child.id.unwrap(), "Front", "Back", None);
+        review.set_queue(CardQueue::Review);
+        review.save(&conn).unwrap();
+
+        let stats = get_decks_tree_stats(&conn, 1).unwrap();
+        let parent_stats = &stats[&parent.id.unwrap()];
+        assert_eq!((parent_stats.new, parent_stats.due), (3, 1));
+        assert_eq!(stats[&child.id.unwrap()].new, 2);
+
+        let limits = StateContext {
+            new_per_day: 2,
+            reviews_per_day: 1,
+            ..StateContext::default()
+        };
+        let mut own = Deck::get_decks_stats(&conn, 1).unwrap();
+        own.get_mut(&parent.id.unwrap()).unwrap().new = 5;
+        let stats = aggregate_stats(&get_decks(&conn).unwrap(), &own, &limits);
+        assert_eq!(stats[&parent.id.unwrap()].new, 2);
+        assert_eq!(stats[&child.id.unwrap()].due, 1);
+
+        assert_eq!(
+            deck_tree_ids(&conn, parent.id.unwrap()).unwrap(),
+            vec![parent.id.unwrap(), child.id.unwrap()]
+        );
+
+        delete_deck_tree(&conn, parent.id.unwrap()).unwrap();
+        assert_eq!(
+            get_decks(&conn)
+                .unwrap()
+                .into_iter()
+                .map(|deck| deck.name)
+                .collect::<Vec<_>>(),
+            vec!["Languages Extra"]
+        );
+    }
+
+    fn names(conn: &Connection) -> Vec<String> {
+        sorted(get_decks(conn).unwrap())
+            .into_iter()
+            .map(|deck| deck.name)
+            .collect()
+    }
+
+    #[test]
+    fn renaming_and_moving_carry_subdecks_along() {
+        let conn = setup();
+        add_deck(&conn, "French::Verbs").unwrap();
+        let french_id = add_deck(&conn, "French").unwrap().id.unwrap();
+        let spanish = add_deck(&conn, "Spanish").unwrap();
+
+        rename_deck(&conn, french_id, "Languages::Français").unwrap();
+        assert_eq!(
+            names(&conn),
+            vec![
+                "Languages",
+                "Languages::Français",
+                "Languages::Français::Verbs",
+                "Spanish"
+            ]
+        );
+
+        let languages = add_deck(&conn, "Languages").unwrap();
+        move_deck(&conn, spanish.id.unwrap(), languages.id).unwrap();
+        move_deck(&conn, french_id, None).unwrap();
+        assert_eq!(
+            names(&conn),
+            vec![
+                "Français",
+                "Français::Verbs",
+                "Languages",
+                "Languages::Spanish"
+            ]
+        );
+    }
+
+    #[test]
+    fn renaming_rejects_taken_names_and_cycles() {
+        let conn = setup();
+        let french = add_deck(&conn, "French").unwrap();
+        add_deck(&conn, "French::Verbs").unwrap();
+        add_deck(&conn, "Verbs").unwrap();
+
+        assert!(matches!(
+            create_deck(&conn, " Verbs "),
+            Err(NgurraError::Deck(_))
+        ));
+        assert!(matches!(
+            rename_deck(&conn, french.id.unwrap(), "Verbs"),
+            Err(NgurraError::Deck(_))
+        ));
+        assert!(matches!(
+            rename_deck(&conn, french.id.unwrap(), "French::Verbs::Old"),
+            Err(NgurraError::Deck(_))
+        ));
+        assert!(matches!(
+            move_deck(&conn, french.id.unwrap(), french.id),
+            Err(NgurraError::Deck(_))
+        ));
+        assert_eq!(names(&conn), vec!["French", "French::Verbs", "Verbs"]);
+    }
+
+    #[test]
+    fn names_differing_only_in_case_are_the_same_deck() {
+        let conn = setup();
+        let french = add_deck(&conn, "French").unwrap();
+
+        assert_eq!(add_deck(&conn, "french").unwrap().id, french.id);
+        assert!(matches!(
+            create_deck(&conn, "FRENCH"),
+            Err(NgurraError::Deck(_))
+        ));
+        assert_eq!(find_deck_id(&conn, "french").unwrap(), french.id.unwrap());
+
+        add_deck(&conn, "french::verbs").unwrap();
+        let spanish = add_deck(&conn, "Spanish").unwrap();
+        assert!(matches!(
+            rename_deck(&conn, spanish.id.unwrap(), "FRENCH::Verbs"),
+            Err(NgurraError::Deck(_))
+        ));
+        rename_deck(&conn, spanish.id.unwrap(), "french::Spanish").unwrap();
+        rename_deck(&conn, french.id.unwrap(), "Français").unwrap();
+        assert_eq!(
+            names(&conn),
+            vec!["Français", "Français::Spanish", "Français::verbs"]
+        );
+
+        rename_deck(&conn, french.id.unwrap(), "FRANÇAIS").unwrap();
+        assert_eq!(
+            names(&conn),
+            vec!["FRANÇAIS", "FRANÇAIS::Spanish", "FRANÇAIS::verbs"]
+        );
+    }
+
+    #[test]
+    fn duplicating_copies_cards_and_notes() {
+        let conn = setup();
+        let deck = add_deck(&conn, "Spanish").unwrap();
+        let deck_id = deck.id.unwrap();
+        let basic = Notetype::load_by_guid(BASIC_GUID, &conn).unwrap().unwrap();
+
+        let mut note = Note::new(&basic);
+        note.fields = vec!["perro".to_string(), "dog".to_string()];
+        let mut card = add_note(&conn, &mut note, deck_id).unwrap().remove(0);
+        card.set_queue(CardQueue::Review);
+        card.interval = 12;
+        card.save(&conn).unwrap();
+
+        let kept = duplicate_deck(&conn, deck_id, false).unwrap();
+        let reset = duplicate_deck(&conn, deck_id, true).unwrap();
+        assert_eq!(kept.name, "Spanish (copy)");
+        assert_eq!(reset.name, "Spanish (copy) 2");
+
+        let cards_in = |deck: &Deck| {
+            conn.prepare("SELECT id FROM cards WHERE deck_id = ?")
+                .unwrap()
+                .query_map([deck.id], |row| row.get(0))
+                .unwrap()
+                .map(|id| FlashCard::load(id.unwrap(), &conn).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        let kept_cards = cards_in(&kept);
+        assert_eq!(kept_cards.len(), 1);
+        assert_eq!(kept_cards[0].interval, 12);
+        assert_ne!(kept_cards[0].guid, card.guid);
+        assert_ne!(kept_cards[0].note_id, card.note_id);
+        assert_eq!(
+            Note::load(kept_cards[0].note_id, &conn).unwrap().fields,
+            note.fields
+        );
+
+        let reset_cards = cards_in(&reset);
+        assert_eq!(reset_cards[0].interval, 1);
+        assert!(matches!(reset_cards[0].get_queue(), CardQueue::New));
+    }
 }
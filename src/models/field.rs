@@ -0,0 +1,285 @@
+//! Card fields are plain text that may reference files in the media folder,
+//! using the same syntax as Anki: `<img src="name.png">` for images and
+//! `[sound:name.mp3]` for audio. Rendered cards also mark the active cloze,
+//! see `models::cloze`, and where an answer is typed in, see
+//! `models::type_answer`.
+
+use super::{
+    cloze::{ACTIVE_CLOSE, ACTIVE_OPEN},
+    type_answer::{self, ANSWER_CLOSE, ANSWER_OPEN, ANSWER_OPEN_IGNORING},
+};
+
+const SOUND_EXTENSIONS: [&str; 7] = ["mp3", "ogg", "wav", "m4a", "flac", "opus", "webm"];
+
+/// A piece of a field, in the order it appears.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment<'a> {
+    Text(&'a str),
+    Image(&'a str),
+    Sound(&'a str),
+    /// The cloze the card asks about, to be highlighted.
+    Cloze(&'a str),
+    /// Where the answer is typed in.
+    TypePrompt,
+    /// The answer to compare the typed text with.
+    TypedAnswer {
+        expected: &'a str,
+        ignore_case_and_diacritics: bool,
+    },
+}
+
+/// Finds the end of the tag opened at `start` and returns it with the text
+/// inside the tag.
+fn enclosed<'a>(field: &'a str, start: usize, open: &str, close: &str) -> Option<(usize, &'a str)> {
+    let inner_start = start + open.len();
+    let inner_end = inner_start + field[inner_start..].find(close)?;
+
+    Some((inner_end + close.len(), &field[inner_start..inner_end]))
+}
+
+/// Splits a field into text and media references.
+pub fn segments(field: &str) -> Vec<Segment<'_>> {
+    // Lowercasing ASCII keeps byte offsets, so positions found in `lower`
+    // can be used to slice `field`.
+    let lower = field.to_ascii_lowercase();
+    let mut segments = Vec::new();
+    let mut rest = 0;
+
+    while rest < field.len() {
+        let next_image = lower[rest..].find("<img").map(|at| rest + at);
+        let next_sound = lower[rest..].find("[sound:").map(|at| rest + at);
+        let next_cloze = field[rest..].find(ACTIVE_OPEN).map(|at| rest + at);
+        let next_prompt = field[rest..].find(type_answer::PROMPT).map(|at| rest + at);
+        let next_answer = field[rest..].find(ANSWER_OPEN).map(|at| rest + at);
+        let next_answer_ignoring = field[rest..].find(ANSWER_OPEN_IGNORING).map(|at| rest + at);
+
+        let Some(next) = [
+            next_image,
+            next_sound,
+            next_cloze,
+            next_prompt,
+            next_answer,
+            next_answer_ignoring,
+        ]
+        .into_iter()
+        .flatten()
+        .min() else {
+            break;
+        };
+
+        let (end, segment) = if Some(next) == next_cloze {
+            let Some((end, inner)) = enclosed(field, next, ACTIVE_OPEN, ACTIVE_CLOSE) else {
+                break;
+            };
+
+            (end, Segment::Cloze(inner))
+        } else if Some(next) == next_prompt {
+            (next + type_answer::PROMPT.len(), Segment::TypePrompt)
+        } else if Some(next) == next_answer || Some(next) == next_answer_ignoring {
+            let ignore_case_and_diacritics = Some(next) == next_answer_ignoring;
+            let open = if ignore_case_and_diacritics {
+                ANSWER_OPEN_IGNORING
+            } else {
+                ANSWER_OPEN
+            };
+            let Some((end, expected)) = enclosed(field, next, open, ANSWER_CLOSE) else {
+                break;
+            };
+
+            (
+                end,
+                Segment::TypedAnswer {
+                    expected,
+                    ignore_case_and_diacritics,
+                },
+            )
+        } else if Some(next) == next_image {
+            let Some(close) = field[next..].find('>').map(|at| next + at) else {
+                break;
+            };
+
+            match image_source(&field[next..close]) {
+                Some(source) => (close + 1, Segment::Image(source)),
+                None => (close + 1, Segment::Text(&field[next..close + 1])),
+            }
+        } else {
+            let Some(close) = field[next..].find(']').map(|at| next + at) else {
+                break;
+            };
+
+            let name = &field[next + "[sound:".len()..close];
+            (close + 1, Segment::Sound(name))
+        };
+
+        let start = next;
+        if start > rest {
+            segments.push(Segment::Text(&field[rest..start]));
+        }
+        segments.push(segment);
+        rest = end;
+    }
+
+    if rest < field.len() {
+        segments.push(Segment::Text(&field[rest..]));
+    }
+
+    segments
+}
+
+/// Reads the `src` attribute of an `<img ...` tag, without the closing `>`.
+fn image_source(tag: &str) -> Option<&str> {
+    let at = tag.to_ascii_lowercase().find("src=")?;
+    let value = &tag[at + "src=".len()..];
+
+    let source = match value.chars().next()? {
+        quote @ ('"' | '\'') => {
+            let value = &value[1..];
+            &value[..value.find(quote)?]
+        }
+        _ => value
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()?,
+    };
+
+    (!source.is_empty()).then_some(source)
+}
+
+/// The names of the media files a field references.
+pub fn media_references(field: &str) -> Vec<String> {
+    segments(field)
+        .into_iter()
+        .filter_map(|segment| match segment {
+            Segment::Image(name) | Segment::Sound(name) => Some(name.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Gives the media files `rename` has a new name for that name in the field,
+/// leaving the rest of the field, including text that merely looks like a
+/// file name, untouched.
+pub fn rename_media_references<'a>(
+    field: &str,
+    rename: impl Fn(&str) -> Option<&'a str>,
+) -> String {
+    let mut renamed = String::with_capacity(field.len());
+    let mut rest = 0;
+
+    for segment in segments(field) {
+        let (Segment::Image(name) | Segment::Sound(name)) = segment else {
+            continue;
+        };
+        let Some(new_name) = rename(name) else {
+            continue;
+        };
+
+        // Segments borrow from the field, so their place in it is known.
+        let start = name.as_ptr() as usize - field.as_ptr() as usize;
+        renamed.push_str(&field[rest..start]);
+        renamed.push_str(new_name);
+        rest = start + name.len();
+    }
+
+    renamed.push_str(&field[rest..]);
+    renamed
+}
+
+/// The text to put in a field to show or play a media file.
+pub fn media_reference(filename: &str) -> String {
+    let extension = filename
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_ascii_lowercase())
+        .unwrap_or_default();
+
+    if SOUND_EXTENSIONS.contains(&extension.as_str()) {
+        format!("[sound:{}]", filename)
+    } else {
+        format!("<img src=\"{}\">", filename)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn splits_text_and_media() {
+        assert_eq!(
+            segments("A <IMG SRC=\"cat.png\"> says [sound:meow.mp3]!"),
+            vec![
+                Segment::Text("A "),
+                Segment::Image("cat.png"),
+                Segment::Text(" says "),
+                Segment::Sound("meow.mp3"),
+                Segment::Text("!"),
+            ]
+        );
+        assert_eq!(
+            segments("<img src='a b.jpg' />"),
+            vec![Segment::Image("a b.jpg")]
+        );
+        assert_eq!(segments("<img src=c.gif>"), vec![Segment::Image("c.gif")]);
+        assert_eq!(segments("plain"), vec![Segment::Text("plain")]);
+        assert_eq!(
+            segments("1 < 2 [sound:"),
+            vec![Segment::Text("1 < 2 [sound:")]
+        );
+    }
+
+    #[test]
+    fn splits_out_the_active_cloze() {
+        let field = format!("{}[...]{} is a capital", ACTIVE_OPEN, ACTIVE_CLOSE);
+
+        assert_eq!(
+            segments(&field),
+            vec![Segment::Cloze("[...]"), Segment::Text(" is a capital")]
+        );
+    }
+
+    #[test]
+    fn splits_out_typed_answers() {
+        let question = format!("perro {}", type_answer::PROMPT);
+        assert_eq!(
+            segments(&question),
+            vec![Segment::Text("perro "), Segment::TypePrompt]
+        );
+
+        let answer = format!("perro {}dog{}", ANSWER_OPEN_IGNORING, ANSWER_CLOSE);
+        assert_eq!(
+            segments(&answer),
+            vec![
+                Segment::Text("perro "),
+                Segment::TypedAnswer {
+                    expected: "dog",
+                    ignore_case_and_diacritics: true
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn references_match_the_file_kind() {
+        assert_eq!(media_reference("cat.PNG"), "<img src=\"cat.PNG\">");
+        assert_eq!(media_reference("meow.mp3"), "[sound:meow.mp3]");
+        assert_eq!(
+            media_references(&format!(
+                "{} {}",
+                media_reference("a.png"),
+                media_reference("b.ogg")
+            )),
+            vec!["a.png", "b.ogg"]
+        );
+    }
+
+    #[test]
+    fn renames_only_media_references() {
+        let field = "cat.jpg <img src='cat.jpg'> <img src=\"bobcat.jpg\"> [sound:cat.jpg]";
+        let renamed =
+            rename_media_references(field, |name| (name == "cat.jpg").then_some("cat-1.jpg"));
+
+        assert_eq!(
+            renamed,
+            "cat.jpg <img src='cat-1.jpg'> <img src=\"bobcat.jpg\"> [sound:cat-1.jpg]"
+        );
+    }
+}
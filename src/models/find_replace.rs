@@ -0,0 +1,367 @@
+//! Finding and replacing text in the fields of many cards at once, e.g. to
+//! fix a term across a deck. Cards with a note have the note's fields
+//! changed, which re-renders all of its cards; a card without a note has its
+//! question and answer changed, as the fields "Front" and "Back".
+
+use std::borrow::Cow;
+
+use regex::{NoExpand, Regex, RegexBuilder};
+use rusqlite::Connection;
+
+use crate::{
+    errors::{NgurraError, Result},
+    repositories::{note::Note, notetype::Notetype},
+    FlashCard,
+};
+
+use super::note::update_note;
+
+/// The fields a card without a note shows as its question and answer.
+const CARD_FIELDS: [&str; 2] = ["Front", "Back"];
+
+#[derive(Debug, Clone, Default)]
+pub struct FindReplace {
+    pub find: String,
+    /// What each match becomes. With `regex`, `$1` or `${name}` put back a
+    /// group of the match.
+    pub replace: String,
+    /// Whether `find` is a regular expression rather than plain text.
+    pub regex: bool,
+    pub match_case: bool,
+    /// The names of the fields to search, compared without regard to case;
+    /// every field if empty.
+    pub fields: Vec<String>,
+}
+
+impl FindReplace {
+    fn matcher(&self) -> Result<Regex> {
+        if self.find.is_empty() {
+            return Err(NgurraError::Parse(
+                "find and replace: there is nothing to find".to_string(),
+            ));
+        }
+
+        let pattern = if self.regex {
+            Cow::Borrowed(self.find.as_str())
+        } else {
+            Cow::Owned(regex::escape(&self.find))
+        };
+
+        Ok(RegexBuilder::new(&pattern)
+            .case_insensitive(!self.match_case)
+            .build()?)
+    }
+
+    fn searches(&self, field: &str) -> bool {
+        self.fields.is_empty()
+            || self
+                .fields
+                .iter()
+                .any(|name| name.trim().eq_ignore_ascii_case(field))
+    }
+
+    fn replace_all<'t>(&self, matcher: &Regex, text: &'t str) -> Cow<'t, str> {
+        if self.regex {
+            matcher.replace_all(text, self.replace.as_str())
+        } else {
+            matcher.replace_all(text, NoExpand(&self.replace))
+        }
+    }
+}
+
+/// A field that a find and replace would change.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    /// One of the cards showing the field.
+    pub card_id: u32,
+    pub field: String,
+    pub before: String,
+    pub after: String,
+}
+
+/// Where the fields of a card come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Source {
+    Note(u32),
+    Card(u32),
+}
+
+/// The notes and noteless cards behind the cards, each once, with the first
+/// card showing it.
+fn sources(conn: &Connection, card_ids: &[u32]) -> Result<Vec<(Source, u32)>> {
+    let mut stmt = conn.prepare("SELECT note_id FROM cards WHERE id = ?")?;
+    let mut sources = Vec::new();
+
+    for id in card_ids {
+        let note_ids = stmt
+            .query_map([id], |row| row.get::<_, u32>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        for note_id in note_ids {
+            let source = match note_id {
+                0 => Source::Card(*id),
+                note_id => Source::Note(note_id),
+            };
+            if !sources.iter().any(|(known, _)| *known == source) {
+                sources.push((source, *id));
+            }
+        }
+    }
+
+    Ok(sources)
+}
+
+/// The names and values of the fields of a source.
+fn fields(conn: &Connection, source: Source) -> Result<Vec<(String, String)>> {
+    Ok(match source {
+        Source::Note(note_id) => {
+            let note = Note::load(note_id, conn)?;
+            let notetype = Notetype::load(note.notetype_id, conn)?;
+            notetype.fields.into_iter().zip(note.fields).collect()
+        }
+        Source::Card(card_id) => {
+            let card = FlashCard::load(card_id, conn)?;
+            CARD_FIELDS
+                .iter()
+                .map(|name| name.to_string())
+                .zip([
+                    card.get_question().to_string(),
+                    card.get_answer().to_string(),
+                ])
+                .collect()
+        }
+    })
+}
+
+/// Lists the changes a find and replace would make to the fields of the
+/// cards, without making them. A note shared by several of the cards is
+/// listed once.
+pub fn preview(
+    conn: &Connection,
+    card_ids: &[u32],
+    find_replace: &FindReplace,
+) -> Result<Vec<FieldChange>> {
+    let matcher = find_replace.matcher()?;
+    let mut changes = Vec::new();
+
+    for (source, card_id) in sources(conn, card_ids)? {
+        for (field, before) in fields(conn, source)? {
+            if !find_replace.searches(&field) {
+                continue;
+            }
+
+            let after = find_replace.replace_all(&matcher, &before);
+            if after != before {
+                changes.push(FieldChange {
+                    card_id,
+                    field,
+                    after: after.into_owned(),
+                    before,
+                });
+            }
+        }
+    }
+
+    Ok(changes)
+}
+
+/// Makes the changes `preview` lists.
+///
+/// # Returns
+///
+/// How many cards changed, counting every card of a changed note.
+pub fn replace(conn: &Connection, card_ids: &[u32], find_replace: &FindReplace) -> Result<usize> {
+    let matcher = find_replace.matcher()?;
+    let mut changed = 0;
+
+    for (source, _) in sources(conn, card_ids)? {
+        match source {
+            Source::Note(note_id) => {
+                let mut note = Note::load(note_id, conn)?;
+                let notetype = Notetype::load(note.notetype_id, conn)?;
+
+                let mut edited = false;
+                for (name, value) in notetype.fields.iter().zip(note.fields.iter_mut()) {
+                    if !find_replace.searches(name) {
+                        continue;
+                    }
+                    if let Cow::Owned(after) = find_replace.replace_all(&matcher, value) {
+                        edited |= after != *value;
+                        *value = after;
+                    }
+                }
+
+                if edited {
+                    update_note(conn, &mut note)?;
+                    changed += note.card_ids(conn)?.len();
+                }
+            }
+            Source::Card(card_id) => {
+                let mut card = FlashCard::load(card_id, conn)?;
+                let mut sides = [
+                    card.get_question().to_string(),
+                    card.get_answer().to_string(),
+                ];
+
+                let mut edited = false;
+                for (name, value) in CARD_FIELDS.iter().zip(sides.iter_mut()) {
+                    if !find_replace.searches(name) {
+                        continue;
+                    }
+                    if let Cow::Owned(after) = find_replace.replace_all(&matcher, value) {
+                        edited |= after != *value;
+                        *value = after;
+                    }
+                }
+
+                if edited {
+                    card.set_content(&sides[0], &sides[1]);
+                    card.save(conn)?;
+                    changed += 1;
+                }
+            }
+        }
+    }
+
+    Ok(changed)
+}
+
+/// The cards a find and replace over the given cards could change: those
+/// cards and the other cards of their notes.
+pub fn affected_cards(conn: &Connection, card_ids: &[u32]) -> Result<Vec<u32>> {
+    let mut affected = Vec::new();
+
+    for (source, _) in sources(conn, card_ids)? {
+        match source {
+            Source::Note(note_id) => affected.extend(Note::load(note_id, conn)?.card_ids(conn)?),
+            Source::Card(card_id) => affected.push(card_id),
+        }
+    }
+
+    Ok(affected)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        db::init_db,
+        models::note::add_note,
+        repositories::notetype::{BASIC_AND_REVERSED_GUID, BASIC_GUID},
+        Deck,
+    };
+
+    use super::*;
+
+    /// A reversed note for "colour", a basic note and a card without a note.
+    fn setup() -> (Connection, Vec<u32>) {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+
+        let mut deck = Deck::new("English");
+        deck.save(&conn).unwrap();
+        let deck_id = deck.id.unwrap();
+
+        let mut ids = Vec::new();
+        for (guid, front, back) in [
+            (BASIC_AND_REVERSED_GUID, "colour", "Colour of the sky"),
+            (BASIC_GUID, "favourite", "the one you like best"),
+        ] {
+            let notetype = Notetype::load_by_guid(guid, &conn).unwrap().unwrap();
+            let mut note = Note::new(&notetype);
+            note.fields = vec![front.to_string(), back.to_string()];
+            ids.extend(
+                add_note(&conn, &mut note, deck_id)
+                    .unwrap()
+                    .into_iter()
+                    .map(|card| card.id.unwrap()),
+            );
+        }
+
+        let mut card = FlashCard::new(deck_id, "honour", "COLOUR", None);
+        card.save(&conn).unwrap();
+        ids.push(card.id.unwrap());
+
+        (conn, ids)
+    }
+
+    fn plain(find: &str, replace: &str) -> FindReplace {
+        FindReplace {
+            find: find.to_string(),
+            replace: replace.to_string(),
+            ..FindReplace::default()
+        }
+    }
+
+    #[test]
+    fn preview_lists_each_field_once() {
+        let (conn, ids) = setup();
+
+        let changes = preview(&conn, &ids, &plain("colour", "color")).unwrap();
+        let fields = changes
+            .iter()
+            .map(|change| (change.field.as_str(), change.after.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fields,
+            vec![
+                ("Front", "color"),
+                ("Back", "color of the sky"),
+                ("Back", "color"),
+            ]
+        );
+        assert_eq!(changes[0].card_id, ids[0]);
+        assert_eq!(changes[2].before, "COLOUR");
+
+        let nothing_saved = FlashCard::load(ids[0], &conn).unwrap();
+        assert_eq!(nothing_saved.get_question(), "colour");
+    }
+
+    #[test]
+    fn case_fields_and_regex_narrow_the_changes() {
+        let (conn, ids) = setup();
+
+        let mut find_replace = plain("colour", "color");
+        find_replace.match_case = true;
+        assert_eq!(preview(&conn, &ids, &find_replace).unwrap().len(), 1);
+
+        let mut find_replace = plain("colour", "color");
+        find_replace.fields = vec!["back".to_string()];
+        assert_eq!(preview(&conn, &ids, &find_replace).unwrap().len(), 2);
+
+        let mut find_replace = plain(r"\b(\w+?)our(\w*)", "${1}or$2");
+        find_replace.regex = true;
+        let changes = preview(&conn, &ids[2..], &find_replace).unwrap();
+        assert_eq!(changes.len(), 3);
+        assert_eq!(changes[0].after, "favorite");
+        assert_eq!(changes[1].after, "honor");
+
+        let mut literal = plain("(", "[");
+        assert_eq!(preview(&conn, &ids, &literal).unwrap().len(), 0);
+        literal.regex = true;
+        assert!(matches!(
+            preview(&conn, &ids, &literal),
+            Err(NgurraError::Parse(_))
+        ));
+        assert!(preview(&conn, &ids, &plain("", "x")).is_err());
+    }
+
+    #[test]
+    fn replace_rerenders_the_cards_of_changed_notes() {
+        let (conn, ids) = setup();
+
+        assert_eq!(
+            replace(&conn, &ids[..1], &plain("colour", "color")).unwrap(),
+            2
+        );
+
+        let reversed = FlashCard::load(ids[1], &conn).unwrap();
+        assert_eq!(reversed.get_question(), "color of the sky");
+        let noteless = FlashCard::load(ids[3], &conn).unwrap();
+        assert_eq!(noteless.get_answer(), "COLOUR");
+
+        assert_eq!(replace(&conn, &ids, &plain("colour", "color")).unwrap(), 1);
+        let noteless = FlashCard::load(ids[3], &conn).unwrap();
+        assert_eq!(noteless.get_answer(), "color");
+
+        assert_eq!(affected_cards(&conn, &ids[1..2]).unwrap(), &ids[..2]);
+    }
+}
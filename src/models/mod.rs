@@ -1,8 +1,19 @@
 pub mod answer;
+pub mod browse;
+pub mod bulk;
 pub mod builder;
 pub mod card;
+pub mod cloze;
 pub mod collection;
 pub mod deck;
+pub mod field;
+pub mod find_replace;
+pub mod note;
 pub mod queue;
+pub mod saved_search;
 pub mod states;
+pub mod tag;
+pub mod template;
 pub mod timing;
+pub mod type_answer;
+pub mod undo;
@@ -0,0 +1,438 @@
+use std::collections::BTreeSet;
+
+use rusqlite::Connection;
+
+use crate::{
+    errors::{NgurraError, Result},
+    repositories::{
+        note::Note,
+        notetype::{Notetype, NotetypeKind},
+    },
+    FlashCard,
+};
+
+use super::{
+    cloze, deck,
+    template::{self, FieldMap, Side},
+    type_answer,
+};
+
+fn field_map<'a>(notetype: &'a Notetype, note: &'a Note) -> FieldMap<'a> {
+    notetype
+        .fields
+        .iter()
+        .map(String::as_str)
+        .zip(note.fields.iter().map(String::as_str))
+        .collect()
+}
+
+/// Renders the question and answer of the note's card `ord`, or `None` if the
+/// note type has no template for it. The back can show the question again
+/// with `{{FrontSide}}`, cloze note types hide the card's cloze with
+/// `{{cloze:Field}}`, and `{{type:Field}}` asks for the field to be typed in.
+pub fn render_card(notetype: &Notetype, note: &Note, ord: u32) -> Option<(String, String)> {
+    let template = notetype.template_for(ord)?;
+    let filter = |side| {
+        move |name: &str, text: &str| match name {
+            "cloze" => Some(cloze::render(text, ord + 1, side)),
+            "type" => Some(type_answer::render(text, false, side)),
+            "type:nc" => Some(type_answer::render(text, true, side)),
+            _ => None,
+        }
+    };
+
+    let mut fields = field_map(notetype, note);
+    let question = template::render_with(&template.front, &fields, &filter(Side::Question));
+
+    // The answer is compared below the question rather than typed again.
+    let front_side = question.replace(type_answer::PROMPT, "").trim().to_string();
+    fields.insert("FrontSide", &front_side);
+    let answer = template::render_with(&template.back, &fields, &filter(Side::Answer));
+
+    Some((question, answer))
+}
+
+/// The cards this note should have: one per template whose front is not
+/// blank, or for cloze note types one per cloze number.
+pub fn card_ords(notetype: &Notetype, note: &Note) -> Vec<u32> {
+    match notetype.kind {
+        NotetypeKind::Normal => {
+            let fields = field_map(notetype, note);
+
+            notetype
+                .templates
+                .iter()
+                .enumerate()
+                .filter(|(_, template)| !template::is_blank(&template.front, &fields))
+                .map(|(ord, _)| ord as u32)
+                .collect()
+        }
+        NotetypeKind::Cloze => note
+            .fields
+            .iter()
+            .flat_map(|field| cloze::numbers(field))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|number| number - 1)
+            .collect(),
+    }
+}
+
+fn new_card(notetype: &Notetype, note: &Note, deck_id: u32, ord: u32) -> FlashCard {
+    let (question, answer) = render_card(notetype, note, ord).unwrap_or_default();
+
+    let mut card = FlashCard::new(deck_id, &question, &answer, None);
+    card.note_id = note.id.unwrap_or_default();
+    card.ord = ord;
+    card
+}
+
+/// Saves a new note and generates its cards in the given deck.
+///
+/// Fails without saving anything if none of the templates would make a card.
+pub fn add_note(conn: &Connection, note: &mut Note, deck_id: u32) -> Result<Vec<FlashCard>> {
+    let notetype = Notetype::load(note.notetype_id, conn)?;
+    let ords = card_ords(&notetype, note);
+
+    if ords.is_empty() {
+        return Err(NgurraError::Note(match notetype.kind {
+            NotetypeKind::Normal => format!(
+                "the fields used on the front of the {} cards are empty",
+                notetype.name
+            ),
+            NotetypeKind::Cloze => {
+                "the text has no cloze deletion, such as {{c1::...}}".to_string()
+            }
+        }));
+    }
+
+    note.save(conn)?;
+
+    let mut cards = Vec::new();
+    for ord in ords {
+        let mut card = new_card(&notetype, note, deck_id, ord);
+        card.save(conn)?;
+        cards.push(card);
+    }
+
+    Ok(cards)
+}
+
+/// Saves an edited note, re-rendering all of its cards. Templates or cloze
+/// numbers that make a card now but did not before get a new card in the deck
+/// of the note's existing cards; cards whose template became blank, or whose
+/// cloze was removed, are kept.
+///
+/// Returns how many cards were added.
+pub fn update_note(conn: &Connection, note: &mut Note) -> Result<usize> {
+    let notetype = Notetype::load(note.notetype_id, conn)?;
+    note.save(conn)?;
+
+    let mut cards = note
+        .card_ids(conn)?
+        .into_iter()
+        .map(|id| FlashCard::load(id, conn))
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    for card in cards.iter_mut() {
+        let Some((question, answer)) = render_card(&notetype, note, card.ord) else {
+            continue;
+        };
+
+        card.set_content(&question, &answer);
+        card.save(conn)?;
+    }
+
+    let Some(deck_id) = cards.first().map(FlashCard::get_deck_id) else {
+        return Ok(0);
+    };
+
+    let mut added = 0;
+    for ord in card_ords(&notetype, note) {
+        if cards.iter().any(|card| card.ord == ord) {
+            continue;
+        }
+
+        new_card(&notetype, note, deck_id, ord).save(conn)?;
+        added += 1;
+    }
+
+    Ok(added)
+}
+
+/// Saves the edits made to a card: the fields and tags of its note, which
+/// re-renders the note's other cards too, and the deck the card is in. A card
+/// without a note takes its front and back from the first two fields.
+///
+/// Fails without saving anything if no deck is named `deck_name`.
+pub fn edit_card(
+    conn: &Connection,
+    card_id: u32,
+    fields: &[String],
+    tags: &[String],
+    deck_name: &str,
+) -> Result<FlashCard> {
+    let deck_id = deck::find_deck_id(conn, deck_name)?;
+
+    let mut card = FlashCard::load(card_id, conn)?;
+
+    if card.note_id != 0 {
+        let mut note = Note::load(card.note_id, conn)?;
+        note.fields = fields.to_vec();
+        note.tags = tags.to_vec();
+        update_note(conn, &mut note)?;
+
+        card = FlashCard::load(card_id, conn)?;
+    } else if let [question, answer, ..] = fields {
+        card.set_content(question, answer);
+    }
+
+    card.set_deck_id(deck_id);
+    card.save(conn)?;
+
+    Ok(card)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        db::init_db,
+        repositories::notetype::{
+            CardTemplate, BASIC_AND_REVERSED_GUID, BASIC_GUID, BASIC_OPTIONAL_REVERSED_GUID,
+            BASIC_TYPE_ANSWER_GUID, CLOZE_GUID,
+        },
+        Deck,
+    };
+
+    use super::*;
+
+    fn setup() -> (Connection, u32) {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+
+        let mut deck = Deck::new("Spanish");
+        deck.save(&conn).unwrap();
+        let deck_id = deck.id.unwrap();
+
+        (conn, deck_id)
+    }
+
+    fn vocabulary(conn: &Connection) -> Notetype {
+        let mut notetype = Notetype::new(
+            "Vocabulary",
+            NotetypeKind::Normal,
+            &["Word", "Meaning", "Example"],
+            vec![
+                CardTemplate::new(
+                    "Recognise",
+                    "{{Word}}",
+                    "{{FrontSide}} = {{Meaning}}{{#Example}} ({{Example}}){{/Example}}",
+                ),
+                CardTemplate::new("Use", "{{Example}}", "{{Word}}"),
+            ],
+        );
+        notetype.save(conn).unwrap();
+        notetype
+    }
+
+    #[test]
+    fn add_note_generates_a_card_per_template_in_use() {
+        let (conn, deck_id) = setup();
+        let notetype = vocabulary(&conn);
+
+        let mut note = Note::new(&notetype);
+        note.fields = vec!["perro".to_string(), "dog".to_string(), String::new()];
+
+        let cards = add_note(&conn, &mut note, deck_id).unwrap();
+        assert_eq!(cards.len(), 1);
+        assert_eq!(cards[0].get_question(), "perro");
+        assert_eq!(cards[0].get_answer(), "perro = dog");
+        assert_eq!(cards[0].note_id, note.id.unwrap());
+    }
+
+    #[test]
+    fn add_note_rejects_notes_without_cards() {
+        let (conn, deck_id) = setup();
+        let basic = Notetype::load_by_guid(BASIC_GUID, &conn).unwrap().unwrap();
+
+        let mut note = Note::new(&basic);
+        note.fields[1] = "only a back".to_string();
+
+        assert!(matches!(
+            add_note(&conn, &mut note, deck_id),
+            Err(NgurraError::Note(_))
+        ));
+        assert_eq!(note.id, None);
+    }
+
+    #[test]
+    fn editing_a_note_updates_all_its_cards() {
+        let (conn, deck_id) = setup();
+        let notetype = vocabulary(&conn);
+
+        let mut note = Note::new(&notetype);
+        note.fields = vec!["perro".to_string(), "dog".to_string(), String::new()];
+        add_note(&conn, &mut note, deck_id).unwrap();
+
+        note.fields[0] = "el perro".to_string();
+        note.fields[2] = "el perro ladra".to_string();
+        assert_eq!(update_note(&conn, &mut note).unwrap(), 1);
+
+        let cards = note
+            .card_ids(&conn)
+            .unwrap()
+            .into_iter()
+            .map(|id| FlashCard::load(id, &conn).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[0].get_answer(), "el perro = dog (el perro ladra)");
+        assert_eq!(cards[1].get_question(), "el perro ladra");
+        assert_eq!(cards[1].get_answer(), "el perro");
+        assert_eq!(cards[1].get_deck_id(), deck_id);
+    }
+
+    #[test]
+    fn reversed_notes_make_sibling_cards() {
+        let (conn, deck_id) = setup();
+        let reversed = Notetype::load_by_guid(BASIC_AND_REVERSED_GUID, &conn)
+            .unwrap()
+            .unwrap();
+
+        let mut note = Note::new(&reversed);
+        note.fields = vec!["perro".to_string(), "dog".to_string()];
+
+        let cards = add_note(&conn, &mut note, deck_id).unwrap();
+        assert_eq!(cards.len(), 2);
+        assert_eq!(
+            (cards[0].get_question(), cards[0].get_answer()),
+            ("perro", "dog")
+        );
+        assert_eq!(
+            (cards[1].get_question(), cards[1].get_answer()),
+            ("dog", "perro")
+        );
+    }
+
+    #[test]
+    fn optional_reverse_is_made_on_request() {
+        let (conn, deck_id) = setup();
+        let optional = Notetype::load_by_guid(BASIC_OPTIONAL_REVERSED_GUID, &conn)
+            .unwrap()
+            .unwrap();
+
+        let mut note = Note::new(&optional);
+        note.fields = vec!["perro".to_string(), "dog".to_string(), String::new()];
+        assert_eq!(add_note(&conn, &mut note, deck_id).unwrap().len(), 1);
+
+        note.fields[2] = "y".to_string();
+        assert_eq!(update_note(&conn, &mut note).unwrap(), 1);
+        assert_eq!(note.card_ids(&conn).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn cloze_notes_make_a_card_per_number() {
+        let (conn, deck_id) = setup();
+        let cloze = Notetype::load_by_guid(CLOZE_GUID, &conn).unwrap().unwrap();
+
+        let mut note = Note::new(&cloze);
+        note.fields = vec![
+            "{{c1::Canberra}} is the capital of {{c2::Australia}}".to_string(),
+            String::new(),
+        ];
+
+        let cards = add_note(&conn, &mut note, deck_id).unwrap();
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[1].ord, 1);
+        assert_eq!(
+            cards[0].get_question(),
+            format!(
+                "{}[...]{} is the capital of Australia",
+                cloze::ACTIVE_OPEN,
+                cloze::ACTIVE_CLOSE
+            )
+        );
+
+        note.fields[0] = "{{c1::Canberra}} is the {{c3::capital}} of {{c2::Australia}}".to_string();
+        note.fields[1] = "Since 1913".to_string();
+        assert_eq!(update_note(&conn, &mut note).unwrap(), 1);
+
+        let card = FlashCard::load(cards[1].id.unwrap(), &conn).unwrap();
+        assert_eq!(
+            card.get_answer(),
+            format!(
+                "Canberra is the capital of {}Australia{}\n\nSince 1913",
+                cloze::ACTIVE_OPEN,
+                cloze::ACTIVE_CLOSE
+            )
+        );
+        assert_eq!(note.card_ids(&conn).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn cloze_notes_need_a_cloze() {
+        let (conn, deck_id) = setup();
+        let cloze = Notetype::load_by_guid(CLOZE_GUID, &conn).unwrap().unwrap();
+
+        let mut note = Note::new(&cloze);
+        note.fields[0] = "No deletions here".to_string();
+
+        assert!(matches!(
+            add_note(&conn, &mut note, deck_id),
+            Err(NgurraError::Note(_))
+        ));
+    }
+
+    #[test]
+    fn type_answer_cards_prompt_on_the_front_only() {
+        let (conn, deck_id) = setup();
+        let typed = Notetype::load_by_guid(BASIC_TYPE_ANSWER_GUID, &conn)
+            .unwrap()
+            .unwrap();
+
+        let mut note = Note::new(&typed);
+        note.fields = vec!["perro".to_string(), "dog".to_string()];
+
+        let cards = add_note(&conn, &mut note, deck_id).unwrap();
+        assert_eq!(
+            cards[0].get_question(),
+            format!("perro\n\n{}", type_answer::PROMPT)
+        );
+        assert!(!cards[0].get_answer().contains(type_answer::PROMPT));
+        assert_eq!(
+            type_answer::expected(cards[0].get_answer()),
+            Some(("dog", false))
+        );
+    }
+
+    #[test]
+    fn editing_a_card_changes_its_note_and_deck() {
+        let (conn, deck_id) = setup();
+        let mut other = Deck::new("Spanish::Animals");
+        other.save(&conn).unwrap();
+        let reversed = Notetype::load_by_guid(BASIC_AND_REVERSED_GUID, &conn)
+            .unwrap()
+            .unwrap();
+
+        let mut note = Note::new(&reversed);
+        note.fields = vec!["pero".to_string(), "dog".to_string()];
+        let cards = add_note(&conn, &mut note, deck_id).unwrap();
+        let card_id = cards[0].id.unwrap();
+
+        let fields = vec!["perro".to_string(), "dog".to_string()];
+        let tags = vec!["animals".to_string()];
+        assert!(matches!(
+            edit_card(&conn, card_id, &fields, &tags, "Missing"),
+            Err(NgurraError::Deck(_))
+        ));
+
+        let card = edit_card(&conn, card_id, &fields, &tags, " Spanish :: Animals ").unwrap();
+        assert_eq!(card.get_question(), "perro");
+        assert_eq!(card.get_deck_id(), other.id.unwrap());
+
+        let sibling = FlashCard::load(cards[1].id.unwrap(), &conn).unwrap();
+        assert_eq!(sibling.get_answer(), "perro");
+        assert_eq!(sibling.get_deck_id(), deck_id);
+        assert_eq!(Note::load(note.id.unwrap(), &conn).unwrap().tags, tags);
+    }
+}
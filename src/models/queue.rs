@@ -2,21 +2,28 @@ use std::collections::VecDeque;
 
 use rusqlite::Connection;
 
-use crate::{errors::Result, repositories::flash_card::CardQueue, FlashCard};
+use crate::{
+    errors::{NgurraError, Result},
+    repositories::flash_card::CardQueue,
+    FlashCard,
+};
 
 use super::{
-    builder::Builder, card::get_current_card_state, collection::Collection,
-    states::card_state::CardState,
+    builder::Builder,
+    card::get_current_card_state,
+    collection::Collection,
+    deck::deck_tree_ids,
+    states::{card_state::CardState, state_context::StateContext},
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Stats {
     pub new: usize,
     pub learning: usize,
     pub review: usize,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct Queue {
     pub stats: Stats,
     pub core: VecDeque<QueueEntry>,
@@ -39,6 +46,8 @@ pub struct QueueEntry {
 
 pub struct QueueBuilder {
     deck_id: u32,
+    /// The daily limits, applied to each deck and to the studied deck's total.
+    limits: StateContext,
     new: Vec<FlashCard>,
     review: Vec<FlashCard>,
     learning: Vec<FlashCard>,
@@ -48,6 +57,7 @@ impl QueueBuilder {
     pub fn new(deck_id: u32) -> Self {
         QueueBuilder {
             deck_id,
+            limits: StateContext::default(),
             new: vec![],
             review: vec![],
             learning: vec![],
@@ -58,23 +68,46 @@ impl QueueBuilder {
         self.collect_new_cards(&col.storage.conn);
     }
 
+    /// Collects the cards of the deck and of every deck below it, within the
+    /// same limits as the deck list's counts.
     fn collect_new_cards(&mut self, conn: &Connection) {
-        FlashCard::for_each_card_in_deck(&conn, self.deck_id, CardQueue::New, |card| {
-            self.new.push(card.clone());
+        let deck_ids = deck_tree_ids(conn, self.deck_id).unwrap_or_else(|e| {
+            log::error!("Error loading subdecks of {}: {}", self.deck_id, e);
+            vec![self.deck_id]
+        });
+
+        for deck_id in deck_ids {
+            self.collect_deck_cards(conn, deck_id);
+        }
+
+        self.new.truncate(self.limits.new_per_day as usize);
+        self.review.truncate(self.limits.reviews_per_day as usize);
+    }
+
+    fn collect_deck_cards(&mut self, conn: &Connection, deck_id: u32) {
+        let new_limit = self.new.len() + self.limits.new_per_day as usize;
+        let review_limit = self.review.len() + self.limits.reviews_per_day as usize;
+
+        FlashCard::for_each_card_in_deck(&conn, deck_id, CardQueue::New, |card| {
+            if self.new.len() < new_limit {
+                self.new.push(card.clone());
+            }
         })
         .unwrap_or_else(|e| {
             println!("Error collecting new cards: {:?}", e);
         });
 
-        FlashCard::for_each_card_in_deck(&conn, self.deck_id, CardQueue::Learning, |card| {
+        FlashCard::for_each_card_in_deck(&conn, deck_id, CardQueue::Learning, |card| {
             self.learning.push(card.clone());
         })
         .unwrap_or_else(|e| {
             println!("Error collecting learning cards: {:?}", e);
         });
 
-        FlashCard::for_each_card_in_deck(&conn, self.deck_id, CardQueue::Review, |card| {
-            self.review.push(card.clone());
+        FlashCard::for_each_card_in_deck(&conn, deck_id, CardQueue::Review, |card| {
+            if self.review.len() < review_limit {
+                self.review.push(card.clone());
+            }
         })
         .unwrap_or_else(|e| {
             println!("Error collecting learning cards: {:?}", e);
@@ -104,14 +137,16 @@ impl Builder for QueueBuilder {
             .chain(self.learning.iter())
             .chain(self.new.iter());
 
-        cards.for_each(|card| {
-            let states = self.get_scheduling_states(card);
+        for card in cards {
+            let card_id = card.id.ok_or_else(|| {
+                NgurraError::Scheduling("cannot queue a card that was never saved".to_string())
+            })?;
 
             core_queue.push_back(QueueEntry {
-                card_id: card.id.unwrap(),
-                states,
+                card_id,
+                states: self.get_scheduling_states(card),
             });
-        });
+        }
 
         Ok(Queue {
             stats: Stats {
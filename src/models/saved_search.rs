@@ -0,0 +1,167 @@
+//! Searches saved under a name, such as "Leeches" for `tag:leech`, listed in
+//! the browser's sidebar in an order of the user's choosing.
+
+use rusqlite::Connection;
+
+use crate::{
+    errors::{NgurraError, Result},
+    search,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SavedSearch {
+    pub id: u32,
+    pub name: String,
+    pub query: String,
+}
+
+/// Trims a name, refusing an empty one or one another saved search has,
+/// ignoring case.
+fn check_name(conn: &Connection, name: &str, except: Option<u32>) -> Result<String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(NgurraError::SavedSearch("the name is empty".to_string()));
+    }
+
+    let taken = all(conn)?
+        .into_iter()
+        .any(|saved| Some(saved.id) != except && saved.name.to_lowercase() == name.to_lowercase());
+    if taken {
+        return Err(NgurraError::SavedSearch(format!(
+            "a search named {} already exists",
+            name
+        )));
+    }
+
+    Ok(name.to_string())
+}
+
+/// The saved searches in the order they are listed in.
+pub fn all(conn: &Connection) -> Result<Vec<SavedSearch>> {
+    let mut stmt =
+        conn.prepare("SELECT id, name, query FROM saved_searches ORDER BY position, id")?;
+    let searches = stmt
+        .query_map([], |row| {
+            Ok(SavedSearch {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                query: row.get(2)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(searches)
+}
+
+/// Saves a search at the end of the list. The search must parse, so that
+/// clicking it always finds cards.
+pub fn add(conn: &Connection, name: &str, query: &str) -> Result<SavedSearch> {
+    let name = check_name(conn, name, None)?;
+    search::parse(query)?;
+
+    conn.execute(
+        "INSERT INTO saved_searches (name, query, position)
+        VALUES (?, ?, (SELECT COALESCE(MAX(position), -1) + 1 FROM saved_searches))",
+        (&name, query),
+    )?;
+
+    Ok(SavedSearch {
+        id: conn.last_insert_rowid() as u32,
+        name,
+        query: query.to_string(),
+    })
+}
+
+pub fn rename(conn: &Connection, id: u32, name: &str) -> Result<()> {
+    let name = check_name(conn, name, Some(id))?;
+    conn.execute(
+        "UPDATE saved_searches SET name = ? WHERE id = ?",
+        (name, id),
+    )?;
+
+    Ok(())
+}
+
+/// Moves a saved search to `index` in the list, or to the end if the list
+/// is shorter.
+pub fn move_to(conn: &Connection, id: u32, index: usize) -> Result<()> {
+    let mut ids = all(conn)?
+        .into_iter()
+        .map(|saved| saved.id)
+        .filter(|other| *other != id)
+        .collect::<Vec<_>>();
+    ids.insert(index.min(ids.len()), id);
+
+    let mut stmt = conn.prepare("UPDATE saved_searches SET position = ? WHERE id = ?")?;
+    for (position, id) in ids.into_iter().enumerate() {
+        stmt.execute((position as u32, id))?;
+    }
+
+    Ok(())
+}
+
+pub fn delete(conn: &Connection, id: u32) -> Result<()> {
+    conn.execute("DELETE FROM saved_searches WHERE id = ?", [id])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::db::init_db;
+
+    use super::*;
+
+    fn names(conn: &Connection) -> Vec<String> {
+        all(conn)
+            .unwrap()
+            .into_iter()
+            .map(|saved| saved.name)
+            .collect()
+    }
+
+    #[test]
+    fn searches_keep_their_order() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+
+        let leeches = add(&conn, " Leeches ", "tag:leech").unwrap();
+        let verbs = add(&conn, "Hard verbs", "deck:Spanish prop:ease<2").unwrap();
+        add(&conn, "Due", "is:due").unwrap();
+        assert_eq!(leeches.name, "Leeches");
+        assert_eq!(names(&conn), ["Leeches", "Hard verbs", "Due"]);
+
+        move_to(&conn, verbs.id, 0).unwrap();
+        move_to(&conn, leeches.id, 9).unwrap();
+        assert_eq!(names(&conn), ["Hard verbs", "Due", "Leeches"]);
+
+        rename(&conn, leeches.id, "Leech cards").unwrap();
+        rename(&conn, verbs.id, "hard VERBS").unwrap();
+        delete(&conn, verbs.id).unwrap();
+        assert_eq!(names(&conn), ["Due", "Leech cards"]);
+        assert_eq!(all(&conn).unwrap()[1].query, "tag:leech");
+    }
+
+    #[test]
+    fn names_and_queries_are_checked() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+        let due = add(&conn, "Due", "is:due").unwrap();
+
+        assert!(matches!(
+            add(&conn, "due", "is:new"),
+            Err(NgurraError::SavedSearch(_))
+        ));
+        assert!(matches!(
+            add(&conn, " ", "is:new"),
+            Err(NgurraError::SavedSearch(_))
+        ));
+        assert!(matches!(
+            add(&conn, "Broken", "is:"),
+            Err(NgurraError::Search(_))
+        ));
+
+        add(&conn, "New", "is:new").unwrap();
+        assert!(rename(&conn, due.id, "NEW").is_err());
+        assert_eq!(names(&conn), ["Due", "New"]);
+    }
+}
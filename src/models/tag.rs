@@ -0,0 +1,399 @@
+//! Tags categorise notes, and with them their cards, across decks. Tags are
+//! hierarchical: `::` separates their levels, as in `lang::fr::verbs`, and a
+//! tag covers every tag below it. Tags are compared ignoring case and keep
+//! the case they were written in.
+
+use std::collections::{BTreeMap, HashSet};
+
+use rusqlite::Connection;
+
+use crate::{
+    errors::{NgurraError, Result},
+    repositories::note::{split_tags, Note},
+};
+
+pub const SEPARATOR: &str = "::";
+
+/// Tags differ only in ASCII case, like SQLite's `LIKE` that searches them, so
+/// `Ébauche` and `ébauche` stay two tags.
+fn key(tag: &str) -> String {
+    tag.to_ascii_lowercase()
+}
+
+/// Cleans up a tag as typed: blank levels are dropped, and spaces inside a
+/// level become underscores since spaces separate tags.
+pub fn normalize(tag: &str) -> Option<String> {
+    let levels = tag
+        .split(SEPARATOR)
+        .map(|level| level.split_whitespace().collect::<Vec<_>>().join("_"))
+        .filter(|level| !level.is_empty())
+        .collect::<Vec<_>>();
+
+    (!levels.is_empty()).then(|| levels.join(SEPARATOR))
+}
+
+/// Adds the tags that are not in the list yet.
+pub fn add(tags: &mut Vec<String>, new: impl IntoIterator<Item = String>) {
+    for tag in new {
+        if !tags.iter().any(|known| key(known) == key(&tag)) {
+            tags.push(tag);
+        }
+    }
+}
+
+/// Reads tags typed into a tag editor, separated by spaces, dropping repeats.
+pub fn parse(input: &str) -> Vec<String> {
+    let mut tags = Vec::new();
+    add(&mut tags, input.split_whitespace().filter_map(normalize));
+    tags
+}
+
+/// The levels of `tag` that follow `parent`, if `tag` is `parent` or below it.
+fn below<'a>(tag: &'a str, parent: &str) -> Option<Vec<&'a str>> {
+    let mut levels = tag.split(SEPARATOR);
+
+    for parent_level in parent.split(SEPARATOR) {
+        if key(levels.next()?) != key(parent_level) {
+            return None;
+        }
+    }
+
+    Some(levels.collect())
+}
+
+pub fn is_within(tag: &str, parent: &str) -> bool {
+    below(tag, parent).is_some()
+}
+
+/// Moves `tag` from under `from` to under `to`, as in `lang::fr` becoming
+/// `languages::fr` when `lang` is renamed to `languages`.
+pub fn reparent(tag: &str, from: &str, to: &str) -> Option<String> {
+    let rest = below(tag, from)?;
+
+    Some(
+        std::iter::once(to)
+            .chain(rest)
+            .collect::<Vec<_>>()
+            .join(SEPARATOR),
+    )
+}
+
+/// The tag and the tags above it, from the top: `lang`, `lang::fr` and
+/// `lang::fr::verbs` for `lang::fr::verbs`.
+pub fn ancestors(tag: &str) -> impl Iterator<Item = &str> {
+    tag.match_indices(SEPARATOR)
+        .map(|(at, _)| &tag[..at])
+        .chain(std::iter::once(tag))
+}
+
+/// Known tags that complete the one being typed. Tags starting with it come
+/// first, then tags with a level starting with it, so that `verbs` suggests
+/// `lang::fr::verbs`.
+pub fn complete(typed: &str, known: &[String], limit: usize) -> Vec<String> {
+    let typed = key(typed.trim());
+    if typed.is_empty() {
+        return Vec::new();
+    }
+
+    let mut from_start = Vec::new();
+    let mut from_level = Vec::new();
+
+    for tag in known {
+        let tag_key = key(tag);
+        if tag_key == typed {
+            continue;
+        }
+
+        if tag_key.starts_with(&typed) {
+            from_start.push(tag.clone());
+        } else if tag_key
+            .match_indices(SEPARATOR)
+            .any(|(at, _)| tag_key[at + SEPARATOR.len()..].starts_with(&typed))
+        {
+            from_level.push(tag.clone());
+        }
+    }
+
+    from_start
+        .into_iter()
+        .chain(from_level)
+        .take(limit)
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagNode {
+    /// The full tag, such as `lang::fr`.
+    pub tag: String,
+    /// The last level of the tag, such as `fr`.
+    pub name: String,
+    /// Cards with this tag or a tag below it, each counted once.
+    pub cards: u32,
+    pub children: Vec<TagNode>,
+}
+
+fn insert(nodes: &mut Vec<TagNode>, tag: &str, rest: &str, cards: u32) {
+    match rest.split_once(SEPARATOR) {
+        Some((level, rest)) => {
+            if let Some(parent) = nodes.iter_mut().find(|node| key(&node.name) == key(level)) {
+                insert(&mut parent.children, tag, rest, cards);
+            }
+        }
+        None => nodes.push(TagNode {
+            tag: tag.to_string(),
+            name: rest.to_string(),
+            cards,
+            children: Vec::new(),
+        }),
+    }
+}
+
+/// Builds the tag tree from the tags of each note and how many cards it has.
+/// Tags that only appear as the parent of another tag are part of the tree
+/// too.
+pub fn build_tree(notes: &[(Vec<String>, u32)]) -> Vec<TagNode> {
+    // Keyed by the tag ignoring case, which also sorts parents before their
+    // children.
+    let mut counts: BTreeMap<String, (String, u32)> = BTreeMap::new();
+
+    for (tags, cards) in notes {
+        let mut counted = HashSet::new();
+
+        for path in tags.iter().flat_map(|tag| ancestors(tag)) {
+            if counted.insert(key(path)) {
+                counts
+                    .entry(key(path))
+                    .or_insert_with(|| (path.to_string(), 0))
+                    .1 += cards;
+            }
+        }
+    }
+
+    let mut roots = Vec::new();
+    for (tag, cards) in counts.into_values() {
+        insert(&mut roots, &tag, &tag, cards);
+    }
+
+    roots
+}
+
+/// Every tag in use, sorted ignoring case.
+pub fn all_tags(conn: &Connection) -> Result<Vec<String>> {
+    let rows = conn
+        .prepare("SELECT tags FROM notes WHERE tags != ''")?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut tags = Vec::new();
+    for row in rows {
+        add(&mut tags, split_tags(&row));
+    }
+    tags.sort_by_key(|tag| key(tag));
+
+    Ok(tags)
+}
+
+/// The tags in use as a tree, with the number of cards under each tag.
+pub fn tag_tree(conn: &Connection) -> Result<Vec<TagNode>> {
+    let notes = conn
+        .prepare(
+            "SELECT n.tags, COUNT(c.id) FROM notes n LEFT JOIN cards c ON c.note_id = n.id
+            WHERE n.tags != '' GROUP BY n.id",
+        )?
+        .query_map([], |row| {
+            Ok((split_tags(&row.get::<_, String>(0)?), row.get(1)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(build_tree(&notes))
+}
+
+/// Passes every tag of every note through `change`, which returns the new
+/// tag or `None` to remove it, and saves the notes whose tags changed.
+///
+/// Returns how many notes were saved.
+fn retag(conn: &Connection, change: impl Fn(&str) -> Option<String>) -> Result<usize> {
+    let rows = conn
+        .prepare("SELECT id, tags FROM notes WHERE tags != ''")?
+        .query_map([], |row| {
+            Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut changed = 0;
+    for (id, tags) in rows {
+        let old = split_tags(&tags);
+        let mut new = Vec::new();
+        add(&mut new, old.iter().filter_map(|tag| change(tag)));
+
+        if new != old {
+            let mut note = Note::load(id, conn)?;
+            note.tags = new;
+            note.save(conn)?;
+            changed += 1;
+        }
+    }
+
+    Ok(changed)
+}
+
+/// Merges `tags`, and the tags below them, into `into` on every note. Tags
+/// below a merged tag move along, so merging `fr` into `lang::fr` turns
+/// `fr::verbs` into `lang::fr::verbs`.
+///
+/// Returns how many notes changed.
+pub fn merge_tags(conn: &Connection, tags: &[String], into: &str) -> Result<usize> {
+    let into = normalize(into).ok_or_else(|| NgurraError::Tag("the name is empty".to_string()))?;
+
+    retag(conn, |tag| {
+        Some(
+            tags.iter()
+                .find_map(|from| reparent(tag, from, &into))
+                .unwrap_or_else(|| tag.to_string()),
+        )
+    })
+}
+
+/// Renames a tag, and the tags below it, on every note. Renaming a tag to
+/// one that is already in use merges the two.
+///
+/// Returns how many notes changed.
+pub fn rename_tag(conn: &Connection, from: &str, to: &str) -> Result<usize> {
+    merge_tags(conn, &[from.to_string()], to)
+}
+
+/// Removes a tag, and the tags below it, from every note.
+///
+/// Returns how many notes changed.
+pub fn delete_tag(conn: &Connection, tag: &str) -> Result<usize> {
+    retag(conn, |other| {
+        (!is_within(other, tag)).then(|| other.to_string())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        db::init_db,
+        models::{deck::add_deck, note::add_note},
+        repositories::notetype::{Notetype, BASIC_AND_REVERSED_GUID},
+    };
+
+    use super::*;
+
+    fn strings(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    fn setup() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+        conn
+    }
+
+    fn add_tagged(conn: &Connection, front: &str, tags: &[&str]) -> Note {
+        let deck = add_deck(conn, "Languages").unwrap();
+
+        let reversed = Notetype::load_by_guid(BASIC_AND_REVERSED_GUID, conn)
+            .unwrap()
+            .unwrap();
+        let mut note = Note::new(&reversed);
+        note.fields = strings(&[front, "back"]);
+        note.tags = strings(tags);
+        add_note(conn, &mut note, deck.id.unwrap()).unwrap();
+        note
+    }
+
+    #[test]
+    fn parses_typed_tags() {
+        assert_eq!(
+            parse("lang::fr  Verbs verbs ::a:: lang::::de"),
+            strings(&["lang::fr", "Verbs", "a", "lang::de"])
+        );
+        assert_eq!(
+            parse("Ébauche ébauche ÉBAUCHE"),
+            strings(&["Ébauche", "ébauche"])
+        );
+        assert_eq!(normalize(" :: "), None);
+        assert_eq!(
+            normalize("irregular verbs"),
+            Some("irregular_verbs".to_string())
+        );
+    }
+
+    #[test]
+    fn moves_tags_below_a_parent() {
+        assert!(is_within("lang::fr::verbs", "Lang::FR"));
+        assert!(!is_within("language", "lang"));
+        assert_eq!(
+            reparent("lang::fr::verbs", "lang", "languages"),
+            Some("languages::fr::verbs".to_string())
+        );
+        assert_eq!(reparent("other", "lang", "languages"), None);
+        assert_eq!(
+            ancestors("a::b::c").collect::<Vec<_>>(),
+            vec!["a", "a::b", "a::b::c"]
+        );
+    }
+
+    #[test]
+    fn completes_from_the_start_of_any_level() {
+        let known = strings(&["lang::fr::verbs", "vocabulary", "verbs"]);
+
+        assert_eq!(
+            complete("v", &known, 5),
+            strings(&["vocabulary", "verbs", "lang::fr::verbs"])
+        );
+        assert_eq!(complete("VERBS", &known, 5), strings(&["lang::fr::verbs"]));
+        assert_eq!(complete("", &known, 5), Vec::<String>::new());
+    }
+
+    #[test]
+    fn tree_counts_each_card_once_per_tag() {
+        let tree = build_tree(&[
+            (strings(&["lang::fr::verbs", "lang::fr"]), 2),
+            (strings(&["Lang::de"]), 1),
+        ]);
+
+        assert_eq!(tree.len(), 1);
+        assert_eq!((tree[0].tag.as_str(), tree[0].cards), ("lang", 3));
+        let children = &tree[0].children;
+        assert_eq!(
+            children
+                .iter()
+                .map(|node| (node.name.as_str(), node.cards))
+                .collect::<Vec<_>>(),
+            vec![("de", 1), ("fr", 2)]
+        );
+        assert_eq!(children[1].children[0].tag, "lang::fr::verbs");
+    }
+
+    #[test]
+    fn rename_merge_and_delete_across_notes() {
+        let conn = setup();
+        let first = add_tagged(&conn, "parler", &["lang::fr::verbs", "todo"]);
+        let second = add_tagged(&conn, "sprechen", &["de::verbs"]);
+
+        assert_eq!(rename_tag(&conn, "lang::fr", "fr").unwrap(), 1);
+        assert_eq!(
+            Note::load(first.id.unwrap(), &conn).unwrap().tags,
+            strings(&["fr::verbs", "todo"])
+        );
+
+        merge_tags(&conn, &strings(&["fr", "DE"]), "lang").unwrap();
+        assert_eq!(all_tags(&conn).unwrap(), strings(&["lang::verbs", "todo"]));
+
+        let tree = tag_tree(&conn).unwrap();
+        assert_eq!((tree[0].tag.as_str(), tree[0].cards), ("lang", 4));
+
+        assert_eq!(delete_tag(&conn, "lang").unwrap(), 2);
+        assert!(Note::load(second.id.unwrap(), &conn)
+            .unwrap()
+            .tags
+            .is_empty());
+        assert!(matches!(
+            rename_tag(&conn, "todo", " :: "),
+            Err(NgurraError::Tag(_))
+        ));
+    }
+}
@@ -0,0 +1,224 @@
+//! Renders card templates, using the subset of Anki's template syntax that
+//! plain-text fields need:
+//!
+//! - `{{Field}}` is replaced by the field's text,
+//! - `{{#Field}}...{{/Field}}` is only kept when the field is not empty,
+//! - `{{^Field}}...{{/Field}}` is only kept when the field is empty,
+//! - `{{filter:Field}}` applies a filter to the field before inserting it.
+//!
+//! Unknown filters are ignored, so templates written for Anki still render.
+//! Options of a filter stay attached to it, as in `{{type:nc:Field}}`.
+
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Text(String),
+    Field {
+        name: String,
+        filters: Vec<String>,
+    },
+    Section {
+        name: String,
+        inverted: bool,
+        children: Vec<Node>,
+    },
+}
+
+/// Which side of a card is rendered.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    Question,
+    Answer,
+}
+
+/// Field values by name, plus the extras a side can refer to, such as
+/// `FrontSide` on the back.
+pub type FieldMap<'a> = HashMap<&'a str, &'a str>;
+
+/// Applies a filter other than the built-in ones; returns `None` to leave the
+/// text as it is.
+pub type Filter<'a> = dyn Fn(&str, &str) -> Option<String> + 'a;
+
+/// Parses `template` into nodes. Unbalanced section tags are kept as text.
+fn parse(template: &str) -> Vec<Node> {
+    let mut stack: Vec<(String, bool, Vec<Node>)> = Vec::new();
+    let mut nodes = Vec::new();
+    let mut rest = template;
+
+    while let Some(open) = rest.find("{{") {
+        let Some(close) = rest[open..].find("}}").map(|at| open + at) else {
+            break;
+        };
+
+        if open > 0 {
+            nodes.push(Node::Text(rest[..open].to_string()));
+        }
+
+        let tag = rest[open + 2..close].trim();
+        let raw = &rest[open..close + 2];
+        rest = &rest[close + 2..];
+
+        if let Some(name) = tag.strip_prefix('#').or_else(|| tag.strip_prefix('^')) {
+            let inverted = tag.starts_with('^');
+            stack.push((
+                name.trim().to_string(),
+                inverted,
+                std::mem::take(&mut nodes),
+            ));
+        } else if let Some(name) = tag.strip_prefix('/') {
+            match stack.pop() {
+                Some((open_name, inverted, parent)) if open_name == name.trim() => {
+                    let children = std::mem::replace(&mut nodes, parent);
+                    nodes.push(Node::Section {
+                        name: open_name,
+                        inverted,
+                        children,
+                    });
+                }
+                unmatched => {
+                    if let Some(frame) = unmatched {
+                        stack.push(frame);
+                    }
+                    nodes.push(Node::Text(raw.to_string()));
+                }
+            }
+        } else {
+            let mut parts = tag
+                .split(':')
+                .map(|part| part.trim().to_string())
+                .collect::<Vec<_>>();
+            let name = parts.pop().unwrap_or_default();
+            nodes.push(Node::Field {
+                name,
+                filters: join_options(parts),
+            });
+        }
+    }
+
+    if !rest.is_empty() {
+        nodes.push(Node::Text(rest.to_string()));
+    }
+
+    // Sections that were never closed keep their content.
+    while let Some((_, _, parent)) = stack.pop() {
+        let children = std::mem::replace(&mut nodes, parent);
+        nodes.extend(children);
+    }
+
+    nodes
+}
+
+/// Attaches the `nc` option to the `type` filter before it.
+fn join_options(parts: Vec<String>) -> Vec<String> {
+    let mut filters: Vec<String> = Vec::new();
+
+    for part in parts {
+        match filters.last_mut() {
+            Some(last) if last == "type" && part == "nc" => last.push_str(":nc"),
+            _ => filters.push(part),
+        }
+    }
+
+    filters
+}
+
+fn render_nodes(nodes: &[Node], fields: &FieldMap, filter: &Filter, out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Field { name, filters } => {
+                let mut text = fields
+                    .get(name.as_str())
+                    .copied()
+                    .unwrap_or_default()
+                    .to_string();
+                for name in filters.iter().rev() {
+                    if let Some(filtered) = filter(name, &text) {
+                        text = filtered;
+                    }
+                }
+                out.push_str(&text);
+            }
+            Node::Section {
+                name,
+                inverted,
+                children,
+            } => {
+                let empty = fields
+                    .get(name.as_str())
+                    .is_none_or(|value| value.trim().is_empty());
+
+                if empty == *inverted {
+                    render_nodes(children, fields, filter, out);
+                }
+            }
+        }
+    }
+}
+
+/// Renders a template with the given fields, passing filtered fields through
+/// `filter`.
+pub fn render_with(template: &str, fields: &FieldMap, filter: &Filter) -> String {
+    let mut out = String::new();
+    render_nodes(&parse(template), fields, filter, &mut out);
+    out.trim().to_string()
+}
+
+pub fn render(template: &str, fields: &FieldMap) -> String {
+    render_with(template, fields, &|_, _| None)
+}
+
+/// Whether the template shows nothing but its own static text for these
+/// fields, in which case no card is generated from it.
+pub fn is_blank(template: &str, fields: &FieldMap) -> bool {
+    let empty = fields.keys().map(|name| (*name, "")).collect::<FieldMap>();
+
+    render(template, fields) == render(template, &empty)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fields<'a>(pairs: &[(&'a str, &'a str)]) -> FieldMap<'a> {
+        pairs.iter().copied().collect()
+    }
+
+    #[test]
+    fn replaces_fields_and_sections() {
+        let fields = fields(&[("Front", "hola"), ("Back", "hello"), ("Extra", "")]);
+
+        assert_eq!(render("{{Front}}", &fields), "hola");
+        assert_eq!(render("{{ Front }} / {{Back}}", &fields), "hola / hello");
+        assert_eq!(render("{{#Extra}}Extra: {{Extra}}{{/Extra}}", &fields), "");
+        assert_eq!(render("{{^Extra}}no extra{{/Extra}}", &fields), "no extra");
+        assert_eq!(render("{{text:Back}}", &fields), "hello");
+        assert_eq!(render("{{Missing}}!", &fields), "!");
+        assert_eq!(render("{{#Front}}open", &fields), "open");
+        assert_eq!(render("close{{/Front}}", &fields), "close{{/Front}}");
+    }
+
+    #[test]
+    fn applies_custom_filters() {
+        let fields = fields(&[("Front", "hola")]);
+        let upper = |name: &str, text: &str| (name == "upper").then(|| text.to_uppercase());
+
+        assert_eq!(render_with("{{upper:Front}}", &fields, &upper), "HOLA");
+
+        let options = |name: &str, _: &str| Some(name.to_string());
+        assert_eq!(
+            render_with("{{type:nc:Front}}", &fields, &options),
+            "type:nc"
+        );
+    }
+
+    #[test]
+    fn blank_templates_show_no_field() {
+        let fields = fields(&[("Front", "hola"), ("Reverse", "")]);
+
+        assert!(!is_blank("{{Front}}", &fields));
+        assert!(is_blank("Reverse: {{Reverse}}", &fields));
+        assert!(is_blank("{{#Reverse}}{{Front}}{{/Reverse}}", &fields));
+    }
+}
@@ -0,0 +1,223 @@
+//! Type-in-the-answer cards. A template with `{{type:Back}}` shows an input on
+//! the question; on the answer the typed text is compared with the field,
+//! character by character. `{{type:nc:Back}}` ignores case and diacritics
+//! when comparing.
+
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+use super::{answer::Answer, template::Side};
+
+/// Where the question asks for the answer to be typed.
+pub const PROMPT: &str = "<input class=\"type-answer\">";
+
+/// Wraps the expected answer on the back of the card, see
+/// `models::field::segments`.
+pub const ANSWER_OPEN: &str = "<span class=\"type-answer\">";
+pub const ANSWER_OPEN_IGNORING: &str = "<span class=\"type-answer-nc\">";
+pub const ANSWER_CLOSE: &str = "</span>";
+
+/// Renders the `type` template filter for one side of the card.
+pub fn render(expected: &str, ignore_case_and_diacritics: bool, side: Side) -> String {
+    match side {
+        Side::Question => PROMPT.to_string(),
+        Side::Answer => {
+            let open = if ignore_case_and_diacritics {
+                ANSWER_OPEN_IGNORING
+            } else {
+                ANSWER_OPEN
+            };
+
+            format!("{}{}{}", open, expected, ANSWER_CLOSE)
+        }
+    }
+}
+
+/// The expected answer on the back of a card, and whether case and
+/// diacritics are ignored, if the card asks for the answer to be typed.
+pub fn expected(answer: &str) -> Option<(&str, bool)> {
+    [(ANSWER_OPEN, false), (ANSWER_OPEN_IGNORING, true)]
+        .into_iter()
+        .find_map(|(open, ignoring)| {
+            let start = answer.find(open)? + open.len();
+            let end = start + answer[start..].find(ANSWER_CLOSE)?;
+            Some((&answer[start..end], ignoring))
+        })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiffKind {
+    /// Typed as expected.
+    Correct,
+    /// Expected but not typed.
+    Missing,
+    /// Typed but not expected.
+    Extra,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffPart {
+    pub kind: DiffKind,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    /// The typed and expected text merged, in reading order.
+    pub parts: Vec<DiffPart>,
+    /// From 0 (nothing in common) to 1 (identical).
+    pub similarity: f32,
+}
+
+impl Comparison {
+    pub fn is_exact(&self) -> bool {
+        self.parts.iter().all(|part| part.kind == DiffKind::Correct)
+    }
+
+    /// The rating a typed answer suggests: good when it matches, hard when it
+    /// is close, again otherwise.
+    pub fn suggested_answer(&self) -> Answer {
+        if self.is_exact() {
+            Answer::Good
+        } else if self.similarity >= 0.8 {
+            Answer::Hard
+        } else {
+            Answer::Again
+        }
+    }
+}
+
+/// Reduces a character to what is compared when ignoring case and diacritics.
+fn fold(c: char) -> String {
+    c.to_lowercase()
+        .collect::<String>()
+        .nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .collect()
+}
+
+fn same(a: char, b: char, ignore_case_and_diacritics: bool) -> bool {
+    a == b || (ignore_case_and_diacritics && fold(a) == fold(b))
+}
+
+fn push(parts: &mut Vec<DiffPart>, kind: DiffKind, c: char) {
+    match parts.last_mut() {
+        Some(last) if last.kind == kind => last.text.push(c),
+        _ => parts.push(DiffPart {
+            kind,
+            text: c.to_string(),
+        }),
+    }
+}
+
+/// Compares typed text with the expected answer, keeping the longest run of
+/// characters they have in common.
+pub fn compare(typed: &str, expected: &str, ignore_case_and_diacritics: bool) -> Comparison {
+    let typed = typed.trim().chars().collect::<Vec<_>>();
+    let expected = expected.trim().chars().collect::<Vec<_>>();
+
+    // common[i][j] is the length of the longest common subsequence of
+    // typed[i..] and expected[j..].
+    let mut common = vec![vec![0usize; expected.len() + 1]; typed.len() + 1];
+    for i in (0..typed.len()).rev() {
+        for j in (0..expected.len()).rev() {
+            common[i][j] = if same(typed[i], expected[j], ignore_case_and_diacritics) {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut parts = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < typed.len() || j < expected.len() {
+        if i < typed.len()
+            && j < expected.len()
+            && same(typed[i], expected[j], ignore_case_and_diacritics)
+        {
+            // Show the expected spelling, which matters when differences in
+            // case or accents are ignored.
+            push(&mut parts, DiffKind::Correct, expected[j]);
+            i += 1;
+            j += 1;
+        } else if j < expected.len()
+            && (i == typed.len() || common[i][j + 1] >= common[i + 1][j])
+        {
+            push(&mut parts, DiffKind::Missing, expected[j]);
+            j += 1;
+        } else {
+            push(&mut parts, DiffKind::Extra, typed[i]);
+            i += 1;
+        }
+    }
+
+    let total = typed.len() + expected.len();
+    let similarity = if total == 0 {
+        1.0
+    } else {
+        (2 * common[0][0]) as f32 / total as f32
+    };
+
+    Comparison { parts, similarity }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn part(kind: DiffKind, text: &str) -> DiffPart {
+        DiffPart {
+            kind,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn marks_missing_and_extra_characters() {
+        let comparison = compare("helo wrld!", "hello world", false);
+
+        assert_eq!(
+            comparison.parts,
+            vec![
+                part(DiffKind::Correct, "hel"),
+                part(DiffKind::Missing, "l"),
+                part(DiffKind::Correct, "o w"),
+                part(DiffKind::Missing, "o"),
+                part(DiffKind::Correct, "rld"),
+                part(DiffKind::Extra, "!"),
+            ]
+        );
+        assert_eq!(comparison.suggested_answer(), Answer::Hard);
+    }
+
+    #[test]
+    fn exact_answers_suggest_good() {
+        let comparison = compare(" perro ", "perro", false);
+
+        assert!(comparison.is_exact());
+        assert_eq!(comparison.similarity, 1.0);
+        assert_eq!(comparison.suggested_answer(), Answer::Good);
+        assert_eq!(
+            compare("gato", "perro", false).suggested_answer(),
+            Answer::Again
+        );
+    }
+
+    #[test]
+    fn can_ignore_case_and_diacritics() {
+        assert!(!compare("Cafe", "café", false).is_exact());
+
+        let comparison = compare("Cafe", "café", true);
+        assert!(comparison.is_exact());
+        assert_eq!(comparison.parts, vec![part(DiffKind::Correct, "café")]);
+    }
+
+    #[test]
+    fn finds_the_expected_answer() {
+        let answer = format!("perro\n\n{}", render("dog", true, Side::Answer));
+
+        assert_eq!(expected(&answer), Some(("dog", true)));
+        assert_eq!(expected("perro"), None);
+        assert_eq!(render("dog", false, Side::Question), PROMPT);
+    }
+}
@@ -0,0 +1,182 @@
+//! Undoing the last change made to many cards at once. Before the change, the
+//! rows of the cards and of their notes are copied; undoing puts the copies
+//! back and forgets the deletions the change recorded for syncing.
+
+use rusqlite::{params_from_iter, types::Value, Connection};
+
+use crate::{
+    errors::Result,
+    repositories::{guid::PENDING_USN, timestamps::mtime_now},
+};
+
+/// Rows copied out of a table, with the names of their columns.
+#[derive(Debug, Clone, Default)]
+struct Rows {
+    columns: Vec<String>,
+    rows: Vec<Vec<Value>>,
+}
+
+impl Rows {
+    fn read(conn: &Connection, table: &str, ids: &[u32]) -> Result<Rows> {
+        let mut stmt = conn.prepare(&format!("SELECT * FROM {} WHERE id = ?", table))?;
+        let columns = stmt
+            .column_names()
+            .into_iter()
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+
+        let mut rows = Vec::new();
+        for id in ids {
+            let mut found = stmt.query([id])?;
+            if let Some(row) = found.next()? {
+                rows.push(
+                    (0..columns.len())
+                        .map(|ix| row.get(ix))
+                        .collect::<rusqlite::Result<Vec<Value>>>()?,
+                );
+            }
+        }
+
+        Ok(Rows { columns, rows })
+    }
+
+    /// Replaces the rows with the copies, marking them as changed since the
+    /// last sync.
+    fn write_back(&self, conn: &Connection, table: &str) -> Result<()> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+
+        let placeholders = vec!["?"; self.columns.len()].join(", ");
+        // A plain delete and insert rather than `INSERT OR REPLACE`, whose
+        // deletions skip the triggers keeping the search index up to date.
+        let mut delete = conn.prepare(&format!("DELETE FROM {} WHERE id = ?", table))?;
+        let mut insert = conn.prepare(&format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            table,
+            self.columns.join(", "),
+            placeholders
+        ))?;
+        let mut touch = conn.prepare(&format!(
+            "UPDATE {} SET mtime = ?, usn = ? WHERE id = ?",
+            table
+        ))?;
+
+        let mtime = mtime_now();
+        for row in &self.rows {
+            delete.execute([&row[0]])?;
+            insert.execute(params_from_iter(row))?;
+            touch.execute((mtime, PENDING_USN, &row[0]))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// What is needed to take back a change to many cards.
+#[derive(Debug, Clone)]
+pub struct UndoStep {
+    /// What the change did, as in "Suspend" or "Delete cards".
+    pub name: String,
+    cards: Rows,
+    notes: Rows,
+    /// The last row of `graves` before the change.
+    last_grave: i64,
+}
+
+/// Copies the cards, and their notes, before `name` changes them.
+pub fn snapshot(conn: &Connection, name: &str, card_ids: &[u32]) -> Result<UndoStep> {
+    let cards = Rows::read(conn, "cards", card_ids)?;
+
+    let note_column = cards.columns.iter().position(|column| column == "note_id");
+    let mut note_ids = cards
+        .rows
+        .iter()
+        .filter_map(|row| match note_column.map(|ix| &row[ix]) {
+            Some(Value::Integer(id)) if *id != 0 => Some(*id as u32),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    note_ids.sort();
+    note_ids.dedup();
+
+    Ok(UndoStep {
+        name: name.to_string(),
+        notes: Rows::read(conn, "notes", &note_ids)?,
+        cards,
+        last_grave: conn.query_row("SELECT COALESCE(MAX(rowid), 0) FROM graves", [], |row| {
+            row.get(0)
+        })?,
+    })
+}
+
+/// Puts the cards and notes back the way they were when `step` was taken.
+/// Notes go first, so that the cards find them again.
+pub fn restore(conn: &Connection, step: &UndoStep) -> Result<()> {
+    conn.execute("DELETE FROM graves WHERE rowid > ?", [step.last_grave])?;
+    step.notes.write_back(conn, "notes")?;
+    step.cards.write_back(conn, "cards")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        db::init_db,
+        models::{bulk, note::add_note},
+        repositories::{
+            note::Note,
+            notetype::{Notetype, BASIC_GUID},
+        },
+        Deck, FlashCard,
+    };
+
+    use super::*;
+
+    #[test]
+    fn restore_brings_back_changed_and_deleted_cards() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+        let mut deck = Deck::new("Spanish");
+        deck.save(&conn).unwrap();
+        let deck_id = deck.id.unwrap();
+
+        let basic = Notetype::load_by_guid(BASIC_GUID, &conn).unwrap().unwrap();
+        let mut note = Note::new(&basic);
+        note.fields = vec!["perro".to_string(), "dog".to_string()];
+        note.tags = vec!["animals".to_string()];
+        let card_id = add_note(&conn, &mut note, deck_id).unwrap()[0].id.unwrap();
+
+        let mut other = FlashCard::new(deck_id, "gato", "cat", None);
+        other.save(&conn).unwrap();
+        let ids = vec![card_id, other.id.unwrap()];
+
+        let step = snapshot(&conn, "Suspend", &ids).unwrap();
+        bulk::set_suspended(&conn, &ids, true).unwrap();
+        bulk::remove_tags(&conn, &ids, &["animals".to_string()]).unwrap();
+        restore(&conn, &step).unwrap();
+
+        let suspended: u32 = conn
+            .query_row("SELECT SUM(suspended) FROM cards", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(suspended, 0);
+        assert_eq!(
+            Note::load(note.id.unwrap(), &conn).unwrap().tags,
+            ["animals"]
+        );
+
+        let step = snapshot(&conn, "Delete cards", &ids).unwrap();
+        bulk::delete_cards(&conn, &ids).unwrap();
+        restore(&conn, &step).unwrap();
+
+        let card = FlashCard::load(card_id, &conn).unwrap();
+        assert_eq!(card.note_id, note.id.unwrap());
+        assert_eq!(card.usn, PENDING_USN);
+        assert!(Note::load(note.id.unwrap(), &conn).is_ok());
+        let graves: u32 = conn
+            .query_row("SELECT COUNT(*) FROM graves", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(graves, 0);
+    }
+}
@@ -1,13 +1,29 @@
 use catppuccin::Flavour;
 use gpui::*;
 
-use crate::{components::tab_panel::TabPanelBuilder, state::TabViewState, theme::Theme};
+use crate::{
+    components::{
+        profile_switcher::{ProfileEvent, ProfileSwitcher},
+        tab_panel::TabPanelBuilder,
+        tag_sidebar::TagSidebar,
+    },
+    errors::{ErrorState, NgurraError},
+    models::{
+        builder::Builder,
+        collection::{Collection, CollectionBuilder},
+    },
+    state::TabViewState,
+    storage::profile::Profiles,
+    theme::Theme,
+};
 
 actions!(ngurra, [Hide]);
 actions!(zed, [OpenSettings, Quit]);
 
 pub struct Ngurra {
-    state: TabViewState,
+    state: Option<TabViewState>,
+    tag_sidebar: Option<View<TagSidebar>>,
+    profile_switcher: Option<View<ProfileSwitcher>>,
 }
 
 fn quit(_: &Quit, cx: &mut AppContext) {
@@ -21,17 +37,87 @@ fn quit(_: &Quit, cx: &mut AppContext) {
 impl Ngurra {
     pub fn view(cx: &mut WindowContext) -> View<Self> {
         cx.new_view(|cx| {
-            let view_state = TabViewState::init(TabPanelBuilder {}, cx);
-            Self { state: view_state }
+            if cx.has_global::<Collection>() {
+                return Self {
+                    state: Some(TabViewState::init(TabPanelBuilder {}, cx)),
+                    tag_sidebar: Some(TagSidebar::view(cx)),
+                    profile_switcher: None,
+                };
+            }
+
+            let profile_switcher = ProfileSwitcher::view(cx);
+            cx.subscribe(&profile_switcher, |this, _, event, cx| match event {
+                ProfileEvent::Selected(name) => this.open_profile(name, cx),
+            })
+            .detach();
+
+            Self {
+                state: None,
+                tag_sidebar: None,
+                profile_switcher: Some(profile_switcher),
+            }
         })
     }
+
+    fn open_profile(&mut self, name: &str, cx: &mut ViewContext<Self>) {
+        let collection = CollectionBuilder::for_profile(cx.global::<Profiles>(), name)
+            .map_err(NgurraError::from)
+            .and_then(|mut builder| builder.build());
+
+        match collection {
+            Ok(collection) => {
+                Collection::init(collection, cx);
+                Collection::schedule_backups(cx);
+                Collection::sync_folder_on_start(cx);
+
+                self.state = Some(TabViewState::init(TabPanelBuilder {}, cx));
+                self.tag_sidebar = Some(TagSidebar::view(cx));
+                self.profile_switcher = None;
+                cx.notify();
+            }
+            Err(e) => {
+                log::error!("Error opening profile {}: {}", name, e);
+
+                if let Some(profile_switcher) = &self.profile_switcher {
+                    profile_switcher.update(cx, |switcher, cx| {
+                        switcher.error = Some(format!("Could not open {}: {}", name, e));
+                        cx.notify();
+                    });
+                }
+            }
+        }
+    }
 }
 
 impl Render for Ngurra {
     fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
         let theme = cx.global::<Theme>();
+        let read_only = cx.has_global::<Collection>() && cx.global::<Collection>().is_read_only();
+        let error = cx.global::<ErrorState>().message.clone();
 
-        let current_view = &self.state;
+        let content = if let Some(current_view) = &self.state {
+            div()
+                .flex()
+                .size_full()
+                .child(current_view.tabbar.clone())
+                .child(
+                    div()
+                        .size_full()
+                        .flex()
+                        .flex_row()
+                        .items_center()
+                        .justify_center()
+                        .child(
+                            div()
+                                .w(Pixels(800.0))
+                                .h_full()
+                                .child(current_view.view.clone()),
+                        ),
+                )
+                .children(self.tag_sidebar.clone())
+        } else {
+            div().size_full().children(self.profile_switcher.clone())
+        };
 
         div()
             .flex()
@@ -39,27 +125,37 @@ impl Render for Ngurra {
             .size_full()
             .bg(theme.base)
             .font(theme.font_mono.clone())
-            .child(div().h(Pixels(42.0)).border_b_1().border_color(theme.crust))
             .child(
                 div()
+                    .h(Pixels(42.0))
                     .flex()
-                    .size_full()
-                    .child(current_view.tabbar.clone())
-                    .child(
-                        div()
-                            .size_full()
-                            .flex()
-                            .flex_row()
-                            .items_center()
-                            .justify_center()
-                            .child(
-                                div()
-                                    .w(Pixels(800.0))
-                                    .h_full()
-                                    .child(current_view.view.clone()),
-                            ),
+                    .items_center()
+                    .justify_center()
+                    .border_b_1()
+                    .border_color(theme.crust)
+                    .text_xs()
+                    .text_color(theme.red)
+                    .children(
+                        read_only
+                            .then(|| "Read-only: this collection is open in another Ngurra window"),
                     ),
             )
+            .child(content)
+            .children(error.map(|message| {
+                div()
+                    .absolute()
+                    .bottom(Pixels(48.0))
+                    .left_4()
+                    .right_4()
+                    .px_4()
+                    .py_2()
+                    .rounded_md()
+                    .bg(theme.red)
+                    .text_color(theme.base)
+                    .text_sm()
+                    .on_mouse_down(MouseButton::Left, |_ev, cx| ErrorState::dismiss(cx))
+                    .child(message)
+            }))
             .child(
                 div()
                     .absolute()
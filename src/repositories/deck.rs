@@ -1,12 +1,15 @@
 use std::{collections::HashMap, time::SystemTime};
 
 use chrono::{DateTime, Utc};
-use rusqlite::{named_params, Connection, Result};
-use time::OffsetDateTime;
+use rusqlite::{named_params, params, Connection, Result};
 
 use crate::FlashCard;
 
-use super::flash_card::CardQueue;
+use super::{
+    flash_card::CardQueue,
+    guid::{new_guid, GraveKind, PENDING_USN},
+    timestamps::{mtime_now, time_from_sql},
+};
 
 /// Represents a deck in the Anki application.
 ///
@@ -39,6 +42,12 @@ pub struct Deck {
     pub id: Option<u32>,
     pub name: String,
     creation_time: SystemTime,
+    /// Identifies the deck across collections; never changes once assigned.
+    pub guid: String,
+    /// Seconds since the epoch at which the deck was last saved.
+    pub mtime: i64,
+    /// Update sequence number; `PENDING_USN` until the change has been synced.
+    pub usn: i32,
     pub cards: Vec<FlashCard>,
     pub stats: Option<DeckStat>,
 }
@@ -66,6 +75,9 @@ impl Deck {
             id: None,
             name: name.to_string(),
             creation_time: SystemTime::now(),
+            guid: new_guid(),
+            mtime: mtime_now(),
+            usn: PENDING_USN,
             cards: Vec::new(),
             stats: None,
         }
@@ -81,34 +93,23 @@ impl Deck {
     ///
     /// A `Result` containing a vector of all decks, or an error if the operation fails.
     pub fn get_all_decks(conn: &Connection) -> Result<Vec<Deck>> {
-        let mut stmt = conn.prepare("SELECT id, name, creation_time FROM decks")?;
+        let mut stmt =
+            conn.prepare("SELECT id, name, creation_time, guid, mtime, usn FROM decks")?;
 
         let decks = stmt.query_map([], |row| {
-            let creation_time: String = row.get(2)?;
-
-            let creation_time = DateTime::parse_from_rfc3339(&creation_time)
-                .unwrap()
-                .naive_utc();
-
-            let creation_time = OffsetDateTime::from_unix_timestamp(creation_time.timestamp())
-                .unwrap()
-                .into();
-
             Ok(Deck {
                 id: Some(row.get(0)?),
                 name: row.get(1)?,
-                creation_time,
+                creation_time: time_from_sql(row, 2)?,
+                guid: row.get(3)?,
+                mtime: row.get(4)?,
+                usn: row.get(5)?,
                 cards: Vec::new(),
                 stats: None,
             })
         })?;
 
-        let mut result = Vec::new();
-        for deck in decks {
-            result.push(deck.unwrap());
-        }
-
-        Ok(result)
+        decks.collect()
     }
 
     /// Loads a deck from the database by its ID.
@@ -122,23 +123,17 @@ impl Deck {
     ///
     /// A `Result` containing the loaded deck, or an error if the operation fails.
     pub fn load(id: u32, conn: &Connection) -> Result<Deck> {
-        let mut stmt = conn.prepare("SELECT id, name, creation_time FROM decks WHERE id = ?")?;
+        let mut stmt = conn
+            .prepare("SELECT id, name, creation_time, guid, mtime, usn FROM decks WHERE id = ?")?;
 
         let deck = stmt.query_row(&[&id], |row| {
-            let creation_time: String = row.get(2)?;
-
-            let creation_time = DateTime::parse_from_rfc3339(&creation_time)
-                .unwrap()
-                .naive_utc();
-
-            let creation_time = OffsetDateTime::from_unix_timestamp(creation_time.timestamp())
-                .unwrap()
-                .into();
-
             Ok(Deck {
                 id: Some(row.get(0)?),
                 name: row.get(1)?,
-                creation_time,
+                creation_time: time_from_sql(row, 2)?,
+                guid: row.get(3)?,
+                mtime: row.get(4)?,
+                usn: row.get(5)?,
                 cards: Vec::new(),
                 stats: None,
             })
@@ -150,7 +145,8 @@ impl Deck {
     /// Saves the deck to the database.
     ///
     /// If the deck already has an ID, it will be updated in the database.
-    /// Otherwise, a new record will be inserted.
+    /// Otherwise, a new record will be inserted. Either way the modification
+    /// time is bumped and the deck is marked as pending sync.
     ///
     /// # Arguments
     ///
@@ -160,19 +156,25 @@ impl Deck {
     ///
     /// A `Result` indicating success or failure.
     pub fn save(&mut self, conn: &Connection) -> Result<()> {
+        self.mtime = mtime_now();
+        self.usn = PENDING_USN;
+
         match self.id {
             Some(id) => {
                 conn.execute(
-                    "UPDATE decks SET name = ? WHERE id = ?",
-                    &[&self.name, &id.to_string()],
+                    "UPDATE decks SET name = ?, mtime = ?, usn = ? WHERE id = ?",
+                    params![self.name, self.mtime, self.usn, id],
                 )?;
             }
             None => {
                 conn.execute(
-                    "INSERT INTO decks (name, creation_time) VALUES (?, ?)",
-                    &[
-                        &self.name,
-                        &DateTime::<Utc>::from(self.creation_time).to_rfc3339(),
+                    "INSERT INTO decks (name, creation_time, guid, mtime, usn) VALUES (?, ?, ?, ?, ?)",
+                    params![
+                        self.name,
+                        DateTime::<Utc>::from(self.creation_time).to_rfc3339(),
+                        self.guid,
+                        self.mtime,
+                        self.usn,
                     ],
                 )?;
 
@@ -184,7 +186,8 @@ impl Deck {
         Ok(())
     }
 
-    /// Deletes the deck from the database.
+    /// Deletes the deck from the database, leaving a grave behind so the
+    /// deletion can be synced.
     ///
     /// # Arguments
     ///
@@ -195,6 +198,10 @@ impl Deck {
     ///
     /// A `Result` indicating success or failure.
     pub fn delete(id: u32, conn: &Connection) -> Result<()> {
+        conn.execute(
+            "INSERT INTO graves (guid, kind, usn, mtime) SELECT guid, ?, ?, ? FROM decks WHERE id = ?",
+            params![GraveKind::Deck as u8, PENDING_USN, mtime_now(), id],
+        )?;
         conn.execute("DELETE FROM decks WHERE id = ?", &[&id])?;
 
         Ok(())
@@ -306,6 +313,30 @@ mod test {
         assert!(deck.id.is_some());
     }
 
+    #[test]
+    fn save_bumps_mtime_and_usn() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+
+        let mut deck = Deck::new("Test Deck");
+        deck.save(&conn).unwrap();
+        let guid = deck.guid.clone();
+
+        conn.execute("UPDATE decks SET mtime = 0, usn = 5", [])
+            .unwrap();
+
+        let mut loaded = Deck::load(deck.id.unwrap(), &conn).unwrap();
+        assert_eq!(loaded.usn, 5);
+
+        loaded.name = "Renamed".to_string();
+        loaded.save(&conn).unwrap();
+
+        let reloaded = Deck::load(deck.id.unwrap(), &conn).unwrap();
+        assert_eq!(reloaded.guid, guid);
+        assert_eq!(reloaded.usn, PENDING_USN);
+        assert!(reloaded.mtime > 0);
+    }
+
     #[test]
     fn delete() {
         let conn = Connection::open_in_memory().unwrap();
@@ -374,4 +405,26 @@ mod test {
         assert_eq!(deck_stat.new, 1);
         assert_eq!(deck_stat.learning, 1);
     }
+
+    #[test]
+    fn get_decks_stats_skips_buried_cards() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+
+        let mut deck = Deck::new("Test Deck");
+        deck.save(&conn).unwrap();
+
+        let mut buried_card = FlashCard::new(1, "Front", "Back", None);
+        buried_card.buried_until = 2;
+        buried_card.save(&conn).unwrap();
+
+        let mut new_card = FlashCard::new(1, "Back", "Front", None);
+        new_card.save(&conn).unwrap();
+
+        let stats = Deck::get_decks_stats(&conn, 1).unwrap();
+        assert_eq!(stats.get(&deck.id.unwrap()).unwrap().new, 1);
+
+        let stats = Deck::get_decks_stats(&conn, 2).unwrap();
+        assert_eq!(stats.get(&deck.id.unwrap()).unwrap().new, 2);
+    }
 }
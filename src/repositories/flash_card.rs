@@ -8,11 +8,13 @@ use rusqlite::{
     Connection, Result,
 };
 
-use time::OffsetDateTime;
-
 use crate::models::collection::CollectionBuilder;
 
-use super::card_data::CardData;
+use super::{
+    card_data::CardData,
+    guid::{new_guid, GraveKind, PENDING_USN},
+    timestamps::{mtime_now, optional_time_from_sql, time_from_sql},
+};
 
 #[derive(PartialEq, Debug, Clone)]
 pub enum Status {
@@ -57,6 +59,20 @@ pub struct FlashCard {
     queue: CardQueue,
     pub data: CardData,
     pub memory_state: Option<MemoryState>,
+    /// Identifies the card across collections; never changes once assigned.
+    pub guid: String,
+    /// Seconds since the epoch at which the card was last saved.
+    pub mtime: i64,
+    /// Update sequence number; `PENDING_USN` until the change has been synced.
+    pub usn: i32,
+    /// The note the card was generated from, or 0 for a card without one.
+    pub note_id: u32,
+    /// Which of the note's cards this is: the template index, or the cloze
+    /// number minus one.
+    pub ord: u32,
+    /// The card stays out of study until this day, counted like
+    /// `SchedTimingToday::days_elapsed`, because a sibling was answered.
+    pub buried_until: u32,
 }
 
 impl FlashCard {
@@ -80,6 +96,12 @@ impl FlashCard {
                 custom_data: String::from("{}"),
             },
             memory_state: None,
+            guid: new_guid(),
+            mtime: mtime_now(),
+            usn: PENDING_USN,
+            note_id: 0,
+            ord: 0,
+            buried_until: 0,
         }
     }
 
@@ -95,6 +117,41 @@ impl FlashCard {
         &self.answer
     }
 
+    pub fn get_deck_id(&self) -> u32 {
+        self.deck_id
+    }
+
+    /// Moves the card to another deck once saved.
+    pub fn set_deck_id(&mut self, deck_id: u32) {
+        self.deck_id = deck_id;
+    }
+
+    /// Replaces the rendered sides, e.g. after the card's note was edited.
+    pub fn set_content(&mut self, question: &str, answer: &str) {
+        self.question = question.to_string();
+        self.answer = answer.to_string();
+    }
+
+    /// A copy of the card in another deck, under a new guid and not yet
+    /// saved. With `reset` the copy starts over as a new card; otherwise it
+    /// keeps the card's scheduling.
+    pub fn duplicate(&self, deck_id: u32, note_id: u32, reset: bool) -> FlashCard {
+        let mut card = if reset {
+            FlashCard::new(deck_id, &self.question, &self.answer, None)
+        } else {
+            FlashCard {
+                id: None,
+                deck_id,
+                guid: new_guid(),
+                ..self.clone()
+            }
+        };
+
+        card.note_id = note_id;
+        card.ord = self.ord;
+        card
+    }
+
     pub fn set_queue(&mut self, queue: CardQueue) {
         self.queue = queue;
     }
@@ -123,46 +180,14 @@ impl FlashCard {
         let mut rows = stmt.query(params![
             deck_id,
             queue as i8,
+            timing_at_stamp.days_elapsed as u32,
             timing_at_stamp.days_elapsed as u32
         ])?;
-        while let row = rows.next()? {
-            if let None = row {
-                break;
-            }
-
-            let row = row.unwrap();
-
-            let creation_time: String = row.get(4)?;
-            let creation_time = DateTime::parse_from_rfc3339(&creation_time)
-                .unwrap()
-                .naive_utc();
-            let creation_time = OffsetDateTime::from_unix_timestamp(creation_time.timestamp())
-                .unwrap()
-                .into();
-
-            let last_studied_time: Result<String> = row.get(5);
-
-            let last_studied_time = if let Ok(last_studied_time) = last_studied_time {
-                let last_studied_time = DateTime::parse_from_rfc3339(&last_studied_time);
-
-                match last_studied_time {
-                    Ok(last_studied_time) => {
-                        let last_studied_time = last_studied_time.naive_utc();
-                        let last_studied_time =
-                            OffsetDateTime::from_unix_timestamp(last_studied_time.timestamp())
-                                .unwrap()
-                                .into();
-
-                        Some(last_studied_time)
-                    }
-                    Err(e) => {
-                        println!("Error parsing last_studied_time: {}", e);
-                        None
-                    }
-                }
-            } else {
+        while let Some(row) = rows.next()? {
+            let last_studied_time = optional_time_from_sql(row, 5).unwrap_or_else(|e| {
+                log::warn!("Error parsing last_studied_time: {}", e);
                 None
-            };
+            });
 
             let card_data: CardData = row.get(10)?;
 
@@ -171,7 +196,7 @@ impl FlashCard {
                 deck_id: row.get(1)?,
                 question: row.get(2)?,
                 answer: row.get(3)?,
-                creation_time,
+                creation_time: time_from_sql(row, 4)?,
                 last_studied_time,
                 ef: row.get(6)?,
                 interval: row.get(7)?,
@@ -179,6 +204,12 @@ impl FlashCard {
                 queue: row.get(9)?,
                 memory_state: card_data.memory_state(),
                 data: card_data,
+                guid: row.get(11)?,
+                mtime: row.get(12)?,
+                usn: row.get(13)?,
+                note_id: row.get(14)?,
+                ord: row.get(15)?,
+                buried_until: row.get(16)?,
             };
 
             func(&card);
@@ -201,30 +232,6 @@ impl FlashCard {
         let mut stmt = conn.prepare(include_str!("get_card.sql"))?;
 
         let deck = stmt.query_row(&[&id], |row| {
-            let creation_time: String = row.get(4)?;
-            let creation_time = DateTime::parse_from_rfc3339(&creation_time)
-                .unwrap()
-                .naive_utc();
-            let creation_time = OffsetDateTime::from_unix_timestamp(creation_time.timestamp())
-                .unwrap()
-                .into();
-
-            let last_studied_time: Result<String> = row.get(5);
-
-            let last_studied_time = if let Ok(last_studied_time) = last_studied_time {
-                let last_studied_time = DateTime::parse_from_rfc3339(&last_studied_time)
-                    .unwrap()
-                    .naive_utc();
-
-                Some(
-                    OffsetDateTime::from_unix_timestamp(last_studied_time.timestamp())
-                        .unwrap()
-                        .into(),
-                )
-            } else {
-                None
-            };
-
             let data: CardData = row.get(10)?;
 
             Ok(FlashCard {
@@ -232,14 +239,20 @@ impl FlashCard {
                 deck_id: row.get(1)?,
                 question: row.get(2)?,
                 answer: row.get(3)?,
-                creation_time,
-                last_studied_time,
+                creation_time: time_from_sql(row, 4)?,
+                last_studied_time: optional_time_from_sql(row, 5)?,
                 ef: row.get(6)?,
                 interval: row.get(7)?,
                 queue: row.get(8)?,
                 due: row.get(9)?,
                 memory_state: data.memory_state(),
                 data,
+                guid: row.get(11)?,
+                mtime: row.get(12)?,
+                usn: row.get(13)?,
+                note_id: row.get(14)?,
+                ord: row.get(15)?,
+                buried_until: row.get(16)?,
             })
         })?;
 
@@ -249,7 +262,8 @@ impl FlashCard {
     /// Save a card to the database
     ///
     /// If the deck already has an ID, it will be updated in the database.
-    /// Otherwise, a new record will be inserted.
+    /// Otherwise, a new record will be inserted. The modification time is
+    /// bumped and the card is marked as pending sync.
     ///
     /// # Arguments
     ///
@@ -265,10 +279,13 @@ impl FlashCard {
             DateTime::<Utc>::from(SystemTime::now()).to_rfc3339()
         };
 
+        self.mtime = mtime_now();
+        self.usn = PENDING_USN;
+
         match self.id {
             Some(id) => {
                 conn.execute(
-                    "UPDATE cards SET question = ?, answer = ?, deck_id = ?, ef = ?, interval = ?, last_studied_time = ?, due = ?, queue = ?, data = ? WHERE id = ?",
+                    "UPDATE cards SET question = ?, answer = ?, deck_id = ?, ef = ?, interval = ?, last_studied_time = ?, due = ?, queue = ?, data = ?, mtime = ?, usn = ?, note_id = ?, ord = ?, buried_until = ? WHERE id = ?",
                     params![
                         self.question,
                         self.answer,
@@ -279,13 +296,18 @@ impl FlashCard {
                         self.due,
                         self.queue.clone() as i8,
                         self.data,
+                        self.mtime,
+                        self.usn,
+                        self.note_id,
+                        self.ord,
+                        self.buried_until,
                         id.to_string(),
                     ]
                 )?;
             }
             None => {
                 let mut stmt = conn.prepare_cached(
-                    "INSERT INTO cards (question, answer, creation_time, deck_id, ef, interval, last_studied_time, queue, due, data) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+                    "INSERT INTO cards (question, answer, creation_time, deck_id, ef, interval, last_studied_time, queue, due, data, guid, mtime, usn, note_id, ord, buried_until) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
                 )?;
 
                 stmt.execute(params![
@@ -298,7 +320,13 @@ impl FlashCard {
                     last_studied_time.to_string(),
                     self.queue.clone() as i8,
                     self.due,
-                    self.data
+                    self.data,
+                    self.guid,
+                    self.mtime,
+                    self.usn,
+                    self.note_id,
+                    self.ord,
+                    self.buried_until
                 ])?;
 
                 let id = conn.last_insert_rowid();
@@ -309,7 +337,8 @@ impl FlashCard {
         Ok(())
     }
 
-    /// Deletes the card from the database.
+    /// Deletes the card from the database, leaving a grave behind so the
+    /// deletion can be synced.
     ///
     /// # Arguments
     ///
@@ -319,11 +348,52 @@ impl FlashCard {
     ///
     /// A `Result` indicating success or failure.
     pub fn delete(&self, conn: &Connection) -> Result<()> {
+        conn.execute(
+            "INSERT INTO graves (guid, kind, usn, mtime) SELECT guid, ?, ?, ? FROM cards WHERE id = ?",
+            params![GraveKind::Card as u8, PENDING_USN, mtime_now(), self.id],
+        )?;
         conn.execute("DELETE FROM cards WHERE id = ?", &[&self.id])?;
 
         Ok(())
     }
 
+    /// Keeps the card's new and review siblings, the other cards of its note,
+    /// out of study until the given day, so that one card does not give away
+    /// the answer to another on the same day. Learning siblings are left alone
+    /// since they are due again within minutes.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - The database connection.
+    /// * `until` - The first day on which the siblings can be studied again.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the IDs of the buried siblings.
+    pub fn bury_siblings(&self, conn: &Connection, until: u32) -> Result<Vec<u32>> {
+        if self.note_id == 0 {
+            return Ok(Vec::new());
+        }
+
+        let ids = conn
+            .prepare(
+                "SELECT id FROM cards WHERE note_id = ? AND id != ? AND queue != ? AND buried_until < ?",
+            )?
+            .query_map(
+                params![self.note_id, self.id, CardQueue::Learning as u8, until],
+                |row| row.get(0),
+            )?
+            .collect::<Result<Vec<u32>>>()?;
+
+        let mut stmt =
+            conn.prepare("UPDATE cards SET buried_until = ?, mtime = ?, usn = ? WHERE id = ?")?;
+        for id in &ids {
+            stmt.execute(params![until, mtime_now(), PENDING_USN, id])?;
+        }
+
+        Ok(ids)
+    }
+
     pub fn get_status(&self) -> Status {
         let current_time = SystemTime::now();
         let interval_duration = Duration::from_secs((self.interval * 24 * 60 * 60) as u64);
@@ -384,6 +454,32 @@ mod test {
         let loaded_card = FlashCard::load(card.id.unwrap(), &conn).unwrap();
         assert_eq!(card.question, loaded_card.question);
         assert_eq!(card.answer, loaded_card.answer);
+        assert_eq!(card.guid, loaded_card.guid);
+    }
+
+    #[test]
+    fn save_bumps_mtime_and_usn() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+
+        let mut deck = Deck::new("Test Deck");
+        deck.save(&conn).unwrap();
+
+        let mut card = FlashCard::new(deck.id.unwrap(), "Front", "Back", None);
+        card.save(&conn).unwrap();
+
+        conn.execute("UPDATE cards SET mtime = 0, usn = 3", [])
+            .unwrap();
+
+        let mut loaded = FlashCard::load(card.id.unwrap(), &conn).unwrap();
+        assert_eq!(loaded.usn, 3);
+
+        loaded.save(&conn).unwrap();
+
+        let reloaded = FlashCard::load(card.id.unwrap(), &conn).unwrap();
+        assert_eq!(reloaded.guid, card.guid);
+        assert_eq!(reloaded.usn, PENDING_USN);
+        assert!(reloaded.mtime > 0);
     }
 
     #[test]
@@ -486,4 +582,47 @@ mod test {
         card.rate(2);
         assert_eq!(card.ef, 2.14);
     }
+
+    #[test]
+    fn bury_siblings() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+
+        let mut deck = Deck::new("Test Deck");
+        deck.save(&conn).unwrap();
+
+        let mut cards = ["Front", "Back", "Other"]
+            .iter()
+            .map(|question| {
+                let mut card = FlashCard::new(deck.id.unwrap(), question, "", None);
+                card.note_id = 7;
+                card
+            })
+            .collect::<Vec<_>>();
+        cards[2].set_queue(CardQueue::Learning);
+        for card in cards.iter_mut() {
+            card.save(&conn).unwrap();
+        }
+
+        let mut unrelated = FlashCard::new(deck.id.unwrap(), "Unrelated", "", None);
+        unrelated.save(&conn).unwrap();
+
+        let buried = cards[0].bury_siblings(&conn, 5).unwrap();
+        assert_eq!(buried, vec![cards[1].id.unwrap()]);
+        assert_eq!(
+            FlashCard::load(cards[1].id.unwrap(), &conn)
+                .unwrap()
+                .buried_until,
+            5
+        );
+
+        let mut studied = Vec::new();
+        FlashCard::for_each_card_in_deck(&conn, deck.id.unwrap(), CardQueue::New, |card| {
+            studied.push(card.get_question().to_string());
+        })
+        .unwrap();
+        assert!(!studied.contains(&"Back".to_string()));
+
+        assert!(unrelated.bury_siblings(&conn, 5).unwrap().is_empty());
+    }
 }
@@ -9,7 +9,13 @@ SELECT
   interval,
   queue,
   due,
-  data
+  data,
+  guid,
+  mtime,
+  usn,
+  note_id,
+  ord,
+  buried_until
 FROM
   cards
 WHERE
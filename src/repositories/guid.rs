@@ -0,0 +1,17 @@
+/// Update sequence number of a row that was changed locally and has not been
+/// synchronised yet.
+pub const PENDING_USN: i32 = -1;
+
+/// What kind of object a grave records the deletion of.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GraveKind {
+    Deck = 0,
+    Card = 1,
+    Note = 2,
+}
+
+/// A globally unique id for a new deck or card. Unlike the integer primary
+/// key it stays the same when the row is imported or synced elsewhere.
+pub fn new_guid() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
@@ -1,4 +1,9 @@
 pub mod card_data;
 pub mod deck;
 pub mod flash_card;
+pub mod guid;
+pub mod note;
+pub mod notetype;
+pub mod revlog;
 pub mod session;
+pub mod timestamps;
@@ -0,0 +1,212 @@
+use rusqlite::{params, Connection, Result, Row};
+
+use super::{
+    guid::{new_guid, GraveKind, PENDING_USN},
+    notetype::Notetype,
+    timestamps::mtime_now,
+};
+
+/// Separates the fields of a note in the `fields` column, as in Anki.
+pub const FIELD_SEPARATOR: char = '\x1f';
+
+/// The content a note's cards are generated from: one value per field of its
+/// note type.
+#[derive(Debug, Clone)]
+pub struct Note {
+    pub id: Option<u32>,
+    /// Identifies the note across collections; never changes once assigned.
+    pub guid: String,
+    pub notetype_id: u32,
+    pub fields: Vec<String>,
+    /// Tags shared by all of the note's cards; see `models::tag`.
+    pub tags: Vec<String>,
+    /// Seconds since the epoch at which the note was last saved.
+    pub mtime: i64,
+    /// Update sequence number; `PENDING_USN` until the change has been synced.
+    pub usn: i32,
+}
+
+pub fn join_fields(fields: &[String]) -> String {
+    fields.join(&FIELD_SEPARATOR.to_string())
+}
+
+pub fn split_fields(fields: &str) -> Vec<String> {
+    fields.split(FIELD_SEPARATOR).map(str::to_string).collect()
+}
+
+/// Stores tags separated by spaces, with a space on both ends so that a tag
+/// can be matched with `LIKE '% tag %'`.
+pub fn join_tags(tags: &[String]) -> String {
+    if tags.is_empty() {
+        String::new()
+    } else {
+        format!(" {} ", tags.join(" "))
+    }
+}
+
+pub fn split_tags(tags: &str) -> Vec<String> {
+    tags.split_whitespace().map(str::to_string).collect()
+}
+
+impl Note {
+    /// Creates a new note with every field of the note type empty.
+    ///
+    /// # Arguments
+    ///
+    /// * `notetype` - The note type, which must already be saved.
+    ///
+    /// # Returns
+    ///
+    /// The newly created note.
+    pub fn new(notetype: &Notetype) -> Note {
+        Note {
+            id: None,
+            guid: new_guid(),
+            notetype_id: notetype.id.unwrap_or_default(),
+            fields: vec![String::new(); notetype.fields.len()],
+            tags: Vec::new(),
+            mtime: mtime_now(),
+            usn: PENDING_USN,
+        }
+    }
+
+    fn from_row(row: &Row) -> Result<Note> {
+        Ok(Note {
+            id: Some(row.get(0)?),
+            guid: row.get(1)?,
+            notetype_id: row.get(2)?,
+            fields: split_fields(&row.get::<_, String>(3)?),
+            tags: split_tags(&row.get::<_, String>(4)?),
+            mtime: row.get(5)?,
+            usn: row.get(6)?,
+        })
+    }
+
+    /// Loads a note from the database by its ID.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the note to load.
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the loaded note, or an error if the operation fails.
+    pub fn load(id: u32, conn: &Connection) -> Result<Note> {
+        conn.query_row(
+            "SELECT id, guid, notetype_id, fields, tags, mtime, usn FROM notes WHERE id = ?",
+            [id],
+            Self::from_row,
+        )
+    }
+
+    /// Saves the note to the database.
+    ///
+    /// If the note already has an ID, it will be updated in the database.
+    /// Otherwise, a new record will be inserted. Either way the modification
+    /// time is bumped and the note is marked as pending sync. This does not
+    /// touch the note's cards; see `models::note` for that.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    pub fn save(&mut self, conn: &Connection) -> Result<()> {
+        self.mtime = mtime_now();
+        self.usn = PENDING_USN;
+
+        match self.id {
+            Some(id) => {
+                conn.execute(
+                    "UPDATE notes SET notetype_id = ?, fields = ?, tags = ?, mtime = ?, usn = ? WHERE id = ?",
+                    params![
+                        self.notetype_id,
+                        join_fields(&self.fields),
+                        join_tags(&self.tags),
+                        self.mtime,
+                        self.usn,
+                        id
+                    ],
+                )?;
+            }
+            None => {
+                conn.execute(
+                    "INSERT INTO notes (guid, notetype_id, fields, tags, mtime, usn) VALUES (?, ?, ?, ?, ?, ?)",
+                    params![
+                        self.guid,
+                        self.notetype_id,
+                        join_fields(&self.fields),
+                        join_tags(&self.tags),
+                        self.mtime,
+                        self.usn
+                    ],
+                )?;
+
+                self.id = Some(conn.last_insert_rowid() as u32);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Deletes the note and its cards, leaving graves behind so the deletion
+    /// can be synced.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    pub fn delete(&self, conn: &Connection) -> Result<()> {
+        conn.execute(
+            "INSERT INTO graves (guid, kind, usn, mtime) SELECT guid, ?, ?, ? FROM cards WHERE note_id = ?",
+            params![GraveKind::Card as u8, PENDING_USN, mtime_now(), self.id],
+        )?;
+        conn.execute("DELETE FROM cards WHERE note_id = ?", [self.id])?;
+
+        conn.execute(
+            "INSERT INTO graves (guid, kind, usn, mtime) SELECT guid, ?, ?, ? FROM notes WHERE id = ?",
+            params![GraveKind::Note as u8, PENDING_USN, mtime_now(), self.id],
+        )?;
+        conn.execute("DELETE FROM notes WHERE id = ?", [self.id])?;
+
+        Ok(())
+    }
+
+    /// The IDs of the note's cards, ordered by template.
+    pub fn card_ids(&self, conn: &Connection) -> Result<Vec<u32>> {
+        conn.prepare("SELECT id FROM cards WHERE note_id = ? ORDER BY ord")?
+            .query_map([self.id], |row| row.get(0))?
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{db::init_db, repositories::notetype::BASIC_GUID};
+
+    use super::*;
+
+    #[test]
+    fn save_and_load() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+
+        let basic = Notetype::load_by_guid(BASIC_GUID, &conn).unwrap().unwrap();
+        let mut note = Note::new(&basic);
+        note.fields = vec!["Front\nwith a newline".to_string(), "Back".to_string()];
+        note.tags = vec!["lang::fr".to_string(), "verbs".to_string()];
+        note.save(&conn).unwrap();
+
+        let loaded = Note::load(note.id.unwrap(), &conn).unwrap();
+        assert_eq!(loaded.fields, note.fields);
+        assert_eq!(loaded.tags, note.tags);
+        assert_eq!(loaded.guid, note.guid);
+        assert_eq!(loaded.notetype_id, basic.id.unwrap());
+    }
+}
@@ -0,0 +1,387 @@
+use rusqlite::{
+    params,
+    types::{FromSql, FromSqlError, FromSqlResult, ValueRef},
+    Connection, OptionalExtension, Result, Row,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    guid::{new_guid, PENDING_USN},
+    timestamps::mtime_now,
+};
+
+/// Guid of the built-in "Basic" note type. Built-in note types have the same
+/// guid in every collection, so they line up when collections are synced.
+pub const BASIC_GUID: &str = "ngurra-basic";
+pub const BASIC_AND_REVERSED_GUID: &str = "ngurra-basic-and-reversed";
+pub const BASIC_OPTIONAL_REVERSED_GUID: &str = "ngurra-basic-optional-reversed";
+pub const BASIC_TYPE_ANSWER_GUID: &str = "ngurra-basic-type-answer";
+pub const CLOZE_GUID: &str = "ngurra-cloze";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NotetypeKind {
+    /// One card per template whose front is not blank.
+    Normal = 0,
+    /// One card per cloze number in the text.
+    Cloze = 1,
+}
+
+impl FromSql for NotetypeKind {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_i64()? {
+            0 => Ok(NotetypeKind::Normal),
+            1 => Ok(NotetypeKind::Cloze),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+/// How one card of a note is laid out. The sides are templates in which
+/// `{{Field}}` is replaced by the note's field, and `{{#Field}}...{{/Field}}`
+/// is only shown when the field is not empty.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CardTemplate {
+    pub name: String,
+    pub front: String,
+    pub back: String,
+}
+
+impl CardTemplate {
+    pub fn new(name: &str, front: &str, back: &str) -> CardTemplate {
+        CardTemplate {
+            name: name.to_string(),
+            front: front.to_string(),
+            back: back.to_string(),
+        }
+    }
+}
+
+/// Defines the fields a note has and the cards generated from it.
+#[derive(Debug, Clone)]
+pub struct Notetype {
+    pub id: Option<u32>,
+    /// Identifies the note type across collections; never changes once assigned.
+    pub guid: String,
+    pub name: String,
+    pub kind: NotetypeKind,
+    pub fields: Vec<String>,
+    pub templates: Vec<CardTemplate>,
+    /// Seconds since the epoch at which the note type was last saved.
+    pub mtime: i64,
+    /// Update sequence number; `PENDING_USN` until the change has been synced.
+    pub usn: i32,
+}
+
+const NOTETYPE_QUERY: &str =
+    "SELECT id, guid, name, kind, fields, templates, mtime, usn FROM notetypes";
+
+fn to_json<T: Serialize>(value: &T) -> Result<String> {
+    serde_json::to_string(value).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+}
+
+fn from_json<T: for<'de> Deserialize<'de>>(row: &Row, idx: usize) -> Result<T> {
+    let text: String = row.get(idx)?;
+    serde_json::from_str(&text).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(idx, rusqlite::types::Type::Text, Box::new(e))
+    })
+}
+
+impl Notetype {
+    /// Creates a new note type.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name shown when picking a note type.
+    /// * `kind` - Whether cards come from the templates or from cloze numbers.
+    /// * `fields` - The names of the note's fields, in order.
+    /// * `templates` - The card templates, in order.
+    ///
+    /// # Returns
+    ///
+    /// The newly created note type.
+    pub fn new(
+        name: &str,
+        kind: NotetypeKind,
+        fields: &[&str],
+        templates: Vec<CardTemplate>,
+    ) -> Notetype {
+        Notetype {
+            id: None,
+            guid: new_guid(),
+            name: name.to_string(),
+            kind,
+            fields: fields.iter().map(|field| field.to_string()).collect(),
+            templates,
+            mtime: mtime_now(),
+            usn: PENDING_USN,
+        }
+    }
+
+    /// The built-in "Basic" note type: a front and a back, one card.
+    pub fn basic() -> Notetype {
+        Notetype {
+            guid: BASIC_GUID.to_string(),
+            ..Notetype::new(
+                "Basic",
+                NotetypeKind::Normal,
+                &["Front", "Back"],
+                vec![CardTemplate::new("Card 1", "{{Front}}", "{{Back}}")],
+            )
+        }
+    }
+
+    /// "Basic (and reversed)": every note makes a front→back and a
+    /// back→front card.
+    pub fn basic_and_reversed() -> Notetype {
+        Notetype {
+            guid: BASIC_AND_REVERSED_GUID.to_string(),
+            ..Notetype::new(
+                "Basic (and reversed)",
+                NotetypeKind::Normal,
+                &["Front", "Back"],
+                vec![
+                    CardTemplate::new("Card 1", "{{Front}}", "{{Back}}"),
+                    CardTemplate::new("Card 2", "{{Back}}", "{{Front}}"),
+                ],
+            )
+        }
+    }
+
+    /// "Basic (optional reversed)": the back→front card is only made when
+    /// something is typed into "Add Reverse".
+    pub fn basic_optional_reversed() -> Notetype {
+        Notetype {
+            guid: BASIC_OPTIONAL_REVERSED_GUID.to_string(),
+            ..Notetype::new(
+                "Basic (optional reversed)",
+                NotetypeKind::Normal,
+                &["Front", "Back", "Add Reverse"],
+                vec![
+                    CardTemplate::new("Card 1", "{{Front}}", "{{Back}}"),
+                    CardTemplate::new(
+                        "Card 2",
+                        "{{#Add Reverse}}{{Back}}{{/Add Reverse}}",
+                        "{{Front}}",
+                    ),
+                ],
+            )
+        }
+    }
+
+    /// "Basic (type in the answer)": the back is typed in before it is shown,
+    /// and compared with what was typed.
+    pub fn basic_type_answer() -> Notetype {
+        Notetype {
+            guid: BASIC_TYPE_ANSWER_GUID.to_string(),
+            ..Notetype::new(
+                "Basic (type in the answer)",
+                NotetypeKind::Normal,
+                &["Front", "Back"],
+                vec![CardTemplate::new(
+                    "Card 1",
+                    "{{Front}}\n\n{{type:Back}}",
+                    "{{FrontSide}}\n\n{{type:Back}}",
+                )],
+            )
+        }
+    }
+
+    /// "Cloze": one card per cloze number in "Text", with "Back Extra" shown
+    /// under the answer.
+    pub fn cloze() -> Notetype {
+        Notetype {
+            guid: CLOZE_GUID.to_string(),
+            ..Notetype::new(
+                "Cloze",
+                NotetypeKind::Cloze,
+                &["Text", "Back Extra"],
+                vec![CardTemplate::new(
+                    "Cloze",
+                    "{{cloze:Text}}",
+                    "{{cloze:Text}}{{#Back Extra}}\n\n{{Back Extra}}{{/Back Extra}}",
+                )],
+            )
+        }
+    }
+
+    /// The note types every collection starts with.
+    pub fn stock() -> Vec<Notetype> {
+        vec![
+            Notetype::basic(),
+            Notetype::basic_and_reversed(),
+            Notetype::basic_optional_reversed(),
+            Notetype::basic_type_answer(),
+            Notetype::cloze(),
+        ]
+    }
+
+    fn from_row(row: &Row) -> Result<Notetype> {
+        Ok(Notetype {
+            id: Some(row.get(0)?),
+            guid: row.get(1)?,
+            name: row.get(2)?,
+            kind: row.get(3)?,
+            fields: from_json(row, 4)?,
+            templates: from_json(row, 5)?,
+            mtime: row.get(6)?,
+            usn: row.get(7)?,
+        })
+    }
+
+    /// Retrieves all note types, sorted by name.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the note types, or an error if the operation fails.
+    pub fn get_all(conn: &Connection) -> Result<Vec<Notetype>> {
+        conn.prepare(&format!("{} ORDER BY name", NOTETYPE_QUERY))?
+            .query_map([], Self::from_row)?
+            .collect()
+    }
+
+    /// Loads a note type from the database by its ID.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the note type to load.
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the loaded note type, or an error if the operation fails.
+    pub fn load(id: u32, conn: &Connection) -> Result<Notetype> {
+        conn.query_row(
+            &format!("{} WHERE id = ?", NOTETYPE_QUERY),
+            [id],
+            Self::from_row,
+        )
+    }
+
+    /// Loads a note type by its guid, if the collection has it.
+    pub fn load_by_guid(guid: &str, conn: &Connection) -> Result<Option<Notetype>> {
+        conn.query_row(
+            &format!("{} WHERE guid = ?", NOTETYPE_QUERY),
+            [guid],
+            Self::from_row,
+        )
+        .optional()
+    }
+
+    /// Saves the note type to the database.
+    ///
+    /// If the note type already has an ID, it will be updated in the database.
+    /// Otherwise, a new record will be inserted. Either way the modification
+    /// time is bumped and the note type is marked as pending sync.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    pub fn save(&mut self, conn: &Connection) -> Result<()> {
+        self.mtime = mtime_now();
+        self.usn = PENDING_USN;
+
+        let fields = to_json(&self.fields)?;
+        let templates = to_json(&self.templates)?;
+
+        match self.id {
+            Some(id) => {
+                conn.execute(
+                    "UPDATE notetypes SET name = ?, kind = ?, fields = ?, templates = ?, mtime = ?, usn = ? WHERE id = ?",
+                    params![self.name, self.kind as u8, fields, templates, self.mtime, self.usn, id],
+                )?;
+            }
+            None => {
+                conn.execute(
+                    "INSERT INTO notetypes (guid, name, kind, fields, templates, mtime, usn) VALUES (?, ?, ?, ?, ?, ?, ?)",
+                    params![self.guid, self.name, self.kind as u8, fields, templates, self.mtime, self.usn],
+                )?;
+
+                self.id = Some(conn.last_insert_rowid() as u32);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Adds the built-in note types a collection is missing. They are stored
+    /// as already synced, since every collection has the same ones.
+    pub fn add_stock(conn: &Connection) -> Result<()> {
+        for notetype in Notetype::stock() {
+            conn.execute(
+                "INSERT OR IGNORE INTO notetypes (guid, name, kind, fields, templates, mtime, usn) VALUES (?, ?, ?, ?, ?, 0, 0)",
+                params![
+                    notetype.guid,
+                    notetype.name,
+                    notetype.kind as u8,
+                    to_json(&notetype.fields)?,
+                    to_json(&notetype.templates)?
+                ],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// The template a card is laid out with: cloze cards all share the first
+    /// one, other cards use the template at their `ord`.
+    pub fn template_for(&self, ord: u32) -> Option<&CardTemplate> {
+        match self.kind {
+            NotetypeKind::Normal => self.templates.get(ord as usize),
+            NotetypeKind::Cloze => self.templates.first(),
+        }
+    }
+
+    /// The position of a field, matched by name.
+    pub fn field_index(&self, name: &str) -> Option<usize> {
+        self.fields.iter().position(|field| field == name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::db::init_db;
+
+    use super::*;
+
+    #[test]
+    fn stock_notetypes_are_added_once() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+        init_db(&conn).unwrap();
+
+        let notetypes = Notetype::get_all(&conn).unwrap();
+        assert_eq!(notetypes.len(), Notetype::stock().len());
+
+        let basic = Notetype::load_by_guid(BASIC_GUID, &conn).unwrap().unwrap();
+        assert_eq!(basic.fields, vec!["Front", "Back"]);
+        assert_eq!(basic.templates.len(), 1);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+
+        let mut notetype = Notetype::new(
+            "Vocabulary",
+            NotetypeKind::Normal,
+            &["Word", "Meaning", "Example"],
+            vec![CardTemplate::new("Recall", "{{Word}}", "{{Meaning}}")],
+        );
+        notetype.save(&conn).unwrap();
+
+        let loaded = Notetype::load(notetype.id.unwrap(), &conn).unwrap();
+        assert_eq!(loaded.name, "Vocabulary");
+        assert_eq!(loaded.fields, notetype.fields);
+        assert_eq!(loaded.templates, notetype.templates);
+        assert_eq!(loaded.field_index("Example"), Some(2));
+    }
+}
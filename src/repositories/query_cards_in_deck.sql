@@ -8,7 +8,13 @@ SELECT
   interval,
   due,
   queue,
-  data
+  data,
+  guid,
+  mtime,
+  usn,
+  note_id,
+  ord,
+  buried_until
 FROM
   cards
 WHERE
@@ -9,10 +9,18 @@ SELECT
   interval,
   due,
   queue,
-  data
+  data,
+  guid,
+  mtime,
+  usn,
+  note_id,
+  ord,
+  buried_until
 FROM
   cards
 WHERE
   deck_id = ?
   AND queue = ?
   AND due <= ?
+  AND buried_until <= ?
+  AND suspended = 0
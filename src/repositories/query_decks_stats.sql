@@ -1,10 +1,19 @@
 SELECT
   deck_id,
-  sum(queue = :new_queue),
-  sum(queue = :learn_queue),
+  sum(
+    queue = :new_queue
+    AND buried_until <= :day_cutoff
+    AND suspended = 0
+  ),
+  sum(
+    queue = :learn_queue
+    AND suspended = 0
+  ),
   sum(
     queue = :review_queue
     AND due <= :day_cutoff
+    AND buried_until <= :day_cutoff
+    AND suspended = 0
   ),
   COUNT(1)
 FROM
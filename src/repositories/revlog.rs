@@ -0,0 +1,118 @@
+use rusqlite::{params, Connection, Result};
+
+use super::guid::PENDING_USN;
+
+/// One answer given while studying, kept so that review history can be
+/// synced and analysed later.
+#[derive(Debug, Clone)]
+pub struct RevlogEntry {
+    /// Milliseconds since the epoch at which the card was answered.
+    pub id: i64,
+    pub card_id: u32,
+    pub usn: i32,
+    /// 1 (again) to 4 (easy).
+    pub ease: u8,
+    pub interval: u32,
+    pub last_interval: u32,
+}
+
+impl RevlogEntry {
+    pub fn new(card_id: u32, ease: u8, interval: u32, last_interval: u32) -> Self {
+        RevlogEntry {
+            id: chrono::Utc::now().timestamp_millis(),
+            card_id,
+            usn: PENDING_USN,
+            ease,
+            interval,
+            last_interval,
+        }
+    }
+
+    /// Saves the entry to the database.
+    ///
+    /// Two answers in the same millisecond would share an ID, so the ID is
+    /// bumped until it is free.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    pub fn save(&mut self, conn: &Connection) -> Result<()> {
+        loop {
+            let inserted = conn.execute(
+                "INSERT OR IGNORE INTO revlog (id, card_id, usn, ease, interval, last_interval) VALUES (?, ?, ?, ?, ?, ?)",
+                params![
+                    self.id,
+                    self.card_id,
+                    self.usn,
+                    self.ease,
+                    self.interval,
+                    self.last_interval
+                ],
+            )?;
+
+            if inserted > 0 {
+                return Ok(());
+            }
+
+            self.id += 1;
+        }
+    }
+
+    /// Loads the review history of a card, oldest first.
+    ///
+    /// # Arguments
+    ///
+    /// * `card_id` - The ID of the card.
+    /// * `conn` - The database connection.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the entries, or an error if the operation fails.
+    pub fn for_card(card_id: u32, conn: &Connection) -> Result<Vec<RevlogEntry>> {
+        let mut stmt = conn.prepare(
+            "SELECT id, card_id, usn, ease, interval, last_interval FROM revlog WHERE card_id = ? ORDER BY id",
+        )?;
+
+        let entries = stmt.query_map([card_id], |row| {
+            Ok(RevlogEntry {
+                id: row.get(0)?,
+                card_id: row.get(1)?,
+                usn: row.get(2)?,
+                ease: row.get(3)?,
+                interval: row.get(4)?,
+                last_interval: row.get(5)?,
+            })
+        })?;
+
+        entries.collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::db::init_db;
+
+    use super::*;
+
+    #[test]
+    fn save_keeps_ids_unique() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+
+        let mut first = RevlogEntry::new(1, 3, 1, 0);
+        let mut second = RevlogEntry::new(1, 4, 3, 1);
+        second.id = first.id;
+
+        first.save(&conn).unwrap();
+        second.save(&conn).unwrap();
+
+        let entries = RevlogEntry::for_card(1, &conn).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].id, entries[0].id + 1);
+        assert_eq!(entries[1].ease, 4);
+    }
+}
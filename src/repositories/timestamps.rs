@@ -0,0 +1,29 @@
+use std::time::SystemTime;
+
+use chrono::DateTime;
+use rusqlite::{types::Type, Error, Result, Row};
+
+/// Reads an RFC 3339 timestamp column, reporting malformed values as a
+/// conversion error instead of panicking.
+pub(crate) fn time_from_sql(row: &Row, idx: usize) -> Result<SystemTime> {
+    let value: String = row.get(idx)?;
+
+    DateTime::parse_from_rfc3339(&value)
+        .map(SystemTime::from)
+        .map_err(|e| Error::FromSqlConversionFailure(idx, Type::Text, Box::new(e)))
+}
+
+/// Like [`time_from_sql`], for columns that may be `NULL`.
+pub(crate) fn optional_time_from_sql(row: &Row, idx: usize) -> Result<Option<SystemTime>> {
+    let value: Option<String> = row.get(idx)?;
+
+    match value {
+        Some(_) => time_from_sql(row, idx).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Seconds since the Unix epoch, used for the `mtime` column.
+pub(crate) fn mtime_now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
@@ -0,0 +1,265 @@
+//! A full-text index over the question and answer of every card, in the
+//! FTS5 table `cards_fts`. Triggers on `cards` keep it up to date, whatever
+//! writes the cards: the editor, sync or an import.
+//!
+//! Words are matched by prefix and without regard to case or diacritics, so
+//! `cafe` finds "Cafés".
+
+use std::collections::HashMap;
+
+use rusqlite::{Connection, Result};
+
+use super::{Node, SearchTerm};
+
+/// Marks the start of a match in highlighted text. Neither marker can be
+/// typed into a card.
+pub const MATCH_START: char = '\u{2}';
+/// Marks the end of a match in highlighted text.
+pub const MATCH_END: char = '\u{3}';
+
+/// Creates the index and the triggers that keep it up to date, if missing.
+/// A new index is empty; see `rebuild_index`.
+pub fn create_index(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS cards_fts USING fts5(
+            question,
+            answer,
+            content = 'cards',
+            content_rowid = 'id',
+            tokenize = 'unicode61 remove_diacritics 2'
+        );
+
+        CREATE TRIGGER IF NOT EXISTS cards_fts_insert AFTER INSERT ON cards BEGIN
+            INSERT INTO cards_fts (rowid, question, answer)
+            VALUES (new.id, new.question, new.answer);
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS cards_fts_delete AFTER DELETE ON cards BEGIN
+            INSERT INTO cards_fts (cards_fts, rowid, question, answer)
+            VALUES ('delete', old.id, old.question, old.answer);
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS cards_fts_update AFTER UPDATE OF question, answer ON cards
+        WHEN old.question IS NOT new.question OR old.answer IS NOT new.answer BEGIN
+            INSERT INTO cards_fts (cards_fts, rowid, question, answer)
+            VALUES ('delete', old.id, old.question, old.answer);
+            INSERT INTO cards_fts (rowid, question, answer)
+            VALUES (new.id, new.question, new.answer);
+        END;",
+    )
+}
+
+/// Indexes every card again, for collections whose cards were written
+/// before the index existed, or an index that went out of step.
+pub fn rebuild_index(conn: &Connection) -> Result<()> {
+    conn.execute("INSERT INTO cards_fts (cards_fts) VALUES ('rebuild')", [])?;
+    Ok(())
+}
+
+/// Turns searched text into an FTS5 query matching its words in order, the
+/// last one by prefix. Returns `None` when the index cannot answer it: text
+/// without any word, or with a wildcard or an escaped star inside.
+pub fn match_query(text: &str) -> Option<String> {
+    let text = text.strip_suffix('*').unwrap_or(text);
+    if text.contains(['*', '\\']) || !text.chars().any(char::is_alphanumeric) {
+        return None;
+    }
+
+    Some(format!("\"{}\"*", text.replace('"', "\"\"")))
+}
+
+/// The text searched for outside of any `-`, which is what highlighting
+/// should show.
+fn matched_texts<'a>(node: &'a Node, texts: &mut Vec<&'a str>) {
+    match node {
+        Node::And(nodes) | Node::Or(nodes) => {
+            for node in nodes {
+                matched_texts(node, texts);
+            }
+        }
+        Node::Not(_) => {}
+        Node::Term(SearchTerm::Text(text)) => texts.push(text),
+        Node::Term(_) => {}
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Highlight {
+    pub question: String,
+    pub answer: String,
+}
+
+/// The question and answer of each card whose words match the text of a
+/// search, with every match between `MATCH_START` and `MATCH_END`. Long
+/// sides are cut down to the words around the first match.
+///
+/// # Returns
+///
+/// The highlights by card id; empty if the search has no text to match.
+pub fn highlights(conn: &Connection, node: &Node) -> Result<HashMap<u32, Highlight>> {
+    let mut texts = Vec::new();
+    matched_texts(node, &mut texts);
+
+    let queries = texts
+        .into_iter()
+        .filter_map(match_query)
+        .collect::<Vec<_>>();
+    if queries.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT rowid,
+            snippet(cards_fts, 0, ?2, ?3, '…', 24),
+            snippet(cards_fts, 1, ?2, ?3, '…', 24)
+        FROM cards_fts WHERE cards_fts MATCH ?1",
+    )?;
+
+    let highlights = stmt
+        .query_map(
+            (
+                queries.join(" OR "),
+                MATCH_START.to_string(),
+                MATCH_END.to_string(),
+            ),
+            |row| {
+                Ok((
+                    row.get(0)?,
+                    Highlight {
+                        question: row.get(1)?,
+                        answer: row.get(2)?,
+                    },
+                ))
+            },
+        )?
+        .collect::<Result<HashMap<_, _>>>()?;
+
+    Ok(highlights)
+}
+
+/// Splits highlighted text into pieces, each saying whether it matched.
+pub fn split_highlight(text: &str) -> Vec<(&str, bool)> {
+    let mut pieces = Vec::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find(MATCH_START) {
+        if start > 0 {
+            pieces.push((&rest[..start], false));
+        }
+        rest = &rest[start + MATCH_START.len_utf8()..];
+
+        let end = rest.find(MATCH_END).unwrap_or(rest.len());
+        pieces.push((&rest[..end], true));
+        rest = rest[end..].strip_prefix(MATCH_END).unwrap_or("");
+    }
+
+    if !rest.is_empty() {
+        pieces.push((rest, false));
+    }
+
+    pieces
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{db::init_db, search::parse, Deck, FlashCard};
+
+    use super::*;
+
+    fn indexed(conn: &Connection, text: &str) -> Vec<u32> {
+        let mut stmt = conn
+            .prepare("SELECT rowid FROM cards_fts WHERE cards_fts MATCH ? ORDER BY rowid")
+            .unwrap();
+        stmt.query_map([match_query(text).unwrap()], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap()
+    }
+
+    fn setup() -> (Connection, u32) {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+
+        let mut deck = Deck::new("French");
+        deck.save(&conn).unwrap();
+
+        (conn, deck.id.unwrap())
+    }
+
+    #[test]
+    fn index_follows_card_changes() {
+        let (conn, deck_id) = setup();
+
+        let mut card = FlashCard::new(deck_id, "Où est le café ?", "Where is the café?", None);
+        card.save(&conn).unwrap();
+        let id = card.id.unwrap();
+
+        assert_eq!(indexed(&conn, "cafe"), vec![id]);
+        assert_eq!(indexed(&conn, "ou es"), vec![id]);
+        assert_eq!(indexed(&conn, "CAF"), vec![id]);
+        assert!(indexed(&conn, "where café").is_empty());
+
+        card.set_content("la gare", "the station");
+        card.save(&conn).unwrap();
+        assert!(indexed(&conn, "cafe").is_empty());
+        assert_eq!(indexed(&conn, "station"), vec![id]);
+
+        card.delete(&conn).unwrap();
+        assert!(indexed(&conn, "station").is_empty());
+    }
+
+    #[test]
+    fn rebuild_indexes_existing_cards() {
+        let (conn, deck_id) = setup();
+        FlashCard::new(deck_id, "le chien", "the dog", None)
+            .save(&conn)
+            .unwrap();
+
+        conn.execute_batch("DROP TABLE cards_fts; DROP TRIGGER cards_fts_insert;")
+            .unwrap();
+        create_index(&conn).unwrap();
+        assert!(indexed(&conn, "dog").is_empty());
+
+        rebuild_index(&conn).unwrap();
+        assert_eq!(indexed(&conn, "dog").len(), 1);
+    }
+
+    #[test]
+    fn only_plain_words_use_the_index() {
+        assert_eq!(match_query("dog"), Some("\"dog\"*".to_string()));
+        assert_eq!(match_query("dog*"), Some("\"dog\"*".to_string()));
+        assert_eq!(
+            match_query("say \"hi\""),
+            Some("\"say \"\"hi\"\"\"*".to_string())
+        );
+        assert_eq!(match_query("d*g"), None);
+        assert_eq!(match_query("a\\*"), None);
+        assert_eq!(match_query("%"), None);
+    }
+
+    #[test]
+    fn highlights_mark_matched_words() {
+        let (conn, deck_id) = setup();
+        let mut card = FlashCard::new(deck_id, "le chien noir", "the black dog", None);
+        card.save(&conn).unwrap();
+        FlashCard::new(deck_id, "le chat", "the cat", None)
+            .save(&conn)
+            .unwrap();
+
+        let found = highlights(&conn, &parse("chien OR blac -chat").unwrap()).unwrap();
+
+        assert_eq!(found.len(), 1);
+        let highlight = &found[&card.id.unwrap()];
+        assert_eq!(
+            split_highlight(&highlight.question),
+            vec![("le ", false), ("chien", true), (" noir", false)]
+        );
+        assert_eq!(
+            split_highlight(&highlight.answer),
+            vec![("the ", false), ("black", true), (" dog", false)]
+        );
+
+        let nothing = highlights(&conn, &parse("deck:French -chien").unwrap()).unwrap();
+        assert!(nothing.is_empty());
+    }
+}
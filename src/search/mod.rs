@@ -0,0 +1,161 @@
+//! The search language used to find cards in the browser and from the command
+//! line. A search is made of terms:
+//!
+//! - `dog` or `"a dog"`: the question or answer has the words, the last
+//!   one as a prefix, ignoring case and diacritics; see `fts`. Text with a
+//!   `*` inside is found anywhere instead, `*` matching any run of
+//!   characters and `\*` a literal star.
+//! - `front:text` and `back:text`: the whole question or answer is the text,
+//!   so `front:*dog*` finds questions containing "dog".
+//! - `deck:Spanish`: cards in the deck or the decks below it.
+//! - `tag:verbs`: cards whose note has the tag or a tag below it;
+//!   `tag:none` finds cards without tags.
+//! - `is:new`, `is:learn`, `is:review`, `is:due`, `is:suspended` and
+//!   `is:buried`.
+//! - `prop:ivl>30`, `prop:ease<2`, `prop:due<=1`, `prop:reps>=10` and
+//!   `prop:lapses>3`, with `<`, `<=`, `=`, `!=`, `>=` or `>`.
+//! - `rated:7` and `rated:7:1`: answered in the last 7 days, optionally
+//!   with the given answer from 1 (again) to 4 (easy).
+//! - `added:3`: created in the last 3 days.
+//! - `flag:1`: cards with the flag from 1 to 7; `flag:0` finds cards
+//!   without a flag.
+//!
+//! Terms next to each other must all match. `OR` between two terms lets
+//! either match, `-` in front of a term excludes the cards it matches, and
+//! parentheses group terms, as in `deck:Spanish (tag:verbs OR tag:nouns)
+//! -is:new`.
+
+pub mod fts;
+mod parser;
+mod sqlwriter;
+
+use std::fmt;
+
+use rusqlite::{params_from_iter, Connection};
+
+use crate::{errors::Result, models::timing::SchedTimingToday};
+
+pub use parser::{exact_term, parse};
+pub use sqlwriter::{write_sql, SqlFilter};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    /// Matches when every node matches; an empty list matches every card.
+    And(Vec<Node>),
+    Or(Vec<Node>),
+    Not(Box<Node>),
+    Term(SearchTerm),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SearchTerm {
+    /// Text in the question or the answer.
+    Text(String),
+    /// The whole question or answer, with `*` wildcards.
+    Side(Side, String),
+    Deck(String),
+    Tag(String),
+    State(State),
+    Prop(Prop, Comparison, f64),
+    /// Answered in the last `days` days, optionally with the given answer.
+    Rated {
+        days: u32,
+        ease: Option<u8>,
+    },
+    /// Created in the last given number of days.
+    Added(u32),
+    Flag(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Question,
+    Answer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    New,
+    Learn,
+    Review,
+    /// Learning or review cards due today or earlier.
+    Due,
+    Suspended,
+    Buried,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Prop {
+    /// The interval in days.
+    Interval,
+    /// The ease factor, 2.5 for a card that was never answered.
+    Ease,
+    /// In how many days a review card is due; negative when overdue.
+    Due,
+    /// How many times the card was answered.
+    Reps,
+    /// How many times the card was forgotten after it was learnt.
+    Lapses,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    NotEqual,
+    GreaterOrEqual,
+    Greater,
+}
+
+/// A search that cannot be understood, and the character it went wrong at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchError {
+    pub message: String,
+    /// Counted in characters from the start of the search, from 0.
+    pub position: usize,
+}
+
+impl SearchError {
+    fn new(message: impl Into<String>, position: usize) -> Self {
+        Self {
+            message: message.into(),
+            position,
+        }
+    }
+}
+
+impl fmt::Display for SearchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (at character {})", self.message, self.position + 1)
+    }
+}
+
+impl std::error::Error for SearchError {}
+
+/// Parses a search and turns it into a condition on the `cards c` and
+/// `decks d` tables.
+pub fn compile(query: &str, timing: &SchedTimingToday) -> Result<SqlFilter> {
+    let node = parse(query)?;
+    Ok(write_sql(&node, timing))
+}
+
+/// Finds the cards matching a search.
+///
+/// # Returns
+///
+/// The ids of the matching cards, oldest first.
+pub fn search_cards(conn: &Connection, query: &str, timing: &SchedTimingToday) -> Result<Vec<u32>> {
+    let filter = compile(query, timing)?;
+    let sql = format!(
+        "SELECT c.id FROM cards c LEFT JOIN decks d ON d.id = c.deck_id WHERE {} ORDER BY c.id",
+        filter.clause
+    );
+
+    let mut stmt = conn.prepare(&sql)?;
+    let ids = stmt
+        .query_map(params_from_iter(filter.params), |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(ids)
+}
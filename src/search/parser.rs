@@ -0,0 +1,581 @@
+//! Turns a search string into a tree of `Node`s.
+
+use super::{Comparison, Node, Prop, SearchError, SearchTerm, Side, State};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Not,
+    Or,
+    And,
+    /// A search term, split at its first colon outside quotes. The value
+    /// keeps `\*` and `\\` escaped so that a literal star can be told apart
+    /// from the `*` wildcard.
+    Term {
+        key: Option<String>,
+        value: String,
+    },
+}
+
+/// A token and the character at which it starts, for error messages.
+type Positioned = (usize, Token);
+
+fn tokenize(query: &str) -> Result<Vec<Positioned>, SearchError> {
+    let chars = query.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut at = 0;
+
+    while at < chars.len() {
+        let c = chars[at];
+
+        if c.is_whitespace() {
+            at += 1;
+        } else if c == '(' {
+            tokens.push((at, Token::Open));
+            at += 1;
+        } else if c == ')' {
+            tokens.push((at, Token::Close));
+            at += 1;
+        } else if c == '-' {
+            // The end of the query counts as a space.
+            let next = chars.get(at + 1).copied().unwrap_or(' ');
+            if next.is_whitespace() || next == ')' {
+                return Err(SearchError::new(
+                    "'-' must be followed by the search to exclude",
+                    at,
+                ));
+            }
+            tokens.push((at, Token::Not));
+            at += 1;
+        } else {
+            let start = at;
+            let (token, end) = read_term(&chars, at)?;
+            tokens.push((start, token));
+            at = end;
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Reads a term up to the next space or parenthesis outside quotes.
+///
+/// # Returns
+///
+/// The token and the position just after it.
+fn read_term(chars: &[char], start: usize) -> Result<(Token, usize), SearchError> {
+    let mut key = None;
+    let mut text = String::new();
+    let mut quoted_at = None;
+    let mut was_quoted = false;
+    let mut at = start;
+
+    while let Some(&c) = chars.get(at) {
+        if quoted_at.is_none() && (c.is_whitespace() || c == '(' || c == ')') {
+            break;
+        }
+
+        match c {
+            '"' => {
+                quoted_at = match quoted_at {
+                    Some(_) => None,
+                    None => Some(at),
+                };
+                was_quoted = true;
+            }
+            '\\' => {
+                at += 1;
+                match chars.get(at) {
+                    Some(&escaped @ ('*' | '\\')) => {
+                        text.push('\\');
+                        text.push(escaped);
+                    }
+                    Some(&escaped) => text.push(escaped),
+                    None => {
+                        return Err(SearchError::new(
+                            "'\\' at the end must be followed by the character to search for",
+                            at - 1,
+                        ))
+                    }
+                }
+            }
+            ':' if quoted_at.is_none() && key.is_none() => {
+                key = Some(std::mem::take(&mut text));
+            }
+            _ => text.push(c),
+        }
+
+        at += 1;
+    }
+
+    if let Some(quote) = quoted_at {
+        return Err(SearchError::new("this quote is never closed", quote));
+    }
+
+    let token = match (&key, was_quoted) {
+        (None, false) if text.eq_ignore_ascii_case("or") => Token::Or,
+        (None, false) if text.eq_ignore_ascii_case("and") => Token::And,
+        _ => Token::Term { key, value: text },
+    };
+
+    Ok((token, at))
+}
+
+struct Parser {
+    tokens: Vec<Positioned>,
+    next: usize,
+    /// Where the query ends, reported for errors about a missing token.
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(_, token)| token)
+    }
+
+    /// The position of the next token, or of the end of the query.
+    fn position(&self) -> usize {
+        self.tokens
+            .get(self.next)
+            .map_or(self.end, |(position, _)| *position)
+    }
+
+    fn advance(&mut self) -> Option<Positioned> {
+        let token = self.tokens.get(self.next).cloned();
+        self.next += 1;
+        token
+    }
+
+    fn parse_or(&mut self) -> Result<Node, SearchError> {
+        let mut nodes = vec![self.parse_and()?];
+
+        while let Some(Token::Or) = self.peek() {
+            self.advance();
+            nodes.push(self.parse_and()?);
+        }
+
+        Ok(if nodes.len() == 1 {
+            nodes.remove(0)
+        } else {
+            Node::Or(nodes)
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<Node, SearchError> {
+        let mut nodes = Vec::new();
+
+        loop {
+            match self.peek() {
+                None | Some(Token::Close) | Some(Token::Or) => break,
+                Some(Token::And) => {
+                    if nodes.is_empty() {
+                        return Err(SearchError::new(
+                            "'AND' must come between two searches",
+                            self.position(),
+                        ));
+                    }
+                    self.advance();
+                    if matches!(self.peek(), None | Some(Token::Close) | Some(Token::Or)) {
+                        return Err(SearchError::new(
+                            "'AND' must come between two searches",
+                            self.position(),
+                        ));
+                    }
+                }
+                Some(_) => nodes.push(self.parse_unary()?),
+            }
+        }
+
+        match nodes.len() {
+            0 => Err(SearchError::new(
+                match self.peek() {
+                    Some(Token::Close) => "there is nothing to search for before this ')'",
+                    _ => "'OR' must come between two searches",
+                },
+                self.position(),
+            )),
+            1 => Ok(nodes.remove(0)),
+            _ => Ok(Node::And(nodes)),
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Node, SearchError> {
+        let Some((position, token)) = self.advance() else {
+            return Err(SearchError::new("the search ends too early", self.end));
+        };
+
+        match token {
+            Token::Not => Ok(Node::Not(Box::new(self.parse_unary()?))),
+            Token::Open => {
+                match self.peek() {
+                    Some(Token::Close) => {
+                        return Err(SearchError::new(
+                            "there is nothing to search for inside these parentheses",
+                            position,
+                        ))
+                    }
+                    None => {
+                        return Err(SearchError::new(
+                            "this '(' is never closed with ')'",
+                            position,
+                        ))
+                    }
+                    _ => {}
+                }
+
+                let inner = self.parse_or()?;
+                match self.advance() {
+                    Some((_, Token::Close)) => Ok(inner),
+                    _ => Err(SearchError::new(
+                        "this '(' is never closed with ')'",
+                        position,
+                    )),
+                }
+            }
+            Token::Term { key, value } => parse_term(key, value, position).map(Node::Term),
+            // `parse_and` stops at these, so they only get here after a `-`.
+            Token::Close | Token::Or | Token::And => Err(SearchError::new(
+                "'-' must be followed by the search to exclude",
+                position,
+            )),
+        }
+    }
+}
+
+/// Parses a search string.
+///
+/// Terms next to each other must all match, `OR` between them lets either
+/// match, `-` excludes a term and parentheses group terms. An empty query
+/// matches every card.
+pub fn parse(query: &str) -> Result<Node, SearchError> {
+    let tokens = tokenize(query)?;
+    if tokens.is_empty() {
+        return Ok(Node::And(Vec::new()));
+    }
+
+    let mut parser = Parser {
+        tokens,
+        next: 0,
+        end: query.chars().count(),
+    };
+    let node = parser.parse_or()?;
+
+    match parser.peek() {
+        None => Ok(node),
+        Some(_) => Err(SearchError::new(
+            "this ')' does not close any '('",
+            parser.position(),
+        )),
+    }
+}
+
+/// Writes a term that finds exactly `value`, as in `deck:"My deck"`, quoting
+/// it and escaping the characters the parser would read otherwise.
+pub fn exact_term(key: &str, value: &str) -> String {
+    let mut term = format!("{}:\"", key);
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | '*') {
+            term.push('\\');
+        }
+        term.push(c);
+    }
+    term.push('"');
+    term
+}
+
+fn parse_term(
+    key: Option<String>,
+    value: String,
+    position: usize,
+) -> Result<SearchTerm, SearchError> {
+    let Some(key) = key else {
+        if value.is_empty() {
+            return Err(SearchError::new(
+                "there is nothing between these quotes",
+                position,
+            ));
+        }
+        return Ok(SearchTerm::Text(value));
+    };
+
+    // The value starts after the key and its colon.
+    let value_at = position + key.chars().count() + 1;
+    let lowercase_key = key.to_lowercase();
+
+    if value.is_empty() {
+        return Err(SearchError::new(
+            format!("'{}:' must be followed by what to search for", key),
+            position,
+        ));
+    }
+
+    match lowercase_key.as_str() {
+        "deck" => Ok(SearchTerm::Deck(value)),
+        "tag" => Ok(SearchTerm::Tag(value)),
+        "front" | "question" => Ok(SearchTerm::Side(Side::Question, value)),
+        "back" | "answer" => Ok(SearchTerm::Side(Side::Answer, value)),
+        "is" => parse_state(&value, value_at).map(SearchTerm::State),
+        "prop" => parse_prop(&value, value_at),
+        "rated" => parse_rated(&value, value_at),
+        "added" => parse_days(&value, value_at).map(SearchTerm::Added),
+        "flag" => match value.parse::<u8>() {
+            Ok(flag @ 0..=7) => Ok(SearchTerm::Flag(flag)),
+            _ => Err(SearchError::new(
+                format!("expected a flag from 0 (none) to 7, not '{}'", value),
+                value_at,
+            )),
+        },
+        _ => Err(SearchError::new(
+            format!(
+                "unknown search '{}:'; use deck, tag, is, prop, rated, added, flag, front or back, \
+                 or put the text in quotes to search for a colon",
+                key
+            ),
+            position,
+        )),
+    }
+}
+
+fn parse_state(value: &str, position: usize) -> Result<State, SearchError> {
+    match value.to_lowercase().as_str() {
+        "new" => Ok(State::New),
+        "learn" => Ok(State::Learn),
+        "review" => Ok(State::Review),
+        "due" => Ok(State::Due),
+        "suspended" => Ok(State::Suspended),
+        "buried" => Ok(State::Buried),
+        _ => Err(SearchError::new(
+            format!(
+                "unknown state 'is:{}'; use new, learn, review, due, suspended or buried",
+                value
+            ),
+            position,
+        )),
+    }
+}
+
+fn parse_prop(value: &str, position: usize) -> Result<SearchTerm, SearchError> {
+    let name_end = value
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(value.len());
+    let (name, rest) = value.split_at(name_end);
+
+    let prop = match name.to_lowercase().as_str() {
+        "ivl" => Prop::Interval,
+        "ease" => Prop::Ease,
+        "due" => Prop::Due,
+        "reps" => Prop::Reps,
+        "lapses" => Prop::Lapses,
+        _ => {
+            return Err(SearchError::new(
+                format!(
+                    "unknown property '{}'; use ivl, ease, due, reps or lapses",
+                    name
+                ),
+                position,
+            ))
+        }
+    };
+
+    let operators = [
+        ("<=", Comparison::LessOrEqual),
+        (">=", Comparison::GreaterOrEqual),
+        ("!=", Comparison::NotEqual),
+        ("<", Comparison::Less),
+        (">", Comparison::Greater),
+        ("=", Comparison::Equal),
+    ];
+    let operator_at = position + name.chars().count();
+    let Some((operator, comparison)) = operators
+        .into_iter()
+        .find(|(operator, _)| rest.starts_with(operator))
+    else {
+        return Err(SearchError::new(
+            format!("'prop:{}' must be followed by <, <=, =, !=, >= or >", name),
+            operator_at,
+        ));
+    };
+
+    let number = &rest[operator.len()..];
+    let number = number.parse::<f64>().map_err(|_| {
+        SearchError::new(
+            format!("expected a number after 'prop:{}{}'", name, operator),
+            operator_at + operator.len(),
+        )
+    })?;
+
+    Ok(SearchTerm::Prop(prop, comparison, number))
+}
+
+fn parse_days(value: &str, position: usize) -> Result<u32, SearchError> {
+    match value.parse::<u32>() {
+        Ok(days) if days > 0 => Ok(days),
+        _ => Err(SearchError::new(
+            format!("expected a number of days of at least 1, not '{}'", value),
+            position,
+        )),
+    }
+}
+
+fn parse_rated(value: &str, position: usize) -> Result<SearchTerm, SearchError> {
+    let (days, ease) = match value.split_once(':') {
+        Some((days, ease)) => (days, Some(ease)),
+        None => (value, None),
+    };
+
+    let days = parse_days(days, position)?;
+    let ease = match ease {
+        None => None,
+        Some(ease) => match ease.parse::<u8>() {
+            Ok(ease @ 1..=4) => Some(ease),
+            _ => {
+                return Err(SearchError::new(
+                    format!(
+                        "expected an answer from 1 (again) to 4 (easy), not '{}'",
+                        ease
+                    ),
+                    position + value.chars().count() - ease.chars().count(),
+                ))
+            }
+        },
+    };
+
+    Ok(SearchTerm::Rated { days, ease })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn text(value: &str) -> Node {
+        Node::Term(SearchTerm::Text(value.to_string()))
+    }
+
+    fn error_at(query: &str) -> usize {
+        parse(query).unwrap_err().position
+    }
+
+    #[test]
+    fn terms_are_joined_with_and() {
+        assert_eq!(parse("").unwrap(), Node::And(Vec::new()));
+        assert_eq!(parse("dog").unwrap(), text("dog"));
+        assert_eq!(
+            parse("deck:Spanish tag:verbs is:due").unwrap(),
+            Node::And(vec![
+                Node::Term(SearchTerm::Deck("Spanish".to_string())),
+                Node::Term(SearchTerm::Tag("verbs".to_string())),
+                Node::Term(SearchTerm::State(State::Due)),
+            ])
+        );
+        assert_eq!(
+            parse("dog and cat").unwrap(),
+            Node::And(vec![text("dog"), text("cat")])
+        );
+    }
+
+    #[test]
+    fn exact_terms_read_back_as_written() {
+        assert_eq!(exact_term("deck", "My deck"), "deck:\"My deck\"");
+        assert_eq!(
+            parse(&exact_term("deck", "Say \"hi\" *now*")).unwrap(),
+            Node::Term(SearchTerm::Deck("Say \"hi\" \\*now\\*".to_string()))
+        );
+    }
+
+    #[test]
+    fn or_binds_looser_than_and() {
+        assert_eq!(
+            parse("a b OR c").unwrap(),
+            Node::Or(vec![Node::And(vec![text("a"), text("b")]), text("c")])
+        );
+        assert_eq!(
+            parse("a (b or c)").unwrap(),
+            Node::And(vec![text("a"), Node::Or(vec![text("b"), text("c")])])
+        );
+    }
+
+    #[test]
+    fn minus_negates_terms_and_groups() {
+        assert_eq!(
+            parse("-is:new -(a OR b)").unwrap(),
+            Node::And(vec![
+                Node::Not(Box::new(Node::Term(SearchTerm::State(State::New)))),
+                Node::Not(Box::new(Node::Or(vec![text("a"), text("b")]))),
+            ])
+        );
+        assert_eq!(parse("well-known").unwrap(), text("well-known"));
+    }
+
+    #[test]
+    fn quotes_group_words_and_hide_operators() {
+        assert_eq!(parse("\"exact phrase\"").unwrap(), text("exact phrase"));
+        assert_eq!(parse("\"or\"").unwrap(), text("or"));
+        assert_eq!(parse("\"12:30\"").unwrap(), text("12:30"));
+        assert_eq!(
+            parse("deck:\"My Deck\"").unwrap(),
+            Node::Term(SearchTerm::Deck("My Deck".to_string()))
+        );
+        assert_eq!(
+            parse("front:*word*").unwrap(),
+            Node::Term(SearchTerm::Side(Side::Question, "*word*".to_string()))
+        );
+        assert_eq!(parse("a\\*b").unwrap(), text("a\\*b"));
+    }
+
+    #[test]
+    fn properties_ratings_and_dates() {
+        assert_eq!(
+            parse("prop:ivl>30").unwrap(),
+            Node::Term(SearchTerm::Prop(Prop::Interval, Comparison::Greater, 30.0))
+        );
+        assert_eq!(
+            parse("prop:ease<=2.5").unwrap(),
+            Node::Term(SearchTerm::Prop(Prop::Ease, Comparison::LessOrEqual, 2.5))
+        );
+        assert_eq!(
+            parse("rated:7:1").unwrap(),
+            Node::Term(SearchTerm::Rated {
+                days: 7,
+                ease: Some(1)
+            })
+        );
+        assert_eq!(
+            parse("rated:2").unwrap(),
+            Node::Term(SearchTerm::Rated {
+                days: 2,
+                ease: None
+            })
+        );
+        assert_eq!(parse("added:3").unwrap(), Node::Term(SearchTerm::Added(3)));
+        assert_eq!(parse("flag:2").unwrap(), Node::Term(SearchTerm::Flag(2)));
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        assert_eq!(error_at("(dog"), 0);
+        assert_eq!(error_at("dog)"), 3);
+        assert_eq!(error_at("a OR"), 4);
+        assert_eq!(error_at("OR a"), 0);
+        assert_eq!(error_at("a ()"), 2);
+        assert_eq!(error_at("a - b"), 2);
+        assert_eq!(error_at("-or a"), 1);
+        assert_eq!(error_at("a ("), 2);
+        assert_eq!(error_at("\"open"), 0);
+        assert_eq!(error_at("is:sleeping"), 3);
+        assert_eq!(error_at("prop:ivl>x"), 9);
+        assert_eq!(error_at("prop:size>1"), 5);
+        assert_eq!(error_at("rated:7:5"), 8);
+        assert_eq!(error_at("added:0"), 6);
+        assert_eq!(error_at("flag:8"), 5);
+        assert_eq!(error_at("a colour:red"), 2);
+        assert_eq!(error_at("deck:"), 0);
+
+        let error = parse("is:sleeping").unwrap_err();
+        assert!(error.message.contains("suspended"));
+        assert_eq!(
+            error.to_string(),
+            format!("{} (at character 4)", error.message)
+        );
+    }
+}
@@ -0,0 +1,460 @@
+//! Turns a parsed search into an SQL condition with its parameters.
+
+use chrono::{DateTime, Utc};
+use rusqlite::types::Value;
+
+use crate::models::{tag::SEPARATOR, timing::SchedTimingToday};
+
+use super::{fts, Comparison, Node, Prop, SearchTerm, Side, State};
+
+/// A condition on the `cards c` table left joined with `decks d`, with a `?`
+/// for each parameter, in order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SqlFilter {
+    pub clause: String,
+    pub params: Vec<Value>,
+}
+
+struct SqlWriter<'a> {
+    sql: String,
+    params: Vec<Value>,
+    timing: &'a SchedTimingToday,
+}
+
+/// Writes the condition a search node stands for. Dates in the search are
+/// counted back from `timing.now`.
+pub fn write_sql(node: &Node, timing: &SchedTimingToday) -> SqlFilter {
+    let mut writer = SqlWriter {
+        sql: String::new(),
+        params: Vec::new(),
+        timing,
+    };
+    writer.write_node(node);
+
+    SqlFilter {
+        clause: writer.sql,
+        params: writer.params,
+    }
+}
+
+/// Turns searched text into a `LIKE` pattern: `*` matches anything, while
+/// `\*`, `%` and `_` match themselves.
+fn like_pattern(text: &str) -> String {
+    let mut pattern = String::new();
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => pattern.push('%'),
+            '\\' => match chars.next() {
+                Some('\\') => pattern.push_str("\\\\"),
+                Some(escaped) => pattern.push(escaped),
+                None => pattern.push_str("\\\\"),
+            },
+            '%' | '_' => {
+                pattern.push('\\');
+                pattern.push(c);
+            }
+            _ => pattern.push(c),
+        }
+    }
+
+    pattern
+}
+
+impl SqlWriter<'_> {
+    fn push_param(&mut self, value: impl Into<Value>) {
+        self.params.push(value.into());
+    }
+
+    fn write_node(&mut self, node: &Node) {
+        match node {
+            Node::And(nodes) if nodes.is_empty() => self.sql.push('1'),
+            Node::And(nodes) => self.write_joined(nodes, " AND "),
+            Node::Or(nodes) => self.write_joined(nodes, " OR "),
+            Node::Not(node) => {
+                self.sql.push_str("NOT ");
+                self.write_node(node);
+            }
+            Node::Term(term) => {
+                self.sql.push('(');
+                self.write_term(term);
+                self.sql.push(')');
+            }
+        }
+    }
+
+    fn write_joined(&mut self, nodes: &[Node], operator: &str) {
+        self.sql.push('(');
+        for (ix, node) in nodes.iter().enumerate() {
+            if ix > 0 {
+                self.sql.push_str(operator);
+            }
+            self.write_node(node);
+        }
+        self.sql.push(')');
+    }
+
+    fn write_term(&mut self, term: &SearchTerm) {
+        match term {
+            SearchTerm::Text(text) => match fts::match_query(text) {
+                Some(query) => {
+                    self.sql
+                        .push_str("c.id IN (SELECT rowid FROM cards_fts WHERE cards_fts MATCH ?)");
+                    self.push_param(query);
+                }
+                None => self.write_text_like(text),
+            },
+            SearchTerm::Side(side, text) => {
+                self.sql.push_str(match side {
+                    Side::Question => "c.question LIKE ? ESCAPE '\\'",
+                    Side::Answer => "c.answer LIKE ? ESCAPE '\\'",
+                });
+                self.push_param(like_pattern(text));
+            }
+            SearchTerm::Deck(name) => {
+                self.sql
+                    .push_str("d.name LIKE ? ESCAPE '\\' OR d.name LIKE ? ESCAPE '\\'");
+                let pattern = like_pattern(name);
+                self.push_param(format!("{}{}%", pattern, SEPARATOR));
+                self.push_param(pattern);
+            }
+            SearchTerm::Tag(tag) if tag.eq_ignore_ascii_case("none") => {
+                self.sql.push_str(
+                    "NOT EXISTS (SELECT 1 FROM notes n WHERE n.id = c.note_id AND TRIM(n.tags) != '')",
+                );
+            }
+            SearchTerm::Tag(tag) => {
+                // Tags are stored between spaces, so a tag or the tags below
+                // it are found without matching longer tags that start alike.
+                self.sql.push_str(
+                    "EXISTS (SELECT 1 FROM notes n WHERE n.id = c.note_id \
+                     AND (n.tags LIKE ? ESCAPE '\\' OR n.tags LIKE ? ESCAPE '\\'))",
+                );
+                let pattern = like_pattern(tag);
+                self.push_param(format!("% {} %", pattern));
+                self.push_param(format!("% {}{}%", pattern, SEPARATOR));
+            }
+            SearchTerm::State(state) => self.write_state(*state),
+            SearchTerm::Prop(prop, comparison, number) => {
+                self.write_prop(*prop, *comparison, *number)
+            }
+            SearchTerm::Rated { days, ease } => {
+                self.sql.push_str(
+                    "EXISTS (SELECT 1 FROM revlog r WHERE r.card_id = c.id AND r.id >= ?",
+                );
+                self.push_param(self.days_ago(*days) * 1000);
+                if let Some(ease) = ease {
+                    self.sql.push_str(" AND r.ease = ?");
+                    self.push_param(*ease as i64);
+                }
+                self.sql.push(')');
+            }
+            SearchTerm::Flag(flag) => {
+                self.sql.push_str("c.flags = ?");
+                self.push_param(*flag as i64);
+            }
+            SearchTerm::Added(days) => {
+                self.sql
+                    .push_str("julianday(c.creation_time) >= julianday(?)");
+                let since = DateTime::<Utc>::from_timestamp(self.days_ago(*days), 0)
+                    .unwrap_or_default()
+                    .to_rfc3339();
+                self.push_param(since);
+            }
+        }
+    }
+
+    /// Finds text anywhere in the question or answer, for the searches the
+    /// full-text index cannot answer.
+    fn write_text_like(&mut self, text: &str) {
+        self.sql
+            .push_str("c.question LIKE ? ESCAPE '\\' OR c.answer LIKE ? ESCAPE '\\'");
+        let pattern = format!("%{}%", like_pattern(text));
+        self.push_param(pattern.clone());
+        self.push_param(pattern);
+    }
+
+    /// The timestamp, in seconds, of this moment the given number of days
+    /// ago.
+    fn days_ago(&self, days: u32) -> i64 {
+        self.timing.now - days as i64 * 86_400
+    }
+
+    fn write_state(&mut self, state: State) {
+        let today = self.timing.days_elapsed as i64;
+
+        match state {
+            State::New => self.sql.push_str("c.queue = 0"),
+            State::Learn => self.sql.push_str("c.queue = 1"),
+            State::Review => self.sql.push_str("c.queue = 2"),
+            State::Due => {
+                self.sql.push_str("c.queue IN (1, 2) AND c.due <= ?");
+                self.push_param(today);
+            }
+            State::Suspended => self.sql.push_str("c.suspended = 1"),
+            State::Buried => {
+                self.sql.push_str("c.buried_until > ?");
+                self.push_param(today);
+            }
+        }
+    }
+
+    fn write_prop(&mut self, prop: Prop, comparison: Comparison, number: f64) {
+        let operator = match comparison {
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Equal => "=",
+            Comparison::NotEqual => "!=",
+            Comparison::GreaterOrEqual => ">=",
+            Comparison::Greater => ">",
+        };
+
+        match prop {
+            Prop::Interval => self.sql.push_str("c.interval"),
+            Prop::Ease => self.sql.push_str("c.ef"),
+            Prop::Due => {
+                self.sql.push_str("c.queue = 2 AND c.due - ?");
+                self.push_param(self.timing.days_elapsed as i64);
+            }
+            Prop::Reps => self
+                .sql
+                .push_str("(SELECT COUNT(*) FROM revlog r WHERE r.card_id = c.id)"),
+            // A lapse is an "again" on a card that had an interval.
+            Prop::Lapses => self.sql.push_str(
+                "(SELECT COUNT(*) FROM revlog r WHERE r.card_id = c.id AND r.ease = 1 AND r.last_interval > 0)",
+            ),
+        }
+
+        self.sql.push_str(&format!(" {} ?", operator));
+        self.push_param(number);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rusqlite::Connection;
+
+    use crate::{
+        db::init_db,
+        models::note::add_note,
+        repositories::{
+            flash_card::CardQueue,
+            note::Note,
+            notetype::{Notetype, BASIC_GUID},
+            revlog::RevlogEntry,
+        },
+        search::{parse, search_cards},
+        Deck, FlashCard,
+    };
+
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn timing() -> SchedTimingToday {
+        SchedTimingToday {
+            now: NOW,
+            days_elapsed: 10,
+            next_day_at: 11 * 86_400,
+        }
+    }
+
+    fn deck(conn: &Connection, name: &str) -> u32 {
+        let mut deck = Deck::new(name);
+        deck.save(conn).unwrap();
+        deck.id.unwrap()
+    }
+
+    fn noted_card(conn: &Connection, deck_id: u32, front: &str, back: &str, tags: &[&str]) {
+        let basic = Notetype::load_by_guid(BASIC_GUID, conn).unwrap().unwrap();
+        let mut note = Note::new(&basic);
+        note.fields = vec![front.to_string(), back.to_string()];
+        note.tags = tags.iter().map(|tag| tag.to_string()).collect();
+        add_note(conn, &mut note, deck_id).unwrap();
+    }
+
+    fn setup() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+
+        let spanish = deck(&conn, "Spanish");
+        let verbs = deck(&conn, "Spanish::Verbs");
+        let french = deck(&conn, "French");
+        deck(&conn, "Spanishish");
+
+        noted_card(&conn, spanish, "perro", "dog", &["animals"]);
+        noted_card(&conn, verbs, "comer", "to eat", &["verbs::regular"]);
+        noted_card(&conn, verbs, "ser", "to be", &["verbs_extra"]);
+
+        let mut review = FlashCard::new(french, "chat", "a black cat", Some(1.8));
+        review.set_queue(CardQueue::Review);
+        review.interval = 40;
+        review.due = 9;
+        review.save(&conn).unwrap();
+        let mut again = RevlogEntry::new(review.id.unwrap(), 1, 1, 30);
+        again.id = (NOW - 2 * 86_400) * 1000;
+        again.save(&conn).unwrap();
+        let mut good = RevlogEntry::new(review.id.unwrap(), 3, 40, 1);
+        good.id = (NOW - 20 * 86_400) * 1000;
+        good.save(&conn).unwrap();
+
+        let mut learning = FlashCard::new(french, "100% sûr", "sure_ly", None);
+        learning.set_queue(CardQueue::Learning);
+        learning.due = 12;
+        learning.buried_until = 11;
+        learning.save(&conn).unwrap();
+        conn.execute(
+            "UPDATE cards SET suspended = 1, flags = 3 WHERE id = ?",
+            [learning.id.unwrap()],
+        )
+        .unwrap();
+
+        conn
+    }
+
+    fn matching(conn: &Connection, query: &str) -> Vec<String> {
+        let mut questions = search_cards(conn, query, &timing())
+            .unwrap()
+            .into_iter()
+            .map(|id| {
+                FlashCard::load(id, conn)
+                    .unwrap()
+                    .get_question()
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+        questions.sort();
+        questions
+    }
+
+    #[test]
+    fn empty_search_matches_everything() {
+        let conn = setup();
+        assert_eq!(matching(&conn, "").len(), 5);
+        assert_eq!(
+            write_sql(&parse("").unwrap(), &timing()),
+            SqlFilter {
+                clause: "1".to_string(),
+                params: Vec::new()
+            }
+        );
+    }
+
+    #[test]
+    fn text_matches_words_of_question_or_answer() {
+        let conn = setup();
+
+        assert_eq!(matching(&conn, "DOG"), vec!["perro"]);
+        assert_eq!(matching(&conn, "\"black cat\""), vec!["chat"]);
+        assert_eq!(matching(&conn, "to e*t"), vec!["comer"]);
+        assert_eq!(matching(&conn, "100%"), vec!["100% sûr"]);
+        assert_eq!(matching(&conn, "sur"), vec!["100% sûr"]);
+        assert_eq!(matching(&conn, "per"), vec!["perro"]);
+        assert!(matching(&conn, "erro").is_empty());
+        assert_eq!(matching(&conn, "*erro"), vec!["perro"]);
+        assert!(matching(&conn, "e\\*").is_empty());
+    }
+
+    #[test]
+    fn front_and_back_match_the_whole_side() {
+        let conn = setup();
+
+        assert!(matching(&conn, "front:er").is_empty());
+        assert_eq!(matching(&conn, "front:*er*"), vec!["comer", "perro", "ser"]);
+        assert_eq!(matching(&conn, "back:dog"), vec!["perro"]);
+    }
+
+    #[test]
+    fn decks_include_their_children() {
+        let conn = setup();
+
+        assert_eq!(
+            matching(&conn, "deck:spanish"),
+            vec!["comer", "perro", "ser"]
+        );
+        assert_eq!(matching(&conn, "deck:Spanish::Verbs"), vec!["comer", "ser"]);
+        assert_eq!(matching(&conn, "deck:Span*").len(), 3);
+        assert!(matching(&conn, "deck:Verbs").is_empty());
+    }
+
+    #[test]
+    fn tags_include_their_children() {
+        let conn = setup();
+
+        assert_eq!(matching(&conn, "tag:verbs"), vec!["comer"]);
+        assert_eq!(matching(&conn, "tag:verbs*"), vec!["comer", "ser"]);
+        assert_eq!(matching(&conn, "tag:Animals"), vec!["perro"]);
+        assert_eq!(matching(&conn, "tag:none"), vec!["100% sûr", "chat"]);
+    }
+
+    #[test]
+    fn states_props_and_dates() {
+        let conn = setup();
+
+        assert_eq!(matching(&conn, "is:new").len(), 3);
+        assert_eq!(matching(&conn, "is:review"), vec!["chat"]);
+        assert_eq!(matching(&conn, "is:due"), vec!["chat"]);
+        assert_eq!(matching(&conn, "is:buried"), vec!["100% sûr"]);
+        assert_eq!(matching(&conn, "is:suspended"), vec!["100% sûr"]);
+        assert_eq!(matching(&conn, "flag:3"), vec!["100% sûr"]);
+        assert_eq!(matching(&conn, "flag:0").len(), 4);
+        assert_eq!(matching(&conn, "prop:ivl>30"), vec!["chat"]);
+        assert_eq!(matching(&conn, "prop:ease<2"), vec!["chat"]);
+        assert_eq!(matching(&conn, "prop:due=-1"), vec!["chat"]);
+        assert_eq!(matching(&conn, "prop:reps=2"), vec!["chat"]);
+        assert_eq!(matching(&conn, "prop:lapses>=1"), vec!["chat"]);
+        assert_eq!(matching(&conn, "rated:7:1"), vec!["chat"]);
+        assert!(matching(&conn, "rated:7:3").is_empty());
+        assert_eq!(matching(&conn, "rated:30:3"), vec!["chat"]);
+    }
+
+    #[test]
+    fn added_counts_back_from_now() {
+        let conn = setup();
+        let now = Utc::now().timestamp();
+        let timing = SchedTimingToday {
+            now,
+            days_elapsed: 0,
+            next_day_at: 86_400,
+        };
+
+        assert_eq!(search_cards(&conn, "added:1", &timing).unwrap().len(), 5);
+
+        let later = SchedTimingToday {
+            now: now + 3 * 86_400,
+            ..timing
+        };
+        assert!(search_cards(&conn, "added:2", &later).unwrap().is_empty());
+    }
+
+    #[test]
+    fn operators_combine_terms() {
+        let conn = setup();
+
+        assert_eq!(
+            matching(&conn, "deck:Spanish tag:verbs is:new"),
+            vec!["comer"]
+        );
+        assert_eq!(
+            matching(&conn, "deck:French OR tag:animals"),
+            vec!["100% sûr", "chat", "perro"]
+        );
+        assert_eq!(
+            matching(&conn, "deck:Spanish -deck:Spanish::Verbs"),
+            vec!["perro"]
+        );
+        assert_eq!(matching(&conn, "-(is:new OR is:learn)"), vec!["chat"]);
+        assert_eq!(
+            matching(&conn, "(deck:French is:review) or (tag:verbs* -front:ser)"),
+            vec!["chat", "comer"]
+        );
+    }
+
+    #[test]
+    fn invalid_searches_are_errors() {
+        let conn = setup();
+        assert!(search_cards(&conn, "deck:(Spanish", &timing()).is_err());
+        assert!(search_cards(&conn, "is:unknown", &timing()).is_err());
+    }
+}
@@ -0,0 +1,324 @@
+use std::{
+    fs::{self, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+};
+
+use chrono::{Datelike, Local, NaiveDate, NaiveDateTime};
+use rusqlite::{backup::Progress, Connection, DatabaseName};
+
+use crate::errors::Result;
+
+const BACKUP_PREFIX: &str = "backup-";
+const BACKUP_EXTENSION: &str = "db";
+/// Backups are named after the time they were taken, to the millisecond, so
+/// that a backup taken right before restoring another gets its own file.
+const BACKUP_TIME_FORMAT: &str = "%Y-%m-%d-%H.%M.%S%.3f";
+/// How backups were named before, to the second.
+const OLD_BACKUP_TIME_FORMAT: &str = "%Y-%m-%d-%H.%M.%S";
+
+/// How many backups to keep around, and how often to take them.
+#[derive(Debug, Clone, Copy)]
+pub struct BackupLimits {
+    pub daily: u32,
+    pub weekly: u32,
+    pub monthly: u32,
+    /// Periodic backups are skipped if the newest backup is younger than this.
+    pub minimum_interval_mins: u32,
+}
+
+impl Default for BackupLimits {
+    fn default() -> Self {
+        BackupLimits {
+            daily: 12,
+            weekly: 10,
+            monthly: 9,
+            minimum_interval_mins: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BackupEntry {
+    pub path: PathBuf,
+    pub created: NaiveDateTime,
+}
+
+impl BackupEntry {
+    fn from_path(path: PathBuf) -> Option<Self> {
+        let stem = path.file_stem()?.to_str()?;
+        let stamp = stem.strip_prefix(BACKUP_PREFIX)?;
+        let created = NaiveDateTime::parse_from_str(stamp, BACKUP_TIME_FORMAT)
+            .or_else(|_| NaiveDateTime::parse_from_str(stamp, OLD_BACKUP_TIME_FORMAT))
+            .ok()?;
+
+        Some(BackupEntry { path, created })
+    }
+
+    fn day(&self) -> NaiveDate {
+        self.created.date()
+    }
+
+    fn week(&self) -> (i32, u32) {
+        let week = self.created.iso_week();
+        (week.year(), week.week())
+    }
+
+    fn month(&self) -> (i32, u32) {
+        (self.created.year(), self.created.month())
+    }
+}
+
+/// Returns the folder backups are written to, next to the collection file.
+pub fn backups_folder(col_path: &Path) -> PathBuf {
+    col_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join("backups")
+}
+
+/// Copies the live database into a new, timestamped file inside `folder`
+/// using the SQLite online backup API, so the collection can stay open.
+/// Fails rather than overwrite an existing backup.
+pub fn backup(conn: &Connection, folder: &Path) -> Result<PathBuf> {
+    fs::create_dir_all(folder)?;
+
+    let name = format!(
+        "{}{}.{}",
+        BACKUP_PREFIX,
+        Local::now().naive_local().format(BACKUP_TIME_FORMAT),
+        BACKUP_EXTENSION
+    );
+    let path = folder.join(name);
+    write_backup(conn, &path)?;
+
+    Ok(path)
+}
+
+/// Creating the file first claims the name. SQLite fills in a temporary file
+/// next to it, which only replaces the claimed file once it is complete, so a
+/// failed backup leaves nothing behind.
+fn write_backup(conn: &Connection, path: &Path) -> Result<()> {
+    OpenOptions::new().write(true).create_new(true).open(path)?;
+
+    let temp = path.with_extension("tmp");
+    let result = conn
+        .backup(DatabaseName::Main, &temp, None)
+        .map_err(Into::into)
+        .and_then(|_| fs::rename(&temp, path).map_err(Into::into));
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+        let _ = fs::remove_file(path);
+    }
+
+    result
+}
+
+/// Replaces the contents of the live database with the given backup file.
+pub fn restore(conn: &mut Connection, backup_path: &Path) -> Result<()> {
+    if !backup_path.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("backup not found: {}", backup_path.display()),
+        )
+        .into());
+    }
+
+    conn.restore(DatabaseName::Main, backup_path, None::<fn(Progress)>)?;
+
+    Ok(())
+}
+
+/// Lists the backups in `folder`, newest first.
+pub fn list_backups(folder: &Path) -> Result<Vec<BackupEntry>> {
+    if !folder.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut backups = fs::read_dir(folder)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == BACKUP_EXTENSION))
+        .filter_map(BackupEntry::from_path)
+        .collect::<Vec<_>>();
+
+    backups.sort_by_key(|b| std::cmp::Reverse(b.created));
+
+    Ok(backups)
+}
+
+/// Deletes the backups that fall outside the daily, weekly and monthly limits.
+pub fn prune(folder: &Path, limits: &BackupLimits) -> Result<()> {
+    let backups = list_backups(folder)?;
+
+    for backup in obsolete_backups(&backups, limits) {
+        if let Err(e) = fs::remove_file(&backup.path) {
+            log::warn!("Error removing backup {:?}: {:?}", backup.path, e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Walks the backups from newest to oldest and keeps the newest backup of each
+/// day, then of each week, then of each month, until the limits run out.
+fn obsolete_backups<'a>(backups: &'a [BackupEntry], limits: &BackupLimits) -> Vec<&'a BackupEntry> {
+    let mut daily = limits.daily;
+    let mut weekly = limits.weekly;
+    let mut monthly = limits.monthly;
+
+    let mut last_day = None;
+    let mut last_week = None;
+    let mut last_month = None;
+
+    let mut obsolete = vec![];
+
+    for backup in backups {
+        let (day, week, month) = (backup.day(), backup.week(), backup.month());
+
+        let keep = if daily > 0 && last_day != Some(day) {
+            daily -= 1;
+            true
+        } else if weekly > 0 && last_week != Some(week) {
+            weekly -= 1;
+            true
+        } else if monthly > 0 && last_month != Some(month) {
+            monthly -= 1;
+            true
+        } else {
+            false
+        };
+
+        if keep {
+            last_day = Some(day);
+            last_week = Some(week);
+            last_month = Some(month);
+        } else {
+            obsolete.push(backup);
+        }
+    }
+
+    obsolete
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{db::init_db, Deck};
+
+    use super::*;
+
+    fn entry(stamp: &str) -> BackupEntry {
+        BackupEntry::from_path(PathBuf::from(format!("backup-{}.db", stamp))).unwrap()
+    }
+
+    fn temp_folder() -> PathBuf {
+        std::env::temp_dir().join(format!("ngurra-test-{}", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn parse_backup_name() {
+        let backup = entry("2024-03-10-14.05.12");
+
+        assert_eq!(backup.created.to_string(), "2024-03-10 14:05:12");
+        assert_eq!(
+            entry("2024-03-10-14.05.12.345").created.to_string(),
+            "2024-03-10 14:05:12.345"
+        );
+        assert!(BackupEntry::from_path(PathBuf::from("ngurra.db")).is_none());
+    }
+
+    #[test]
+    fn keeps_newest_backup_per_day() {
+        let backups = vec![
+            entry("2024-03-10-18.00.00"),
+            entry("2024-03-10-12.00.00"),
+            entry("2024-03-09-12.00.00"),
+        ];
+
+        let obsolete = obsolete_backups(&backups, &BackupLimits::default());
+
+        assert_eq!(obsolete.len(), 1);
+        assert_eq!(obsolete[0].created, backups[1].created);
+    }
+
+    #[test]
+    fn falls_back_to_weekly_and_monthly() {
+        let backups = vec![
+            entry("2024-03-10-12.00.00"),
+            entry("2024-03-09-12.00.00"),
+            entry("2024-03-08-12.00.00"),
+            entry("2024-02-28-12.00.00"),
+            entry("2024-02-27-12.00.00"),
+            entry("2024-01-15-12.00.00"),
+        ];
+        let limits = BackupLimits {
+            daily: 2,
+            weekly: 1,
+            monthly: 1,
+            ..Default::default()
+        };
+
+        let obsolete = obsolete_backups(&backups, &limits)
+            .into_iter()
+            .map(|b| b.created.to_string())
+            .collect::<Vec<_>>();
+
+        // 03-10 and 03-09 are daily, 02-28 is weekly and 01-15 is monthly.
+        assert_eq!(obsolete, vec!["2024-03-08 12:00:00", "2024-02-27 12:00:00"]);
+    }
+
+    #[test]
+    fn backup_and_restore() {
+        let folder = temp_folder();
+        let mut conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+
+        let mut deck = Deck::new("Test Deck");
+        deck.save(&conn).unwrap();
+
+        let path = backup(&conn, &folder).unwrap();
+        assert_eq!(list_backups(&folder).unwrap().len(), 1);
+
+        Deck::delete(deck.id.unwrap(), &conn).unwrap();
+        assert!(Deck::get_all_decks(&conn).unwrap().is_empty());
+
+        restore(&mut conn, &path).unwrap();
+        assert_eq!(Deck::get_all_decks(&conn).unwrap().len(), 1);
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn backups_in_the_same_second_get_their_own_files() {
+        let folder = temp_folder();
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+
+        let first = backup(&conn, &folder).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let second = backup(&conn, &folder).unwrap();
+
+        assert_ne!(first, second);
+        assert_eq!(list_backups(&folder).unwrap().len(), 2);
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn failed_backups_leave_no_file() {
+        let folder = temp_folder();
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+
+        // A folder in the way of the temporary file makes the backup fail.
+        let path = folder.join("backup-2024-03-10-14.05.12.345.db");
+        fs::create_dir_all(path.with_extension("tmp")).unwrap();
+
+        assert!(write_backup(&conn, &path).is_err());
+        assert!(!path.exists());
+        assert!(list_backups(&folder).unwrap().is_empty());
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+}
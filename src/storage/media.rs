@@ -0,0 +1,302 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use rusqlite::{params, Connection, OptionalExtension};
+use sha1::{Digest, Sha1};
+
+use crate::{errors::Result, models::field::media_references, repositories::note::FIELD_SEPARATOR};
+
+/// Returns the folder media files are kept in, next to the collection file.
+pub fn media_folder(col_path: &Path) -> PathBuf {
+    col_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join("media")
+}
+
+/// Hex-encoded SHA-1 of a file's contents, which identifies it in the
+/// `media` table.
+pub fn checksum(bytes: &[u8]) -> String {
+    format!("{:x}", Sha1::digest(bytes))
+}
+
+/// Copies the file at `source` into the media folder and returns the name it
+/// is stored under. A file whose contents are already stored is not copied
+/// again; the existing name is returned instead.
+pub fn add_file(conn: &Connection, folder: &Path, source: &Path) -> Result<String> {
+    let bytes = fs::read(source)?;
+    let name = source
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    add_bytes(conn, folder, &name, &bytes)
+}
+
+/// Stores `bytes` in the media folder, preferably under `name`, and returns
+/// the name it is stored under.
+pub fn add_bytes(conn: &Connection, folder: &Path, name: &str, bytes: &[u8]) -> Result<String> {
+    let hash = checksum(bytes);
+
+    let existing: Option<String> = conn
+        .query_row(
+            "SELECT filename FROM media WHERE hash = ?",
+            [&hash],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    let filename = match existing {
+        Some(filename) if folder.join(&filename).exists() => return Ok(filename),
+        // The file went missing, so it is written again under its old name.
+        Some(filename) => filename,
+        None => unused_name(conn, folder, &sanitize(name, &hash), &hash)?,
+    };
+
+    fs::create_dir_all(folder)?;
+    fs::write(folder.join(&filename), bytes)?;
+
+    conn.execute(
+        "INSERT OR REPLACE INTO media (hash, filename, size) VALUES (?, ?, ?)",
+        params![hash, filename, bytes.len() as i64],
+    )?;
+
+    Ok(filename)
+}
+
+/// Whether `name` names a file directly inside the media folder, as stored
+/// names always do. Other names, like `../notes.db`, must not be followed.
+pub fn is_plain_name(name: &str) -> bool {
+    !name.is_empty() && sanitize(name, "") == name
+}
+
+/// Keeps only the file name, without any folders, and makes one up from the
+/// hash when nothing is left.
+fn sanitize(name: &str, hash: &str) -> String {
+    let name = name
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .replace(['"', '<', '>', '[', ']', ':'], "");
+
+    if name.is_empty() || name.starts_with('.') {
+        hash.to_string()
+    } else {
+        name
+    }
+}
+
+/// Picks a name no other file uses, by adding part of the hash to `name`
+/// when needed.
+fn unused_name(conn: &Connection, folder: &Path, name: &str, hash: &str) -> Result<String> {
+    let taken = |candidate: &str| -> Result<bool> {
+        let in_table: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM media WHERE filename = ?)",
+            [candidate],
+            |row| row.get(0),
+        )?;
+
+        Ok(in_table || folder.join(candidate).exists())
+    };
+
+    if !taken(name)? {
+        return Ok(name.to_string());
+    }
+
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{}", extension)),
+        _ => (name, String::new()),
+    };
+
+    Ok(format!("{}-{}{}", stem, &hash[..8], extension))
+}
+
+/// Files the cards refer to but which are not in the media folder, and files
+/// in the media folder that no card refers to.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MediaCheck {
+    pub missing: Vec<String>,
+    pub unused: Vec<String>,
+}
+
+/// Compares the media folder with the notes' and cards' references. The
+/// `media` table is brought in line with the folder on the way: files added
+/// by hand are hashed and recorded, and rows for deleted files are dropped.
+pub fn check(conn: &Connection, folder: &Path) -> Result<MediaCheck> {
+    let mut referenced = BTreeSet::new();
+
+    // A note field need not show up on any of its cards.
+    let mut stmt = conn.prepare("SELECT fields FROM notes")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let fields: String = row.get(0)?;
+        for field in fields.split(FIELD_SEPARATOR) {
+            referenced.extend(media_references(field));
+        }
+    }
+
+    // Cards without a note keep their text on the card itself.
+    let mut stmt = conn.prepare("SELECT question, answer FROM cards")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        for column in 0..2 {
+            let field: String = row.get(column)?;
+            referenced.extend(media_references(&field));
+        }
+    }
+
+    let mut present = BTreeSet::new();
+    if folder.exists() {
+        for entry in fs::read_dir(folder)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                present.insert(entry.file_name().to_string_lossy().to_string());
+            }
+        }
+    }
+
+    sync_table(conn, folder, &present)?;
+
+    Ok(MediaCheck {
+        missing: referenced.difference(&present).cloned().collect(),
+        unused: present.difference(&referenced).cloned().collect(),
+    })
+}
+
+fn sync_table(conn: &Connection, folder: &Path, present: &BTreeSet<String>) -> Result<()> {
+    let recorded = conn
+        .prepare("SELECT filename FROM media")?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<BTreeSet<_>>>()?;
+
+    for filename in recorded.difference(present) {
+        conn.execute("DELETE FROM media WHERE filename = ?", [filename])?;
+    }
+
+    for filename in present.difference(&recorded) {
+        let bytes = fs::read(folder.join(filename))?;
+        conn.execute(
+            "INSERT OR IGNORE INTO media (hash, filename, size) VALUES (?, ?, ?)",
+            params![checksum(&bytes), filename, bytes.len() as i64],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Deletes the given files from the media folder, and returns how many were
+/// deleted.
+pub fn delete_files(conn: &Connection, folder: &Path, filenames: &[String]) -> Result<usize> {
+    let mut deleted = 0;
+
+    for filename in filenames {
+        let path = folder.join(filename);
+        if path.exists() {
+            fs::remove_file(&path)?;
+            deleted += 1;
+        }
+
+        conn.execute("DELETE FROM media WHERE filename = ?", [filename])?;
+    }
+
+    Ok(deleted)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        db::init_db,
+        models::{field::media_reference, note::add_note},
+        repositories::{
+            note::Note,
+            notetype::{CardTemplate, Notetype, NotetypeKind},
+        },
+        Deck, FlashCard,
+    };
+
+    fn setup() -> (Connection, PathBuf) {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+
+        let folder = std::env::temp_dir().join(format!("ngurra-media-{}", uuid::Uuid::new_v4()));
+
+        (conn, folder)
+    }
+
+    #[test]
+    fn identical_files_are_stored_once() {
+        let (conn, folder) = setup();
+
+        let first = add_bytes(&conn, &folder, "cat.png", b"cat").unwrap();
+        let again = add_bytes(&conn, &folder, "kitten.png", b"cat").unwrap();
+        let other = add_bytes(&conn, &folder, "cat.png", b"dog").unwrap();
+
+        assert_eq!(first, "cat.png");
+        assert_eq!(again, "cat.png");
+        assert_eq!(other, format!("cat-{}.png", &checksum(b"dog")[..8]));
+        assert_eq!(fs::read_dir(&folder).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn check_finds_missing_and_unused_files() {
+        let (conn, folder) = setup();
+
+        let mut deck = Deck::new("Animals");
+        deck.save(&conn).unwrap();
+
+        let used = add_bytes(&conn, &folder, "cat.png", b"cat").unwrap();
+        add_bytes(&conn, &folder, "dog.png", b"dog").unwrap();
+        FlashCard::new(
+            deck.id.unwrap(),
+            &media_reference(&used),
+            &media_reference("meow.mp3"),
+            None,
+        )
+        .save(&conn)
+        .unwrap();
+
+        let report = check(&conn, &folder).unwrap();
+        assert_eq!(report.missing, vec!["meow.mp3"]);
+        assert_eq!(report.unused, vec!["dog.png"]);
+
+        assert_eq!(delete_files(&conn, &folder, &report.unused).unwrap(), 1);
+        let report = check(&conn, &folder).unwrap();
+        assert!(report.unused.is_empty());
+
+        let rows: u32 = conn
+            .query_row("SELECT COUNT(1) FROM media", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(rows, 1);
+    }
+
+    #[test]
+    fn check_counts_note_fields_no_card_shows() {
+        let (conn, folder) = setup();
+
+        let mut deck = Deck::new("Animals");
+        deck.save(&conn).unwrap();
+
+        let picture = add_bytes(&conn, &folder, "cat.png", b"cat").unwrap();
+        let mut notetype = Notetype::new(
+            "Animal",
+            NotetypeKind::Normal,
+            &["Name", "Sound", "Picture"],
+            vec![CardTemplate::new("Recall", "{{Name}}", "{{Sound}}")],
+        );
+        notetype.save(&conn).unwrap();
+        let mut note = Note::new(&notetype);
+        note.fields = vec![
+            "cat".to_string(),
+            "meow".to_string(),
+            media_reference(&picture),
+        ];
+        add_note(&conn, &mut note, deck.id.unwrap()).unwrap();
+
+        assert_eq!(check(&conn, &folder).unwrap(), MediaCheck::default());
+    }
+}
@@ -1 +1,4 @@
+pub mod backup;
+pub mod media;
+pub mod profile;
 pub mod sqlite;
@@ -0,0 +1,179 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use gpui::Global;
+
+pub const DEFAULT_PROFILE: &str = "default";
+const COLLECTION_FILE: &str = "collection.db";
+/// Where the collection was kept before there were profiles, relative to the
+/// working directory.
+pub const LEGACY_COLLECTION: &str = "ngurra.db";
+
+/// The folder holding one sub-folder per profile, each with its own
+/// collection and backups.
+pub struct Profiles {
+    base: PathBuf,
+}
+
+impl Global for Profiles {}
+
+impl Profiles {
+    pub fn new(base: PathBuf) -> Self {
+        Profiles { base }
+    }
+
+    /// `$XDG_DATA_HOME/ngurra` on Linux, `~/Library/Application Support/ngurra`
+    /// on macOS, falling back to the working directory.
+    pub fn default_base() -> PathBuf {
+        dirs::data_dir()
+            .map(|dir| dir.join("ngurra"))
+            .unwrap_or_else(|| PathBuf::from("."))
+    }
+
+    pub fn base(&self) -> &Path {
+        &self.base
+    }
+
+    /// Lists the profiles that already have a collection, sorted by name.
+    pub fn list(&self) -> io::Result<Vec<String>> {
+        if !self.base.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut names = fs::read_dir(&self.base)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().join(COLLECTION_FILE).is_file())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect::<Vec<_>>();
+
+        names.sort();
+
+        Ok(names)
+    }
+
+    /// Returns the profile to open without asking: the only existing one, or
+    /// the default profile when there are none yet.
+    pub fn sole_profile(&self) -> Option<String> {
+        let mut names = self.list().unwrap_or_else(|e| {
+            log::error!("Error listing profiles: {:?}", e);
+            vec![]
+        });
+
+        match names.len() {
+            0 => Some(DEFAULT_PROFILE.to_string()),
+            1 => names.pop(),
+            _ => None,
+        }
+    }
+
+    /// Returns the collection path for the profile, creating its folder.
+    /// Spaces around the name are ignored.
+    pub fn collection_path(&self, name: &str) -> io::Result<PathBuf> {
+        let name = name.trim();
+        if !is_valid_name(name) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid profile name: {:?}", name),
+            ));
+        }
+
+        let folder = self.base.join(name);
+        fs::create_dir_all(&folder)?;
+
+        Ok(folder.join(COLLECTION_FILE))
+    }
+
+    /// Copies a collection from before there were profiles into the default
+    /// profile, as long as no profile exists yet. The old file is left
+    /// where it is.
+    ///
+    /// # Returns
+    ///
+    /// Where the collection was copied to, or `None` if nothing was copied.
+    pub fn adopt_legacy(&self, legacy: &Path) -> io::Result<Option<PathBuf>> {
+        if !legacy.is_file() || !self.list()?.is_empty() {
+            return Ok(None);
+        }
+
+        let path = self.collection_path(DEFAULT_PROFILE)?;
+        fs::copy(legacy, &path)?;
+
+        Ok(Some(path))
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\', ':'])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn temp_profiles() -> Profiles {
+        Profiles::new(std::env::temp_dir().join(format!("ngurra-test-{}", uuid::Uuid::new_v4())))
+    }
+
+    #[test]
+    fn sole_profile() {
+        let profiles = temp_profiles();
+        assert_eq!(profiles.sole_profile(), Some(DEFAULT_PROFILE.to_string()));
+
+        let path = profiles.collection_path("alice").unwrap();
+        fs::write(&path, "").unwrap();
+        assert_eq!(profiles.sole_profile(), Some("alice".to_string()));
+
+        let path = profiles.collection_path("bob").unwrap();
+        fs::write(&path, "").unwrap();
+        assert_eq!(profiles.sole_profile(), None);
+        assert_eq!(profiles.list().unwrap(), vec!["alice", "bob"]);
+
+        fs::remove_dir_all(profiles.base()).unwrap();
+    }
+
+    #[test]
+    fn rejects_invalid_names() {
+        let profiles = temp_profiles();
+
+        assert!(profiles.collection_path("").is_err());
+        assert!(profiles.collection_path("..").is_err());
+        assert!(profiles.collection_path("a/b").is_err());
+        assert!(profiles.collection_path("  ").is_err());
+    }
+
+    #[test]
+    fn legacy_collection_moves_into_the_default_profile() {
+        let profiles = temp_profiles();
+        fs::create_dir_all(profiles.base()).unwrap();
+        let legacy = profiles.base().join(LEGACY_COLLECTION);
+        assert_eq!(profiles.adopt_legacy(&legacy).unwrap(), None);
+
+        fs::write(&legacy, "decks").unwrap();
+        let path = profiles.adopt_legacy(&legacy).unwrap().unwrap();
+        assert_eq!(path, profiles.collection_path(DEFAULT_PROFILE).unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "decks");
+        assert!(legacy.exists());
+
+        // Once there is a profile, the old file is left alone.
+        fs::write(&legacy, "newer").unwrap();
+        assert_eq!(profiles.adopt_legacy(&legacy).unwrap(), None);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "decks");
+
+        fs::remove_dir_all(profiles.base()).unwrap();
+    }
+
+    #[test]
+    fn names_are_trimmed() {
+        let profiles = temp_profiles();
+
+        assert_eq!(
+            profiles.collection_path(" work ").unwrap(),
+            profiles.collection_path("work").unwrap()
+        );
+        assert!(!profiles.base().join(" work ").exists());
+
+        fs::remove_dir_all(profiles.base()).unwrap();
+    }
+}
@@ -1,29 +1,126 @@
-use std::path::Path;
+use std::{
+    fs::{File, OpenOptions},
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use rusqlite::Connection;
+use fs2::FileExt;
+use rusqlite::{Connection, OpenFlags};
 
 use crate::errors::Result;
 
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
 pub struct SqliteStorage {
     pub conn: Connection,
+    /// Set when another process holds the collection lock. The collection can
+    /// still be browsed, but every write will fail.
+    pub read_only: bool,
+    /// Held for as long as the collection is open; dropping it releases the lock.
+    _lock: Option<File>,
 }
 
-pub fn open_or_create_db(path: &Path) -> Connection {
-    let db = Connection::open(path).unwrap_or_else(|e| {
-        println!("Error opening db: {:?}", e);
-        Connection::open_in_memory().unwrap()
-    });
+pub fn open_or_create_db(path: &Path, read_only: bool) -> Result<Connection> {
+    let db = if read_only {
+        Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?
+    } else {
+        Connection::open(path)?
+    };
 
-    db.busy_timeout(std::time::Duration::from_secs(0));
+    db.busy_timeout(BUSY_TIMEOUT)?;
     db.set_prepared_statement_cache_capacity(50);
 
-    db
+    if !read_only {
+        db.pragma_update(None, "journal_mode", "wal")?;
+    }
+
+    Ok(db)
+}
+
+fn lock_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".lock");
+    PathBuf::from(name)
+}
+
+/// Takes an exclusive lock on a file next to the collection, so that only one
+/// Ngurra process writes to it at a time. Returns `None` if another process
+/// already holds the lock.
+fn acquire_lock(path: &Path) -> Result<Option<File>> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(lock_path(path))?;
+
+    match file.try_lock_exclusive() {
+        Ok(()) => Ok(Some(file)),
+        Err(e) if e.raw_os_error() == fs2::lock_contended_error().raw_os_error() => Ok(None),
+        Err(e) => Err(e.into()),
+    }
 }
 
 impl SqliteStorage {
     pub fn open_or_create(path: &Path) -> Result<Self> {
-        let db = open_or_create_db(path);
+        let (lock, read_only) = if path == Path::new(":memory:") {
+            (None, false)
+        } else {
+            match acquire_lock(path)? {
+                Some(lock) => (Some(lock), false),
+                None => {
+                    log::warn!("{:?} is open in another process, opening read-only", path);
+                    (None, true)
+                }
+            }
+        };
+
+        let db = open_or_create_db(path, read_only)?;
+
+        Ok(Self {
+            conn: db,
+            read_only,
+            _lock: lock,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn second_open_is_read_only() {
+        let folder = std::env::temp_dir().join(format!("ngurra-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&folder).unwrap();
+        let path = folder.join("collection.db");
+
+        let first = SqliteStorage::open_or_create(&path).unwrap();
+        first
+            .conn
+            .execute("CREATE TABLE t (x INTEGER)", [])
+            .unwrap();
+        assert!(!first.read_only);
+
+        let second = SqliteStorage::open_or_create(&path).unwrap();
+        assert!(second.read_only);
+        assert!(second.conn.execute("INSERT INTO t VALUES (1)", []).is_err());
+
+        drop(second);
+        drop(first);
+
+        let third = SqliteStorage::open_or_create(&path).unwrap();
+        assert!(!third.read_only);
+
+        let journal_mode: String = third
+            .conn
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(journal_mode, "wal");
 
-        Ok(Self { conn: db })
+        drop(third);
+        std::fs::remove_dir_all(folder).unwrap();
     }
 }
@@ -0,0 +1,682 @@
+//! Reads and writes the rows exchanged during a sync. This works directly on
+//! the SQLite tables so that the sync server does not need the rest of the app.
+
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
+use serde::Serialize;
+
+use super::protocol::{
+    CardEntry, Changes, DeckEntry, Grave, GraveKind, NoteEntry, NotetypeEntry, RevlogEntry,
+    PENDING_USN,
+};
+
+/// Which rows `gather` collects.
+#[derive(Debug, Clone, Copy)]
+pub enum Selection {
+    /// Rows changed locally that have not been synced yet.
+    Pending,
+    /// Rows stored on the server after the given usn.
+    Since(i32),
+    /// Rows modified after `after` and up to `until`, in seconds.
+    Modified { after: i64, until: i64 },
+}
+
+impl Selection {
+    fn filter(&self, usn: &str, mtime: &str) -> String {
+        match self {
+            Selection::Pending => format!("{} = {}", usn, PENDING_USN),
+            Selection::Since(since) => format!("{} > {}", usn, since),
+            Selection::Modified { after, until } => {
+                format!("{} > {} AND {} <= {}", mtime, after, mtime, until)
+            }
+        }
+    }
+}
+
+/// Which end of the sync is applying changes. Conflicts are resolved per
+/// object by modification time; on a tie the server's copy wins on both ends,
+/// so that client and server always agree on the result.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    Client,
+    Server,
+    /// Another device's changes read from a shared folder. With no server to
+    /// settle a tie, the copy that sorts last wins, and a deletion beats any
+    /// edit made before it, so every device ends up with the same rows no
+    /// matter in which order it reads the changes.
+    Peer,
+}
+
+/// Sync bookkeeping kept in the single row of the `sync_state` table.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SyncState {
+    pub endpoint: Option<String>,
+    /// On a client, the server usn at the last sync. On the server, its
+    /// current usn.
+    pub last_usn: i32,
+    /// The server schema the collection last synced against.
+    pub server_schema: i64,
+    /// Set when the local collection was replaced, e.g. by restoring a backup.
+    pub full_sync_required: bool,
+}
+
+pub fn sync_state(conn: &Connection) -> Result<SyncState> {
+    conn.query_row(
+        "SELECT endpoint, last_usn, server_schema, full_sync_required FROM sync_state WHERE id = 1",
+        [],
+        |row| {
+            Ok(SyncState {
+                endpoint: row.get(0)?,
+                last_usn: row.get(1)?,
+                server_schema: row.get(2)?,
+                full_sync_required: row.get(3)?,
+            })
+        },
+    )
+    .optional()
+    .map(Option::unwrap_or_default)
+}
+
+pub fn save_sync_state(conn: &Connection, state: &SyncState) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO sync_state (id, endpoint, last_usn, server_schema, full_sync_required) VALUES (1, ?, ?, ?, ?)",
+        params![
+            state.endpoint,
+            state.last_usn,
+            state.server_schema,
+            state.full_sync_required
+        ],
+    )?;
+
+    Ok(())
+}
+
+/// Whether the collection holds no decks or cards, in which case a full
+/// download can replace it without losing anything.
+pub fn is_empty(conn: &Connection) -> Result<bool> {
+    conn.query_row(
+        "SELECT NOT EXISTS (SELECT 1 FROM decks) AND NOT EXISTS (SELECT 1 FROM cards)",
+        [],
+        |row| row.get(0),
+    )
+}
+
+const DECK_QUERY: &str = "SELECT guid, name, creation_time, mtime FROM decks";
+
+fn deck_from_row(row: &Row) -> Result<DeckEntry> {
+    Ok(DeckEntry {
+        guid: row.get(0)?,
+        name: row.get(1)?,
+        creation_time: row.get(2)?,
+        mtime: row.get(3)?,
+    })
+}
+
+const NOTETYPE_QUERY: &str = "SELECT guid, name, kind, fields, templates, mtime FROM notetypes";
+
+fn notetype_from_row(row: &Row) -> Result<NotetypeEntry> {
+    Ok(NotetypeEntry {
+        guid: row.get(0)?,
+        name: row.get(1)?,
+        kind: row.get(2)?,
+        fields: row.get(3)?,
+        templates: row.get(4)?,
+        mtime: row.get(5)?,
+    })
+}
+
+const NOTE_QUERY: &str = "SELECT n.guid, t.guid, n.fields, n.tags, n.mtime
+    FROM notes n JOIN notetypes t ON t.id = n.notetype_id";
+
+fn note_from_row(row: &Row) -> Result<NoteEntry> {
+    Ok(NoteEntry {
+        guid: row.get(0)?,
+        notetype_guid: row.get(1)?,
+        fields: row.get(2)?,
+        tags: row.get(3)?,
+        mtime: row.get(4)?,
+    })
+}
+
+const CARD_QUERY: &str = "SELECT c.guid, d.guid, c.question, c.answer, c.creation_time, c.last_studied_time, c.ef, c.interval, c.due, c.queue, c.data, c.mtime, n.guid, c.ord, c.buried_until, c.suspended, c.flags
+    FROM cards c JOIN decks d ON d.id = c.deck_id LEFT JOIN notes n ON n.id = c.note_id";
+
+fn card_from_row(row: &Row) -> Result<CardEntry> {
+    Ok(CardEntry {
+        guid: row.get(0)?,
+        deck_guid: row.get(1)?,
+        question: row.get(2)?,
+        answer: row.get(3)?,
+        creation_time: row.get(4)?,
+        last_studied_time: row.get(5)?,
+        ef: row.get(6)?,
+        interval: row.get(7)?,
+        due: row.get(8)?,
+        queue: row.get(9)?,
+        data: row.get(10)?,
+        mtime: row.get(11)?,
+        note_guid: row.get(12)?,
+        ord: row.get(13)?,
+        buried_until: row.get(14)?,
+        suspended: row.get(15)?,
+        flags: row.get(16)?,
+    })
+}
+
+pub fn gather(conn: &Connection, selection: Selection) -> Result<Changes> {
+    let notetypes = conn
+        .prepare(&format!(
+            "{} WHERE {}",
+            NOTETYPE_QUERY,
+            selection.filter("usn", "mtime")
+        ))?
+        .query_map([], notetype_from_row)?
+        .collect::<Result<Vec<_>>>()?;
+
+    let decks = conn
+        .prepare(&format!(
+            "{} WHERE {}",
+            DECK_QUERY,
+            selection.filter("usn", "mtime")
+        ))?
+        .query_map([], deck_from_row)?
+        .collect::<Result<Vec<_>>>()?;
+
+    let notes = conn
+        .prepare(&format!(
+            "{} WHERE {}",
+            NOTE_QUERY,
+            selection.filter("n.usn", "n.mtime")
+        ))?
+        .query_map([], note_from_row)?
+        .collect::<Result<Vec<_>>>()?;
+
+    let cards = conn
+        .prepare(&format!(
+            "{} WHERE {}",
+            CARD_QUERY,
+            selection.filter("c.usn", "c.mtime")
+        ))?
+        .query_map([], card_from_row)?
+        .collect::<Result<Vec<_>>>()?;
+
+    let revlog = conn
+        .prepare(&format!(
+            "SELECT r.id, c.guid, r.ease, r.interval, r.last_interval
+            FROM revlog r JOIN cards c ON c.id = r.card_id WHERE {}",
+            selection.filter("r.usn", "r.id / 1000")
+        ))?
+        .query_map([], |row| {
+            Ok(RevlogEntry {
+                id: row.get(0)?,
+                card_guid: row.get(1)?,
+                ease: row.get(2)?,
+                interval: row.get(3)?,
+                last_interval: row.get(4)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
+    let graves = conn
+        .prepare(&format!(
+            "SELECT guid, kind, mtime FROM graves WHERE {}",
+            selection.filter("usn", "mtime")
+        ))?
+        .query_map([], |row| {
+            Ok(Grave {
+                guid: row.get(0)?,
+                kind: row.get(1)?,
+                mtime: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Changes {
+        notetypes,
+        decks,
+        notes,
+        cards,
+        revlog,
+        graves,
+    })
+}
+
+fn should_replace(local: Option<(i64, i32)>, incoming_mtime: i64, side: Side) -> bool {
+    match (local, side) {
+        (None, _) => true,
+        // The client only keeps an unsynced change of its own that is newer.
+        (Some((mtime, usn)), Side::Client) => usn != PENDING_USN || incoming_mtime >= mtime,
+        (Some((mtime, _)), Side::Server | Side::Peer) => incoming_mtime > mtime,
+    }
+}
+
+/// Settles a tie between a peer's copy and the local one by comparing their
+/// contents, so both devices pick the same copy.
+fn peer_wins<T: Serialize>(incoming: &T, local: Option<T>) -> bool {
+    match local {
+        Some(local) => serde_json::to_string(incoming).ok() > serde_json::to_string(&local).ok(),
+        None => true,
+    }
+}
+
+/// Whether the object was deleted at or after the given time.
+fn is_buried(conn: &Connection, guid: &str, mtime: i64) -> Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM graves WHERE guid = ? AND mtime >= ?)",
+        params![guid, mtime],
+        |row| row.get(0),
+    )
+}
+
+/// Whether an incoming row should overwrite the local one with the same guid.
+/// `query` selects the rows of `table` in the shape they are exchanged in,
+/// with `guid_column` naming the guid column in it.
+fn replace_row<T: Serialize>(
+    conn: &Connection,
+    table: &str,
+    (query, guid_column): (&str, &str),
+    from_row: fn(&Row) -> Result<T>,
+    (incoming, guid, mtime): (&T, &str, i64),
+    side: Side,
+) -> Result<bool> {
+    let local = local_stamp(conn, table, guid)?;
+
+    if side == Side::Peer {
+        if is_buried(conn, guid, mtime)? {
+            return Ok(false);
+        }
+
+        if matches!(local, Some((local_mtime, _)) if local_mtime == mtime) {
+            let local = conn
+                .query_row(
+                    &format!("{} WHERE {} = ?", query, guid_column),
+                    [guid],
+                    from_row,
+                )
+                .optional()?;
+            return Ok(peer_wins(incoming, local));
+        }
+    }
+
+    Ok(should_replace(local, mtime, side))
+}
+
+fn replace_notetype(conn: &Connection, notetype: &NotetypeEntry, side: Side) -> Result<bool> {
+    replace_row(
+        conn,
+        "notetypes",
+        (NOTETYPE_QUERY, "guid"),
+        notetype_from_row,
+        (notetype, &notetype.guid, notetype.mtime),
+        side,
+    )
+}
+
+fn replace_deck(conn: &Connection, deck: &DeckEntry, side: Side) -> Result<bool> {
+    replace_row(
+        conn,
+        "decks",
+        (DECK_QUERY, "guid"),
+        deck_from_row,
+        (deck, &deck.guid, deck.mtime),
+        side,
+    )
+}
+
+fn replace_note(conn: &Connection, note: &NoteEntry, side: Side) -> Result<bool> {
+    replace_row(
+        conn,
+        "notes",
+        (NOTE_QUERY, "n.guid"),
+        note_from_row,
+        (note, &note.guid, note.mtime),
+        side,
+    )
+}
+
+fn replace_card(conn: &Connection, card: &CardEntry, side: Side) -> Result<bool> {
+    replace_row(
+        conn,
+        "cards",
+        (CARD_QUERY, "c.guid"),
+        card_from_row,
+        (card, &card.guid, card.mtime),
+        side,
+    )
+}
+
+fn local_stamp(conn: &Connection, table: &str, guid: &str) -> Result<Option<(i64, i32)>> {
+    conn.query_row(
+        &format!("SELECT mtime, usn FROM {} WHERE guid = ?", table),
+        [guid],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()
+}
+
+/// When the server rejects a client's change because its own copy is newer,
+/// the copy is stamped with the new usn so that it is sent back to the client.
+fn keep_local(conn: &Connection, table: &str, guid: &str, usn: i32, side: Side) -> Result<()> {
+    if side == Side::Server {
+        conn.execute(
+            &format!("UPDATE {} SET usn = ? WHERE guid = ?", table),
+            params![usn, guid],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// The incoming deck's name, or the first of `name 2`, `name 3`... when
+/// another deck already has that name in some other case.
+fn unclashed_deck_name(conn: &Connection, guid: &str, name: &str) -> Result<String> {
+    let taken = |candidate: &str| -> Result<bool> {
+        conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM decks WHERE name = ? COLLATE NOCASE AND guid != ?)",
+            params![candidate, guid],
+            |row| row.get(0),
+        )
+    };
+
+    if !taken(name)? {
+        return Ok(name.to_string());
+    }
+
+    let mut n = 2;
+    loop {
+        let candidate = format!("{} {}", name, n);
+        if !taken(&candidate)? {
+            return Ok(candidate);
+        }
+        n += 1;
+    }
+}
+
+/// Applies the other side's changes, storing every accepted row under `usn`.
+/// Call it inside a transaction so a failed sync leaves nothing half-applied.
+pub fn apply(conn: &Connection, changes: &Changes, usn: i32, side: Side) -> Result<()> {
+    for notetype in &changes.notetypes {
+        if !replace_notetype(conn, notetype, side)? {
+            keep_local(conn, "notetypes", &notetype.guid, usn, side)?;
+            continue;
+        }
+
+        conn.execute(
+            "INSERT INTO notetypes (guid, name, kind, fields, templates, mtime, usn) VALUES (?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT (guid) DO UPDATE SET name = excluded.name, kind = excluded.kind, fields = excluded.fields,
+                templates = excluded.templates, mtime = excluded.mtime, usn = excluded.usn",
+            params![
+                notetype.guid,
+                notetype.name,
+                notetype.kind,
+                notetype.fields,
+                notetype.templates,
+                notetype.mtime,
+                usn
+            ],
+        )?;
+    }
+
+    let mut accepted = Vec::new();
+    for deck in &changes.decks {
+        if !replace_deck(conn, deck, side)? {
+            keep_local(conn, "decks", &deck.guid, usn, side)?;
+            continue;
+        }
+
+        // Decks being replaced give up their names first, so that decks can
+        // swap names within one batch.
+        conn.execute("UPDATE decks SET name = guid WHERE guid = ?", [&deck.guid])?;
+        accepted.push(deck);
+    }
+
+    for deck in accepted {
+        // A deck renamed to keep names unique is newer than the incoming one,
+        // so the new name travels back to where the deck came from.
+        let name = unclashed_deck_name(conn, &deck.guid, &deck.name)?;
+        let (mtime, deck_usn) = match (name == deck.name, side) {
+            (true, _) => (deck.mtime, usn),
+            (false, Side::Server) => (deck.mtime + 1, usn),
+            (false, _) => (deck.mtime + 1, PENDING_USN),
+        };
+
+        conn.execute(
+            "INSERT INTO decks (name, creation_time, guid, mtime, usn) VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT (guid) DO UPDATE SET name = excluded.name, mtime = excluded.mtime, usn = excluded.usn",
+            params![name, deck.creation_time, deck.guid, mtime, deck_usn],
+        )?;
+    }
+
+    for note in &changes.notes {
+        if !replace_note(conn, note, side)? {
+            keep_local(conn, "notes", &note.guid, usn, side)?;
+            continue;
+        }
+
+        let notetype_id: Option<u32> = conn
+            .query_row(
+                "SELECT id FROM notetypes WHERE guid = ?",
+                [&note.notetype_guid],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        let Some(notetype_id) = notetype_id else {
+            log::warn!(
+                "Skipping note {} of unknown note type {}",
+                note.guid,
+                note.notetype_guid
+            );
+            continue;
+        };
+
+        conn.execute(
+            "INSERT INTO notes (guid, notetype_id, fields, tags, mtime, usn) VALUES (?, ?, ?, ?, ?, ?)
+            ON CONFLICT (guid) DO UPDATE SET notetype_id = excluded.notetype_id, fields = excluded.fields,
+                tags = excluded.tags, mtime = excluded.mtime, usn = excluded.usn",
+            params![note.guid, notetype_id, note.fields, note.tags, note.mtime, usn],
+        )?;
+    }
+
+    for card in &changes.cards {
+        if !replace_card(conn, card, side)? {
+            keep_local(conn, "cards", &card.guid, usn, side)?;
+            continue;
+        }
+
+        let deck_id: Option<u32> = conn
+            .query_row(
+                "SELECT id FROM decks WHERE guid = ?",
+                [&card.deck_guid],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        let Some(deck_id) = deck_id else {
+            log::warn!(
+                "Skipping card {} of unknown deck {}",
+                card.guid,
+                card.deck_guid
+            );
+            continue;
+        };
+
+        let note_id: u32 = match &card.note_guid {
+            Some(note_guid) => conn
+                .query_row("SELECT id FROM notes WHERE guid = ?", [note_guid], |row| {
+                    row.get(0)
+                })
+                .optional()?
+                .unwrap_or_default(),
+            None => 0,
+        };
+
+        conn.execute(
+            "INSERT INTO cards (question, answer, creation_time, last_studied_time, deck_id, ef, interval, due, queue, data, guid, mtime, usn, note_id, ord, buried_until, suspended, flags)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT (guid) DO UPDATE SET question = excluded.question, answer = excluded.answer,
+                last_studied_time = excluded.last_studied_time, deck_id = excluded.deck_id, ef = excluded.ef,
+                interval = excluded.interval, due = excluded.due, queue = excluded.queue, data = excluded.data,
+                mtime = excluded.mtime, usn = excluded.usn, note_id = excluded.note_id, ord = excluded.ord,
+                buried_until = excluded.buried_until, suspended = excluded.suspended, flags = excluded.flags",
+            params![
+                card.question,
+                card.answer,
+                card.creation_time,
+                card.last_studied_time,
+                deck_id,
+                card.ef,
+                card.interval,
+                card.due,
+                card.queue,
+                card.data,
+                card.guid,
+                card.mtime,
+                usn,
+                note_id,
+                card.ord,
+                card.buried_until,
+                card.suspended,
+                card.flags
+            ],
+        )?;
+    }
+
+    for entry in &changes.revlog {
+        // Reviews are never edited, so an entry that already exists is skipped.
+        conn.execute(
+            "INSERT OR IGNORE INTO revlog (id, card_id, usn, ease, interval, last_interval)
+            SELECT ?, id, ?, ?, ?, ? FROM cards WHERE guid = ?",
+            params![
+                entry.id,
+                usn,
+                entry.ease,
+                entry.interval,
+                entry.last_interval,
+                entry.card_guid
+            ],
+        )?;
+    }
+
+    for grave in &changes.graves {
+        let table = match grave.kind {
+            kind if kind == GraveKind::Deck as u8 => "decks",
+            kind if kind == GraveKind::Card as u8 => "cards",
+            kind if kind == GraveKind::Note as u8 => "notes",
+            kind => {
+                log::warn!("Skipping grave of unknown kind {}", kind);
+                continue;
+            }
+        };
+
+        match side {
+            Side::Client | Side::Server => {
+                conn.execute(
+                    &format!("DELETE FROM {} WHERE guid = ?", table),
+                    [&grave.guid],
+                )?;
+            }
+            // A peer may have edited the object after the deletion, in which
+            // case the edit is kept.
+            Side::Peer => {
+                conn.execute(
+                    &format!("DELETE FROM {} WHERE guid = ? AND mtime <= ?", table),
+                    params![grave.guid, grave.mtime],
+                )?;
+            }
+        }
+
+        // The server keeps graves around so that other clients learn about
+        // the deletion too, and a peer keeps them so that older edits read
+        // later do not bring the object back. A grave that is replayed again
+        // is already there.
+        if side != Side::Client {
+            conn.execute(
+                "INSERT INTO graves (guid, kind, usn, mtime)
+                 SELECT ?1, ?2, ?3, ?4
+                 WHERE NOT EXISTS (SELECT 1 FROM graves WHERE guid = ?1 AND kind = ?2)",
+                params![grave.guid, grave.kind, usn, grave.mtime],
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Stamps the rows that were sent to the server with the usn it assigned.
+/// Rows changed again while the sync was running stay pending.
+pub fn mark_synced(conn: &Connection, sent: &Changes, usn: i32) -> Result<()> {
+    for notetype in &sent.notetypes {
+        conn.execute(
+            "UPDATE notetypes SET usn = ? WHERE guid = ? AND mtime = ? AND usn = ?",
+            params![usn, notetype.guid, notetype.mtime, PENDING_USN],
+        )?;
+    }
+
+    for deck in &sent.decks {
+        conn.execute(
+            "UPDATE decks SET usn = ? WHERE guid = ? AND mtime = ? AND usn = ?",
+            params![usn, deck.guid, deck.mtime, PENDING_USN],
+        )?;
+    }
+
+    for note in &sent.notes {
+        conn.execute(
+            "UPDATE notes SET usn = ? WHERE guid = ? AND mtime = ? AND usn = ?",
+            params![usn, note.guid, note.mtime, PENDING_USN],
+        )?;
+    }
+
+    for card in &sent.cards {
+        conn.execute(
+            "UPDATE cards SET usn = ? WHERE guid = ? AND mtime = ? AND usn = ?",
+            params![usn, card.guid, card.mtime, PENDING_USN],
+        )?;
+    }
+
+    for entry in &sent.revlog {
+        conn.execute(
+            "UPDATE revlog SET usn = ? WHERE id = ?",
+            params![usn, entry.id],
+        )?;
+    }
+
+    for grave in &sent.graves {
+        conn.execute(
+            "DELETE FROM graves WHERE guid = ? AND usn = ?",
+            params![grave.guid, PENDING_USN],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// After a full sync both sides hold the same rows, so everything is marked
+/// as synced under `usn` and the graves are no longer needed.
+pub fn reset_usns(conn: &Connection, usn: i32) -> Result<()> {
+    conn.execute("UPDATE notetypes SET usn = ?", [usn])?;
+    conn.execute("UPDATE decks SET usn = ?", [usn])?;
+    conn.execute("UPDATE notes SET usn = ?", [usn])?;
+    conn.execute("UPDATE cards SET usn = ?", [usn])?;
+    conn.execute("UPDATE revlog SET usn = ?", [usn])?;
+    conn.execute("DELETE FROM graves", [])?;
+
+    Ok(())
+}
+
+/// Drops the rows the client just sent from the server's reply, so they are
+/// not echoed back.
+pub fn without_echoes(mut changes: Changes, sent: &Changes) -> Changes {
+    changes
+        .notetypes
+        .retain(|notetype| !sent.notetypes.contains(notetype));
+    changes.decks.retain(|deck| !sent.decks.contains(deck));
+    changes.notes.retain(|note| !sent.notes.contains(note));
+    changes.cards.retain(|card| !sent.cards.contains(card));
+    changes
+        .revlog
+        .retain(|entry| !sent.revlog.iter().any(|sent| sent.id == entry.id));
+    changes
+        .graves
+        .retain(|grave| !sent.graves.iter().any(|sent| sent.guid == grave.guid));
+    changes
+}
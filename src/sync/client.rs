@@ -0,0 +1,226 @@
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use rusqlite::{Connection, DatabaseName};
+
+use crate::{
+    errors::Result,
+    storage::{backup, sqlite::open_or_create_db},
+};
+
+use super::{
+    changes::{self, Selection, Side},
+    journal,
+    protocol::{ChangesRequest, ChangesResponse, Meta},
+};
+
+/// Talks to a sync server over HTTP.
+pub struct SyncClient {
+    endpoint: String,
+    agent: ureq::Agent,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyncOutcome {
+    NoChanges,
+    Synced {
+        sent: usize,
+        received: usize,
+    },
+    /// The server was empty, so the whole collection was uploaded.
+    Uploaded,
+    /// The local collection was empty, so the server's was downloaded.
+    Downloaded,
+    /// Both sides hold unrelated data; the user has to pick which one to keep
+    /// with `full_upload` or `full_download`.
+    FullSyncRequired,
+}
+
+impl SyncClient {
+    pub fn new(endpoint: &str) -> Self {
+        SyncClient {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(60))
+                .build(),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.endpoint, path)
+    }
+
+    pub fn meta(&self) -> Result<Meta> {
+        Ok(self.agent.get(&self.url("/meta")).call()?.into_json()?)
+    }
+
+    /// Sends local changes. Returns `None` if the server asks for a full sync.
+    pub fn changes(&self, request: &ChangesRequest) -> Result<Option<ChangesResponse>> {
+        match self.agent.post(&self.url("/changes")).send_json(request) {
+            Ok(response) => Ok(Some(response.into_json()?)),
+            Err(ureq::Error::Status(409, _)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn upload(&self, bytes: &[u8]) -> Result<Meta> {
+        Ok(self
+            .agent
+            .post(&self.url("/upload"))
+            .send_bytes(bytes)?
+            .into_json()?)
+    }
+
+    pub fn download(&self) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.agent
+            .get(&self.url("/download"))
+            .call()?
+            .into_reader()
+            .read_to_end(&mut bytes)?;
+
+        Ok(bytes)
+    }
+}
+
+/// Exchanges changes with the server, falling back to a full upload or
+/// download when one side is empty.
+pub fn sync(conn: &mut Connection, client: &SyncClient) -> Result<SyncOutcome> {
+    let mut state = changes::sync_state(conn)?;
+    let meta = client.meta()?;
+
+    if meta.empty {
+        if changes::is_empty(conn)? {
+            return Ok(SyncOutcome::NoChanges);
+        }
+
+        full_upload(conn, client)?;
+        return Ok(SyncOutcome::Uploaded);
+    }
+
+    if state.full_sync_required || state.server_schema != meta.schema {
+        if changes::is_empty(conn)? {
+            full_download(conn, client)?;
+            return Ok(SyncOutcome::Downloaded);
+        }
+
+        return Ok(SyncOutcome::FullSyncRequired);
+    }
+
+    let local = changes::gather(conn, Selection::Pending)?;
+    if local.is_empty() && meta.usn == state.last_usn {
+        return Ok(SyncOutcome::NoChanges);
+    }
+
+    let request = ChangesRequest {
+        since_usn: state.last_usn,
+        schema: state.server_schema,
+        changes: local,
+    };
+
+    let Some(response) = client.changes(&request)? else {
+        return Ok(SyncOutcome::FullSyncRequired);
+    };
+
+    let tx = conn.transaction()?;
+    changes::apply(&tx, &response.changes, response.usn, Side::Client)?;
+    changes::mark_synced(&tx, &request.changes, response.usn)?;
+
+    state.last_usn = response.usn;
+    changes::save_sync_state(&tx, &state)?;
+    tx.commit()?;
+
+    Ok(SyncOutcome::Synced {
+        sent: request.changes.len(),
+        received: response.changes.len(),
+    })
+}
+
+fn temp_path(purpose: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("ngurra-{}-{}.db", purpose, uuid::Uuid::new_v4()))
+}
+
+/// Replaces the server's collection with the local one.
+pub fn full_upload(conn: &Connection, client: &SyncClient) -> Result<()> {
+    let path = temp_path("upload");
+    conn.backup(DatabaseName::Main, &path, None)?;
+    let bytes = fs::read(&path);
+    fs::remove_file(&path)?;
+
+    let meta = client.upload(&bytes?)?;
+    finish_full_sync(conn, &meta)
+}
+
+/// Replaces the local collection with the server's.
+pub fn full_download(conn: &mut Connection, client: &SyncClient) -> Result<()> {
+    let meta = client.meta()?;
+    let bytes = client.download()?;
+
+    let path = temp_path("download");
+    fs::write(&path, bytes)?;
+    let restored = restore_download(conn, &path);
+    fs::remove_file(&path)?;
+    restored?;
+
+    finish_full_sync(conn, &meta)
+}
+
+fn restore_download(conn: &mut Connection, path: &Path) -> Result<()> {
+    let endpoint = changes::sync_state(conn)?.endpoint;
+    let folder = journal::folder_state(conn)?;
+    let progress = journal::journal_progress(conn)?;
+
+    backup::restore(conn, path)?;
+
+    // The server's copy carries the bookkeeping of the device that uploaded
+    // it, including its folder sync device id, which has to stay unique.
+    let tx = conn.transaction()?;
+    let mut state = changes::sync_state(&tx)?;
+    state.endpoint = endpoint;
+    changes::save_sync_state(&tx, &state)?;
+    journal::save_folder_state(&tx, &folder)?;
+    journal::save_journal_progress(&tx, &progress)?;
+    tx.commit()?;
+
+    Ok(())
+}
+
+fn finish_full_sync(conn: &Connection, meta: &Meta) -> Result<()> {
+    let mut state = changes::sync_state(conn)?;
+
+    changes::reset_usns(conn, meta.usn)?;
+
+    state.last_usn = meta.usn;
+    state.server_schema = meta.schema;
+    state.full_sync_required = false;
+    changes::save_sync_state(conn, &state)?;
+
+    Ok(())
+}
+
+/// Which kind of sync the user asked for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncMode {
+    Normal,
+    FullUpload,
+    FullDownload,
+}
+
+/// Syncs the collection at `path` through a connection of its own, so that it
+/// can run on a background thread while the app keeps using the collection.
+pub fn sync_collection_at(path: &Path, endpoint: &str, mode: SyncMode) -> Result<SyncOutcome> {
+    let mut conn = open_or_create_db(path, false)?;
+    let client = SyncClient::new(endpoint);
+
+    match mode {
+        SyncMode::Normal => sync(&mut conn, &client),
+        SyncMode::FullUpload => full_upload(&conn, &client).map(|_| SyncOutcome::Uploaded),
+        SyncMode::FullDownload => {
+            full_download(&mut conn, &client).map(|_| SyncOutcome::Downloaded)
+        }
+    }
+}
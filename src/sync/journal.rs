@@ -0,0 +1,497 @@
+//! Sync through a shared folder, such as a network drive or a folder kept in
+//! step by Syncthing, instead of a sync server.
+//!
+//! Every device appends its changes to a journal of its own in the folder, one
+//! JSON line per sync, and replays the lines the other devices added since it
+//! last looked. A device never writes to another device's journal, so the
+//! files can be copied around by tools that know nothing about Ngurra, and a
+//! line that has only been copied half-way is picked up on the next sync.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::{
+    errors::{NgurraError, Result},
+    repositories::{guid::new_guid, timestamps::mtime_now},
+    storage::sqlite::open_or_create_db,
+};
+
+use super::{
+    changes::{self, Selection, Side},
+    protocol::{Changes, PENDING_USN},
+};
+
+const JOURNAL_EXTENSION: &str = "journal";
+
+/// Folder sync bookkeeping kept in the single row of the `folder_sync` table.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FolderState {
+    pub folder: Option<PathBuf>,
+    /// Names this device's journal in the folder.
+    pub device: String,
+    /// Local changes up to this modification time are in the journal.
+    pub exported_until: i64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FolderSyncOutcome {
+    /// Local changes appended to this device's journal.
+    pub sent: usize,
+    /// Changes read from the other devices' journals.
+    pub received: usize,
+}
+
+/// Reads the folder sync state, picking a device id the first time.
+pub fn folder_state(conn: &Connection) -> Result<FolderState> {
+    let state = conn
+        .query_row(
+            "SELECT folder, device, exported_until FROM folder_sync WHERE id = 1",
+            [],
+            |row| {
+                Ok(FolderState {
+                    folder: row.get::<_, Option<String>>(0)?.map(PathBuf::from),
+                    device: row.get(1)?,
+                    exported_until: row.get(2)?,
+                })
+            },
+        )
+        .optional()?
+        .unwrap_or_default();
+
+    if !state.device.is_empty() {
+        return Ok(state);
+    }
+
+    let state = FolderState {
+        device: new_guid(),
+        ..state
+    };
+    save_folder_state(conn, &state)?;
+
+    Ok(state)
+}
+
+pub fn save_folder_state(conn: &Connection, state: &FolderState) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO folder_sync (id, folder, device, exported_until) VALUES (1, ?, ?, ?)",
+        params![
+            state
+                .folder
+                .as_ref()
+                .map(|folder| folder.to_string_lossy().to_string()),
+            state.device,
+            state.exported_until
+        ],
+    )?;
+
+    Ok(())
+}
+
+fn journal_path(folder: &Path, device: &str) -> PathBuf {
+    folder.join(format!("{}.{}", device, JOURNAL_EXTENSION))
+}
+
+/// Appends the local changes made since the last sync to this device's
+/// journal, then applies the other devices' new journal lines.
+pub fn sync_folder(conn: &mut Connection, folder: &Path) -> Result<FolderSyncOutcome> {
+    if !folder.is_dir() {
+        return Err(NgurraError::Sync(format!(
+            "the sync folder {} does not exist",
+            folder.display()
+        )));
+    }
+
+    let tx = conn.transaction()?;
+    let mut state = folder_state(&tx)?;
+
+    // Changes saved during the current second may still be followed by
+    // others with the same mtime, so they wait for the next sync.
+    let until = mtime_now() - 1;
+    let local = changes::gather(
+        &tx,
+        Selection::Modified {
+            after: state.exported_until,
+            until,
+        },
+    )?;
+
+    if !local.is_empty() {
+        append(&journal_path(folder, &state.device), &local)?;
+    }
+    state.exported_until = until.max(state.exported_until);
+
+    let mut incoming = Changes::default();
+    for (device, path) in other_journals(folder, &state.device)? {
+        let position = journal_position(&tx, &device)?;
+        let (changes, position) = read_journal(&path, position)?;
+
+        merge(&mut incoming, changes);
+        save_journal_position(&tx, &device, position)?;
+    }
+
+    // Replayed rows keep their peer's mtime, which is normally older than
+    // `until`, so they are not written back to this device's journal. If a
+    // peer's clock runs ahead they are, and replaying them again is harmless.
+    changes::apply(&tx, &incoming, PENDING_USN, Side::Peer)?;
+    save_folder_state(&tx, &state)?;
+    tx.commit()?;
+
+    Ok(FolderSyncOutcome {
+        sent: local.len(),
+        received: incoming.len(),
+    })
+}
+
+fn append(path: &Path, changes: &Changes) -> Result<()> {
+    let mut line = serde_json::to_vec(changes)?;
+    line.push(b'\n');
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(&line)?;
+    file.sync_all()?;
+
+    Ok(())
+}
+
+fn other_journals(folder: &Path, device: &str) -> Result<Vec<(String, PathBuf)>> {
+    let mut journals = Vec::new();
+
+    for entry in fs::read_dir(folder)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some(JOURNAL_EXTENSION) {
+            continue;
+        }
+
+        match path.file_stem().and_then(|stem| stem.to_str()) {
+            Some(stem) if stem != device => journals.push((stem.to_string(), path.clone())),
+            _ => {}
+        }
+    }
+
+    journals.sort();
+
+    Ok(journals)
+}
+
+/// How far each of the other devices' journals has been read.
+pub fn journal_progress(conn: &Connection) -> Result<Vec<(String, u64)>> {
+    let progress = conn
+        .prepare("SELECT device, position FROM journal_progress ORDER BY device")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+
+    Ok(progress)
+}
+
+/// Replaces the recorded journal positions with `progress`.
+pub fn save_journal_progress(conn: &Connection, progress: &[(String, u64)]) -> Result<()> {
+    conn.execute("DELETE FROM journal_progress", [])?;
+    for (device, position) in progress {
+        save_journal_position(conn, device, *position)?;
+    }
+
+    Ok(())
+}
+
+fn save_journal_position(conn: &Connection, device: &str, position: u64) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO journal_progress (device, position) VALUES (?, ?)",
+        params![device, position],
+    )?;
+
+    Ok(())
+}
+
+fn journal_position(conn: &Connection, device: &str) -> Result<u64> {
+    Ok(conn
+        .query_row(
+            "SELECT position FROM journal_progress WHERE device = ?",
+            [device],
+            |row| row.get(0),
+        )
+        .optional()?
+        .unwrap_or(0))
+}
+
+/// Reads the complete lines after `position`, and returns them with the
+/// position to continue from next time.
+fn read_journal(path: &Path, position: u64) -> Result<(Vec<Changes>, u64)> {
+    let mut file = fs::File::open(path)?;
+
+    // A journal shorter than what was read before has been replaced, so it
+    // is read again from the start. Replaying a line twice changes nothing.
+    let position = if file.metadata()?.len() < position {
+        0
+    } else {
+        position
+    };
+
+    let mut bytes = Vec::new();
+    file.seek(SeekFrom::Start(position))?;
+    file.read_to_end(&mut bytes)?;
+
+    let Some(end) = bytes.iter().rposition(|byte| *byte == b'\n') else {
+        return Ok((Vec::new(), position));
+    };
+
+    let mut entries = Vec::new();
+    for line in bytes[..end].split(|byte| *byte == b'\n') {
+        if line.is_empty() {
+            continue;
+        }
+
+        match serde_json::from_slice(line) {
+            Ok(changes) => entries.push(changes),
+            Err(e) => log::warn!("Skipping unreadable line in {:?}: {}", path, e),
+        }
+    }
+
+    Ok((entries, position + end as u64 + 1))
+}
+
+fn merge(into: &mut Changes, batches: Vec<Changes>) {
+    for batch in batches {
+        into.notetypes.extend(batch.notetypes);
+        into.decks.extend(batch.decks);
+        into.notes.extend(batch.notes);
+        into.cards.extend(batch.cards);
+        into.revlog.extend(batch.revlog);
+        into.graves.extend(batch.graves);
+    }
+}
+
+/// Syncs the collection at `path` with its shared folder through a connection
+/// of its own. Returns `None` when no folder has been set up.
+pub fn sync_folder_at(path: &Path) -> Result<Option<FolderSyncOutcome>> {
+    let mut conn = open_or_create_db(path, false)?;
+
+    let Some(folder) = folder_state(&conn)?.folder else {
+        return Ok(None);
+    };
+
+    sync_folder(&mut conn, &folder).map(Some)
+}
+
+#[cfg(test)]
+mod test {
+    use rusqlite::params;
+
+    use crate::{db::init_db, Deck, FlashCard};
+
+    use super::*;
+
+    fn temp_folder() -> PathBuf {
+        let folder = std::env::temp_dir().join(format!("ngurra-journal-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&folder).unwrap();
+        folder
+    }
+
+    fn open_collection() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        init_db(&conn).unwrap();
+        conn
+    }
+
+    fn questions(conn: &Connection) -> Vec<String> {
+        conn.prepare("SELECT question FROM cards ORDER BY question")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap()
+    }
+
+    /// Lets the next sync export rows that the test backdated, rather than
+    /// waiting for the clock to move past the last export.
+    fn forget_exports(conn: &Connection) {
+        conn.execute("UPDATE folder_sync SET exported_until = 0", [])
+            .unwrap();
+    }
+
+    /// Pretends everything was saved a while ago.
+    fn backdate(conn: &Connection, seconds: i64) {
+        let mtime = mtime_now() - seconds;
+        conn.execute("UPDATE decks SET mtime = ?", [mtime]).unwrap();
+        conn.execute("UPDATE notes SET mtime = ?", [mtime]).unwrap();
+        conn.execute("UPDATE cards SET mtime = ?", [mtime]).unwrap();
+        conn.execute("UPDATE graves SET mtime = ?", [mtime])
+            .unwrap();
+        forget_exports(conn);
+    }
+
+    #[test]
+    fn changes_reach_other_devices_through_the_folder() {
+        let folder = temp_folder();
+        let mut first = open_collection();
+        let mut second = open_collection();
+
+        let mut deck = Deck::new("Spanish");
+        deck.save(&first).unwrap();
+        let mut card = FlashCard::new(deck.id.unwrap(), "hola", "hello", None);
+        card.save(&first).unwrap();
+        backdate(&first, 10);
+
+        let outcome = sync_folder(&mut first, &folder).unwrap();
+        assert_eq!(outcome.sent, 2);
+
+        let outcome = sync_folder(&mut second, &folder).unwrap();
+        assert_eq!(outcome.received, 2);
+        assert_eq!(questions(&second), vec!["hola"]);
+
+        // Nothing new on either side.
+        assert_eq!(
+            sync_folder(&mut first, &folder).unwrap(),
+            FolderSyncOutcome::default()
+        );
+        assert_eq!(
+            sync_folder(&mut second, &folder).unwrap(),
+            FolderSyncOutcome::default()
+        );
+
+        card.delete(&first).unwrap();
+        backdate(&first, 5);
+        sync_folder(&mut first, &folder).unwrap();
+        sync_folder(&mut second, &folder).unwrap();
+        assert!(questions(&second).is_empty());
+    }
+
+    #[test]
+    fn devices_agree_on_conflicting_edits() {
+        let folder = temp_folder();
+        let mut first = open_collection();
+        let mut second = open_collection();
+
+        let mut deck = Deck::new("Spanish");
+        deck.save(&first).unwrap();
+        FlashCard::new(deck.id.unwrap(), "hola", "hello", None)
+            .save(&first)
+            .unwrap();
+        backdate(&first, 100);
+        sync_folder(&mut first, &folder).unwrap();
+        sync_folder(&mut second, &folder).unwrap();
+
+        // Both devices edit the card in the same second.
+        let mtime = mtime_now() - 10;
+        first
+            .execute(
+                "UPDATE cards SET question = 'hola (first)', mtime = ?",
+                [mtime],
+            )
+            .unwrap();
+        second
+            .execute(
+                "UPDATE cards SET question = 'hola (second)', mtime = ?",
+                [mtime],
+            )
+            .unwrap();
+        forget_exports(&first);
+        forget_exports(&second);
+
+        sync_folder(&mut first, &folder).unwrap();
+        sync_folder(&mut second, &folder).unwrap();
+        sync_folder(&mut first, &folder).unwrap();
+
+        assert_eq!(questions(&first), questions(&second));
+    }
+
+    #[test]
+    fn deletion_wins_over_older_edits_only() {
+        let folder = temp_folder();
+        let mut first = open_collection();
+        let mut second = open_collection();
+
+        let mut deck = Deck::new("Spanish");
+        deck.save(&first).unwrap();
+        let mut kept = FlashCard::new(deck.id.unwrap(), "hola", "hello", None);
+        kept.save(&first).unwrap();
+        let mut deleted = FlashCard::new(deck.id.unwrap(), "adios", "bye", None);
+        deleted.save(&first).unwrap();
+        backdate(&first, 100);
+        sync_folder(&mut first, &folder).unwrap();
+        sync_folder(&mut second, &folder).unwrap();
+
+        // The first device deletes both cards; the second edits one of them
+        // before the deletions and the other one after.
+        kept.delete(&first).unwrap();
+        deleted.delete(&first).unwrap();
+        first
+            .execute("UPDATE graves SET mtime = ?", [mtime_now() - 20])
+            .unwrap();
+
+        second
+            .execute(
+                "UPDATE cards SET question = 'hola!', mtime = ? WHERE guid = ?",
+                params![mtime_now() - 10, kept.guid],
+            )
+            .unwrap();
+        second
+            .execute(
+                "UPDATE cards SET question = 'adios!', mtime = ? WHERE guid = ?",
+                params![mtime_now() - 30, deleted.guid],
+            )
+            .unwrap();
+        forget_exports(&first);
+        forget_exports(&second);
+
+        sync_folder(&mut first, &folder).unwrap();
+        sync_folder(&mut second, &folder).unwrap();
+        sync_folder(&mut first, &folder).unwrap();
+
+        assert_eq!(questions(&first), vec!["hola!"]);
+        assert_eq!(questions(&second), vec!["hola!"]);
+    }
+
+    #[test]
+    fn replayed_deletions_leave_one_grave() {
+        let folder = temp_folder();
+        let mut first = open_collection();
+        let mut second = open_collection();
+
+        let mut deck = Deck::new("Spanish");
+        deck.save(&first).unwrap();
+        let mut card = FlashCard::new(deck.id.unwrap(), "hola", "hello", None);
+        card.save(&first).unwrap();
+        backdate(&first, 100);
+        sync_folder(&mut first, &folder).unwrap();
+        sync_folder(&mut second, &folder).unwrap();
+
+        card.delete(&first).unwrap();
+        backdate(&first, 10);
+        sync_folder(&mut first, &folder).unwrap();
+
+        // The second device reads the whole journal again, as it does when
+        // the journal file has been replaced.
+        for _ in 0..2 {
+            sync_folder(&mut second, &folder).unwrap();
+            second.execute("DELETE FROM journal_progress", []).unwrap();
+        }
+
+        let graves: u32 = second
+            .query_row("SELECT COUNT(1) FROM graves", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(graves, 1);
+    }
+
+    #[test]
+    fn partial_lines_are_read_once_complete() {
+        let folder = temp_folder();
+        let path = journal_path(&folder, "other");
+
+        let changes = Changes::default();
+        let line = serde_json::to_string(&changes).unwrap();
+        fs::write(&path, format!("{}\n{}", line, &line[..5])).unwrap();
+
+        let (entries, position) = read_journal(&path, 0).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(position, line.len() as u64 + 1);
+
+        fs::write(&path, format!("{}\n{}\n", line, line)).unwrap();
+        let (entries, _) = read_journal(&path, position).unwrap();
+        assert_eq!(entries.len(), 1);
+    }
+}
@@ -0,0 +1,347 @@
+//! Two-way sync between collections through a sync server.
+//!
+//! Every deck, card and review carries a guid and an update sequence number
+//! (usn). Local changes are stored with a pending usn; a sync sends those to
+//! the server and receives everything the server stored since the usn seen at
+//! the previous sync. When the two sides cannot be merged, for example after
+//! a backup was restored, one side replaces the other wholesale.
+//!
+//! Without a server, collections can also sync through a shared folder; see
+//! `journal`.
+
+pub mod changes;
+pub mod client;
+pub mod journal;
+pub mod protocol;
+// The server itself runs in the `ngurra-sync-server` binary; the app only
+// uses it in tests.
+#[allow(dead_code)]
+pub mod server;
+
+#[cfg(test)]
+mod test {
+    use std::{
+        path::{Path, PathBuf},
+        thread,
+    };
+
+    use rusqlite::{params, Connection};
+
+    use crate::{
+        db::init_db,
+        models::note::{add_note, update_note},
+        repositories::{
+            guid::PENDING_USN,
+            note::Note,
+            notetype::{CardTemplate, Notetype, NotetypeKind},
+            revlog::RevlogEntry,
+            timestamps::mtime_now,
+        },
+        Deck, FlashCard,
+    };
+
+    use super::{
+        client::{full_download, sync, SyncClient, SyncOutcome},
+        journal::{folder_state, journal_progress, save_folder_state, sync_folder},
+        server::SyncServer,
+    };
+
+    fn temp_folder() -> PathBuf {
+        let folder = std::env::temp_dir().join(format!("ngurra-sync-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&folder).unwrap();
+        folder
+    }
+
+    /// Starts a server on a free loopback port and returns a client for it.
+    fn start_server(folder: &Path) -> SyncClient {
+        let listener = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let addr = listener.server_addr().to_ip().unwrap();
+        let server = SyncServer::new(folder.join("server").join("collection.db"));
+
+        thread::spawn(move || server.serve(listener));
+
+        SyncClient::new(&format!("http://{}", addr))
+    }
+
+    fn open_collection(folder: &Path, name: &str) -> Connection {
+        let conn = Connection::open(folder.join(format!("{}.db", name))).unwrap();
+        init_db(&conn).unwrap();
+        conn
+    }
+
+    fn questions(conn: &Connection) -> Vec<String> {
+        conn.prepare("SELECT question FROM cards ORDER BY question")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap()
+    }
+
+    fn add_card(conn: &Connection, deck: &Deck, question: &str) -> FlashCard {
+        let mut card = FlashCard::new(deck.id.unwrap(), question, "answer", None);
+        card.save(conn).unwrap();
+        card
+    }
+
+    #[test]
+    fn first_sync_uploads_then_other_side_downloads() {
+        let folder = temp_folder();
+        let client = start_server(&folder);
+
+        let mut first = open_collection(&folder, "first");
+        let mut deck = Deck::new("Spanish");
+        deck.save(&first).unwrap();
+        add_card(&first, &deck, "hola");
+
+        assert_eq!(sync(&mut first, &client).unwrap(), SyncOutcome::Uploaded);
+
+        let mut second = open_collection(&folder, "second");
+        assert_eq!(sync(&mut second, &client).unwrap(), SyncOutcome::Downloaded);
+        assert_eq!(questions(&second), vec!["hola"]);
+
+        let guid: String = second
+            .query_row("SELECT guid FROM decks", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(guid, deck.guid);
+
+        assert_eq!(sync(&mut first, &client).unwrap(), SyncOutcome::NoChanges);
+        assert_eq!(sync(&mut second, &client).unwrap(), SyncOutcome::NoChanges);
+    }
+
+    #[test]
+    fn changes_flow_both_ways_and_newest_edit_wins() {
+        let folder = temp_folder();
+        let client = start_server(&folder);
+
+        let mut first = open_collection(&folder, "first");
+        let mut deck = Deck::new("Spanish");
+        deck.save(&first).unwrap();
+        add_card(&first, &deck, "hola");
+        sync(&mut first, &client).unwrap();
+
+        let mut second = open_collection(&folder, "second");
+        sync(&mut second, &client).unwrap();
+
+        // Both sides edit the same card. The first side syncs first, but its
+        // edit is the newer one, so the second side's edit is rejected.
+        let now = mtime_now();
+        first
+            .execute(
+                "UPDATE cards SET question = 'hola (first)', mtime = ?, usn = ?",
+                params![now + 200, PENDING_USN],
+            )
+            .unwrap();
+        second
+            .execute(
+                "UPDATE cards SET question = 'hola (second)', mtime = ?, usn = ?",
+                params![now + 100, PENDING_USN],
+            )
+            .unwrap();
+
+        // Each side also adds something new.
+        add_card(&first, &deck, "adios");
+        let second_card_id: u32 = second
+            .query_row("SELECT id FROM cards", [], |row| row.get(0))
+            .unwrap();
+        RevlogEntry::new(second_card_id, 3, 1, 0)
+            .save(&second)
+            .unwrap();
+
+        assert_eq!(
+            sync(&mut first, &client).unwrap(),
+            SyncOutcome::Synced {
+                sent: 2,
+                received: 0
+            }
+        );
+        assert!(matches!(
+            sync(&mut second, &client).unwrap(),
+            SyncOutcome::Synced { sent: 2, .. }
+        ));
+        sync(&mut first, &client).unwrap();
+
+        assert_eq!(questions(&first), vec!["adios", "hola (first)"]);
+        assert_eq!(questions(&second), vec!["adios", "hola (first)"]);
+
+        let reviews: u32 = first
+            .query_row("SELECT COUNT(1) FROM revlog", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(reviews, 1);
+
+        let pending: u32 = first
+            .query_row(
+                "SELECT COUNT(1) FROM cards WHERE usn = ?",
+                [PENDING_USN],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(pending, 0);
+    }
+
+    #[test]
+    fn deletions_are_synced() {
+        let folder = temp_folder();
+        let client = start_server(&folder);
+
+        let mut first = open_collection(&folder, "first");
+        let mut deck = Deck::new("Spanish");
+        deck.save(&first).unwrap();
+        let card = add_card(&first, &deck, "hola");
+        add_card(&first, &deck, "adios");
+        sync(&mut first, &client).unwrap();
+
+        let mut second = open_collection(&folder, "second");
+        sync(&mut second, &client).unwrap();
+
+        card.delete(&first).unwrap();
+        sync(&mut first, &client).unwrap();
+        sync(&mut second, &client).unwrap();
+
+        assert_eq!(questions(&second), vec!["adios"]);
+    }
+
+    #[test]
+    fn decks_named_alike_are_kept_apart() {
+        let folder = temp_folder();
+        let client = start_server(&folder);
+
+        let mut first = open_collection(&folder, "first");
+        Deck::new("Spanish").save(&first).unwrap();
+        sync(&mut first, &client).unwrap();
+
+        let mut second = open_collection(&folder, "second");
+        sync(&mut second, &client).unwrap();
+
+        Deck::new("French").save(&first).unwrap();
+        Deck::new("french").save(&second).unwrap();
+        sync(&mut first, &client).unwrap();
+        sync(&mut second, &client).unwrap();
+        sync(&mut first, &client).unwrap();
+
+        let names = |conn: &Connection| -> Vec<String> {
+            conn.prepare("SELECT name FROM decks ORDER BY name")
+                .unwrap()
+                .query_map([], |row| row.get(0))
+                .unwrap()
+                .collect::<rusqlite::Result<_>>()
+                .unwrap()
+        };
+        assert_eq!(names(&first), vec!["French", "Spanish", "french 2"]);
+        assert_eq!(names(&second), names(&first));
+    }
+
+    #[test]
+    fn notes_and_note_types_are_synced() {
+        let folder = temp_folder();
+        let client = start_server(&folder);
+
+        let mut first = open_collection(&folder, "first");
+        let mut deck = Deck::new("Spanish");
+        deck.save(&first).unwrap();
+        sync(&mut first, &client).unwrap();
+
+        let mut second = open_collection(&folder, "second");
+        sync(&mut second, &client).unwrap();
+
+        let mut notetype = Notetype::new(
+            "Vocabulary",
+            NotetypeKind::Normal,
+            &["Word", "Meaning"],
+            vec![CardTemplate::new("Recall", "{{Word}}", "{{Meaning}}")],
+        );
+        notetype.save(&first).unwrap();
+        let mut note = Note::new(&notetype);
+        note.fields = vec!["perro".to_string(), "dog".to_string()];
+        add_note(&first, &mut note, deck.id.unwrap()).unwrap();
+
+        // Keeps the edit below newer than the note it replaces.
+        first
+            .execute("UPDATE notes SET mtime = mtime - 100", [])
+            .unwrap();
+        first
+            .execute("UPDATE cards SET mtime = mtime - 100", [])
+            .unwrap();
+
+        sync(&mut first, &client).unwrap();
+        sync(&mut second, &client).unwrap();
+
+        let note_id: u32 = second
+            .query_row("SELECT id FROM notes WHERE guid = ?", [&note.guid], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        let mut synced = Note::load(note_id, &second).unwrap();
+        assert_eq!(synced.fields, note.fields);
+        assert_eq!(synced.card_ids(&second).unwrap().len(), 1);
+
+        synced.fields[0] = "el perro".to_string();
+        update_note(&second, &mut synced).unwrap();
+        sync(&mut second, &client).unwrap();
+        sync(&mut first, &client).unwrap();
+
+        assert_eq!(questions(&first), vec!["el perro"]);
+    }
+
+    #[test]
+    fn unrelated_collections_need_a_full_sync() {
+        let folder = temp_folder();
+        let client = start_server(&folder);
+
+        let mut first = open_collection(&folder, "first");
+        let mut deck = Deck::new("Spanish");
+        deck.save(&first).unwrap();
+        add_card(&first, &deck, "hola");
+        sync(&mut first, &client).unwrap();
+
+        let mut second = open_collection(&folder, "second");
+        let mut other = Deck::new("French");
+        other.save(&second).unwrap();
+        add_card(&second, &other, "bonjour");
+
+        assert_eq!(
+            sync(&mut second, &client).unwrap(),
+            SyncOutcome::FullSyncRequired
+        );
+
+        full_download(&mut second, &client).unwrap();
+        assert_eq!(questions(&second), vec!["hola"]);
+        assert_eq!(sync(&mut second, &client).unwrap(), SyncOutcome::NoChanges);
+    }
+
+    #[test]
+    fn full_download_keeps_the_folder_sync_device() {
+        let folder = temp_folder();
+        let client = start_server(&folder);
+        let shared = folder.join("shared");
+        std::fs::create_dir_all(&shared).unwrap();
+
+        let mut first = open_collection(&folder, "first");
+        let mut deck = Deck::new("Spanish");
+        deck.save(&first).unwrap();
+        add_card(&first, &deck, "hola");
+        // Saved long enough ago to go into the journal.
+        first
+            .execute("UPDATE decks SET mtime = mtime - 10", [])
+            .unwrap();
+        sync_folder(&mut first, &shared).unwrap();
+        sync(&mut first, &client).unwrap();
+
+        let mut second = open_collection(&folder, "second");
+        let mut other = Deck::new("French");
+        other.save(&second).unwrap();
+        let mut state = folder_state(&second).unwrap();
+        state.folder = Some(shared.clone());
+        save_folder_state(&second, &state).unwrap();
+        sync_folder(&mut second, &shared).unwrap();
+        let state = folder_state(&second).unwrap();
+        let progress = journal_progress(&second).unwrap();
+        assert_eq!(progress.len(), 1);
+
+        full_download(&mut second, &client).unwrap();
+
+        assert_eq!(folder_state(&second).unwrap(), state);
+        assert_ne!(state.device, folder_state(&first).unwrap().device);
+        assert_eq!(journal_progress(&second).unwrap(), progress);
+    }
+}
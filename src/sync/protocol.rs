@@ -0,0 +1,142 @@
+//! Messages exchanged between the app and the sync server, serialised as JSON.
+
+use serde::{Deserialize, Serialize};
+
+pub use crate::repositories::guid::{GraveKind, PENDING_USN};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DeckEntry {
+    pub guid: String,
+    pub name: String,
+    pub creation_time: String,
+    pub mtime: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NotetypeEntry {
+    pub guid: String,
+    pub name: String,
+    pub kind: u8,
+    /// The field names, as stored: a JSON array.
+    pub fields: String,
+    /// The card templates, as stored: a JSON array.
+    pub templates: String,
+    pub mtime: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NoteEntry {
+    pub guid: String,
+    pub notetype_guid: String,
+    /// The field values, separated by `FIELD_SEPARATOR`.
+    pub fields: String,
+    /// The tags, as stored: separated and surrounded by spaces.
+    #[serde(default)]
+    pub tags: String,
+    pub mtime: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CardEntry {
+    pub guid: String,
+    pub deck_guid: String,
+    pub question: String,
+    pub answer: String,
+    pub creation_time: String,
+    pub last_studied_time: Option<String>,
+    pub ef: f64,
+    pub interval: u32,
+    pub due: u32,
+    pub queue: u8,
+    pub data: String,
+    pub mtime: i64,
+    /// The note the card was generated from; cards made before notes existed
+    /// have none.
+    #[serde(default)]
+    pub note_guid: Option<String>,
+    #[serde(default)]
+    pub ord: u32,
+    #[serde(default)]
+    pub buried_until: u32,
+    #[serde(default)]
+    pub suspended: bool,
+    /// The colour flag, from 0 for none to 7.
+    #[serde(default)]
+    pub flags: u8,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RevlogEntry {
+    pub id: i64,
+    pub card_guid: String,
+    pub ease: u8,
+    pub interval: u32,
+    pub last_interval: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Grave {
+    pub guid: String,
+    /// A `GraveKind`: 0 for a deck, 1 for a card, 2 for a note.
+    pub kind: u8,
+    /// When the object was deleted.
+    #[serde(default)]
+    pub mtime: i64,
+}
+
+/// Objects added, modified or deleted on one side since the last sync.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Changes {
+    #[serde(default)]
+    pub notetypes: Vec<NotetypeEntry>,
+    pub decks: Vec<DeckEntry>,
+    #[serde(default)]
+    pub notes: Vec<NoteEntry>,
+    pub cards: Vec<CardEntry>,
+    pub revlog: Vec<RevlogEntry>,
+    pub graves: Vec<Grave>,
+}
+
+impl Changes {
+    pub fn len(&self) -> usize {
+        self.notetypes.len()
+            + self.decks.len()
+            + self.notes.len()
+            + self.cards.len()
+            + self.revlog.len()
+            + self.graves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Returned by `GET /meta` and after a full upload.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Meta {
+    /// The server's current update sequence number.
+    pub usn: i32,
+    /// Changes whenever the server's collection is replaced wholesale. A client
+    /// that last synced against a different schema must do a full sync.
+    pub schema: i64,
+    /// Set when the server holds no collection yet.
+    pub empty: bool,
+}
+
+/// Body of `POST /changes`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChangesRequest {
+    /// The server usn the client saw at its last sync.
+    pub since_usn: i32,
+    pub schema: i64,
+    pub changes: Changes,
+}
+
+/// Response of `POST /changes`: everything the client has not seen yet, and
+/// the usn that the client's changes were stored under.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChangesResponse {
+    pub usn: i32,
+    pub changes: Changes,
+}
@@ -0,0 +1,193 @@
+//! The sync server keeps the canonical copy of a collection and hands out the
+//! changes each client has not seen yet. It is deliberately small: requests
+//! are handled one at a time, and there is no authentication, so run it on a
+//! trusted network or behind a reverse proxy that adds it.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::Result;
+use rusqlite::Connection;
+use tiny_http::{Response, Server};
+
+use super::{
+    changes::{self, Selection, Side},
+    protocol::{ChangesRequest, ChangesResponse, Meta},
+};
+
+pub struct SyncServer {
+    collection_path: PathBuf,
+}
+
+/// A response to send back: the HTTP status and the body.
+pub type Reply = (u16, Vec<u8>);
+
+fn json_reply<T: serde::Serialize>(value: &T) -> Reply {
+    match serde_json::to_vec(value) {
+        Ok(body) => (200, body),
+        Err(e) => error_reply(500, e),
+    }
+}
+
+fn error_reply(status: u16, error: impl std::fmt::Display) -> Reply {
+    (status, error.to_string().into_bytes())
+}
+
+fn open(path: &Path) -> Result<Connection> {
+    let conn = Connection::open(path)?;
+    conn.busy_timeout(Duration::from_secs(5))?;
+    conn.pragma_update(None, "journal_mode", "delete")?;
+
+    Ok(conn)
+}
+
+impl SyncServer {
+    pub fn new(collection_path: PathBuf) -> Self {
+        SyncServer { collection_path }
+    }
+
+    /// Serves requests until the listener is closed.
+    pub fn serve(&self, server: Server) {
+        for mut request in server.incoming_requests() {
+            let mut body = Vec::new();
+
+            let (status, body) = match request.as_reader().read_to_end(&mut body) {
+                Ok(_) => self.handle(request.method().as_str(), request.url(), body),
+                Err(e) => error_reply(400, e),
+            };
+
+            log::info!("{} {} -> {}", request.method(), request.url(), status);
+
+            let response = Response::from_data(body).with_status_code(status);
+            if let Err(e) = request.respond(response) {
+                log::error!("Error sending response: {}", e);
+            }
+        }
+    }
+
+    pub fn handle(&self, method: &str, url: &str, body: Vec<u8>) -> Reply {
+        match (method, url) {
+            ("GET", "/meta") => match self.meta() {
+                Ok(meta) => json_reply(&meta),
+                Err(e) => error_reply(500, e),
+            },
+            ("POST", "/changes") => match serde_json::from_slice::<ChangesRequest>(&body) {
+                Ok(request) => self.changes(request),
+                Err(e) => error_reply(400, e),
+            },
+            ("POST", "/upload") => match self.upload(&body) {
+                Ok(meta) => json_reply(&meta),
+                Err(e) => error_reply(400, e),
+            },
+            ("GET", "/download") => match fs::read(&self.collection_path) {
+                Ok(bytes) => (200, bytes),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    error_reply(404, "the server has no collection yet")
+                }
+                Err(e) => error_reply(500, e),
+            },
+            _ => error_reply(404, "not found"),
+        }
+    }
+
+    fn meta(&self) -> Result<Meta> {
+        if !self.collection_path.exists() {
+            return Ok(Meta {
+                usn: 0,
+                schema: 0,
+                empty: true,
+            });
+        }
+
+        let state = changes::sync_state(&open(&self.collection_path)?)?;
+
+        Ok(Meta {
+            usn: state.last_usn,
+            schema: state.server_schema,
+            empty: false,
+        })
+    }
+
+    fn changes(&self, request: ChangesRequest) -> Reply {
+        match self.apply_changes(request) {
+            Ok(Some(response)) => json_reply(&response),
+            Ok(None) => error_reply(409, "full sync required"),
+            Err(e) => error_reply(500, e),
+        }
+    }
+
+    /// Returns `None` when the client last synced against another schema and
+    /// has to do a full sync instead.
+    fn apply_changes(&self, request: ChangesRequest) -> Result<Option<ChangesResponse>> {
+        if !self.collection_path.exists() {
+            return Ok(None);
+        }
+
+        let mut conn = open(&self.collection_path)?;
+        let mut state = changes::sync_state(&conn)?;
+
+        if request.schema != state.server_schema {
+            return Ok(None);
+        }
+
+        let tx = conn.transaction()?;
+
+        state.last_usn += 1;
+        changes::apply(&tx, &request.changes, state.last_usn, Side::Server)?;
+        changes::save_sync_state(&tx, &state)?;
+
+        let server_changes = changes::without_echoes(
+            changes::gather(&tx, Selection::Since(request.since_usn))?,
+            &request.changes,
+        );
+        tx.commit()?;
+
+        Ok(Some(ChangesResponse {
+            usn: state.last_usn,
+            changes: server_changes,
+        }))
+    }
+
+    /// Replaces the server's collection with the uploaded one, and starts a
+    /// new schema so every other client has to do a full sync.
+    fn upload(&self, bytes: &[u8]) -> Result<Meta> {
+        if let Some(folder) = self.collection_path.parent() {
+            fs::create_dir_all(folder)?;
+        }
+
+        let incoming = self.collection_path.with_extension("upload");
+        fs::write(&incoming, bytes)?;
+
+        if let Err(e) = Self::prepare_upload(&incoming) {
+            let _ = fs::remove_file(&incoming);
+            return Err(e);
+        }
+
+        fs::rename(&incoming, &self.collection_path)?;
+
+        self.meta()
+    }
+
+    fn prepare_upload(path: &Path) -> Result<()> {
+        let conn = open(path)?;
+
+        let check: String = conn.query_row("PRAGMA quick_check", [], |row| row.get(0))?;
+        if check != "ok" {
+            anyhow::bail!("the uploaded collection is corrupt: {}", check);
+        }
+
+        let state = changes::SyncState {
+            endpoint: None,
+            last_usn: 1,
+            server_schema: chrono::Utc::now().timestamp_millis(),
+            full_sync_required: false,
+        };
+        changes::reset_usns(&conn, state.last_usn)?;
+        changes::save_sync_state(&conn, &state)?;
+
+        Ok(())
+    }
+}
@@ -101,6 +101,22 @@ impl ListItem {
         self.on_toggle = Some(Arc::new(on_toggle));
         self
     }
+
+    /// Shows a disclosure arrow, open or closed, that calls `on_toggle`.
+    pub fn toggle(mut self, toggle: impl Into<Option<bool>>) -> Self {
+        self.toggle = toggle.into();
+        self
+    }
+
+    pub fn start_slot(mut self, start_slot: impl IntoElement) -> Self {
+        self.start_slot = Some(start_slot.into_any_element());
+        self
+    }
+
+    pub fn end_slot(mut self, end_slot: impl IntoElement) -> Self {
+        self.end_slot = Some(end_slot.into_any_element());
+        self
+    }
 }
 
 impl Disableable for ListItem {
@@ -169,6 +185,26 @@ impl RenderOnce for ListItem {
                             this.ml(self.indent_level as f32 * self.indent_step_size)
                         }
                     })
+                    .when_some(self.toggle, |this, is_open| {
+                        let on_toggle = self.on_toggle.clone();
+
+                        this.child(
+                            div()
+                                .id("toggle")
+                                .w_3()
+                                .flex()
+                                .justify_center()
+                                .cursor_pointer()
+                                .child(if is_open { "▾" } else { "▸" })
+                                // Toggling should not also trigger the row.
+                                .on_mouse_down(MouseButton::Left, |_event, cx| {
+                                    cx.stop_propagation()
+                                })
+                                .when_some(on_toggle, |this, on_toggle| {
+                                    this.on_click(move |event, cx| (on_toggle)(event, cx))
+                                }),
+                        )
+                    })
                     .child(
                         div()
                             .flex()